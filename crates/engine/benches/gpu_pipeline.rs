@@ -0,0 +1,108 @@
+//! Fused vs per-CALC GPU execution over a large frame.
+//!
+//! Fused: the frame goes up once, each dependency wave runs as one batch over the
+//! resident columns, and the outputs come back in one download. Per-CALC: every CALC
+//! uploads its inputs, runs and downloads on its own, as the engine used to.
+//!
+//! `cargo bench -p engine --bench gpu_pipeline [rows]`
+
+extern crate engine;
+
+use engine::parser::{parse, ActionSection};
+use engine::runtime::GpuRuntime;
+use engine::utils::action::action_over_data_gpu;
+use polars::prelude::*;
+use std::time::{Duration, Instant};
+
+const SRC: &str = r#"
+PROVIDER p USING synthetic
+    FROM 2020-01-01 TO 2020-06-30
+
+FRAME f
+    PROVIDER p
+    PULL open, high, low, close
+    CALC close, open DIFFERENCE CALLED oc
+    CALC high, low DIFFERENCE CALLED hl
+    CALC close SMA CALLED c_sma
+    CALC close VOLATILITY CALLED c_vol
+    CALC oc SMA CALLED oc_sma
+    CALC hl, oc DIFFERENCE CALLED wick
+    CALC c_vol_pos, c_sma DIFFERENCE CALLED upper
+    CALC c_vol_neg, c_sma DIFFERENCE CALLED lower
+    CALC upper, lower DIFFERENCE CALLED width
+    CALC width SMA CALLED width_sma
+"#;
+
+const RUNS: u32 = 10;
+
+fn frame(rows: usize) -> DataFrame {
+    let close: Vec<f64> = (0..rows)
+        .map(|i| 4000.0 + (i as f64 * 0.01).sin() * 50.0 + (i * 7919 % 100) as f64 * 0.1)
+        .collect();
+    df!(
+        "timestamp" => (0..rows as i64).map(|i| i * 60).collect::<Vec<_>>(),
+        "open" => close.iter().map(|c| c - 0.5).collect::<Vec<_>>(),
+        "high" => close.iter().map(|c| c + 2.0).collect::<Vec<_>>(),
+        "low" => close.iter().map(|c| c - 2.0).collect::<Vec<_>>(),
+        "close" => close,
+    )
+    .unwrap()
+}
+
+fn fused(action: &ActionSection, df: &DataFrame, rt: &mut GpuRuntime) -> DataFrame {
+    action_over_data_gpu(action, df.clone(), rt).unwrap()
+}
+
+fn per_calc(action: &ActionSection, df: &DataFrame, rt: &mut GpuRuntime) -> DataFrame {
+    let mut df = df.clone();
+    for calc in action.calc.iter().flatten() {
+        let one = ActionSection {
+            fields: vec![],
+            calc: Some(vec![calc.clone()]),
+        };
+        let out = action_over_data_gpu(&one, df.clone(), rt).unwrap();
+        for column in &out.get_columns()[1..] {
+            df.with_column(column.clone()).unwrap();
+        }
+    }
+    df
+}
+
+fn time(
+    action: &ActionSection,
+    df: &DataFrame,
+    rt: &mut GpuRuntime,
+    run: fn(&ActionSection, &DataFrame, &mut GpuRuntime) -> DataFrame,
+) -> Duration {
+    // warm up: compiles the pipelines
+    run(action, df, rt);
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        run(action, df, rt);
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let rows = std::env::args()
+        .skip(1)
+        .find_map(|a| a.parse().ok())
+        .unwrap_or(1_000_000);
+    let query = parse(SRC).unwrap();
+    let action = &query.frame["f"].actions;
+    let df = frame(rows);
+    let mut rt = pollster::block_on(GpuRuntime::new()).unwrap();
+
+    let fused_time = time(action, &df, &mut rt, fused);
+    let per_calc_time = time(action, &df, &mut rt, per_calc);
+    println!(
+        "{} CALCs over {rows} rows, mean of {RUNS} runs",
+        action.calc.iter().flatten().count()
+    );
+    println!("  per CALC: {per_calc_time:>10.2?}");
+    println!("  fused:    {fused_time:>10.2?}");
+    println!(
+        "  speedup:  {:>9.2}x",
+        per_calc_time.as_secs_f64() / fused_time.as_secs_f64()
+    );
+}
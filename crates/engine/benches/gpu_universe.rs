@@ -0,0 +1,99 @@
+//! One frame at a time vs packed GPU execution over a universe of tickers.
+//!
+//! One at a time: each ticker's frame goes up, runs its waves and comes back on its own.
+//! Packed: all the frames go up end to end as segments of one table, and each wave runs
+//! once for the lot.
+//!
+//! `cargo bench -p engine --bench gpu_universe [tickers] [rows]`
+
+extern crate engine;
+
+use engine::calendar::Calendar;
+use engine::parser::{parse, ActionSection};
+use engine::runtime::GpuRuntime;
+use engine::utils::action::{action_over_data_gpu, action_over_frames_gpu};
+use engine::utils::incremental::CalcOutputs;
+use polars::prelude::*;
+use std::time::{Duration, Instant};
+
+const SRC: &str = r#"
+PROVIDER p USING synthetic
+    FROM 2020-01-01 TO 2020-06-30
+
+FRAME f
+    PROVIDER p
+    PULL open, close
+    CALC close, open DIFFERENCE CALLED oc
+    CALC close SMA CALLED c_sma
+    CALC close VOLATILITY CALLED c_vol
+    CALC c_vol_pos, c_sma DIFFERENCE CALLED upper
+    CALC oc SMA CALLED oc_sma
+"#;
+
+const RUNS: u32 = 5;
+
+fn frames(tickers: usize, rows: usize) -> Vec<DataFrame> {
+    (0..tickers)
+        .map(|t| {
+            let level = 20.0 + (t * 37 % 500) as f64;
+            let close: Vec<f64> = (0..rows)
+                .map(|i| level + ((i + t) as f64 * 0.05).sin() + (i * 7919 % 100) as f64 * 0.01)
+                .collect();
+            df!(
+                "timestamp" => (0..rows as i64).map(|i| i * 86_400).collect::<Vec<_>>(),
+                "open" => close.iter().map(|c| c - 0.1).collect::<Vec<_>>(),
+                "close" => close,
+            )
+            .unwrap()
+        })
+        .collect()
+}
+
+fn one_at_a_time(action: &ActionSection, dfs: &[DataFrame], rt: &mut GpuRuntime) {
+    for df in dfs {
+        action_over_data_gpu(action, df.clone(), rt).unwrap();
+    }
+}
+
+fn packed(action: &ActionSection, dfs: &[DataFrame], rt: &mut GpuRuntime) {
+    let mut outputs = vec![CalcOutputs::new(); dfs.len()];
+    action_over_frames_gpu(action, dfs.to_vec(), &Calendar::default(), rt, &mut outputs).unwrap();
+}
+
+fn time(
+    action: &ActionSection,
+    dfs: &[DataFrame],
+    rt: &mut GpuRuntime,
+    run: fn(&ActionSection, &[DataFrame], &mut GpuRuntime),
+) -> Duration {
+    // warm up: compiles the pipelines
+    run(action, dfs, rt);
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        run(action, dfs, rt);
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1).filter_map(|a| a.parse().ok());
+    let tickers = args.next().unwrap_or(500);
+    let rows = args.next().unwrap_or(2_000);
+    let query = parse(SRC).unwrap();
+    let action = &query.frame["f"].actions;
+    let dfs = frames(tickers, rows);
+    let mut rt = pollster::block_on(GpuRuntime::new()).unwrap();
+
+    let one_time = time(action, &dfs, &mut rt, one_at_a_time);
+    let packed_time = time(action, &dfs, &mut rt, packed);
+    println!(
+        "{} CALCs over {tickers} tickers of {rows} rows, mean of {RUNS} runs",
+        action.calc.iter().flatten().count()
+    );
+    println!("  one at a time: {one_time:>10.2?}");
+    println!("  packed:        {packed_time:>10.2?}");
+    println!(
+        "  speedup:       {:>9.2}x",
+        one_time.as_secs_f64() / packed_time.as_secs_f64()
+    );
+}
@@ -1,11 +1,17 @@
+use crate::calendar::{bar_interval, timestamps, Calendar};
 use crate::{lexer::Keyword, parser::Calc};
 use polars::prelude::*;
 
-pub struct Calculation(Calc);
+pub struct Calculation(Calc, Calendar);
 
 impl Calculation {
-    pub fn new(calc: Calc) -> Self {
-        Calculation(calc)
+    /// `calc` over bars of an exchange on `calendar`, which annualizes VOLATILITY.
+    pub fn new(calc: Calc, calendar: &Calendar) -> Self {
+        Calculation(calc, calendar.clone())
+    }
+
+    fn periods_per_year(&self, df: &DataFrame) -> f64 {
+        periods_per_year(&self.1, df)
     }
 
     pub fn calculate(&self, df: &DataFrame) -> Result<DataFrame, String> {
@@ -82,21 +88,14 @@ impl Calculation {
                     if i + 1 < period {
                         sma_values.push(None); // Not enough data for SMA
                     } else {
+                        // a missing value in the window leaves the average missing
                         let start = i + 1 - period;
-                        let sum: f64 = data[0][start..=i].iter().filter_map(|&x| x).sum();
-                        let sma = sum / period as f64;
-                        sma_values.push(Some(sma));
+                        let sum: Option<f64> = data[0][start..=i].iter().copied().sum();
+                        sma_values.push(sum.map(|sum| sum / period as f64));
                     }
                 }
-                // Shift the first [0..period] elements to the end of the array
-                let mut shifted_sma_values = sma_values;
-                let front = shifted_sma_values.drain(0..period / 2).collect::<Vec<_>>();
-                shifted_sma_values.extend(front);
-
-                let sma_values = shifted_sma_values
-                    .into_iter()
-                    .map(|x| x.unwrap_or(0.0)) // Replace None with 0.0 for consistency
-                    .collect::<Vec<f64>>();
+                let sma_values = align(sma_values, self.0.window_ahead(period));
+
                 let name = self.0.alias.clone();
                 let series = Series::new(name.into(), sma_values);
                 DataFrame::new(vec![series.into_column()])
@@ -106,6 +105,8 @@ impl Calculation {
             Keyword::Volatility => {
                 // `data[0]` is assumed to be Vec<Option<f64>> of closing prices.
                 let period: usize = 14;
+                // per-bar volatility to yearly, by how many bars the calendar fits in a year
+                let annualize = self.periods_per_year(df).sqrt();
 
                 // 1) Log returns
                 let mut log_ret: Vec<Option<f64>> = Vec::with_capacity(data[0].len());
@@ -137,11 +138,11 @@ impl Calculation {
                         / ((window.len() - 1) as f64);
                     let std = var.sqrt();
 
-                    // Optional: annualize (daily data -> * sqrt(252))
-                    let annualized = std * 252f64.sqrt();
+                    let annualized = std * annualize;
 
                     vol.push(Some(annualized)); // or push `std` if you prefer non-annualized
                 }
+                let vol = align(vol, self.0.window_ahead(period));
 
                 // 3) Keep nulls; don't zero-fill
                 let name = format!("{}", self.0.alias);
@@ -198,6 +199,7 @@ impl Calculation {
 
                 // `data[0]` is assumed to be Vec<Option<f64>> of closing prices.
                 let period: usize = 14;
+                let annualize = self.periods_per_year(df).sqrt();
 
                 // 1) Log returns
                 let mut log_ret: Vec<Option<f64>> = Vec::with_capacity(data[0].len());
@@ -229,11 +231,11 @@ impl Calculation {
                         / ((window.len() - 1) as f64);
                     let std = var.sqrt();
 
-                    // Optional: annualize (daily data -> * sqrt(252))
-                    let annualized = std * 252f64.sqrt();
+                    let annualized = std * annualize;
 
                     vol.push(Some(annualized)); // or push `std` if you prefer non-annualized
                 }
+                let vol = align(vol, self.0.window_ahead(period));
 
                 // 3) Keep nulls; don't zero-fill
                 let name = format!("{}", self.0.alias);
@@ -369,3 +371,22 @@ impl Calculation {
         }
     }
 }
+
+/// Bars per year of `df` on `calendar`, from the spacing of its timestamps (daily when
+/// it has none), to annualize VOLATILITY.
+pub(crate) fn periods_per_year(calendar: &Calendar, df: &DataFrame) -> f64 {
+    let interval = timestamps(df)
+        .ok()
+        .and_then(|ts| bar_interval(&ts))
+        .unwrap_or(86_400);
+    calendar.periods_per_year(interval)
+}
+
+/// Put the window ending `ahead` bars after each bar on that bar; the last `ahead` bars
+/// have no window and are left missing.
+fn align(mut values: Vec<Option<f64>>, ahead: usize) -> Vec<Option<f64>> {
+    let n = values.len();
+    values.drain(..ahead.min(n));
+    values.resize(n, None);
+    values
+}
@@ -0,0 +1,667 @@
+// calendar.rs
+// -----------------------------------------------------------------------------
+// Exchange calendars: trading days, session hours, gaps and annualization
+// -----------------------------------------------------------------------------
+
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime, Timelike, Weekday};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+pub(crate) const DAY: i64 = 86_400;
+
+/// Days NYSE closed outside its holiday rules.
+const NYSE_CLOSURES: [&str; 11] = [
+    "1994-04-27", // President Nixon's funeral
+    "2001-09-11", // September 11
+    "2001-09-12",
+    "2001-09-13",
+    "2001-09-14",
+    "2004-06-11", // President Reagan's funeral
+    "2007-01-02", // President Ford's funeral
+    "2012-10-29", // Hurricane Sandy
+    "2012-10-30",
+    "2018-12-05", // President Bush's funeral
+    "2025-01-09", // President Carter's funeral
+];
+
+/// Daylight saving rule of an exchange's time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dst {
+    None,
+    /// second Sunday of March to the first Sunday of November
+    Us,
+    /// last Sunday of March to the last Sunday of October
+    Eu,
+}
+
+/// When an exchange trades: its trading days and their session hours in exchange time.
+///
+/// `nyse` (the default), `24/7`, or a JSON definition file, picked per PROVIDER with
+/// `PARAM calendar = ...`. Daily bars are dated by their UTC date, intraday bars by
+/// exchange time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calendar {
+    pub name: String,
+    /// seconds east of UTC, outside daylight saving
+    utc_offset: i64,
+    dst: Dst,
+    /// session hours, seconds after local midnight
+    open: i64,
+    close: i64,
+    early_close: i64,
+    weekend: Vec<Weekday>,
+    /// NYSE's rule-based holidays and early closes on top of the listed ones
+    nyse_rules: bool,
+    holidays: HashSet<NaiveDate>,
+    early_closes: HashSet<NaiveDate>,
+}
+
+impl Default for Calendar {
+    fn default() -> Self {
+        Calendar::nyse()
+    }
+}
+
+impl Calendar {
+    /// New York Stock Exchange: 09:30 to 16:00 New York time, 13:00 on early closes.
+    pub fn nyse() -> Self {
+        Calendar {
+            name: "NYSE".to_string(),
+            utc_offset: -5 * 3_600,
+            dst: Dst::Us,
+            open: 9 * 3_600 + 30 * 60,
+            close: 16 * 3_600,
+            early_close: 13 * 3_600,
+            weekend: vec![Weekday::Sat, Weekday::Sun],
+            nyse_rules: true,
+            holidays: NYSE_CLOSURES
+                .iter()
+                .filter_map(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+                .collect(),
+            early_closes: HashSet::new(),
+        }
+    }
+
+    /// Every day, all day, in UTC: crypto, or generated data.
+    pub fn always() -> Self {
+        Calendar {
+            name: "24/7".to_string(),
+            utc_offset: 0,
+            dst: Dst::None,
+            open: 0,
+            close: DAY,
+            early_close: DAY,
+            weekend: Vec::new(),
+            nyse_rules: false,
+            holidays: HashSet::new(),
+            early_closes: HashSet::new(),
+        }
+    }
+
+    /// `nyse`, `24/7`, or the path of a definition file relative to `base_dir`.
+    pub fn named(name: &str, base_dir: &Path) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "nyse" => Ok(Self::nyse()),
+            "24/7" | "always" => Ok(Self::always()),
+            _ => Self::from_file(&base_dir.join(name)),
+        }
+    }
+
+    /// A calendar defined in a JSON file, see [`CalendarFile`].
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read calendar {}: {}", path.display(), e))?;
+        serde_json::from_str::<CalendarFile>(&text)
+            .map_err(|e| e.to_string())
+            .and_then(CalendarFile::build)
+            .map_err(|e| format!("calendar {}: {}", path.display(), e))
+    }
+
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        let holiday = self.holidays.contains(&date)
+            || (self.nyse_rules && nyse_holidays(date.year()).contains(&date));
+        !holiday && !self.weekend.contains(&date.weekday())
+    }
+
+    fn is_early_close(&self, date: NaiveDate) -> bool {
+        self.early_closes.contains(&date)
+            || (self.nyse_rules && nyse_early_closes(date.year()).contains(&date))
+    }
+
+    /// Open and close of `date`'s session in epoch seconds, `None` when closed.
+    pub fn session(&self, date: NaiveDate) -> Option<(i64, i64)> {
+        if !self.is_trading_day(date) {
+            return None;
+        }
+        let midnight = epoch_day(date) * DAY - self.offset_on(date);
+        let close = match self.is_early_close(date) {
+            true => self.early_close,
+            false => self.close,
+        };
+        Some((midnight + self.open, midnight + close))
+    }
+
+    /// Whether `ts` is in a session, from its open up to its close.
+    pub fn in_session(&self, ts: i64) -> bool {
+        self.session(self.local_date(ts))
+            .is_some_and(|(open, close)| ts >= open && ts < close)
+    }
+
+    /// The date of a bar at `ts`: its UTC date for `daily` bars, as daily bars are
+    /// stamped at midnight UTC as often as at the open, else its date in exchange time.
+    pub fn bar_date(&self, ts: i64, daily: bool) -> NaiveDate {
+        match daily {
+            true => date_of_day(ts.div_euclid(DAY)),
+            false => self.local_date(ts),
+        }
+    }
+
+    /// The `n`th trading day after `date`.
+    pub fn add_trading_days(&self, date: NaiveDate, n: u32) -> NaiveDate {
+        let mut date = date;
+        for _ in 0..n {
+            date = self.next_trading_day(date);
+        }
+        date
+    }
+
+    /// When `secs` of trading time have passed since `ts`, counting only session hours.
+    pub fn add_trading_time(&self, ts: i64, secs: i64) -> i64 {
+        let mut left = secs;
+        let mut date = self.local_date(ts);
+        // a year of days without the time running out means there are no sessions left
+        for _ in 0..366 {
+            if let Some((open, close)) = self.session(date) {
+                let start = ts.max(open);
+                if start < close {
+                    if start + left <= close {
+                        return start + left;
+                    }
+                    left -= close - start;
+                }
+            }
+            date = self.next_day(date);
+        }
+        i64::MAX
+    }
+
+    /// Bars per year at `interval` seconds apart: trading days for daily bars, weeks
+    /// and months for coarser ones, trading time over the interval for intraday ones.
+    pub fn periods_per_year(&self, interval: i64) -> f64 {
+        match interval {
+            i if i >= 28 * DAY => 12.0,
+            i if i >= 7 * DAY => 52.0,
+            i if i >= DAY => self.trading_days_per_year(),
+            i => self.trading_days_per_year() * (self.close - self.open) as f64 / i.max(1) as f64,
+        }
+    }
+
+    /// Averaged over 2015 to 2024, so one year's holidays don't skew it.
+    fn trading_days_per_year(&self) -> f64 {
+        let from = NaiveDate::from_ymd_opt(2015, 1, 1).unwrap();
+        let days = from
+            .iter_days()
+            .take_while(|d| d.year() < 2025)
+            .filter(|d| self.is_trading_day(*d))
+            .count();
+        days as f64 / 10.0
+    }
+
+    /// Sessions with no bars between consecutive bars of `timestamps` (sorted). Bars a
+    /// week or more apart aren't checked.
+    pub fn gaps(&self, timestamps: &[i64]) -> Vec<Gap> {
+        let Some(interval) = bar_interval(timestamps) else {
+            return Vec::new();
+        };
+        if interval >= 7 * DAY {
+            return Vec::new();
+        }
+        let daily = interval >= DAY;
+        timestamps
+            .windows(2)
+            .filter_map(|pair| {
+                let (after, before) = (pair[0], pair[1]);
+                let missing = match daily {
+                    true => self.trading_days_between(after, before),
+                    false => self.slots_between(after, before, interval),
+                };
+                (missing > 0).then_some(Gap {
+                    after,
+                    before,
+                    missing,
+                })
+            })
+            .collect()
+    }
+
+    /// Intraday `bars` inside sessions, dropping pre- and post-market bars. Daily and
+    /// coarser bars come back as they are.
+    pub fn session_bars(&self, bars: &DataFrame) -> Result<DataFrame, String> {
+        let ts = timestamps(bars)?;
+        if bar_interval(&ts).is_none_or(|i| i >= DAY) {
+            return Ok(bars.clone());
+        }
+        let keep: Vec<bool> = ts.iter().map(|t| self.in_session(*t)).collect();
+        bars.filter(&BooleanChunked::from_slice("keep".into(), &keep))
+            .map_err(|e| e.to_string())
+    }
+
+    fn trading_days_between(&self, after: i64, before: i64) -> usize {
+        let (first, last) = (self.bar_date(after, true), self.bar_date(before, true));
+        first
+            .iter_days()
+            .skip(1)
+            .take_while(|d| *d < last)
+            .filter(|d| self.is_trading_day(*d))
+            .count()
+    }
+
+    /// Bars expected strictly between `after` and `before`, `interval` apart from each
+    /// session's open.
+    fn slots_between(&self, after: i64, before: i64, interval: i64) -> usize {
+        let (first, last) = (self.local_date(after), self.local_date(before));
+        let mut missing = 0;
+        let mut date = first;
+        while date <= last {
+            if let Some((open, close)) = self.session(date) {
+                let (lo, hi) = ((after + 1).max(open), before.min(close));
+                if lo < hi {
+                    // slots open + k * interval with lo <= slot < hi
+                    let first_slot = (lo - open + interval - 1).div_euclid(interval);
+                    let end_slot = (hi - open + interval - 1).div_euclid(interval);
+                    missing += (end_slot - first_slot).max(0) as usize;
+                }
+            }
+            date = self.next_day(date);
+        }
+        missing
+    }
+
+    fn next_trading_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut next = self.next_day(date);
+        // a definition has fewer than 7 weekend days, so a trading day comes soon
+        for _ in 0..366 {
+            if self.is_trading_day(next) {
+                break;
+            }
+            next = self.next_day(next);
+        }
+        next
+    }
+
+    fn next_day(&self, date: NaiveDate) -> NaiveDate {
+        date.succ_opt().unwrap_or(date)
+    }
+
+    /// Offset from UTC, daylight saving included, on local `date`.
+    fn offset_on(&self, date: NaiveDate) -> i64 {
+        let year = date.year();
+        let summer = match self.dst {
+            Dst::None => false,
+            Dst::Us => {
+                date >= nth_weekday(year, 3, Weekday::Sun, 2)
+                    && date < nth_weekday(year, 11, Weekday::Sun, 1)
+            }
+            Dst::Eu => {
+                date >= last_weekday(year, 3, Weekday::Sun)
+                    && date < last_weekday(year, 10, Weekday::Sun)
+            }
+        };
+        self.utc_offset + if summer { 3_600 } else { 0 }
+    }
+
+    /// The exchange's date at `ts`.
+    fn local_date(&self, ts: i64) -> NaiveDate {
+        let standard = date_of_day((ts + self.utc_offset).div_euclid(DAY));
+        date_of_day((ts + self.offset_on(standard)).div_euclid(DAY))
+    }
+}
+
+/// Bars missing between two bars, according to the calendar.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Gap {
+    /// timestamps of the bars either side
+    pub after: i64,
+    pub before: i64,
+    pub missing: usize,
+}
+
+impl fmt::Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let daily = self.after % DAY == 0 && self.before % DAY == 0;
+        let time = |t: i64| format_time(t, daily);
+        write!(
+            f,
+            "{} bar{} missing between {} and {}",
+            self.missing,
+            if self.missing == 1 { "" } else { "s" },
+            time(self.after),
+            time(self.before)
+        )
+    }
+}
+
+/// `2024-03-01` for daily bars, `2024-03-01 14:30 UTC` otherwise.
+pub fn format_time(t: i64, daily: bool) -> String {
+    let t = DateTime::from_timestamp(t, 0).unwrap_or_default();
+    match daily {
+        true => t.format("%Y-%m-%d").to_string(),
+        false => t.format("%Y-%m-%d %H:%M UTC").to_string(),
+    }
+}
+
+/// The usual spacing of `timestamps` (sorted): the median gap between bars, so
+/// weekends and holidays don't count. `None` for fewer than two bars.
+pub fn bar_interval(timestamps: &[i64]) -> Option<i64> {
+    let mut steps: Vec<i64> = timestamps
+        .windows(2)
+        .map(|w| w[1] - w[0])
+        .filter(|s| *s > 0)
+        .collect();
+    if steps.is_empty() {
+        return None;
+    }
+    let mid = steps.len() / 2;
+    Some(*steps.select_nth_unstable(mid).1)
+}
+
+/// The `timestamp` column of `bars`, nulls dropped.
+pub fn timestamps(bars: &DataFrame) -> Result<Vec<i64>, String> {
+    Ok(bars
+        .column("timestamp")
+        .and_then(|c| c.cast(&DataType::Int64))
+        .map_err(|e| e.to_string())?
+        .i64()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .flatten()
+        .collect())
+}
+
+/// A calendar definition file:
+///
+/// ```json
+/// {
+///     "name": "LSE",
+///     "utc_offset": "+00:00",
+///     "dst": "eu",
+///     "open": "08:00",
+///     "close": "16:30",
+///     "early_close": "12:30",
+///     "holidays": ["2024-12-25", "2024-12-26"],
+///     "early_closes": ["2024-12-24"]
+/// }
+/// ```
+///
+/// `dst` is `us`, `eu` or `none` (the default), `weekend` defaults to `["Sat", "Sun"]`,
+/// and `"rules": "nyse"` adds NYSE's holidays to the listed ones.
+#[derive(Debug, Deserialize)]
+struct CalendarFile {
+    name: Option<String>,
+    rules: Option<String>,
+    utc_offset: Option<String>,
+    dst: Option<String>,
+    open: String,
+    close: String,
+    early_close: Option<String>,
+    weekend: Option<Vec<String>>,
+    #[serde(default)]
+    holidays: Vec<String>,
+    #[serde(default)]
+    early_closes: Vec<String>,
+}
+
+impl CalendarFile {
+    fn build(self) -> Result<Calendar, String> {
+        let time = |s: &str| match s {
+            "24:00" => Ok(DAY),
+            _ => NaiveTime::parse_from_str(s, "%H:%M")
+                .map(|t| t.num_seconds_from_midnight() as i64)
+                .map_err(|_| format!("'{}': expected a time like 09:30", s)),
+        };
+        let dates = |list: &[String]| {
+            list.iter()
+                .map(|d| {
+                    NaiveDate::parse_from_str(d, "%Y-%m-%d")
+                        .map_err(|_| format!("'{}': expected a date like 2024-12-25", d))
+                })
+                .collect::<Result<HashSet<_>, _>>()
+        };
+
+        let utc_offset = match self.utc_offset.as_deref() {
+            None => 0,
+            Some(s) => {
+                let (sign, hhmm) = match s.strip_prefix('-') {
+                    Some(rest) => (-1, rest),
+                    None => (1, s.trim_start_matches('+')),
+                };
+                sign * time(hhmm).map_err(|_| format!("utc_offset '{}': expected +HH:MM", s))?
+            }
+        };
+        let dst = match self.dst.as_deref().map(str::to_ascii_lowercase).as_deref() {
+            None | Some("none") => Dst::None,
+            Some("us") => Dst::Us,
+            Some("eu") => Dst::Eu,
+            Some(other) => return Err(format!("dst '{}': expected us, eu or none", other)),
+        };
+        let nyse_rules = match self.rules.as_deref() {
+            None => false,
+            Some(r) if r.eq_ignore_ascii_case("nyse") => true,
+            Some(other) => return Err(format!("rules '{}': only nyse is known", other)),
+        };
+        let weekend = match &self.weekend {
+            None => vec![Weekday::Sat, Weekday::Sun],
+            Some(days) => days
+                .iter()
+                .map(|d| {
+                    d.parse::<Weekday>()
+                        .map_err(|_| format!("weekend '{}': expected a day like Sat", d))
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        let (open, close) = (time(&self.open)?, time(&self.close)?);
+        if open >= close {
+            return Err("sessions must close after they open, on the same day".to_string());
+        }
+        if weekend.len() >= 7 {
+            return Err("a calendar needs at least one trading day a week".to_string());
+        }
+
+        Ok(Calendar {
+            name: self.name.unwrap_or_else(|| "custom".to_string()),
+            utc_offset,
+            dst,
+            open,
+            close,
+            early_close: match &self.early_close {
+                Some(t) => time(t)?.clamp(open, close),
+                None => close,
+            },
+            weekend,
+            nyse_rules,
+            holidays: dates(&self.holidays)?,
+            early_closes: dates(&self.early_closes)?,
+        })
+    }
+}
+
+fn date_of_day(day: i64) -> NaiveDate {
+    DateTime::from_timestamp(day * DAY, 0)
+        .unwrap_or_default()
+        .date_naive()
+}
+
+fn epoch_day(date: NaiveDate) -> i64 {
+    date.and_time(NaiveTime::MIN).and_utc().timestamp() / DAY
+}
+
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).unwrap_or_default()
+}
+
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, 5)
+        .unwrap_or_else(|| nth_weekday(year, month, weekday, 4))
+}
+
+/// Weekend holidays move to the Friday before or the Monday after.
+fn observed(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date - Days::new(1),
+        Weekday::Sun => date + Days::new(1),
+        _ => date,
+    }
+}
+
+/// Western Easter Sunday (the anonymous Gregorian algorithm).
+fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let (b, c) = (year / 100, year % 100);
+    let (d, e) = (b / 4, b % 4);
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let (i, k) = (c / 4, c % 4);
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap_or_default()
+}
+
+/// NYSE's full-day holidays in `year`, by its current rules.
+fn nyse_holidays(year: i32) -> Vec<NaiveDate> {
+    let date = |m, d| NaiveDate::from_ymd_opt(year, m, d).unwrap_or_default();
+    let mut days = Vec::with_capacity(10);
+    // New Year's Day on a Saturday isn't moved back into the old year
+    let new_year = date(1, 1);
+    if new_year.weekday() != Weekday::Sat {
+        days.push(observed(new_year));
+    }
+    if year >= 1998 {
+        days.push(nth_weekday(year, 1, Weekday::Mon, 3));
+    }
+    days.push(nth_weekday(year, 2, Weekday::Mon, 3));
+    days.push(easter(year) - Days::new(2));
+    days.push(last_weekday(year, 5, Weekday::Mon));
+    if year >= 2022 {
+        days.push(observed(date(6, 19)));
+    }
+    days.push(observed(date(7, 4)));
+    days.push(nth_weekday(year, 9, Weekday::Mon, 1));
+    days.push(nth_weekday(year, 11, Weekday::Thu, 4));
+    days.push(observed(date(12, 25)));
+    days
+}
+
+/// NYSE's 13:00 closes in `year`: July 3rd, the day after Thanksgiving and Christmas
+/// Eve, when they are weekdays and not holidays themselves.
+fn nyse_early_closes(year: i32) -> Vec<NaiveDate> {
+    let holidays = nyse_holidays(year);
+    let date = |m, d| NaiveDate::from_ymd_opt(year, m, d).unwrap_or_default();
+    [
+        date(7, 3),
+        nth_weekday(year, 11, Weekday::Thu, 4) + Days::new(1),
+        date(12, 24),
+    ]
+    .into_iter()
+    .filter(|d| !matches!(d.weekday(), Weekday::Sat | Weekday::Sun) && !holidays.contains(d))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bar_interval, Calendar, Gap};
+    use chrono::NaiveDate;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn epoch(s: &str) -> i64 {
+        chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M")
+            .unwrap()
+            .and_utc()
+            .timestamp()
+    }
+
+    #[test]
+    fn test_nyse_sessions_gaps_and_annualization() {
+        let nyse = Calendar::nyse();
+        // Good Friday, Juneteenth, observed July 4th and Christmas, Carter's funeral
+        for day in [
+            "2024-03-29",
+            "2024-06-19",
+            "2021-07-05",
+            "2022-12-26",
+            "2025-01-09",
+        ] {
+            assert!(!nyse.is_trading_day(date(day)), "{day}");
+        }
+        assert!(nyse.is_trading_day(date("2024-07-03")));
+        // winter and summer opens in UTC, the early close after Thanksgiving
+        assert_eq!(
+            nyse.session(date("2024-01-02")).unwrap().0,
+            epoch("2024-01-02 14:30")
+        );
+        assert_eq!(
+            nyse.session(date("2024-07-01")).unwrap().0,
+            epoch("2024-07-01 13:30")
+        );
+        assert_eq!(
+            nyse.session(date("2024-11-29")).unwrap().1,
+            epoch("2024-11-29 18:00")
+        );
+        assert!(!nyse.in_session(epoch("2024-07-01 13:00")), "pre-market");
+
+        // daily bars Thu 28 Mar to Thu 4 Apr 2024 without Tue 2 Apr: Good Friday and the
+        // weekend are expected, the Tuesday is a gap
+        let days = ["2024-03-28", "2024-04-01", "2024-04-03", "2024-04-04"];
+        let daily: Vec<i64> = days.iter().map(|d| epoch(&format!("{d} 00:00"))).collect();
+        let gaps = nyse.gaps(&daily);
+        assert_eq!(
+            gaps,
+            [Gap {
+                after: daily[1],
+                before: daily[2],
+                missing: 1
+            }]
+        );
+        assert_eq!(
+            gaps[0].to_string(),
+            "1 bar missing between 2024-04-01 and 2024-04-03"
+        );
+        // hourly bars from the open, 11:30 missing, overnight isn't a gap
+        let hourly = [
+            epoch("2024-04-01 13:30"),
+            epoch("2024-04-01 14:30"),
+            epoch("2024-04-01 16:30"),
+            epoch("2024-04-01 17:30"),
+            epoch("2024-04-01 18:30"),
+            epoch("2024-04-01 19:30"),
+            epoch("2024-04-02 13:30"),
+        ];
+        assert_eq!(nyse.gaps(&hourly).len(), 1);
+        assert_eq!(nyse.gaps(&hourly)[0].missing, 1);
+
+        // HOLD 2d from Thursday before Easter lands on Tuesday; 2h from 15:00 New York
+        // time runs into the next morning
+        assert_eq!(
+            nyse.add_trading_days(date("2024-03-28"), 2),
+            date("2024-04-02")
+        );
+        assert_eq!(
+            nyse.add_trading_time(epoch("2024-04-01 19:00"), 7_200),
+            epoch("2024-04-02 14:30")
+        );
+
+        assert_eq!(bar_interval(&hourly), Some(3_600));
+        let per_year = nyse.periods_per_year(86_400);
+        assert!((251.0..253.0).contains(&per_year), "{per_year}");
+        assert!((nyse.periods_per_year(300) - per_year * 78.0).abs() < 1e-6);
+        assert!(Calendar::always().periods_per_year(86_400) > 365.0);
+    }
+}
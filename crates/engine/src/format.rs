@@ -0,0 +1,540 @@
+// format.rs
+// -----------------------------------------------------------------------------
+// Canonical pretty-printer for QQL
+// -----------------------------------------------------------------------------
+//
+// The layout is rebuilt from the parsed `Query`: upper-case keywords, four-space
+// indentation inside sections, one blank line between sections and sections in
+// source order. `--` comments are carried over by source line: a comment on its own
+// line stays in front of the item that followed it (indented with it when that item is
+// inside a section) unless it trails the end of a section, and a comment after code
+// stays at the end of that item's line.
+
+use crate::lexer::{Keyword, Lexer, Token, TokenKind};
+use crate::parser::{
+    parse_recovering, DataKind, DrawCommand, FillPolicy, Frame, GraphSection, Kernel,
+    ProviderInstance, Query, Span, TimeSpec, TradeSection, TradeType,
+};
+
+const INDENT: &str = "    ";
+
+/// Format QQL source. Fails with the parse errors if the source does not parse cleanly,
+/// since a broken query cannot be printed back without losing text.
+pub fn format_source(src: &str) -> Result<String, String> {
+    let outcome = parse_recovering(src);
+    if !outcome.is_ok() {
+        return Err(outcome
+            .errors
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("; "));
+    }
+
+    let tokens: Vec<Token> = Lexer::new(src)
+        .take_while(|t| {
+            !matches!(
+                t,
+                Ok(Token {
+                    kind: TokenKind::EOF,
+                    ..
+                })
+            )
+        })
+        .filter_map(Result::ok)
+        .filter(|t| t.kind != TokenKind::Newline)
+        .collect();
+
+    let printer = Printer::new(&tokens);
+    Ok(render(printer.lines(&outcome.query), comments(&tokens)))
+}
+
+/// Print a query without comments.
+pub fn print_query(query: &Query) -> String {
+    render(Printer::new(&[]).lines(query), Vec::new())
+}
+
+/* ------------------------------- layout ---------------------------------- */
+
+struct Line {
+    /// Source line the printed line came from, used to place comments.
+    src: Option<usize>,
+    indented: bool,
+    text: String,
+    section_start: bool,
+}
+
+struct Comment {
+    line: usize,
+    indented: bool,
+    trailing: bool,
+    text: String,
+}
+
+fn comments(tokens: &[Token]) -> Vec<Comment> {
+    tokens
+        .iter()
+        .enumerate()
+        .filter_map(|(i, t)| match &t.kind {
+            TokenKind::Comment(text) => Some(Comment {
+                line: t.line,
+                indented: t.column > 1,
+                trailing: i > 0 && tokens[i - 1].line == t.line,
+                text: text.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn render(lines: Vec<Line>, comments: Vec<Comment>) -> String {
+    let mut out = String::new();
+    let mut next = 0;
+
+    let push_comment = |out: &mut String, c: &Comment, inside_section: bool| {
+        if c.indented || inside_section {
+            out.push_str(INDENT);
+        }
+        out.push_str("--");
+        out.push_str(&c.text);
+        out.push('\n');
+    };
+
+    for (i, line) in lines.iter().enumerate() {
+        if line.section_start && i > 0 {
+            // comments still belonging to the previous section go above the blank line;
+            // only the block directly on top of the header moves with it
+            if let Some(src) = line.src {
+                let mut first = src;
+                while comments.iter().any(|c| c.line + 1 == first && !c.trailing) {
+                    first -= 1;
+                }
+                while next < comments.len() && comments[next].line < first {
+                    push_comment(&mut out, &comments[next], true);
+                    next += 1;
+                }
+            }
+            out.push('\n');
+        }
+        if let Some(src) = line.src {
+            while next < comments.len() && comments[next].line < src {
+                push_comment(&mut out, &comments[next], line.indented);
+                next += 1;
+            }
+        }
+        if line.indented {
+            out.push_str(INDENT);
+        }
+        out.push_str(&line.text);
+        while next < comments.len()
+            && comments[next].trailing
+            && Some(comments[next].line) == line.src
+        {
+            out.push_str(" --");
+            out.push_str(&comments[next].text);
+            next += 1;
+        }
+        out.push('\n');
+    }
+
+    if next < comments.len() && !lines.is_empty() {
+        out.push('\n');
+    }
+    for c in &comments[next..] {
+        push_comment(&mut out, c, false);
+    }
+    out
+}
+
+/* ------------------------------- printer --------------------------------- */
+
+struct Printer {
+    /// (line, first keyword on that line) for every line starting with a keyword
+    line_starts: Vec<(usize, Keyword)>,
+}
+
+impl Printer {
+    fn new(tokens: &[Token]) -> Self {
+        let mut line_starts: Vec<(usize, Keyword)> = Vec::new();
+        let mut last_line = 0;
+        for t in tokens {
+            if matches!(t.kind, TokenKind::Comment(_)) || t.line == last_line {
+                continue;
+            }
+            last_line = t.line;
+            if let TokenKind::Keyword(kw) = &t.kind {
+                line_starts.push((t.line, kw.clone()));
+            }
+        }
+        Self { line_starts }
+    }
+
+    /// Source line of the `nth` line inside `span` (header excluded) starting with `kw`.
+    fn keyword_line(&self, span: Span, kw: Keyword, nth: usize) -> Option<usize> {
+        self.line_starts
+            .iter()
+            .filter(|(line, k)| *line > span.line && *line <= span.end_line && *k == kw)
+            .nth(nth)
+            .map(|(line, _)| *line)
+    }
+
+    fn lines(&self, query: &Query) -> Vec<Line> {
+        enum Section<'q> {
+            Provider(&'q ProviderInstance),
+            Kernel(&'q Kernel),
+            Frame(&'q String, &'q Frame),
+            Graph(&'q GraphSection),
+            Trade(&'q TradeSection),
+        }
+
+        let mut sections: Vec<(Span, Section)> = Vec::new();
+        for p in query.providers.values() {
+            sections.push((p.span, Section::Provider(p)));
+        }
+        for k in query.kernels.values() {
+            sections.push((k.span, Section::Kernel(k)));
+        }
+        for (name, f) in &query.frame {
+            sections.push((f.span, Section::Frame(name, f)));
+        }
+        if let Some(g) = &query.graph {
+            sections.push((g.span, Section::Graph(g)));
+        }
+        if let Some(t) = &query.trade {
+            sections.push((t.span, Section::Trade(t)));
+        }
+        // spans are all zero for hand-built queries; fall back to a stable order
+        sections.sort_by(|(a, sa), (b, sb)| {
+            let rank = |s: &Section| match s {
+                Section::Provider(p) => (0, p.name.clone()),
+                Section::Kernel(k) => (1, k.name.clone()),
+                Section::Frame(name, _) => (2, name.to_string()),
+                Section::Graph(_) => (3, String::new()),
+                Section::Trade(_) => (4, String::new()),
+            };
+            (a.line, a.column, rank(sa)).cmp(&(b.line, b.column, rank(sb)))
+        });
+
+        let mut out = Vec::new();
+        // the file's own PARAM lines come first, before any section's
+        if let Some(calcs) = query.calcs {
+            let src = self
+                .line_starts
+                .iter()
+                .find(|(_, k)| *k == Keyword::Param)
+                .map(|(line, _)| *line);
+            out.push(Line {
+                src,
+                indented: false,
+                text: format!("PARAM calcs = {}", calcs.as_str()),
+                section_start: true,
+            });
+        }
+        for (_, section) in sections {
+            match section {
+                Section::Provider(p) => self.provider(p, &mut out),
+                Section::Kernel(k) => self.kernel(k, &mut out),
+                Section::Frame(name, f) => self.frame(name, f, &mut out),
+                Section::Graph(g) => self.graph(g, &mut out),
+                Section::Trade(t) => self.trade(t, &mut out),
+            }
+        }
+        out
+    }
+
+    fn provider(&self, p: &ProviderInstance, out: &mut Vec<Line>) {
+        let span = p.span;
+        out.push(header(span, format!("PROVIDER {}", p.name)));
+        if let Some(backend) = &p.backend {
+            let src = self
+                .keyword_line(span, Keyword::Provider, 0)
+                .or_else(|| self.keyword_line(span, Keyword::Using, 0));
+            out.push(item(src, format!("PROVIDER {}", backend)));
+        }
+        if let Some(ticker) = &p.ticker {
+            let src = self.keyword_line(span, Keyword::Ticker, 0);
+            out.push(item(src, format!("TICKER {}", ticker)));
+        }
+        match &p.time_spec {
+            Some(TimeSpec::DateRange { from, to }) => {
+                let src = self.keyword_line(span, Keyword::From, 0);
+                out.push(item(src, format!("FROM {} TO {}", from, to)));
+            }
+            Some(TimeSpec::LiveSpec { interval, duration }) => {
+                let src = self.keyword_line(span, Keyword::Live, 0);
+                out.push(item(
+                    src,
+                    format!("LIVE TICK {} FOR {}", interval, duration),
+                ));
+            }
+            None => {}
+        }
+        if p.kind == DataKind::Fundamental {
+            let src = self.keyword_line(span, Keyword::Fundamental, 0);
+            out.push(item(src, "FUNDAMENTAL".into()));
+        }
+        for (i, (key, value)) in p.params.iter().enumerate() {
+            let src = self.keyword_line(span, Keyword::Param, i);
+            out.push(item(src, format!("PARAM {} = {}", key, param_value(value))));
+        }
+    }
+
+    fn kernel(&self, k: &Kernel, out: &mut Vec<Line>) {
+        let span = k.span;
+        out.push(header(
+            span,
+            format!("KERNEL {} FROM {}", k.name, quote(&k.path)),
+        ));
+        if !k.inputs.is_empty() {
+            out.push(item(
+                self.keyword_line(span, Keyword::Input, 0),
+                format!("INPUT {}", k.inputs.join(", ")),
+            ));
+        }
+        out.push(item(
+            self.keyword_line(span, Keyword::Output, 0),
+            format!("OUTPUT {}", k.outputs.join(", ")),
+        ));
+        if !k.uniforms.is_empty() {
+            let uniforms: Vec<String> = k
+                .uniforms
+                .iter()
+                .map(|u| format!("{} = {}", u.name, u.value))
+                .collect();
+            out.push(item(
+                self.keyword_line(span, Keyword::Uniform, 0),
+                format!("UNIFORM {}", uniforms.join(", ")),
+            ));
+        }
+    }
+
+    fn frame(&self, name: &str, f: &Frame, out: &mut Vec<Line>) {
+        let span = f.span;
+        out.push(header(span, format!("FRAME {}", name)));
+        out.push(item(
+            self.keyword_line(span, Keyword::Provider, 0),
+            format!("PROVIDER {}", f.providers().collect::<Vec<_>>().join(", ")),
+        ));
+        if f.adjust.any() {
+            let kinds = [
+                (f.adjust.splits, "splits"),
+                (f.adjust.dividends, "dividends"),
+            ];
+            let kinds: Vec<_> = kinds
+                .iter()
+                .filter(|(on, _)| *on)
+                .map(|(_, k)| *k)
+                .collect();
+            out.push(item(
+                self.keyword_line(span, Keyword::Adjust, 0),
+                format!("ADJUST {}", kinds.join(", ")),
+            ));
+        }
+        if f.fill != FillPolicy::default() {
+            out.push(item(
+                self.keyword_line(span, Keyword::Fill, 0),
+                format!("FILL {}", f.fill.as_str()),
+            ));
+        }
+        out.push(item(
+            self.keyword_line(span, Keyword::Pull, 0),
+            format!("PULL {}", f.actions.fields.join(", ")),
+        ));
+
+        // the parser stores CALCs dependency-ordered; print them as written
+        let mut calcs: Vec<_> = f.actions.calc.iter().flatten().collect();
+        calcs.sort_by_key(|c| (c.span.line, c.span.column));
+        for c in calcs {
+            let mut operation = match &c.kernel {
+                Some(kernel) => format!("KERNEL {}", kernel.name),
+                None => c.operation.as_str().to_string(),
+            };
+            if c.centered {
+                operation += " CENTERED";
+            }
+            out.push(item(
+                Some(c.span.line),
+                format!(
+                    "CALC {} {} CALLED {}",
+                    c.inputs.join(", "),
+                    operation,
+                    c.alias
+                ),
+            ));
+        }
+    }
+
+    fn graph(&self, g: &GraphSection, out: &mut Vec<Line>) {
+        out.push(header(g.span, "GRAPH".into()));
+        out.push(item(
+            self.keyword_line(g.span, Keyword::Xaxis, 0),
+            format!("XAXIS {}", g.xaxis),
+        ));
+
+        let mut last_span: Option<Span> = None;
+        for command in &g.commands {
+            // one `LINE a, b FOR f` becomes a DrawCommand per series, all sharing a span
+            if last_span == Some(command.span()) && matches!(command, DrawCommand::Line { .. }) {
+                continue;
+            }
+            last_span = Some(command.span());
+            let text = match command {
+                DrawCommand::Line { series, frame, .. } => {
+                    format!("LINE {} FOR {}", series.join(", "), frame)
+                }
+                DrawCommand::Bar { y, frame, .. } => format!("BAR {} FOR {}", y, frame),
+                DrawCommand::Candle {
+                    open,
+                    high,
+                    low,
+                    close,
+                    frame,
+                    ..
+                } => format!(
+                    "CANDLE {}, {}, {}, {} FOR {}",
+                    open, high, low, close, frame
+                ),
+            };
+            out.push(item(Some(command.span().line), text));
+        }
+    }
+
+    fn trade(&self, t: &TradeSection, out: &mut Vec<Line>) {
+        let span = t.span;
+        out.push(header(span, "TRADE".into()));
+
+        let kind = match t.trade_type {
+            TradeType::Stock => Keyword::Stock,
+            TradeType::OptionCall => Keyword::OptionCall,
+            TradeType::OptionPut => Keyword::OptionPut,
+        };
+        out.push(item(
+            self.keyword_line(span, kind.clone(), 0),
+            kind.as_str().to_string(),
+        ));
+        out.push(item(
+            self.keyword_line(span, Keyword::OverFrame, 0),
+            format!("OVERFRAME {}", t.over_frame),
+        ));
+        out.push(item(
+            self.keyword_line(span, Keyword::Entry, 0),
+            format!("ENTRY {}, {}", t.entry.join(", "), t.within_entry),
+        ));
+        out.push(item(
+            self.keyword_line(span, Keyword::Exit, 0),
+            format!("EXIT {}, {}", t.exit.join(", "), t.within_exit),
+        ));
+        out.push(item(
+            self.keyword_line(span, Keyword::Limit, 0),
+            format!("LIMIT {}", t.stop_loss),
+        ));
+        out.push(item(
+            self.keyword_line(span, Keyword::Hold, 0),
+            format!("HOLD {}", t.hold),
+        ));
+    }
+}
+
+/// Bare word if it lexes back as the same identifier, otherwise a quoted string.
+fn param_value(value: &str) -> String {
+    let bare = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '^' | '/'))
+        && matches!(
+            Lexer::new(value).next(),
+            Some(Ok(Token {
+                kind: TokenKind::Identifier(_),
+                ..
+            }))
+        );
+    if bare {
+        value.to_string()
+    } else {
+        quote(value)
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn header(span: Span, text: String) -> Line {
+    Line {
+        src: Some(span.line),
+        indented: false,
+        text,
+        section_start: true,
+    }
+}
+
+fn item(src: Option<usize>, text: String) -> Line {
+    Line {
+        src,
+        indented: true,
+        text,
+        section_start: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use indoc::indoc;
+
+    const MESSY: &str = "-- header comment\nparam CALCS = Lazy\nprovider appl_data\n\tPROVIDER yahoo_finance\n  TICKER aapl\n\n\n\tFROM 20200101 TO 20250901\n\tparam path = \"data/aapl prices.csv\"\n\nFRAME aapl\n\tProvider appl_data\n\tfill Drop\n\tPULL open, close,low,   high\n\tCALC h_sma, l_sma DIFFERENCE CALLED band -- uses the two below\n\tcalc high SMA CALLED h_sma\n\tCALC low SMA CALLED l_sma\n\n\nGRAPH\n\tXAXIS aapl\n\n\tCANDLE open, high, low, close FOR aapl\n\t\n\t-- LINE band FOR aapl\n\tLINE h_sma, l_sma FOR aapl\nTRADE\n\tSTOCK\n\tOVERFRAME aapl\n\tENTRY aapl.low, aapl.l_sma, 0.05 \n\tEXIT aapl.high, aapl.h_sma, 0.05\n\tLIMIT 0.1\n\tHOLD 40\n";
+
+    #[test]
+    fn test_format_canonical_layout() {
+        let expected = indoc! {r#"
+            -- header comment
+            PARAM calcs = lazy
+
+            PROVIDER appl_data
+                PROVIDER yahoo_finance
+                TICKER aapl
+                FROM 2020-01-01 TO 2025-09-01
+                PARAM path = "data/aapl prices.csv"
+
+            FRAME aapl
+                PROVIDER appl_data
+                FILL drop
+                PULL open, close, low, high
+                CALC h_sma, l_sma DIFFERENCE CALLED band -- uses the two below
+                CALC high SMA CALLED h_sma
+                CALC low SMA CALLED l_sma
+
+            GRAPH
+                XAXIS aapl
+                CANDLE open, high, low, close FOR aapl
+                -- LINE band FOR aapl
+                LINE h_sma, l_sma FOR aapl
+
+            TRADE
+                STOCK
+                OVERFRAME aapl
+                ENTRY aapl.low, aapl.l_sma, 0.05
+                EXIT aapl.high, aapl.h_sma, 0.05
+                LIMIT 0.1
+                HOLD 40
+        "#};
+        assert_eq!(format_source(MESSY).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_format_round_trips_and_is_idempotent() {
+        let formatted = format_source(MESSY).unwrap();
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+        assert_eq!(
+            print_query(&parse(&formatted).unwrap()),
+            print_query(&parse(MESSY).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_format_rejects_broken_source() {
+        let err = format_source("FRAME aapl\n    PULL open,, close\n").unwrap_err();
+        assert!(err.contains("line 2"), "{}", err);
+    }
+}
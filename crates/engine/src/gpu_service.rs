@@ -0,0 +1,255 @@
+// gpu_service.rs
+// -----------------------------------------------------------------------------
+// One GPU device for every Engine in the process: a worker thread owns the
+// GpuRuntime (and so its shader cache) and runs jobs from a queue, within a memory
+// budget. Without a device, jobs fail and their CALCs run on the CPU instead; a
+// job that panics leaves the device in doubt, so the worker makes a new one.
+// -----------------------------------------------------------------------------
+
+use crate::runtime::GpuRuntime;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+/// Memory budget of the shared service: how much a job may put on the device, 1 GiB.
+pub const DEFAULT_BUDGET: u64 = 1 << 30;
+
+/// A queued job; false when it panicked, and the device must be made again.
+type Job = Box<dyn FnOnce(Option<&mut GpuRuntime>) -> bool + Send>;
+
+/// Where the device is at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GpuStatus {
+    /// The worker is still creating the device.
+    Starting,
+    /// Running on the named adapter.
+    Ready(String),
+    /// No device could be created, for the reason given.
+    Unavailable(String),
+}
+
+/// Jobs run so far, the shaders compiled for them, and the devices made again.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GpuStats {
+    pub jobs: usize,
+    /// Jobs refused or failed: over the budget, no device, or a panic in the job.
+    pub refused: usize,
+    pub shaders: usize,
+    /// Times the device was dropped and made again after a job panicked.
+    pub restarts: usize,
+}
+
+#[derive(Debug)]
+struct State {
+    status: GpuStatus,
+    stats: GpuStats,
+}
+
+/// Handle on a GPU worker; clones share the worker, its device and its shader cache.
+#[derive(Clone)]
+pub struct GpuService {
+    jobs: Sender<Job>,
+    state: Arc<Mutex<State>>,
+    budget: u64,
+}
+
+impl std::fmt::Debug for GpuService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GpuService")
+            .field("status", &self.status())
+            .field("budget", &self.budget)
+            .finish()
+    }
+}
+
+impl GpuService {
+    /// Start a worker with a device of its own, taking jobs of up to `budget` bytes.
+    pub fn start(budget: u64) -> Self {
+        let (jobs, queue) = channel::<Job>();
+        let state = Arc::new(Mutex::new(State {
+            status: GpuStatus::Starting,
+            stats: GpuStats::default(),
+        }));
+        let worker = Arc::clone(&state);
+        thread::Builder::new()
+            .name("gpu".into())
+            .spawn(move || {
+                let mut rt = open_device(&worker);
+                for job in queue {
+                    if !job(rt.as_mut()) {
+                        log::warn!("A GPU job panicked, making the device again");
+                        // the old device goes first, with whatever the job left on it
+                        drop(rt.take());
+                        worker.lock().unwrap().status = GpuStatus::Starting;
+                        rt = open_device(&worker);
+                        worker.lock().unwrap().stats.restarts += 1;
+                    }
+                    if let Some(rt) = &rt {
+                        worker.lock().unwrap().stats.shaders = rt.cached_shaders();
+                    }
+                }
+            })
+            .expect("spawn the GPU worker");
+        Self {
+            jobs,
+            state,
+            budget,
+        }
+    }
+
+    /// The service every Engine shares unless given another, started on first use.
+    pub fn shared() -> Self {
+        Self::init_shared(DEFAULT_BUDGET)
+    }
+
+    /// [`shared`](GpuService::shared), with `budget` if this starts it.
+    pub fn init_shared(budget: u64) -> Self {
+        static SHARED: OnceLock<GpuService> = OnceLock::new();
+        SHARED.get_or_init(|| Self::start(budget)).clone()
+    }
+
+    /// Run `job` on the device once the jobs queued before it are done, and wait for
+    /// what it returns. `bytes` is what it puts on the device, at most the budget.
+    ///
+    /// Errors mean the job didn't run on the GPU (over budget, no device) or didn't
+    /// finish (it panicked), so the caller can do the work on the CPU.
+    pub fn run<T, F>(&self, bytes: u64, job: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut GpuRuntime) -> T + Send + 'static,
+    {
+        if !self.fits(bytes) {
+            self.state.lock().unwrap().stats.refused += 1;
+            return Err(format!(
+                "needs {} MiB on the GPU, over the {} MiB budget",
+                bytes.div_ceil(1 << 20),
+                self.budget >> 20
+            ));
+        }
+        let (done, result) = channel();
+        let state = Arc::clone(&self.state);
+        let job: Job = Box::new(move |rt| {
+            let on_device = rt.is_some();
+            let out = match rt {
+                Some(rt) => catch_unwind(AssertUnwindSafe(|| job(rt)))
+                    .map_err(|_| "the GPU job failed".to_string()),
+                None => Err(match &state.lock().unwrap().status {
+                    GpuStatus::Unavailable(why) => format!("no GPU: {}", why),
+                    _ => "no GPU".to_string(),
+                }),
+            };
+            let panicked = out.is_err() && on_device;
+            let mut state = state.lock().unwrap();
+            match out {
+                Ok(_) => state.stats.jobs += 1,
+                Err(_) => state.stats.refused += 1,
+            }
+            let _ = done.send(out);
+            !panicked
+        });
+        let stopped = "the GPU worker has stopped";
+        self.jobs.send(job).map_err(|_| stopped.to_string())?;
+        result.recv().map_err(|_| stopped.to_string())?
+    }
+
+    /// Whether a job putting `bytes` on the device is within the budget.
+    pub fn fits(&self, bytes: u64) -> bool {
+        bytes <= self.budget
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    pub fn status(&self) -> GpuStatus {
+        self.state.lock().unwrap().status.clone()
+    }
+
+    pub fn stats(&self) -> GpuStats {
+        self.state.lock().unwrap().stats
+    }
+}
+
+/// A new device for the worker, with `state`'s status set to how that went.
+fn open_device(state: &Mutex<State>) -> Option<GpuRuntime> {
+    match pollster::block_on(GpuRuntime::new()) {
+        Ok(rt) => {
+            log::info!("GPU ready on {}", rt.adapter_name());
+            state.lock().unwrap().status = GpuStatus::Ready(rt.adapter_name());
+            Some(rt)
+        }
+        Err(e) => {
+            log::warn!("No GPU, CALCs will run on the CPU: {}", e);
+            state.lock().unwrap().status = GpuStatus::Unavailable(e.to_string());
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::Calendar;
+    use crate::testing::{daily_bars, frame_actions};
+    use crate::utils::action::{action_over_frames, gpu_bytes};
+    use crate::utils::incremental::CalcOutputs;
+    use polars::prelude::*;
+
+    #[test]
+    fn test_budget_falls_back_to_cpu() {
+        let action = &frame_actions(
+            "open, close",
+            "    CALC 2 CONSTANT CALLED two\n    CALC close LINEAR_REGRESSION CALLED fit\n",
+        );
+        let frames: Vec<(DataFrame, CalcOutputs)> = [40, 60]
+            .iter()
+            .map(|&n| {
+                let close = (0..n).map(|i| 100.0 + (i * 7 % 5) as f64).collect();
+                (daily_bars(close, 0.5), CalcOutputs::new())
+            })
+            .collect();
+        let calendar = Calendar::default();
+        let bytes = gpu_bytes(action, 100);
+        assert!(bytes > 0);
+
+        let gpu = GpuService::start(bytes);
+        let twice = gpu.clone();
+        let ran = twice.run(0, |_| 2 + 2);
+        let on_gpu = action_over_frames(action, frames.clone(), &calendar, &gpu).unwrap();
+        match gpu.status() {
+            GpuStatus::Ready(_) => {
+                // clones share the worker; jobs run in turn on its device
+                assert_eq!(ran, Ok(4));
+                assert_eq!(gpu.stats().jobs, 2);
+
+                // a job that panics fails, and the next one gets a new device
+                assert!(gpu.run(0, |_| -> () { panic!("lost the device") }).is_err());
+                assert_eq!(gpu.run(0, |rt| rt.cached_shaders()), Ok(0));
+                assert_eq!(gpu.stats().restarts, 1);
+                let again = action_over_frames(action, frames.clone(), &calendar, &gpu).unwrap();
+                assert_eq!(again.len(), on_gpu.len());
+                assert_eq!(gpu.stats().jobs, 4);
+            }
+            status => {
+                // no device: jobs are refused and the frames come out of the CPU
+                assert!(matches!(status, GpuStatus::Unavailable(_)), "{status:?}");
+                assert!(ran.unwrap_err().starts_with("no GPU"));
+                assert_eq!(gpu.stats().jobs, 0);
+                assert_eq!(on_gpu.len(), frames.len());
+            }
+        }
+
+        // over the budget: refused, and the same frames come out of the CPU
+        let small = GpuService::start(bytes - 1);
+        assert!(small.run(bytes, |_| ()).unwrap_err().contains("budget"));
+        let on_cpu = action_over_frames(action, frames, &calendar, &small).unwrap();
+        assert_eq!(small.stats().refused, 2);
+        assert_eq!(small.stats().jobs, 0);
+        for ((gpu_df, gpu_out), (cpu_df, cpu_out)) in on_gpu.iter().zip(&on_cpu) {
+            assert_eq!(gpu_df.get_column_names(), cpu_df.get_column_names());
+            assert_eq!(gpu_df.height(), cpu_df.height());
+            assert_eq!(gpu_out["two"][0], cpu_out["two"][0]);
+        }
+    }
+}
@@ -0,0 +1,442 @@
+// kernel.rs
+// -----------------------------------------------------------------------------
+// User WGSL kernels: `KERNEL name FROM "file.wgsl"` declarations, checked against
+// their signature with naga when the query is parsed and run by `CALC ... KERNEL name`
+// -----------------------------------------------------------------------------
+
+use crate::lexer::Span;
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::{AddressSpace, ArraySize, Module, Scalar, ScalarKind, ShaderStage, TypeInner};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+/// Scalar type of a uniform struct member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UniformType {
+    F32,
+    U32,
+    I32,
+}
+
+/// A `UNIFORM name = value` of a kernel, with where it goes in the kernel's uniform struct.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uniform {
+    pub name: String,
+    pub value: f64,
+    pub ty: UniformType,
+    /// Byte offset of the member in the struct.
+    pub offset: u32,
+}
+
+/// A kernel declaration:
+///
+/// ```qql
+/// KERNEL spread FROM "kernels/spread.wgsl"
+///     INPUT a, b
+///     OUTPUT out
+///     UNIFORM scale = 0.5
+/// ```
+///
+/// The WGSL has a `@compute fn main` with, in `@group(0)`, one `var<storage, read>
+/// array<f32>` per INPUT and then one `var<storage, read_write> array<f32>` per OUTPUT,
+/// each a column of the frame, and with UNIFORMs a `var<uniform>` struct at
+/// `@group(1) @binding(0)` holding them by name (f32, u32 or i32).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    pub name: String,
+    pub path: String,
+    /// The WGSL, as read when the query was parsed.
+    pub source: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub uniforms: Vec<Uniform>,
+    /// `@workgroup_size` of `main`.
+    pub workgroup_size: u32,
+    /// Byte size of the uniform struct, 0 without UNIFORMs.
+    pub uniform_size: u32,
+    pub span: Span,
+}
+
+impl Kernel {
+    /// Read the WGSL from `path`, relative to `base_dir`, and [`check`](Kernel::check) it.
+    pub fn load(&mut self, base_dir: &Path) -> Result<(), String> {
+        self.source = std::fs::read_to_string(base_dir.join(&self.path))
+            .map_err(|e| format!("can't read \"{}\": {}", self.path, e))?;
+        self.check()
+    }
+
+    /// Validate the WGSL with naga and match its bindings against the declared signature,
+    /// filling in the workgroup size and the uniforms' layout.
+    pub fn check(&mut self) -> Result<(), String> {
+        let at = |location: Option<naga::SourceLocation>| match location {
+            Some(l) => format!("{}:{}:{}", self.path, l.line_number, l.line_position),
+            None => self.path.clone(),
+        };
+        let module = naga::front::wgsl::parse_str(&self.source)
+            .map_err(|e| format!("{}: {}", at(e.location(&self.source)), e.message()))?;
+        Validator::new(ValidationFlags::all(), Capabilities::default())
+            .validate(&module)
+            .map_err(|e| format!("{}: {}", at(e.location(&self.source)), e.as_inner()))?;
+
+        let main = module
+            .entry_points
+            .iter()
+            .find(|e| e.name == "main" && e.stage == ShaderStage::Compute)
+            .ok_or_else(|| format!("{} has no `@compute fn main`", self.path))?;
+        let [x, 1, 1] = main.workgroup_size else {
+            return Err(format!(
+                "{}: `main` must have a 1D @workgroup_size",
+                self.path
+            ));
+        };
+        self.workgroup_size = x;
+
+        let mut columns = 0;
+        let mut uniform = None;
+        for (_, var) in module.global_variables.iter() {
+            let Some(binding) = &var.binding else {
+                continue;
+            };
+            let name = var.name.as_deref().unwrap_or("_");
+            let (group, index) = (binding.group, binding.binding as usize);
+            let inputs = self.inputs.len();
+            match (group, &var.space) {
+                (0, AddressSpace::Storage { access }) if index < inputs + self.outputs.len() => {
+                    let (kind, decl, column) = match self.inputs.get(index) {
+                        Some(input) => ("read", "INPUT", input),
+                        None => ("read_write", "OUTPUT", &self.outputs[index - inputs]),
+                    };
+                    let writable = access.contains(naga::StorageAccess::STORE);
+                    if writable != (kind == "read_write") || !is_f32_array(&module, var.ty) {
+                        return Err(format!(
+                            "{}: @group(0) @binding({index}) `{name}` should be \
+                             `var<storage, {kind}> {name}: array<f32>`, for {decl} {column}",
+                            self.path
+                        ));
+                    }
+                    columns += 1;
+                }
+                (1, AddressSpace::Uniform) if index == 0 && !self.uniforms.is_empty() => {
+                    uniform = Some(var.ty);
+                }
+                _ => {
+                    return Err(format!(
+                        "{}: unexpected @group({group}) @binding({index}) `{name}` for {} \
+                         INPUT, {} OUTPUT and {} UNIFORM",
+                        self.path,
+                        self.inputs.len(),
+                        self.outputs.len(),
+                        self.uniforms.len(),
+                    ))
+                }
+            }
+        }
+        if columns != self.inputs.len() + self.outputs.len() {
+            return Err(format!(
+                "{}: expected {} INPUT and {} OUTPUT storage bindings in @group(0), found {}",
+                self.path,
+                self.inputs.len(),
+                self.outputs.len(),
+                columns
+            ));
+        }
+
+        self.uniform_size = 0;
+        let Some(ty) = uniform else {
+            if self.uniforms.is_empty() {
+                return Ok(());
+            }
+            return Err(format!(
+                "{}: UNIFORMs need a `var<uniform>` struct at @group(1) @binding(0)",
+                self.path
+            ));
+        };
+        let TypeInner::Struct { members, span } = &module.types[ty].inner else {
+            return Err(format!("{}: the uniform should be a struct", self.path));
+        };
+        for member in members {
+            let name = member.name.as_deref().unwrap_or("_");
+            let declared = self.uniforms.iter_mut().find(|u| u.name == name);
+            let ty = match module.types[member.ty].inner {
+                TypeInner::Scalar(Scalar { kind, width: 4 }) => match kind {
+                    ScalarKind::Float => Some(UniformType::F32),
+                    ScalarKind::Uint => Some(UniformType::U32),
+                    ScalarKind::Sint => Some(UniformType::I32),
+                    _ => None,
+                },
+                _ => None,
+            };
+            match (declared, ty) {
+                (Some(u), Some(ty)) => {
+                    u.ty = ty;
+                    u.offset = member.offset;
+                }
+                (Some(_), None) => {
+                    return Err(format!(
+                        "{}: uniform `{name}` should be f32, u32 or i32",
+                        self.path
+                    ))
+                }
+                // padding
+                (None, _) if name.starts_with('_') => {}
+                (None, _) => {
+                    return Err(format!(
+                        "{}: uniform `{name}` has no UNIFORM value",
+                        self.path
+                    ))
+                }
+            }
+        }
+        if let Some(u) = self
+            .uniforms
+            .iter()
+            .find(|u| !members.iter().any(|m| m.name.as_deref() == Some(&u.name)))
+        {
+            return Err(format!(
+                "{}: the uniform struct has no member `{}`",
+                self.path, u.name
+            ));
+        }
+        self.uniform_size = span.next_multiple_of(16);
+        Ok(())
+    }
+
+    /// Shader cache key: changes with the source, so an edited file compiles afresh.
+    pub fn shader_key(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.source.hash(&mut hasher);
+        format!("kernel:{}:{:016x}", self.name, hasher.finish())
+    }
+
+    /// Columns a `CALC ... KERNEL name CALLED alias` adds: `alias`, or `alias_<output>` for
+    /// each of several OUTPUTs.
+    pub fn output_columns(&self, alias: &str) -> Vec<String> {
+        match self.outputs.as_slice() {
+            [_] => vec![alias.to_string()],
+            outputs => outputs.iter().map(|o| format!("{alias}_{o}")).collect(),
+        }
+    }
+
+    /// The uniform struct's bytes: the UNIFORM values, the first `values.len()` of them
+    /// replaced by `values`. `None` without UNIFORMs.
+    pub fn uniform_bytes(&self, values: &[f64]) -> Option<Vec<u8>> {
+        if self.uniforms.is_empty() {
+            return None;
+        }
+        let mut bytes = vec![0u8; self.uniform_size as usize];
+        for (i, u) in self.uniforms.iter().enumerate() {
+            let value = values.get(i).copied().unwrap_or(u.value);
+            let raw = match u.ty {
+                UniformType::F32 => (value as f32).to_ne_bytes(),
+                UniformType::U32 => (value as u32).to_ne_bytes(),
+                UniformType::I32 => (value as i32).to_ne_bytes(),
+            };
+            let at = u.offset as usize;
+            bytes[at..at + 4].copy_from_slice(&raw);
+        }
+        Some(bytes)
+    }
+}
+
+fn is_f32_array(module: &Module, ty: naga::Handle<naga::Type>) -> bool {
+    match module.types[ty].inner {
+        TypeInner::Array {
+            base,
+            size: ArraySize::Dynamic,
+            ..
+        } => module.types[base].inner == TypeInner::Scalar(Scalar::F32),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse, parse_recovering, Parser};
+    use crate::runtime::GpuRuntime;
+    use crate::testing::{daily_bars, frame_src};
+    use crate::utils::action::action_over_data_gpu;
+    use crate::Engine;
+
+    const SPREAD: &str = r#"
+struct Params { scale: f32, _pad0: f32, _pad1: f32, _pad2: f32 };
+@group(1) @binding(0) var<uniform> P: Params;
+
+@group(0) @binding(0) var<storage, read> a: array<f32>;
+@group(0) @binding(1) var<storage, read> b: array<f32>;
+@group(0) @binding(2) var<storage, read_write> out: array<f32>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+  let i = id.x;
+  if (i < arrayLength(&out)) { out[i] = (b[i] - a[i]) * P.scale; }
+}
+"#;
+
+    const RANGE: &str = r#"
+@group(0) @binding(0) var<storage, read> a: array<f32>;
+@group(0) @binding(1) var<storage, read> b: array<f32>;
+@group(0) @binding(2) var<storage, read_write> lo: array<f32>;
+@group(0) @binding(3) var<storage, read_write> hi: array<f32>;
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+  let i = id.x;
+  if (i < arrayLength(&lo)) { lo[i] = min(a[i], b[i]); hi[i] = max(a[i], b[i]); }
+}
+"#;
+
+    fn write_kernel(name: &str, src: &str) -> String {
+        let path = std::env::temp_dir().join(format!("qql_{}_{}.wgsl", name, std::process::id()));
+        std::fs::write(&path, src).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_kernel_calc() {
+        let spread = write_kernel("spread", SPREAD);
+        let range = write_kernel("range", RANGE);
+        let kernels = format!(
+            r#"
+KERNEL spread FROM "{spread}"
+    INPUT a, b
+    OUTPUT out
+    UNIFORM scale = 0.5
+
+KERNEL range FROM "{range}"
+    INPUT a, b
+    OUTPUT lo, hi
+
+"#
+        );
+        let frame = frame_src(
+            "open, close",
+            "
+    CALC open, close KERNEL spread CALLED half
+    CALC open, close, 2 KERNEL spread CALLED double
+    CALC half, close KERNEL range CALLED r
+    CALC r_hi, half DIFFERENCE CALLED gap
+",
+        );
+        let query = parse(&(kernels + &frame)).unwrap();
+        assert_eq!(query.kernels["spread"].workgroup_size, 64);
+        assert_eq!(query.kernels["spread"].uniform_size, 16);
+        let action = &query.frame["f"].actions;
+
+        let n = 1000;
+        let close: Vec<f64> = (0..n).map(|i| 100.0 + (i % 13) as f64).collect();
+        let df = daily_bars(close.clone(), 4.0);
+
+        let mut rt = pollster::block_on(GpuRuntime::new()).unwrap();
+        let out = action_over_data_gpu(action, df, &mut rt).unwrap();
+        let values = |name: &str| -> Vec<f64> {
+            out.column(name)
+                .unwrap()
+                .f64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect()
+        };
+        let (half, double) = (values("half"), values("double"));
+        let (lo, hi, gap) = (values("r_lo"), values("r_hi"), values("gap"));
+        for i in 0..n {
+            assert_eq!(half[i], 2.0, "half[{i}]");
+            assert_eq!(double[i], 8.0, "double[{i}]");
+            assert_eq!((lo[i], hi[i]), (2.0, close[i]), "r[{i}]");
+            assert_eq!(gap[i].abs(), close[i] - 2.0, "gap[{i}]");
+        }
+    }
+
+    #[test]
+    fn test_kernel_checked_at_parse() {
+        let errors = |src: &str, decl: &str| -> Vec<String> {
+            let path = write_kernel("bad", src);
+            let query = format!("KERNEL k FROM \"{path}\"\n{decl}\n");
+            parse_recovering(&query)
+                .errors
+                .into_iter()
+                .map(|e| e.message)
+                .collect()
+        };
+        let decl = "    INPUT a, b\n    OUTPUT out\n    UNIFORM scale = 0.5";
+        assert!(errors(SPREAD, decl).is_empty());
+
+        // a syntax error, with its place in the file
+        let e = errors(&SPREAD.replace("(b[i] - a[i])", "(b[i] - a[i]"), decl);
+        assert_eq!(e.len(), 1, "{e:?}");
+        assert!(e[0].contains(".wgsl:12:"), "{}", e[0]);
+
+        // an INPUT bound read_write
+        let e = errors(
+            &SPREAD.replace("storage, read> b", "storage, read_write> b"),
+            decl,
+        );
+        assert!(
+            e[0].contains("`var<storage, read> b: array<f32>`, for INPUT b"),
+            "{}",
+            e[0]
+        );
+
+        // UNIFORMs not in the struct, or the struct missing
+        let e = errors(SPREAD, &decl.replace("scale", "factor"));
+        assert!(
+            e[0].contains("uniform `scale` has no UNIFORM value"),
+            "{}",
+            e[0]
+        );
+        let e = errors(SPREAD, "    INPUT a, b\n    OUTPUT out");
+        assert!(
+            e[0].contains("unexpected @group(1) @binding(0)"),
+            "{}",
+            e[0]
+        );
+
+        // a CALC using an undeclared or mismatched kernel
+        let src = format!(
+            "KERNEL k FROM \"{}\"\n{decl}\n{}",
+            write_kernel("ok", SPREAD),
+            frame_src(
+                "open, close",
+                "    CALC open KERNEL k CALLED x\n    CALC open, close KERNEL j CALLED y\n"
+            )
+        );
+        let e: Vec<String> = parse_recovering(&src)
+            .errors
+            .into_iter()
+            .map(|e| e.message)
+            .collect();
+        assert!(
+            e.iter().any(|m| m.contains("KERNEL k takes 2 column(s)")),
+            "{e:?}"
+        );
+        assert!(
+            e.iter().any(|m| m.contains("unknown KERNEL \"j\"")),
+            "{e:?}"
+        );
+    }
+
+    #[test]
+    fn test_relative_kernel_path() {
+        let dir = std::env::temp_dir().join(format!("qql_kernels_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("kernels")).unwrap();
+        std::fs::write(dir.join("kernels").join("spread.wgsl"), SPREAD).unwrap();
+        let src = "KERNEL spread FROM \"kernels/spread.wgsl\"\n    INPUT a, b\n    OUTPUT out\n    UNIFORM scale = 0.5\n";
+        let path = dir.join("test.qql");
+        std::fs::write(&path, src).unwrap();
+
+        // read next to the query file, not from the working directory
+        let engine = Engine::new(path.to_str().unwrap(), "127.0.0.1:7000", None).unwrap();
+        assert_eq!(engine.query().kernels["spread"].workgroup_size, 64);
+        assert!(engine.analyze().is_ok());
+        assert!(Parser::new(src)
+            .with_base_dir(&dir)
+            .parse_recovering()
+            .is_ok());
+        let e = parse_recovering(src).errors;
+        assert!(
+            e[0].message.contains("can't read \"kernels/spread.wgsl\""),
+            "{e:?}"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
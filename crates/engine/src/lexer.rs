@@ -1,4 +1,5 @@
 // lexer.rs
+use serde::{Deserialize, Serialize};
 use std::iter::Peekable;
 use std::str::Chars;
 
@@ -44,12 +45,127 @@ pub enum Keyword {
     Provider,
     Using,
     Param,
+    Adjust,
+    Fill,
+    Kernel,
+    Input,
+    Output,
+    Uniform,
+    Centered,
 }
 
 impl Keyword {
+    /// Every keyword that can be written in QQL source, in lexer table order.
+    pub const ALL: [Keyword; 46] = {
+        use Keyword::*;
+        [
+            Live,
+            Historical,
+            Fundamental,
+            Ticker,
+            From,
+            To,
+            Tick,
+            For,
+            Pull,
+            Calc,
+            Called,
+            ShowTable,
+            Graph,
+            Difference,
+            Sma,
+            Volatility,
+            Sum,
+            Multiply,
+            Divide,
+            Line,
+            Candle,
+            Bar,
+            Trade,
+            OptionCall,
+            OptionPut,
+            Limit,
+            Stock,
+            Entry,
+            Exit,
+            Hold,
+            Frame,
+            Xaxis,
+            LinearRegression,
+            DoubleVolatility,
+            Constant,
+            OverFrame,
+            Provider,
+            Using,
+            Param,
+            Adjust,
+            Fill,
+            Kernel,
+            Input,
+            Output,
+            Uniform,
+            Centered,
+        ]
+    };
+
+    /// Canonical (upper-case) spelling of the keyword.
+    pub fn as_str(&self) -> &'static str {
+        use Keyword::*;
+        match self {
+            Live => "LIVE",
+            Historical => "HISTORICAL",
+            Fundamental => "FUNDAMENTAL",
+            Ticker => "TICKER",
+            From => "FROM",
+            To => "TO",
+            Tick => "TICK",
+            For => "FOR",
+            Pull => "PULL",
+            Calc => "CALC",
+            Called => "CALLED",
+            ShowTable => "SHOWTABLE",
+            Graph => "GRAPH",
+            Difference => "DIFFERENCE",
+            Sma => "SMA",
+            Volatility => "VOLATILITY",
+            Sum => "SUM",
+            Multiply => "MULTIPLY",
+            Divide => "DIVIDE",
+            Line => "LINE",
+            Candle => "CANDLE",
+            Bar => "BAR",
+            Trade => "TRADE",
+            OptionCall => "OPTIONCALL",
+            OptionPut => "OPTIONPUT",
+            Limit => "LIMIT",
+            Stock => "STOCK",
+            Entry => "ENTRY",
+            Exit => "EXIT",
+            Hold => "HOLD",
+            Frame => "FRAME",
+            Xaxis => "XAXIS",
+            LinearRegression => "LINEAR_REGRESSION",
+            DoubleVolatility => "DOUBLE_VOLATILITY",
+            Constant => "CONSTANT",
+            OverFrame => "OVERFRAME",
+            Provider => "PROVIDER",
+            Using => "USING",
+            Param => "PARAM",
+            Adjust => "ADJUST",
+            Fill => "FILL",
+            Kernel => "KERNEL",
+            Input => "INPUT",
+            Output => "OUTPUT",
+            Uniform => "UNIFORM",
+            Centered => "CENTERED",
+            Comma => ",",
+        }
+    }
+
+    /// Look up a keyword, ignoring case (`calc`, `Calc` and `CALC` are the same keyword).
     pub fn from_str(s: &str) -> Option<Self> {
         use Keyword::*;
-        match s {
+        match s.to_ascii_uppercase().as_str() {
             "LIVE" => Some(Live),
             "HISTORICAL" => Some(Historical),
             "FUNDAMENTAL" => Some(Fundamental),
@@ -89,6 +205,13 @@ impl Keyword {
             "PROVIDER" => Some(Provider),
             "USING" => Some(Using),
             "PARAM" => Some(Param),
+            "ADJUST" => Some(Adjust),
+            "FILL" => Some(Fill),
+            "KERNEL" => Some(Kernel),
+            "INPUT" => Some(Input),
+            "OUTPUT" => Some(Output),
+            "UNIFORM" => Some(Uniform),
+            "CENTERED" => Some(Centered),
             _ => None,
         }
     }
@@ -98,8 +221,15 @@ impl Keyword {
 pub enum TokenKind {
     Keyword(Keyword),
     Identifier(String),
-    Interval(String),
-    Literal(String),
+    /// Calendar date, normalised to `YYYY-MM-DD` (written `2020-01-01`, or `20200101`
+    /// right after FROM or TO).
+    Date(String),
+    /// Count plus unit `s`, `m`, `h` or `d`, e.g. `5m`, `2d`.
+    Duration(String),
+    /// Double-quoted string, quotes removed and `\"` / `\\` unescaped.
+    Str(String),
+    Bool(bool),
+    Equals,
     Comma,
     Newline,
     EOF,
@@ -111,6 +241,53 @@ pub struct Token {
     pub kind: TokenKind,
     pub line: usize,
     pub column: usize,
+    /// Column just past the last character of the token (tokens never span lines).
+    pub end_column: usize,
+}
+
+impl Token {
+    pub fn span(&self) -> Span {
+        Span::new(self.line, self.column, self.line, self.end_column)
+    }
+}
+
+/// Source range, 1-based; `end_column` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize, end_line: usize, end_column: usize) -> Self {
+        Self {
+            line,
+            column,
+            end_line,
+            end_column,
+        }
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        let (line, column) = (self.line, self.column).min((other.line, other.column));
+        let (end_line, end_column) =
+            (self.end_line, self.end_column).max((other.end_line, other.end_column));
+        Span::new(line, column, end_line, end_column)
+    }
+
+    pub fn contains(&self, line: usize, column: usize) -> bool {
+        (self.line, self.column) <= (line, column)
+            && (line, column) < (self.end_line, self.end_column)
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -125,6 +302,8 @@ pub struct Lexer<'a> {
     input: Peekable<Chars<'a>>,
     current_line: usize,
     current_col: usize,
+    /// The last token was FROM or TO, so eight digits are a `20200101` date.
+    after_range: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -133,6 +312,7 @@ impl<'a> Lexer<'a> {
             input: source.chars().peekable(),
             current_line: 1,
             current_col: 0,
+            after_range: false,
         }
     }
 
@@ -153,32 +333,72 @@ impl<'a> Lexer<'a> {
         self.input.peek().copied()
     }
 
-    fn lex_word_like(&mut self, first: char) -> TokenKind {
+    fn lex_word_like(&mut self, first: char) -> Result<TokenKind, String> {
         let mut buf = String::new();
         buf.push(first);
         while let Some(&c) = self.input.peek() {
-            if c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '^' {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '^' || c == '/' {
                 buf.push(c);
                 self.advance();
             } else {
                 break;
             }
         }
-        if buf.len() == 8 && buf.chars().all(|c| c.is_ascii_digit()) {
-            TokenKind::Literal(buf)
-        } else if buf
-            .chars()
-            .next_back()
-            .map(|c| c.is_ascii_alphabetic())
-            .unwrap_or(false)
+
+        // `2020-01-01`: the year has been read, pick up `-MM-DD`
+        if buf.len() == 4 && buf.chars().all(|c| c.is_ascii_digit()) && self._peek() == Some('-') {
+            for _ in 0..6 {
+                match self._peek() {
+                    Some(c) if c.is_ascii_digit() || c == '-' => {
+                        buf.push(c);
+                        self.advance();
+                    }
+                    _ => break,
+                }
+            }
+            return parse_date(&buf, "%Y-%m-%d");
+        }
+
+        if self.after_range && buf.len() == 8 && buf.chars().all(|c| c.is_ascii_digit()) {
+            parse_date(&buf, "%Y%m%d")
+        } else if buf.len() > 1
             && buf[..buf.len() - 1].chars().all(|c| c.is_ascii_digit())
             && matches!(buf.chars().last().unwrap(), 's' | 'm' | 'h' | 'd')
         {
-            TokenKind::Interval(buf)
-        } else if let Some(kw) = Keyword::from_str(&buf.to_uppercase()) {
-            TokenKind::Keyword(kw)
+            Ok(TokenKind::Duration(buf))
+        } else if buf.eq_ignore_ascii_case("true") || buf.eq_ignore_ascii_case("false") {
+            Ok(TokenKind::Bool(buf.eq_ignore_ascii_case("true")))
+        } else if let Some(kw) = Keyword::from_str(&buf) {
+            Ok(TokenKind::Keyword(kw))
         } else {
-            TokenKind::Identifier(buf)
+            Ok(TokenKind::Identifier(buf))
+        }
+    }
+
+    /// Read a string after its opening quote, up to the closing quote on the same line.
+    fn lex_string(&mut self) -> Result<TokenKind, String> {
+        let mut buf = String::new();
+        loop {
+            match self._peek() {
+                None | Some('\n') => return Err("unterminated string".to_string()),
+                Some('"') => {
+                    self.advance();
+                    return Ok(TokenKind::Str(buf));
+                }
+                Some('\\') => {
+                    self.advance();
+                    match self.advance() {
+                        Some(c @ ('"' | '\\')) => buf.push(c),
+                        Some('n') => buf.push('\n'),
+                        Some('t') => buf.push('\t'),
+                        _ => return Err("invalid escape in string".to_string()),
+                    }
+                }
+                Some(c) => {
+                    buf.push(c);
+                    self.advance();
+                }
+            }
         }
     }
 
@@ -193,6 +413,18 @@ impl<'a> Lexer<'a> {
     }
 
     pub fn next_token(&mut self) -> Result<Token, LexError> {
+        let token = self.lex_token();
+        self.after_range = matches!(
+            token,
+            Ok(Token {
+                kind: TokenKind::Keyword(Keyword::From | Keyword::To),
+                ..
+            })
+        );
+        token
+    }
+
+    fn lex_token(&mut self) -> Result<Token, LexError> {
         self.skip_whitespace();
 
         let line = self.current_line;
@@ -217,6 +449,7 @@ impl<'a> Lexer<'a> {
                     kind: TokenKind::Comment(comment.trim_end().to_string()),
                     line,
                     column,
+                    end_column: self.current_col + 1,
                 });
             } else {
                 // Not a comment, return error or allow single '-' if needed
@@ -234,15 +467,45 @@ impl<'a> Lexer<'a> {
                     kind: TokenKind::Comma,
                     line,
                     column,
+                    end_column: column + 1,
                 }),
                 '\n' => Ok(Token {
                     kind: TokenKind::Newline,
                     line,
                     column,
+                    end_column: column + 1,
+                }),
+                '=' => Ok(Token {
+                    kind: TokenKind::Equals,
+                    line,
+                    column,
+                    end_column: column + 1,
                 }),
-                c if c.is_ascii_alphanumeric() || c == '^' => {
-                    let kind = self.lex_word_like(c);
-                    Ok(Token { kind, line, column })
+                '"' => {
+                    let kind = self.lex_string().map_err(|message| LexError {
+                        message,
+                        line,
+                        column,
+                    })?;
+                    Ok(Token {
+                        kind,
+                        line,
+                        column,
+                        end_column: self.current_col + 1,
+                    })
+                }
+                c if c.is_ascii_alphanumeric() || c == '^' || c == '/' => {
+                    let kind = self.lex_word_like(c).map_err(|message| LexError {
+                        message,
+                        line,
+                        column,
+                    })?;
+                    Ok(Token {
+                        kind,
+                        line,
+                        column,
+                        end_column: self.current_col + 1,
+                    })
                 }
                 _ => Err(LexError {
                     message: format!("Unexpected character '{}'", c),
@@ -255,11 +518,19 @@ impl<'a> Lexer<'a> {
                 kind: TokenKind::EOF,
                 line,
                 column,
+                end_column: column,
             })
         }
     }
 }
 
+/// Validate a date written in `format` and normalise it to `YYYY-MM-DD`.
+fn parse_date(s: &str, format: &str) -> Result<TokenKind, String> {
+    chrono::NaiveDate::parse_from_str(s, format)
+        .map(|d| TokenKind::Date(d.format("%Y-%m-%d").to_string()))
+        .map_err(|_| format!("invalid date '{}'", s))
+}
+
 impl<'a> Iterator for Lexer<'a> {
     type Item = Result<Token, LexError>;
 
@@ -270,6 +541,7 @@ impl<'a> Iterator for Lexer<'a> {
                 kind: TokenKind::EOF,
                 line: self.current_line,
                 column: self.current_col,
+                end_column: self.current_col,
             }));
         }
 
@@ -321,4 +593,71 @@ mod test {
             TokenKind::Keyword(Keyword::Ticker)
         );
     }
+
+    fn kinds(input: &str) -> Vec<TokenKind> {
+        Lexer::new(input)
+            .map(|t| t.unwrap().kind)
+            .take_while(|k| *k != TokenKind::EOF)
+            .collect()
+    }
+
+    #[test]
+    fn test_keywords_are_case_insensitive() {
+        assert_eq!(
+            kinds("calc Calc CALC linear_regression"),
+            vec![
+                TokenKind::Keyword(Keyword::Calc),
+                TokenKind::Keyword(Keyword::Calc),
+                TokenKind::Keyword(Keyword::Calc),
+                TokenKind::Keyword(Keyword::LinearRegression),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_literal_kinds() {
+        assert_eq!(
+            kinds(
+                r#"2020-01-31 TO 20200131 5m 2d true FALSE = "data/a \"b\".csv" 0.05 data/aapl.csv"#
+            ),
+            vec![
+                TokenKind::Date("2020-01-31".into()),
+                TokenKind::Keyword(Keyword::To),
+                TokenKind::Date("2020-01-31".into()),
+                TokenKind::Duration("5m".into()),
+                TokenKind::Duration("2d".into()),
+                TokenKind::Bool(true),
+                TokenKind::Bool(false),
+                TokenKind::Equals,
+                TokenKind::Str(r#"data/a "b".csv"#.into()),
+                TokenKind::Identifier("0.05".into()),
+                TokenKind::Identifier("data/aapl.csv".into()),
+            ]
+        );
+        // eight digits elsewhere are a number, not a date
+        assert_eq!(
+            kinds("PARAM seed = 12345678 20200101"),
+            vec![
+                TokenKind::Keyword(Keyword::Param),
+                TokenKind::Identifier("seed".into()),
+                TokenKind::Equals,
+                TokenKind::Identifier("12345678".into()),
+                TokenKind::Identifier("20200101".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_literal_errors() {
+        let mut lexer = Lexer::new("2020-13-01");
+        assert!(lexer.next_token().is_err());
+        let mut lexer = Lexer::new("FROM 20201301");
+        lexer.next_token().unwrap();
+        assert!(lexer.next_token().is_err());
+
+        let mut lexer = Lexer::new("\"no end\nTICKER");
+        let err = lexer.next_token().unwrap_err();
+        assert_eq!((err.line, err.column), (1, 1));
+        assert!(err.message.contains("unterminated"));
+    }
 }
@@ -1,18 +1,27 @@
 mod calculation;
-mod lexer;
+pub mod calendar;
+pub mod format;
+pub mod gpu_service;
+pub mod kernel;
+pub mod lexer;
 pub mod parser;
+pub mod profile;
 pub mod runtime;
 pub mod utils;
 
+#[cfg(test)]
+mod testing;
+
 // Import Graph type
 use parser::Graph;
 
 use std::collections::HashMap;
 
-use parser::{parse, Query};
+use parser::{DataKind, LookAhead, ParseError, Parser, Query, TimeSpec};
 use polars::frame::DataFrame;
 use serde_json::Value;
 use std::fs;
+use std::path::Path;
 
 use polars::prelude::*;
 use std::io::Cursor;
@@ -22,10 +31,16 @@ use crate::parser::Trades;
 pub mod output;
 use crate::output::Output;
 
-use provider::{
-    models::Entity,
-    tcp::client::client::{Client, ClientBuilder},
-};
+pub mod providers;
+use crate::calendar::Calendar;
+use crate::gpu_service::GpuService;
+use crate::profile::Profile;
+use crate::providers::{append_bars, CacheStats, DataProvider, ProviderRegistry, ReplayControl};
+use crate::utils::incremental::{same_actions, CalcOutputs, Plan};
+use crate::utils::live::{last_bar, LiveFeed};
+use crate::utils::quality::DataQuality;
+use std::collections::HashSet;
+use std::time::Instant;
 
 #[derive(Debug, Clone)]
 pub enum EngineStatus {
@@ -34,21 +49,70 @@ pub enum EngineStatus {
     Error(String),
 }
 
+/// What runs an engine's CALCs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CalcBackend {
+    /// WGSL on the shared GPU service, on the CPU when it can't take them.
+    #[default]
+    Gpu,
+    /// One Polars lazy plan a frame, optimized and run on Polars' thread pool.
+    Lazy,
+}
+
+impl CalcBackend {
+    /// The backend a file picks with `PARAM calcs = gpu` or `lazy`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "gpu" => Some(CalcBackend::Gpu),
+            "lazy" => Some(CalcBackend::Lazy),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CalcBackend::Gpu => "gpu",
+            CalcBackend::Lazy => "lazy",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Engine {
     file_path: String,
     query: Query,
     status: EngineStatus,
     provider_frames: HashMap<String, DataFrame>,
+    /// exchange calendar of each bars provider, for TRADE's HOLD
+    calendars: HashMap<String, Calendar>,
+    /// splits and dividends of the providers of ADJUST frames, by provider
+    provider_actions: HashMap<String, DataFrame>,
     frames: HashMap<String, DataFrame>,
+    /// each frame's data quality before its FILL, and the issues not yet reported
+    quality: HashMap<String, DataQuality>,
+    /// TRADE rules reading columns that see later bars
+    look_ahead: Vec<LookAhead>,
+    /// data quality issues and look-ahead not yet reported
+    warnings: Vec<String>,
 
-    providers: Client,
+    providers: ProviderRegistry,
+
+    /// query of the last successful run and its CALC outputs, to re-run only what changed
+    last_run: Option<Query>,
+    calc_outputs: HashMap<String, CalcOutputs>,
+    /// LIVE providers still streaming, by name
+    live: HashMap<String, LiveFeed>,
+    /// where the last run spent its time
+    profile: Profile,
 
     // code_diff: Option<CodeDiff>,
     output: Option<Output>,
     new_output: bool,
     _for_test_flag: bool,
-    rt: runtime::GpuRuntime,
+    /// the GPU the CALCs run on, shared with the process's other engines
+    gpu: GpuService,
+    /// for files that don't pick one with `PARAM calcs`
+    backend: CalcBackend,
 }
 
 impl Engine {
@@ -56,6 +120,31 @@ impl Engine {
         file_path: &str,
         provider_addr: &str,
         is_src_input: Option<bool>,
+    ) -> Result<Self, String> {
+        // file PROVIDERs read paths relative to the query file, remote data is cached
+        // next to it; sources given inline get no cache
+        let (base_dir, cache_dir) = match is_src_input {
+            Some(true) => (Path::new("."), None),
+            _ => {
+                let dir = Path::new(file_path)
+                    .parent()
+                    .filter(|p| !p.as_os_str().is_empty())
+                    .unwrap_or(Path::new("."));
+                (dir, Some(dir.join(".qstudio").join("cache")))
+            }
+        };
+        Self::with_providers(
+            file_path,
+            ProviderRegistry::standard(provider_addr, base_dir, cache_dir.as_deref()),
+            is_src_input,
+        )
+    }
+
+    /// Like [`Engine::new`], with an explicit set of data providers.
+    pub fn with_providers(
+        file_path: &str,
+        providers: ProviderRegistry,
+        is_src_input: Option<bool>,
     ) -> Result<Self, String> {
         let is_src_input = is_src_input.unwrap_or(false);
         // let stripped = remove_comments(token_stream);
@@ -68,33 +157,36 @@ impl Engine {
                 fs::read_to_string(file_path).map_err(|e| format!("Failed to read file: {}", e))?;
         }
 
-        let providers = ClientBuilder::new(provider_addr)
-            .connect()
-            .map_err(|e| format!("Failed to connect to provider: {}", e))?;
-
-        let rt = match pollster::block_on(async move {
-            runtime::GpuRuntime::new().await.map_err(|e| e.to_string())
-        }) {
-            Ok(rt) => rt,
-            Err(e) => return Err(e),
-        };
-
-        match parse(&token_stream) {
+        match Parser::new(&token_stream)
+            .with_base_dir(providers.base_dir())
+            .parse()
+        {
             Ok(query) => Ok(Engine {
                 file_path: file_path.to_string(),
                 query,
                 status: EngineStatus::Stopped,
 
                 provider_frames: HashMap::new(),
+                calendars: HashMap::new(),
+                provider_actions: HashMap::new(),
                 frames: HashMap::new(),
+                quality: HashMap::new(),
+                look_ahead: Vec::new(),
+                warnings: Vec::new(),
+                profile: Profile::default(),
 
                 providers,
 
+                last_run: None,
+                calc_outputs: HashMap::new(),
+                live: HashMap::new(),
+
                 // code_diff: None,
                 output: None,
                 new_output: false,
                 _for_test_flag: is_src_input,
-                rt,
+                gpu: GpuService::shared(),
+                backend: CalcBackend::default(),
             }),
             Err(e) => {
                 return Err(format!(
@@ -114,10 +206,63 @@ impl Engine {
         ret
     }
 
+    /// Data quality issues and TRADE look-ahead found since the last call, one line
+    /// each, for warnings.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// TRADE rules of the query that read columns seeing later bars.
+    pub fn look_ahead(&self) -> &[LookAhead] {
+        &self.look_ahead
+    }
+
     pub fn get_output(&self) -> Option<Output> {
         self.output.clone()
     }
 
+    /// Serve PROVIDER blocks using `backend` from `provider`.
+    pub fn register_provider(&mut self, backend: &str, provider: Box<dyn DataProvider>) {
+        self.providers.register(backend, provider);
+    }
+
+    /// Timings of the last run's steps.
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// Run CALCs on `gpu` instead of the process's shared GPU service.
+    pub fn set_gpu(&mut self, gpu: GpuService) {
+        self.gpu = gpu;
+    }
+
+    pub fn gpu(&self) -> &GpuService {
+        &self.gpu
+    }
+
+    /// Run CALCs with `backend` from the next run on, unless the file picks its own with
+    /// `PARAM calcs`.
+    pub fn set_backend(&mut self, backend: CalcBackend) {
+        self.backend = backend;
+    }
+
+    /// What runs the CALCs: the file's `PARAM calcs`, or the engine's default.
+    pub fn backend(&self) -> CalcBackend {
+        self.query.calcs.unwrap_or(self.backend)
+    }
+
+    /// Provider cache hits and misses of the last run, if remote data is cached.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.providers.cache().map(|c| c.stats())
+    }
+
+    pub fn clear_provider_cache(&mut self) -> Result<(), String> {
+        match self.providers.cache_mut() {
+            Some(cache) => cache.clear(),
+            None => Ok(()),
+        }
+    }
+
     pub fn status(&self) -> &EngineStatus {
         &self.status
     }
@@ -136,33 +281,38 @@ impl Engine {
             file = fs::read_to_string(&self.file_path)
                 .map_err(|e| format!("Failed to read file: {}", e))?;
         }
-        match parse(&file) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!(
-                "Failed to analyze query: {}, line {}, column {}",
-                e.message, e.line, e.column
-            )),
+        let outcome = Parser::new(&file)
+            .with_base_dir(self.providers.base_dir())
+            .parse_recovering();
+        if outcome.is_ok() {
+            return Ok(());
         }
+        Err(format!(
+            "Failed to analyze query: {}",
+            format_parse_errors(&outcome.errors)
+        ))
     }
 
     pub fn update_code(&mut self) -> Result<(), String> {
         let code = fs::read_to_string(&self.file_path)
             .map_err(|e| format!("Failed to read file: {}", e))?;
 
-        match parse(&code) {
-            Ok(query) => {
-                self.query = query;
-                self.run()
-            }
-            Err(e) => Err(format!(
-                "Failed to parse updated code: {}, line {}, column {}",
-                e.message, e.line, e.column
-            )),
+        let outcome = Parser::new(&code)
+            .with_base_dir(self.providers.base_dir())
+            .parse_recovering();
+        if !outcome.is_ok() {
+            return Err(format!(
+                "Failed to parse updated code: {}",
+                format_parse_errors(&outcome.errors)
+            ));
         }
+        self.query = outcome.query;
+        self.run()
     }
 
     pub async fn restart(&mut self) -> Result<(), String> {
         self.status = EngineStatus::Stopped;
+        self.last_run = None;
 
         self.run()
     }
@@ -180,53 +330,276 @@ impl Engine {
 
         log::info!("Running engine for file: {}", self.file_path);
 
-        let mut queries = match self.query.build_provider_queries() {
-            Ok(queries) => queries,
-            Err(e) => {
-                self.status = EngineStatus::Error(format!("Failed to build queries: {:?}", e));
-                log::error!("Failed to build queries: {:?}", e);
-                return Err(format!("Failed to build queries: {:?}", e));
-            }
+        let look_ahead = parser::look_ahead(&self.query);
+        for found in look_ahead.iter().filter(|f| !self.look_ahead.contains(f)) {
+            log::warn!("{}", found);
+            self.warnings.push(found.to_string());
+        }
+        self.look_ahead = look_ahead;
+
+        // only a successful run can be built on, so a failure below means a full run next time
+        let plan = match self.last_run.take() {
+            Some(prev) => Plan::diff(&prev, &self.query, &self.calc_outputs),
+            None => Plan::full(&self.query),
         };
+        if let Some(cache) = self.providers.cache_mut() {
+            cache.reset_stats();
+        }
+        let result = self.execute(plan);
+        if result.is_err() {
+            // LIVE bars need a run to go onto, the next one starts the feeds again
+            self.live.clear();
+        }
+        result
+    }
 
-        let get_data_result = match self.providers.get_data::<Vec<Entity>>(queries.clone()) {
-            Ok(data) => data,
-            Err(e) => {
-                self.status = EngineStatus::Error(format!("Failed to get data: {}", e));
-                log::error!("Failed to get data: {} for {:#?}", e, queries);
-                return Err(format!("Failed to get data: {} for {:#?}", e, queries));
+    /// Whether any LIVE provider is still streaming.
+    pub fn is_live(&self) -> bool {
+        !self.live.is_empty()
+    }
+
+    /// When the next LIVE provider is due to be polled.
+    pub fn next_tick(&self) -> Option<Instant> {
+        self.live.values().filter_map(|feed| feed.next_tick()).min()
+    }
+
+    /// Pause, resume or step the replayed LIVE providers. A step re-runs with the next
+    /// bar right away; returns whether there is new output.
+    pub fn control_replay(&mut self, control: ReplayControl) -> Result<bool, String> {
+        let now = Instant::now();
+        let mut replays = 0;
+        for (name, feed) in self.live.iter_mut() {
+            let Some(instance) = self.query.providers.get(name) else {
+                continue;
+            };
+            if self.providers.control(instance, control)? {
+                replays += 1;
+                if control == ReplayControl::Step {
+                    feed.poll_now(now);
+                }
             }
-        };
+        }
+        if replays == 0 {
+            return Err("No replay is running".to_string());
+        }
+        match control {
+            ReplayControl::Step => self.tick_at(now),
+            _ => Ok(false),
+        }
+    }
 
-        for (name, entity) in get_data_result {
-            let data: Vec<Value> = match serde_json::from_str(&entity[0].data) {
-                Ok(data) => data,
+    /// Poll the LIVE providers that are due and re-run whatever their new bars feed.
+    /// Returns whether there is new output.
+    pub fn tick(&mut self) -> Result<bool, String> {
+        self.tick_at(Instant::now())
+    }
+
+    fn tick_at(&mut self, now: Instant) -> Result<bool, String> {
+        // new bars can only be added to a successful run
+        if self.last_run.is_none() {
+            return Ok(false);
+        }
+
+        let mut updated = HashSet::new();
+        for (name, feed) in self.live.iter_mut() {
+            if !feed.is_due(now) {
+                continue;
+            }
+            feed.advance(now);
+            let Some(instance) = self.query.providers.get(name) else {
+                continue;
+            };
+            let bars = match self.providers.poll(instance, feed.last_bar) {
+                Ok(bars) if bars.height() > 0 => bars,
+                Ok(_) => continue,
                 Err(e) => {
-                    self.status = EngineStatus::Error(format!("Failed to deserialize data: {}", e));
-                    log::error!(
-                        "Failed to deserialize data: {} for {:#?}",
-                        e,
-                        entity[0].data
-                    );
-                    return Err(format!(
-                        "Failed to deserialize data: {} for {:#?}",
-                        e, entity[0].data
-                    ));
+                    log::error!("Failed to poll LIVE data: {}", e);
+                    continue;
                 }
             };
-            let df = match json_values_to_df(&data) {
+            let old = self.provider_frames.remove(name).unwrap_or_default();
+            match append_bars(&old, &bars) {
+                Ok(df) => {
+                    feed.last_bar = last_bar(&df).or(feed.last_bar);
+                    self.provider_frames.insert(name.clone(), df);
+                    updated.insert(name.clone());
+                }
+                Err(e) => {
+                    log::error!("Failed to add LIVE bars to {}: {}", name, e);
+                    self.provider_frames.insert(name.clone(), old);
+                }
+            }
+        }
+        self.live.retain(|name, feed| {
+            if feed.is_over() {
+                log::info!("LIVE PROVIDER {} finished streaming", name);
+            }
+            !feed.is_over()
+        });
+        if updated.is_empty() {
+            return Ok(false);
+        }
+
+        // the last run stays the one to build on until this one succeeds, so a failed
+        // tick doesn't stop the ticks after it
+        self.status = EngineStatus::Running;
+        let plan = Plan::new_bars(&self.query, &updated);
+        self.execute(plan)?;
+        Ok(true)
+    }
+
+    /// `bars` of FRAME `frame` with the filings of FUNDAMENTAL provider `join` as known at each bar.
+    fn join_fundamentals(
+        &self,
+        frame: &str,
+        join: &str,
+        bars: &DataFrame,
+    ) -> Result<DataFrame, String> {
+        let is_fundamental = self
+            .query
+            .providers
+            .get(join)
+            .is_some_and(|p| p.kind == DataKind::Fundamental);
+        if !is_fundamental {
+            return Err(format!(
+                "FRAME {}: {} is not a FUNDAMENTAL PROVIDER, only those can be joined",
+                frame, join
+            ));
+        }
+        let filings = self
+            .provider_frames
+            .get(join)
+            .ok_or_else(|| format!("Provider : {} not found for frame: {}", join, frame))?;
+        providers::fundamental::join_asof(bars, filings)
+            .map_err(|e| format!("FRAME {}: joining {}: {}", frame, join, e))
+    }
+
+    /// [`run_plan`](Engine::run_plan), timing its steps into the run's profile.
+    fn execute(&mut self, plan: Plan) -> Result<(), String> {
+        let (result, profile) = profile::record(|| {
+            let _run = profile::span("run");
+            self.run_plan(plan)
+        });
+        log::info!("Run took {} ms", profile.total_us() / 1000);
+        if let (Ok(()), Some(Output::Data { profile: last, .. })) = (&result, &mut self.output) {
+            *last = profile.clone();
+        }
+        self.profile = profile;
+        result
+    }
+
+    /// Fetch, compute and draw what `plan` asks for, keeping the rest of the last output.
+    fn run_plan(&mut self, plan: Plan) -> Result<(), String> {
+        log::info!(
+            "Re-running: providers {:?}, frames {:?}, graph {}, trade {}",
+            plan.fetch,
+            plan.frames.keys().collect::<Vec<_>>(),
+            plan.graph,
+            plan.trade
+        );
+
+        self.provider_frames
+            .retain(|name, _| self.query.providers.contains_key(name));
+        self.provider_actions
+            .retain(|name, _| self.query.providers.contains_key(name));
+        self.calendars
+            .retain(|name, _| self.query.providers.contains_key(name));
+        self.live
+            .retain(|name, _| self.query.providers.contains_key(name));
+        for (name, instance) in self.query.providers.iter() {
+            if !plan.fetch.contains(name) {
+                continue;
+            }
+            if instance.backend.is_none() {
+                log::warn!("PROVIDER {} has no backend, skipping", name);
+                continue;
+            }
+            let _fetch = profile::span(format!("fetch PROVIDER {}", name));
+            // LIVE starts from the bars so far, later ticks add to them
+            let fetched = match &instance.time_spec {
+                Some(TimeSpec::LiveSpec { interval, duration }) => {
+                    LiveFeed::new(interval, duration, Instant::now()).and_then(|mut feed| {
+                        let df = self.providers.poll(instance, None)?;
+                        feed.last_bar = last_bar(&df);
+                        self.live.insert(name.clone(), feed);
+                        Ok(df)
+                    })
+                }
+                _ => {
+                    self.live.remove(name);
+                    self.providers.fetch(instance)
+                }
+            };
+            let fetched = fetched.and_then(|df| {
+                // gaps against it go in the data quality report of the frames over it
+                if instance.kind == DataKind::Bars {
+                    let calendar = self.providers.calendar(instance)?;
+                    self.calendars.insert(name.clone(), calendar);
+                }
+                Ok(df)
+            });
+            let df = match fetched {
                 Ok(df) => df,
                 Err(e) => {
-                    self.status = EngineStatus::Error(format!("Failed to deserialize data: {}", e));
-                    log::error!("Failed to deserialize data: {} for {:#?}", e, data);
-                    return Err(format!("Failed to deserialize data: {} for {:#?}", e, data));
+                    self.status = EngineStatus::Error(format!("Failed to get data: {}", e));
+                    log::error!("Failed to get data: {}", e);
+                    return Err(format!("Failed to get data: {}", e));
                 }
             };
 
-            self.provider_frames.insert(name, df);
+            self.provider_frames.insert(name.clone(), df);
+            self.provider_actions.remove(name);
+        }
+        for (name, frame) in self.query.frame.iter() {
+            let provider = &frame.provider;
+            if !frame.adjust.any()
+                || !plan.frames.contains_key(name)
+                || self.provider_actions.contains_key(provider)
+            {
+                continue;
+            }
+            let Some(instance) = self.query.providers.get(provider) else {
+                continue;
+            };
+            let _actions = profile::span(format!("splits and dividends of {}", provider));
+            match self.providers.actions(instance) {
+                Ok(actions) => {
+                    self.provider_actions.insert(provider.clone(), actions);
+                }
+                Err(e) => {
+                    let e = format!("FRAME {}: ADJUST needs splits and dividends: {}", name, e);
+                    self.status = EngineStatus::Error(e.clone());
+                    log::error!("{}", e);
+                    return Err(e);
+                }
+            }
+        }
+        if let Some(stats) = self.cache_stats() {
+            log::info!("Provider {}", stats);
         }
 
+        self.frames
+            .retain(|name, _| self.query.frame.contains_key(name));
+        self.quality
+            .retain(|name, _| self.query.frame.contains_key(name));
+        self.calc_outputs
+            .retain(|name, _| self.query.frame.contains_key(name));
+        // a frame to (re)run, with its data ready for the CALCs
+        struct Pending {
+            name: String,
+            data: DataFrame,
+            dividends: Option<Column>,
+            reuse: CalcOutputs,
+            /// the provider's, which annualizes VOLATILITY along with the bar spacing
+            calendar: Calendar,
+            interval: Option<i64>,
+        }
+        let mut pending: Vec<Pending> = Vec::new();
         for (name, frame) in self.query.frame.iter() {
+            let Some(reusable) = plan.frames.get(name) else {
+                continue;
+            };
+            let _prepare = profile::span(format!("prepare FRAME {}", name));
             let p = match self.provider_frames.get(&frame.provider) {
                 Some(provider) => provider,
                 None => {
@@ -236,6 +609,52 @@ impl Engine {
                     ))
                 }
             };
+            let mut data = match self.provider_actions.get(&frame.provider) {
+                Some(actions) if frame.adjust.any() => {
+                    providers::corporate::adjust_bars(p, actions, frame.adjust)
+                        .map_err(|e| format!("FRAME {}: ADJUST: {}", name, e))?
+                }
+                _ => p.clone(),
+            };
+            for join in &frame.joins {
+                data = self.join_fundamentals(name, join, &data)?;
+            }
+
+            let report = DataQuality::check(&data, self.calendars.get(&frame.provider))
+                .map_err(|e| format!("FRAME {}: checking data: {}", name, e))?;
+            let last = self.quality.get(name);
+            let new: Vec<String> = report
+                .issues
+                .iter()
+                .filter(|issue| last.is_none_or(|last| !last.issues.contains(issue)))
+                .map(|issue| issue.to_string())
+                .collect();
+            for issue in &new {
+                log::warn!("FRAME {}: {}", name, issue);
+            }
+            // one warning a frame, the first few issues in it
+            if !new.is_empty() {
+                let mut warning = format!("FRAME {}: {}", name, new[..new.len().min(3)].join("; "));
+                if new.len() > 3 {
+                    warning += &format!(" and {} more", new.len() - 3);
+                }
+                self.warnings.push(warning);
+            }
+            self.quality.insert(name.clone(), report);
+            let columns: HashSet<&str> = frame
+                .actions
+                .fields
+                .iter()
+                .chain(frame.actions.calc.iter().flatten().flat_map(|c| &c.inputs))
+                .map(|c| c.as_str())
+                .collect();
+            let data = utils::quality::apply_fill(
+                &data,
+                &columns.into_iter().collect::<Vec<_>>(),
+                frame.fill,
+            )
+            .map_err(|e| format!("FRAME {}: {}", name, e))?;
+            let dividends = data.column("dividend").ok().cloned();
 
             // let provider = match action_over_data(&frame.actions, p.clone()) {
             //     Ok(provider) => provider,
@@ -245,28 +664,115 @@ impl Engine {
             //     }
             // };
 
-            println!("Time for th gpu");
-            let provider = match utils::action::action_over_data_gpu(
-                &frame.actions,
-                p.clone(),
-                &mut self.rt,
-            ) {
-                Ok(provider) => provider,
+            let reuse: CalcOutputs = self
+                .calc_outputs
+                .remove(name)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(alias, _)| reusable.contains(alias))
+                .collect();
+            let interval = calendar::timestamps(&data)
+                .ok()
+                .and_then(|ts| calendar::bar_interval(&ts));
+            pending.push(Pending {
+                name: name.clone(),
+                data,
+                dividends,
+                reuse,
+                calendar: self
+                    .calendars
+                    .get(&frame.provider)
+                    .cloned()
+                    .unwrap_or_default(),
+                interval,
+            });
+        }
+
+        // frames running the same CALCs from scratch (a universe of tickers) go to the
+        // GPU together, packed into one table within its memory budget; the rest one
+        // at a time
+        let backend = self.backend();
+        let on_gpu = backend == CalcBackend::Gpu;
+        let packable = |f: &Pending| on_gpu && f.reuse.is_empty() && f.data.height() > 0;
+        let mut batches: Vec<Vec<Pending>> = Vec::new();
+        for frame in pending {
+            let actions = &self.query.frame[&frame.name].actions;
+            let batch = batches.iter_mut().find(|batch| {
+                let rows: usize = batch.iter().map(|f| f.data.height()).sum();
+                packable(&frame)
+                    && packable(&batch[0])
+                    && same_actions(&self.query.frame[&batch[0].name].actions, actions)
+                    && (&frame.calendar, frame.interval) == (&batch[0].calendar, batch[0].interval)
+                    && self.gpu.fits(utils::action::gpu_bytes(
+                        actions,
+                        rows + frame.data.height(),
+                    ))
+            });
+            match batch {
+                Some(batch) => batch.push(frame),
+                None => batches.push(vec![frame]),
+            }
+        }
+
+        for batch in batches {
+            let names: Vec<String> = batch.iter().map(|f| f.name.clone()).collect();
+            let _calcs = profile::span(format!("CALCs FRAME {}", names.join(", ")));
+            if names.len() > 1 {
+                log::info!("Running FRAMEs {} as one GPU batch", names.join(", "));
+            }
+            let calendar = batch[0].calendar.clone();
+            let (frames, dividends): (Vec<(DataFrame, CalcOutputs)>, Vec<Option<Column>>) = batch
+                .into_iter()
+                .map(|f| ((f.data, f.reuse), f.dividends))
+                .unzip();
+            let actions = &self.query.frame[&names[0]].actions;
+            let results = match backend {
+                CalcBackend::Gpu => {
+                    utils::action::action_over_frames(actions, frames, &calendar, &self.gpu)
+                }
+                CalcBackend::Lazy => {
+                    utils::lazy::action_over_frames_lazy(actions, frames, &calendar)
+                }
+            };
+            let results = match results {
+                Ok(results) => results,
                 Err(e) => {
                     log::error!("Failed to apply actions for frame: {}", e);
                     return Err(format!("Failed to apply actions for frame: {}", e));
                 }
             };
 
-            println!("Adding provider_frame: {}", provider.head(Some(40)));
-            self.frames.insert(name.clone(), provider.clone());
+            for ((name, (provider, outputs)), dividends) in
+                names.into_iter().zip(results).zip(dividends)
+            {
+                // cash dividends ride along for the TRADE P&L, pulled or not
+                let provider = match dividends {
+                    Some(dividend) if provider.column("dividend").is_err() => provider
+                        .hstack(&[dividend])
+                        .map_err(|e| format!("FRAME {}: {}", name, e))?,
+                    _ => provider,
+                };
+
+                println!("Adding provider_frame: {}", provider.head(Some(40)));
+                self.frames.insert(name.clone(), provider);
+                self.calc_outputs.insert(name, outputs);
+            }
         }
 
+        // graph and trades the plan doesn't touch come from the last output
+        let (last_graph, last_trades) = match &self.output {
+            Some(Output::Data { graph, trades, .. }) => (graph.clone(), trades.clone()),
+            _ => (None, None),
+        };
+
         let mut graph: Option<Graph> = None;
         let mut trades: Option<DataFrame> = None;
 
         // Now this will check if it needs to build a graph
-        if let Some(g) = &self.query.graph {
+        if !plan.graph {
+            graph = last_graph.filter(|_| self.query.graph.is_some());
+        } else if let Some(g) = &self.query.graph {
+            let _graph = profile::span("GRAPH");
             graph = match utils::graph::graph_over_data(g, &self.frames) {
                 Ok(g) => Some(g),
                 Err(e) => {
@@ -276,9 +782,20 @@ impl Engine {
             };
         }
 
-        if let Some(trade_section) = &self.query.trade {
+        let mut t: Option<Trades> = None;
+        if !plan.trade {
+            t = last_trades.filter(|_| self.query.trade.is_some());
+        } else if let Some(trade_section) = &self.query.trade {
             log::info!("Building trades over data");
-            trades = match utils::trade::trades_over_data(trade_section, &self.frames) {
+            let _trade = profile::span("TRADE");
+            let calendar = self
+                .query
+                .frame
+                .get(&trade_section.over_frame)
+                .and_then(|frame| self.calendars.get(&frame.provider))
+                .cloned()
+                .unwrap_or_default();
+            trades = match utils::trade::trades_over_data(trade_section, &self.frames, &calendar) {
                 Ok(df) => Some(df),
                 Err(e) => {
                     log::error!("Failed to build trades: {}", e);
@@ -305,8 +822,8 @@ impl Engine {
             log::info!("No trades generated");
         }
 
-        let mut t: Option<Trades> = None;
         if let Some(trades_df) = trades {
+            let _summary = profile::span("TRADE summary");
             let over_frame = self.query.trade.as_ref().unwrap().over_frame.clone();
             let over_frame_df = self
                 .frames
@@ -324,7 +841,8 @@ impl Engine {
                     self.query.trade.clone().unwrap(),
                     &trades_df,
                     &over_frame_df,
-                ),
+                )
+                .map_err(|e| format!("Failed to summarize trades: {}", e))?,
                 over_frame,
             });
         }
@@ -333,15 +851,27 @@ impl Engine {
             graph,
             tables: self.frames.clone(),
             trades: t,
+            quality: self.quality.clone(),
+            profile: Profile::default(),
         });
 
         self.new_output = true;
+        self.last_run = Some(self.query.clone());
 
         Ok(())
     }
 }
 
+fn format_parse_errors(errors: &[ParseError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
 pub fn json_values_to_df(values: &[Value]) -> std::io::Result<DataFrame> {
+    let _decode = profile::span("decode JSON");
     // values should be an array of JSON objects (or nested; Polars can hold Struct/Lists)
     let bytes = serde_json::to_vec(values)?;
     let df = JsonReader::new(Cursor::new(bytes))
@@ -363,7 +893,7 @@ mod tests {
         let src = r#"
 
             PROVIDER aapl_data
-                PROVIDER yahoo_finance
+                PROVIDER synthetic
                 TICKER aapl
                 FROM 20200101 TO 20250901
 
@@ -374,6 +904,7 @@ mod tests {
 
             TRADE
                 STOCK
+                OVERFRAME test
                 ENTRY test.open, test.close, 0.5
                 EXIT test.high, test.low, 0.5
                 LIMIT 0.1
@@ -393,5 +924,157 @@ mod tests {
                 assert_eq!(false, true);
             }
         }
+
+        // a failing TRADE rule is reported at its own line
+        let src = src.replace("EXIT test.high", "EXIT test.missing");
+        let mut engine = Engine::new(&src, "127.0.0.1:7000", Some(true)).unwrap();
+        let err = engine.run().unwrap_err();
+        assert!(err.contains("EXIT (line 17, column 17)"), "{}", err);
+    }
+
+    #[test]
+    fn test_update_code_reruns_changed_calcs() {
+        let src = indoc::indoc! {"
+            PROVIDER demo USING synthetic
+                FROM 2020-01-01 TO 2020-03-31
+
+            FRAME test
+                PROVIDER demo
+                PULL open, high, low, close
+                CALC open, close DIFFERENCE CALLED oc
+                CALC low SMA CALLED l_sma
+                CALC l_sma, oc DIFFERENCE CALLED spread
+        "};
+        let edited = src.replace("CALC low SMA", "CALC high SMA");
+
+        let dir = std::env::temp_dir().join(format!("qstudio_incremental_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.qql");
+        std::fs::write(&path, src).unwrap();
+
+        let mut engine = Engine::new(path.to_str().unwrap(), "127.0.0.1:7000", None).unwrap();
+        engine.run().unwrap();
+        let oc = engine.calc_outputs["test"]["oc"].clone();
+
+        std::fs::write(&path, &edited).unwrap();
+        engine.update_code().unwrap();
+        // untouched CALCs keep their columns, edited ones and their dependents are recomputed
+        assert!(engine.calc_outputs["test"]["oc"][0].equals(&oc[0]));
+
+        let mut fresh = Engine::new(&edited, "127.0.0.1:7000", Some(true)).unwrap();
+        fresh.run().unwrap();
+        assert!(engine.frames["test"].equals_missing(&fresh.frames["test"]));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Serves 5 bars up front, then one more on each poll.
+    #[derive(Debug)]
+    struct Replay {
+        served: i64,
+    }
+
+    impl crate::providers::DataProvider for Replay {
+        fn fetch(
+            &mut self,
+            _: &crate::parser::ProviderInstance,
+        ) -> Result<polars::prelude::DataFrame, String> {
+            Err("replay only streams".to_string())
+        }
+
+        fn poll(
+            &mut self,
+            _: &crate::parser::ProviderInstance,
+            after: Option<i64>,
+        ) -> Result<polars::prelude::DataFrame, String> {
+            let (start, end) = match after {
+                None => (0, 5),
+                Some(_) => (self.served, self.served + 1),
+            };
+            self.served = end;
+            let ts: Vec<i64> = (start..end).map(|i| i * 60).collect();
+            let close: Vec<f64> = (start..end).map(|i| 100.0 + i as f64).collect();
+            polars::df!("timestamp" => ts, "open" => close.clone(), "close" => close)
+                .map_err(|e| e.to_string())
+        }
+    }
+
+    #[test]
+    fn test_live_ticks_append_bars() {
+        let src = indoc::indoc! {"
+            PROVIDER feed USING replay
+                LIVE TICK 1m FOR 3m
+
+            FRAME test
+                PROVIDER feed
+                PULL open, close
+                CALC open, close DIFFERENCE CALLED oc
+        "};
+        let mut engine = Engine::new(src, "127.0.0.1:7000", Some(true)).unwrap();
+        engine.register_provider("replay", Box::new(Replay { served: 0 }));
+        engine.run().unwrap();
+        assert!(engine.output_changed());
+        assert!(engine.is_live());
+        assert_eq!(engine.frames["test"].height(), 5);
+
+        let start = std::time::Instant::now();
+        let minute = std::time::Duration::from_secs(61);
+        assert!(!engine.tick_at(start).unwrap(), "nothing is due yet");
+        assert!(engine.tick_at(start + minute).unwrap());
+        assert!(engine.output_changed());
+        assert_eq!(engine.frames["test"].height(), 6);
+        assert_eq!(engine.calc_outputs["test"]["oc"][0].len(), 6);
+
+        // ticks missed while busy are skipped, and the feed stops after its duration
+        assert!(engine.tick_at(start + minute * 3).unwrap());
+        assert_eq!(engine.frames["test"].height(), 7);
+        assert!(!engine.is_live());
+        assert!(engine.next_tick().is_none());
+
+        // a tick that fails leaves the next one to re-run with every bar since
+        let mut engine = Engine::new(src, "127.0.0.1:7000", Some(true)).unwrap();
+        engine.register_provider("replay", Box::new(Replay { served: 0 }));
+        engine.run().unwrap();
+        let pulled = engine.query.frame["test"].actions.fields.clone();
+        let frame = engine.query.frame.get_mut("test").unwrap();
+        frame.actions.fields.push("missing".to_string());
+        assert!(engine.tick_at(start + minute).is_err());
+        engine.query.frame.get_mut("test").unwrap().actions.fields = pulled;
+        assert!(engine.tick_at(start + minute * 2).unwrap());
+        assert_eq!(engine.frames["test"].height(), 7);
+
+        // a failed run stops the feeds instead of leaving them due forever
+        let broken = src.replace("PULL open, close", "PULL open, close, volume");
+        let mut engine = Engine::new(&broken, "127.0.0.1:7000", Some(true)).unwrap();
+        engine.register_provider("replay", Box::new(Replay { served: 0 }));
+        assert!(engine.run().is_err());
+        assert!(!engine.is_live());
+        assert!(engine.next_tick().is_none());
+    }
+
+    #[test]
+    fn test_step_replay() {
+        let src = indoc::indoc! {"
+            PROVIDER feed USING replay
+                LIVE TICK 1h FOR 1d
+                PARAM from = 2020-01-01
+                PARAM to = 2020-01-31
+                PARAM speed = step
+                PARAM warmup = 10
+
+            FRAME test
+                PROVIDER feed
+                PULL open, close
+        "};
+        let mut engine = Engine::new(src, "127.0.0.1:7000", Some(true)).unwrap();
+        engine.run().unwrap();
+        assert_eq!(engine.frames["test"].height(), 10);
+
+        assert!(engine.control_replay(super::ReplayControl::Step).unwrap());
+        assert_eq!(engine.frames["test"].height(), 11);
+        assert!(
+            !engine.tick().unwrap(),
+            "a stepped replay waits for the next step"
+        );
+        assert!(!engine.control_replay(super::ReplayControl::Pause).unwrap());
     }
 }
@@ -1,4 +1,6 @@
 use crate::parser::{Graph, Trades};
+use crate::profile::Profile;
+use crate::utils::quality::DataQuality;
 use polars::frame::DataFrame;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -10,6 +12,10 @@ pub enum Output {
         graph: Option<Graph>,
         tables: HashMap<String, DataFrame>,
         trades: Option<Trades>,
+        /// each frame's data quality before its FILL
+        quality: HashMap<String, DataQuality>,
+        /// where the run spent its time
+        profile: Profile,
     },
     Error(String),
     None,
@@ -59,6 +65,13 @@ impl Output {
         }
     }
 
+    pub fn get_profile(&self) -> Option<&Profile> {
+        match self {
+            Output::Data { profile, .. } => Some(profile),
+            _ => None,
+        }
+    }
+
     pub fn get_tables(&self) -> Option<&HashMap<String, DataFrame>> {
         match self {
             Output::Data { tables, .. } => Some(tables),
@@ -3,24 +3,95 @@
 // Recursive-descent parser for Quant Query Language (QQL)
 // -----------------------------------------------------------------------------
 
+pub use crate::kernel::Kernel;
+use crate::kernel::{Uniform, UniformType};
 use crate::lexer::Lexer;
+pub use crate::lexer::Span;
 use crate::lexer::{Keyword, Token, TokenKind};
+use crate::CalcBackend;
 use polars::frame::DataFrame;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::iter::Peekable;
+use std::path::{Path, PathBuf};
 
 /* ------------------------------- AST types ------------------------------- */
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Frame {
     pub provider: String,
+    /// FUNDAMENTAL providers joined onto the provider's bars: `PROVIDER aapl_data, aapl_fund`
+    pub joins: Vec<String>,
+    pub adjust: Adjustments,
+    pub fill: FillPolicy,
     pub actions: ActionSection,
+    pub span: Span,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Corporate actions a FRAME's prices are adjusted for: `ADJUST splits, dividends`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Adjustments {
+    pub splits: bool,
+    pub dividends: bool,
+}
+
+impl Adjustments {
+    pub fn any(&self) -> bool {
+        self.splits || self.dividends
+    }
+}
+
+/// What a FRAME does with missing values before its CALCs run: `FILL forward`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FillPolicy {
+    /// carry the last value forward; values before the first stay missing
+    #[default]
+    Forward,
+    /// take the next value, which looks ahead: only for data known in advance
+    Backward,
+    Zero,
+    /// drop bars missing a PULL field
+    Drop,
+    /// leave them missing, so CALCs over them are missing too
+    None,
+}
+
+impl FillPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FillPolicy::Forward => "forward",
+            FillPolicy::Backward => "backward",
+            FillPolicy::Zero => "zero",
+            FillPolicy::Drop => "drop",
+            FillPolicy::None => "none",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "forward" => Some(FillPolicy::Forward),
+            "backward" => Some(FillPolicy::Backward),
+            "zero" => Some(FillPolicy::Zero),
+            "drop" => Some(FillPolicy::Drop),
+            "none" => Some(FillPolicy::None),
+            _ => None,
+        }
+    }
+}
+
+impl Frame {
+    /// The provider, then the joined ones.
+    pub fn providers(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.provider.as_str()).chain(self.joins.iter().map(|j| j.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct Query {
+    /// `PARAM calcs = gpu|lazy` before the first section: what runs this file's CALCs
+    pub calcs: Option<CalcBackend>,
     pub providers: HashMap<String, ProviderInstance>,
+    pub kernels: HashMap<String, Kernel>,
     pub frame: HashMap<String, Frame>,
     pub graph: Option<GraphSection>,
     pub trade: Option<TradeSection>,
@@ -30,29 +101,8 @@ impl Query {
     pub fn build_provider_queries(&self) -> Result<Vec<(String, String)>, ParseError> {
         let mut out: Vec<(String, String)> = Vec::new();
         for prov in self.providers.values() {
-            let backend = match &prov.backend {
-                Some(b) if !b.is_empty() => b,
-                _ => continue,
-            };
-            let ticker = match &prov.ticker {
-                Some(t) if !t.is_empty() => t,
-                _ => continue,
-            };
-            match &prov.time_spec {
-                Some(TimeSpec::DateRange { from, to }) => {
-                    let from_iso = yyyymmdd_to_iso8601_z(from).map_err(|msg| {
-                        ParseError::new(format!("provider \"{}\": {}", prov.name, msg), 0, 0)
-                    })?;
-                    let to_iso = yyyymmdd_to_iso8601_z(to).map_err(|msg| {
-                        ParseError::new(format!("provider \"{}\": {}", prov.name, msg), 0, 0)
-                    })?;
-                    let query = format!(
-                        "provider {} search ticker={} date={}..{}",
-                        backend, ticker, from_iso, to_iso
-                    );
-                    out.push((prov.name.clone(), query));
-                }
-                _ => continue,
+            if let Some(query) = prov.search_query()? {
+                out.push((prov.name.clone(), query));
             }
         }
         Ok(out)
@@ -65,13 +115,61 @@ pub enum TimeSpec {
     LiveSpec { interval: String, duration: String },
 }
 
+/// What a PROVIDER block serves: price bars, or filings with a `FUNDAMENTAL` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DataKind {
+    #[default]
+    Bars,
+    Fundamental,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ProviderInstance {
     pub name: String,
     pub backend: Option<String>,
     pub ticker: Option<String>,
     pub time_spec: Option<TimeSpec>,
+    pub kind: DataKind,
     pub params: Vec<(String, String)>,
+    pub span: Span,
+}
+
+impl ProviderInstance {
+    /// The provider server query for this block, or `None` if it has no backend,
+    /// ticker or date range to search with.
+    pub fn search_query(&self) -> Result<Option<String>, ParseError> {
+        let backend = match &self.backend {
+            Some(b) if !b.is_empty() => b,
+            _ => return Ok(None),
+        };
+        let ticker = match &self.ticker {
+            Some(t) if !t.is_empty() => t,
+            _ => return Ok(None),
+        };
+        match &self.time_spec {
+            Some(TimeSpec::DateRange { from, to }) => {
+                let from_iso = date_to_iso8601_z(from).map_err(|msg| {
+                    ParseError::new(
+                        format!("provider \"{}\": {}", self.name, msg),
+                        self.span.line,
+                        self.span.column,
+                    )
+                })?;
+                let to_iso = date_to_iso8601_z(to).map_err(|msg| {
+                    ParseError::new(
+                        format!("provider \"{}\": {}", self.name, msg),
+                        self.span.line,
+                        self.span.column,
+                    )
+                })?;
+                Ok(Some(format!(
+                    "provider {} search ticker={} date={}..{}",
+                    backend, ticker, from_iso, to_iso
+                )))
+            }
+            _ => Ok(None),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -89,14 +187,33 @@ pub struct ActionSection {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Calc {
     pub inputs: Vec<String>,
-    pub operation: Keyword, // Difference, Sum, Multiply, Divide, Sma, Volatility, DoubleVolatility, Constant, LinearRegression
+    pub operation: Keyword, // Difference, Sum, Multiply, Divide, Sma, Volatility, DoubleVolatility, Constant, LinearRegression, Kernel
+    /// The declaration a `CALC ... KERNEL name` runs.
+    pub kernel: Option<Kernel>,
+    /// `CENTERED`: the window is centered on each bar, ending period / 2 bars after it,
+    /// rather than ending at it. Only for SMA and the VOLATILITYs.
+    pub centered: bool,
     pub alias: String,
+    pub span: Span,
+}
+
+impl Calc {
+    /// How many bars after a bar its window of `period` bars ends: none when trailing,
+    /// half the period when CENTERED.
+    pub fn window_ahead(&self, period: usize) -> usize {
+        if self.centered {
+            period / 2
+        } else {
+            0
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GraphSection {
     pub xaxis: String,
     pub commands: Vec<DrawCommand>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -105,11 +222,13 @@ pub enum DrawCommand {
         name: String,
         series: Vec<String>,
         frame: String,
+        span: Span,
     },
     Bar {
         name: String,
         y: String,
         frame: String,
+        span: Span,
     },
     Candle {
         name: String,
@@ -118,6 +237,7 @@ pub enum DrawCommand {
         low: String,
         close: String,
         frame: String,
+        span: Span,
     },
 }
 
@@ -129,13 +249,23 @@ impl DrawCommand {
             DrawCommand::Candle { frame, .. } => frame.clone(),
         }
     }
+
+    pub fn span(&self) -> Span {
+        match self {
+            DrawCommand::Line { span, .. }
+            | DrawCommand::Bar { span, .. }
+            | DrawCommand::Candle { span, .. } => *span,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum DrawType {
-    Line(String, Vec<f64>),
+    /// `None` where the value is missing, drawn as a break in the line
+    Line(String, Vec<Option<f64>>),
     Bar(String, Vec<f64>),
-    Candlestick(String, Vec<(f64, f64, f64, f64)>),
+    /// `None` for bars missing a price, left out
+    Candlestick(String, Vec<Option<(f64, f64, f64, f64)>>),
     RedRect(String, Vec<(f64, f64, f64)>),
     GreenRect(String, Vec<(f64, f64, f64)>),
 }
@@ -162,17 +292,25 @@ pub struct Graph {
 impl Graph {
     pub fn max(&self) -> f64 {
         self.data.iter().fold(0.0, |max, dt| match dt {
-            DrawType::Line(_, v) => v.iter().cloned().fold(max, f64::max),
+            DrawType::Line(_, v) => v.iter().flatten().cloned().fold(max, f64::max),
             DrawType::Bar(_, v) => v.iter().cloned().fold(max, f64::max),
-            DrawType::Candlestick(_, c) => c.iter().map(|&(_o, h, _l, _c)| h).fold(max, f64::max),
+            DrawType::Candlestick(_, c) => c
+                .iter()
+                .flatten()
+                .map(|&(_o, h, _l, _c)| h)
+                .fold(max, f64::max),
             _ => max,
         })
     }
     pub fn min(&self) -> f64 {
         self.data.iter().fold(f64::INFINITY, |min, dt| match dt {
-            DrawType::Line(_, v) => v.iter().cloned().fold(min, f64::min),
+            DrawType::Line(_, v) => v.iter().flatten().cloned().fold(min, f64::min),
             DrawType::Bar(_, v) => v.iter().cloned().fold(min, f64::min),
-            DrawType::Candlestick(_, c) => c.iter().map(|&(_o, _h, l, _c)| l).fold(min, f64::min),
+            DrawType::Candlestick(_, c) => c
+                .iter()
+                .flatten()
+                .map(|&(_o, _h, l, _c)| l)
+                .fold(min, f64::min),
             _ => min,
         })
     }
@@ -194,7 +332,41 @@ pub struct TradeSection {
     pub exit: Vec<String>,
     pub within_exit: f64,
     pub stop_loss: f64,
-    pub hold: i32,
+    pub hold: Hold,
+    pub span: Span,
+    /// the OVERFRAME, ENTRY and EXIT lines, for errors and warnings about one of them
+    pub over_frame_span: Span,
+    pub entry_span: Span,
+    pub exit_span: Span,
+}
+
+/// How long a TRADE keeps a position open at most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hold {
+    /// `HOLD 14`: bars
+    Bars(usize),
+    /// `HOLD 10d`: trading days of the over frame's calendar
+    Sessions(u32),
+    /// `HOLD 90m`: seconds of trading time, counting only session hours
+    TradingTime(i64),
+}
+
+impl Default for Hold {
+    fn default() -> Self {
+        Hold::Bars(0)
+    }
+}
+
+impl std::fmt::Display for Hold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Hold::Bars(n) => write!(f, "{}", n),
+            Hold::Sessions(n) => write!(f, "{}d", n),
+            Hold::TradingTime(s) if s % 3_600 == 0 => write!(f, "{}h", s / 3_600),
+            Hold::TradingTime(s) if s % 60 == 0 => write!(f, "{}m", s / 60),
+            Hold::TradingTime(s) => write!(f, "{}s", s),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -237,12 +409,42 @@ impl ParseError {
     }
 }
 
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, line {}, column {}",
+            self.message, self.line, self.column
+        )
+    }
+}
+
 /* -------------------------------- Parser -------------------------------- */
 
+/// Result of a recovering parse: whatever could be built, plus every error hit on the way.
+#[derive(Debug, Clone)]
+pub struct ParseOutcome {
+    pub query: Query,
+    pub errors: Vec<ParseError>,
+}
+
+impl ParseOutcome {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
 #[derive(Clone)]
 pub struct Parser<'a> {
     iter: Peekable<Lexer<'a>>,
     last_pos: (usize, usize),
+    last_end: (usize, usize),
+    last_was_newline: bool,
+    errors: Vec<ParseError>,
+    /// KERNELs declared so far, for the CALCs after them
+    kernels: HashMap<String, Kernel>,
+    /// where relative KERNEL files are
+    base_dir: PathBuf,
 }
 
 impl<'a> Parser<'a> {
@@ -250,27 +452,144 @@ impl<'a> Parser<'a> {
         Self {
             iter: Lexer::new(src).peekable(),
             last_pos: (0, 0),
+            last_end: (0, 0),
+            last_was_newline: true,
+            errors: Vec::new(),
+            kernels: HashMap::new(),
+            base_dir: PathBuf::from("."),
         }
     }
 
+    /// Read relative KERNEL files from `dir` rather than the working directory.
+    pub fn with_base_dir(mut self, dir: &Path) -> Self {
+        self.base_dir = dir.to_path_buf();
+        self
+    }
+
+    /// Strict parse: fails with the first error found.
     pub fn parse(&mut self) -> Result<Query, ParseError> {
-        let providers = self.parse_provider_sections()?;
-        let frame = self.parse_frame_section()?;
+        let outcome = self.parse_recovering();
+        match outcome.errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(outcome.query),
+        }
+    }
 
-        let graph = self.parse_graph_section().unwrap_or(None);
-        let trade = match self.parse_trade_section() {
-            Ok(t) => t,
-            Err(e) => {
-                log::error!("Failed to parse trade section: {}", e.message);
-                None
+    /// Parse the whole source, recovering from errors.
+    ///
+    /// After an error the parser skips to the end of the offending line and carries on
+    /// inside the current section; unknown lines at the top level are skipped up to the
+    /// next PROVIDER, KERNEL, FRAME, GRAPH or TRADE section.
+    pub fn parse_recovering(&mut self) -> ParseOutcome {
+        let mut query = Query::default();
+        let mut before_sections = true;
+
+        loop {
+            let Some(kind) = self.peek_kind() else {
+                break;
+            };
+            let param_ok = before_sections;
+            before_sections &= matches!(
+                kind,
+                TokenKind::Newline | TokenKind::Comment(_) | TokenKind::Keyword(Keyword::Param)
+            );
+            match kind {
+                TokenKind::Newline | TokenKind::Comment(_) => {
+                    let _ = self.next_token();
+                }
+                TokenKind::EOF => break,
+                TokenKind::Keyword(Keyword::Param) => {
+                    if let Err(e) = self.parse_query_param(&mut query, param_ok) {
+                        self.recover(e);
+                    }
+                }
+                TokenKind::Keyword(Keyword::Provider) => match self.parse_provider_block() {
+                    Ok(inst) => {
+                        if query.providers.contains_key(&inst.name) {
+                            self.errors.push(ParseError::new(
+                                format!("provider \"{}\" is already defined", inst.name),
+                                inst.span.line,
+                                inst.span.column,
+                            ));
+                        } else {
+                            query.providers.insert(inst.name.clone(), inst);
+                        }
+                    }
+                    Err(e) => self.recover(e),
+                },
+                TokenKind::Keyword(Keyword::Kernel) => match self.parse_kernel_block() {
+                    Ok(kernel) => {
+                        if self.kernels.contains_key(&kernel.name) {
+                            self.errors.push(ParseError::new(
+                                format!("kernel \"{}\" is already defined", kernel.name),
+                                kernel.span.line,
+                                kernel.span.column,
+                            ));
+                        } else {
+                            self.kernels.insert(kernel.name.clone(), kernel);
+                        }
+                    }
+                    Err(e) => self.recover(e),
+                },
+                TokenKind::Keyword(Keyword::Frame) => match self.parse_frame_block() {
+                    Ok((name, frame)) => {
+                        if query.frame.contains_key(&name) {
+                            self.errors.push(ParseError::new(
+                                format!("frame \"{}\" is already defined", name),
+                                frame.span.line,
+                                frame.span.column,
+                            ));
+                        } else {
+                            query.frame.insert(name, frame);
+                        }
+                    }
+                    Err(e) => self.recover(e),
+                },
+                TokenKind::Keyword(Keyword::Graph) => {
+                    let (line, column) = self.peek_pos();
+                    let graph = self.parse_graph_section();
+                    if query.graph.is_some() {
+                        self.errors.push(ParseError::new(
+                            "GRAPH section is already defined",
+                            line,
+                            column,
+                        ));
+                    } else {
+                        query.graph = graph;
+                    }
+                }
+                TokenKind::Keyword(Keyword::Trade) => {
+                    let (line, column) = self.peek_pos();
+                    let trade = self.parse_trade_section();
+                    if query.trade.is_some() {
+                        self.errors.push(ParseError::new(
+                            "TRADE section is already defined",
+                            line,
+                            column,
+                        ));
+                    } else {
+                        query.trade = trade;
+                    }
+                }
+                _ => {
+                    let tok = self.next_token();
+                    let err = match tok {
+                        Ok(tok) => ParseError::expected(
+                            &tok,
+                            "section (PROVIDER|KERNEL|FRAME|GRAPH|TRADE)",
+                        ),
+                        Err(e) => e,
+                    };
+                    self.recover(err);
+                }
             }
-        };
-        Ok(Query {
-            providers,
-            frame,
-            trade,
-            graph,
-        })
+        }
+
+        query.kernels = self.kernels.clone();
+        ParseOutcome {
+            query,
+            errors: std::mem::take(&mut self.errors),
+        }
     }
 
     /* --------------------------- token helpers -------------------------- */
@@ -279,9 +598,19 @@ impl<'a> Parser<'a> {
         match self.iter.next() {
             Some(Ok(tok)) => {
                 self.last_pos = (tok.line, tok.column);
+                self.last_was_newline = tok.kind == TokenKind::Newline;
+                if !matches!(
+                    tok.kind,
+                    TokenKind::Newline | TokenKind::Comment(_) | TokenKind::EOF
+                ) {
+                    self.last_end = (tok.line, tok.end_column);
+                }
                 Ok(tok)
             }
-            Some(Err(e)) => Err(ParseError::new(e.message, e.line, e.column)),
+            Some(Err(e)) => {
+                self.last_was_newline = false;
+                Err(ParseError::new(e.message, e.line, e.column))
+            }
             None => Err(ParseError::eof("unexpected EOF")),
         }
     }
@@ -292,6 +621,75 @@ impl<'a> Parser<'a> {
             None => None,
         }
     }
+    /// Peek the next token kind, recording (and skipping) any lexer errors in front of it.
+    fn peek_kind(&mut self) -> Option<TokenKind> {
+        loop {
+            match self.iter.peek() {
+                Some(Ok(tok)) => return Some(tok.kind.clone()),
+                Some(Err(_)) => {
+                    if let Err(e) = self.next_token() {
+                        self.errors.push(e);
+                    }
+                }
+                None => return None,
+            }
+        }
+    }
+    fn peek_pos(&mut self) -> (usize, usize) {
+        match self.iter.peek() {
+            Some(Ok(tok)) => (tok.line, tok.column),
+            Some(Err(e)) => (e.line, e.column),
+            None => self.last_pos,
+        }
+    }
+    /// Span from the start of `start` to the end of the last token consumed.
+    fn span_from(&self, start: &Token) -> Span {
+        start.span().to(Span::new(
+            self.last_end.0,
+            self.last_end.1,
+            self.last_end.0,
+            self.last_end.1,
+        ))
+    }
+    /// Record `err` and skip the rest of the current line.
+    fn recover(&mut self, err: ParseError) {
+        self.errors.push(err);
+        self.synchronize();
+    }
+    fn synchronize(&mut self) {
+        if self.last_was_newline {
+            return;
+        }
+        loop {
+            match self.iter.peek() {
+                Some(Ok(tok)) => match tok.kind {
+                    TokenKind::EOF => break,
+                    TokenKind::Newline => {
+                        let _ = self.next_token();
+                        break;
+                    }
+                    _ => {
+                        let _ = self.next_token();
+                    }
+                },
+                Some(Err(_)) => {
+                    let _ = self.next_token();
+                }
+                None => break,
+            }
+        }
+    }
+    fn is_section_start(kind: &TokenKind) -> bool {
+        matches!(
+            kind,
+            TokenKind::EOF
+                | TokenKind::Keyword(Keyword::Provider)
+                | TokenKind::Keyword(Keyword::Kernel)
+                | TokenKind::Keyword(Keyword::Frame)
+                | TokenKind::Keyword(Keyword::Graph)
+                | TokenKind::Keyword(Keyword::Trade)
+        )
+    }
     fn consume_newlines(&mut self) -> Result<(), ParseError> {
         loop {
             match self.peek_token() {
@@ -323,11 +721,18 @@ impl<'a> Parser<'a> {
             _ => Err(ParseError::expected(&tok, "identifier")),
         }
     }
-    fn expect_literal(&mut self) -> Result<String, ParseError> {
+    fn expect_date(&mut self) -> Result<String, ParseError> {
         let tok = self.next_token()?;
         match tok.kind {
-            TokenKind::Literal(lit) => Ok(lit),
-            _ => Err(ParseError::expected(&tok, "literal")),
+            TokenKind::Date(date) => Ok(date),
+            _ => Err(ParseError::expected(&tok, "date (YYYY-MM-DD)")),
+        }
+    }
+    fn expect_duration(&mut self) -> Result<String, ParseError> {
+        let tok = self.next_token()?;
+        match tok.kind {
+            TokenKind::Duration(d) => Ok(d),
+            _ => Err(ParseError::expected(&tok, "duration (e.g. 5m, 2d)")),
         }
     }
     fn _expect_comma_or_newline(&mut self) -> Result<(), ParseError> {
@@ -337,108 +742,77 @@ impl<'a> Parser<'a> {
             _ => Err(ParseError::expected(&tok, ", or newline")),
         }
     }
-
-    /* --------------------------- PROVIDERS ------------------------------ */
-
-    fn parse_provider_sections(&mut self) -> Result<HashMap<String, ProviderInstance>, ParseError> {
-        self.consume_newlines()?;
-        let mut map = HashMap::new();
-        loop {
-            match self.peek_token() {
-                Some(Ok(tok)) if tok.kind == TokenKind::Keyword(Keyword::Provider) => {
-                    let inst = self.parse_provider_block()?;
-                    if map.contains_key(&inst.name) {
-                        return Err(ParseError::new(
-                            format!("provider \"{}\" is already defined", inst.name),
-                            self.last_pos.0,
-                            self.last_pos.1,
-                        ));
-                    }
-                    map.insert(inst.name.clone(), inst);
-                }
-                Some(Ok(tok)) if tok.kind == TokenKind::Newline => {
-                    self.next_token()?;
-                }
-                _ => break,
-            }
+    fn expect_number<T: std::str::FromStr>(&mut self, what: &str) -> Result<T, ParseError> {
+        let tok = self.next_token()?;
+        match &tok.kind {
+            TokenKind::Identifier(lit) => lit
+                .parse::<T>()
+                .map_err(|_| ParseError::new(format!("invalid {}", what), tok.line, tok.column)),
+            _ => Err(ParseError::expected(&tok, format!("{} value", what))),
         }
-        Ok(map)
     }
+    fn missing(&mut self, what: &str, section: &str, pos: (usize, usize)) {
+        self.errors.push(ParseError::new(
+            format!("{} is missing {}", section, what),
+            pos.0,
+            pos.1,
+        ));
+    }
+
+    /* --------------------------- PROVIDERS ------------------------------ */
 
     fn parse_provider_block(&mut self) -> Result<ProviderInstance, ParseError> {
-        self.expect_keyword(Keyword::Provider)?;
+        let provider_tok = self.expect_keyword(Keyword::Provider)?;
         let name = self.expect_identifier()?;
         self.consume_newlines()?;
 
         let mut backend: Option<String> = None;
         let mut ticker: Option<String> = None;
         let mut time_spec: Option<TimeSpec> = None;
+        let mut data_kind = DataKind::Bars;
         let mut params: Vec<(String, String)> = Vec::new();
 
         loop {
-            match self.peek_token() {
-                Some(Ok(tok)) => match &tok.kind {
-                    TokenKind::Keyword(Keyword::Provider) => {
-                        if backend.is_some() {
-                            break;
-                        }
-                        self.next_token()?; // inner PROVIDER = backend
-                        backend = Some(self.expect_identifier()?);
-                        self.consume_newlines()?;
-                    }
-                    TokenKind::Keyword(Keyword::Using) => {
-                        self.next_token()?;
-                        backend = Some(self.expect_identifier()?);
-                        self.consume_newlines()?;
-                    }
-                    TokenKind::Keyword(Keyword::Ticker) => {
-                        self.next_token()?;
-                        ticker = Some(self.expect_identifier()?);
-                        self.consume_newlines()?;
-                    }
-                    TokenKind::Keyword(Keyword::From) => {
-                        self.next_token()?;
-                        let from = self.expect_literal()?;
-                        self.expect_keyword(Keyword::To)?;
-                        let to = self.expect_literal()?;
-                        time_spec = Some(TimeSpec::DateRange { from, to });
-                        self.consume_newlines()?;
-                    }
-                    TokenKind::Keyword(Keyword::Live) => {
-                        self.next_token()?;
-                        self.expect_keyword(Keyword::Tick)?;
-                        let interval = self.expect_literal()?;
-                        self.expect_keyword(Keyword::For)?;
-                        let duration = self.expect_literal()?;
-                        time_spec = Some(TimeSpec::LiveSpec { interval, duration });
-                        self.consume_newlines()?;
-                    }
-                    TokenKind::Keyword(Keyword::Param) => {
-                        self.next_token()?;
-                        let key = self.expect_identifier()?;
-                        let eq_tok = self.next_token()?;
-                        let is_eq = match &eq_tok.kind {
-                            TokenKind::Identifier(s) if s == "=" => true,
-                            TokenKind::Literal(s) if s == "=" => true,
-                            _ => false,
-                        };
-                        if !is_eq {
-                            return Err(ParseError::expected(&eq_tok, "'='"));
-                        }
-                        let val_tok = self.next_token()?;
-                        let val = match val_tok.kind {
-                            TokenKind::Identifier(s) | TokenKind::Literal(s) => s,
-                            _ => return Err(ParseError::expected(&val_tok, "value")),
-                        };
-                        params.push((key, val));
-                        self.consume_newlines()?;
-                    }
-                    TokenKind::Newline => {
-                        self.next_token()?;
-                    }
-                    _ => break,
-                },
-                _ => break,
+            let Some(kind) = self.peek_kind() else {
+                break;
+            };
+            let line = match kind {
+                TokenKind::Newline | TokenKind::Comment(_) => {
+                    let _ = self.next_token();
+                    continue;
+                }
+                TokenKind::Keyword(Keyword::Provider) if backend.is_none() => {
+                    // inner PROVIDER = backend
+                    self.next_token()
+                        .and_then(|_| self.expect_identifier())
+                        .map(|b| backend = Some(b))
+                }
+                k if Self::is_section_start(&k) => break,
+                TokenKind::Keyword(Keyword::Using) => self
+                    .next_token()
+                    .and_then(|_| self.expect_identifier())
+                    .map(|b| backend = Some(b)),
+                TokenKind::Keyword(Keyword::Ticker) => self
+                    .next_token()
+                    .and_then(|_| self.expect_identifier())
+                    .map(|t| ticker = Some(t)),
+                TokenKind::Keyword(Keyword::From) => {
+                    self.parse_date_range().map(|ts| time_spec = Some(ts))
+                }
+                TokenKind::Keyword(Keyword::Live) => {
+                    self.parse_live_spec().map(|ts| time_spec = Some(ts))
+                }
+                TokenKind::Keyword(Keyword::Fundamental) => {
+                    self.next_token().map(|_| data_kind = DataKind::Fundamental)
+                }
+                TokenKind::Keyword(Keyword::Param) => self.parse_param().map(|p| params.push(p)),
+                _ => self
+                    .next_token()
+                    .and_then(|tok| Err(ParseError::expected(&tok, "PROVIDER setting"))),
+            };
+            match line {
+                Ok(()) => self.consume_newlines()?,
+                Err(e) => self.recover(e),
             }
         }
 
@@ -447,60 +821,247 @@ impl<'a> Parser<'a> {
             backend,
             ticker,
             time_spec,
+            kind: data_kind,
             params,
+            span: self.span_from(&provider_tok),
         })
     }
 
-    /* ----------------------------- FRAMES ------------------------------- */
+    fn parse_date_range(&mut self) -> Result<TimeSpec, ParseError> {
+        self.expect_keyword(Keyword::From)?;
+        let from = self.expect_date()?;
+        self.expect_keyword(Keyword::To)?;
+        let to = self.expect_date()?;
+        Ok(TimeSpec::DateRange { from, to })
+    }
+
+    fn parse_live_spec(&mut self) -> Result<TimeSpec, ParseError> {
+        self.expect_keyword(Keyword::Live)?;
+        self.expect_keyword(Keyword::Tick)?;
+        let interval = self.expect_duration()?;
+        self.expect_keyword(Keyword::For)?;
+        let duration = self.expect_duration()?;
+        Ok(TimeSpec::LiveSpec { interval, duration })
+    }
+
+    /// `PARAM calcs = gpu|lazy`, a setting of the whole file, only `first` in it.
+    fn parse_query_param(&mut self, query: &mut Query, first: bool) -> Result<(), ParseError> {
+        let (line, column) = self.peek_pos();
+        let (key, value) = self.parse_param()?;
+        if !first {
+            return Err(ParseError::new(
+                "a PARAM outside PROVIDER goes before the first section",
+                line,
+                column,
+            ));
+        }
+        match key.to_ascii_lowercase().as_str() {
+            "calcs" => match CalcBackend::from_name(&value) {
+                Some(backend) => query.calcs = Some(backend),
+                None => {
+                    return Err(ParseError::new(
+                        format!("PARAM calcs is gpu or lazy, not \"{}\"", value),
+                        line,
+                        column,
+                    ))
+                }
+            },
+            _ => {
+                return Err(ParseError::new(
+                    format!("unknown PARAM \"{}\" for the file, expected calcs", key),
+                    line,
+                    column,
+                ))
+            }
+        }
+        Ok(())
+    }
 
-    fn parse_frame_section(&mut self) -> Result<HashMap<String, Frame>, ParseError> {
+    fn parse_param(&mut self) -> Result<(String, String), ParseError> {
+        self.expect_keyword(Keyword::Param)?;
+        let key_tok = self.next_token()?;
+        let key = match key_tok.kind {
+            TokenKind::Identifier(s) => s,
+            // keys are backend names, they may collide with keywords: `PARAM volatility = 0.3`
+            TokenKind::Keyword(k) if k != Keyword::Comma => k.as_str().to_ascii_lowercase(),
+            _ => return Err(ParseError::expected(&key_tok, "identifier")),
+        };
+        let eq_tok = self.next_token()?;
+        if eq_tok.kind != TokenKind::Equals {
+            return Err(ParseError::expected(&eq_tok, "'='"));
+        }
+        let val_tok = self.next_token()?;
+        let val = match val_tok.kind {
+            TokenKind::Identifier(s)
+            | TokenKind::Str(s)
+            | TokenKind::Date(s)
+            | TokenKind::Duration(s) => s,
+            TokenKind::Bool(b) => b.to_string(),
+            _ => return Err(ParseError::expected(&val_tok, "value")),
+        };
+        Ok((key, val))
+    }
+
+    /* ----------------------------- KERNELS ------------------------------ */
+
+    fn parse_kernel_block(&mut self) -> Result<Kernel, ParseError> {
+        let kernel_tok = self.expect_keyword(Keyword::Kernel)?;
+        let kernel_pos = (kernel_tok.line, kernel_tok.column);
+        let name = self.expect_identifier()?;
+        self.expect_keyword(Keyword::From)?;
+        let path_tok = self.next_token()?;
+        let path = match path_tok.kind {
+            TokenKind::Str(s) | TokenKind::Identifier(s) => s,
+            _ => return Err(ParseError::expected(&path_tok, "WGSL file path")),
+        };
         self.consume_newlines()?;
-        let mut frames = HashMap::new();
-
-        while let Some(Ok(tok)) = self.peek_token() {
-            if tok.kind == TokenKind::Keyword(Keyword::Frame) {
-                self.next_token()?;
-                let frame_name = self.expect_identifier()?;
-                self.consume_newlines()?;
-
-                self.expect_keyword(Keyword::Provider)?;
-                let provider_name = self.expect_identifier()?;
-                self.consume_newlines()?;
-
-                let actions = self.parse_action_section()?;
-                frames.insert(
-                    frame_name,
-                    Frame {
-                        provider: provider_name,
-                        actions,
-                    },
-                );
-            } else if tok.kind == TokenKind::Newline {
-                self.next_token()?;
-            } else {
+
+        let mut inputs = Vec::new();
+        let mut outputs: Option<Vec<String>> = None;
+        let mut uniforms = Vec::new();
+        loop {
+            let Some(kind) = self.peek_kind() else {
                 break;
+            };
+            let line = match kind {
+                TokenKind::Newline | TokenKind::Comment(_) => {
+                    let _ = self.next_token();
+                    continue;
+                }
+                k if Self::is_section_start(&k) => break,
+                TokenKind::Keyword(Keyword::Input) => self
+                    .next_token()
+                    .and_then(|_| self.parse_field_list())
+                    .map(|f| inputs = f),
+                TokenKind::Keyword(Keyword::Output) => self
+                    .next_token()
+                    .and_then(|_| self.parse_field_list())
+                    .map(|f| outputs = Some(f)),
+                TokenKind::Keyword(Keyword::Uniform) => self.parse_uniforms().map(|u| uniforms = u),
+                _ => self
+                    .next_token()
+                    .and_then(|tok| Err(ParseError::expected(&tok, "INPUT, OUTPUT or UNIFORM"))),
+            };
+            match line {
+                Ok(()) => self.consume_newlines()?,
+                Err(e) => self.recover(e),
             }
         }
-        Ok(frames)
+
+        let section = format!("KERNEL \"{}\"", name);
+        if outputs.is_none() {
+            self.missing("OUTPUT", &section, kernel_pos);
+        }
+        let mut kernel = Kernel {
+            name,
+            path,
+            source: String::new(),
+            inputs,
+            outputs: outputs.unwrap_or_default(),
+            uniforms,
+            workgroup_size: 0,
+            uniform_size: 0,
+            span: self.span_from(&kernel_tok),
+        };
+        if !kernel.outputs.is_empty() {
+            if let Err(e) = kernel.load(&self.base_dir) {
+                self.errors.push(ParseError::new(
+                    format!("{}: {}", section, e),
+                    path_tok.line,
+                    path_tok.column,
+                ));
+            }
+        }
+        Ok(kernel)
     }
 
-    /* ---------------------- Action-section parsing --------------------- */
+    /// `UNIFORM name = value, ...`
+    fn parse_uniforms(&mut self) -> Result<Vec<Uniform>, ParseError> {
+        self.expect_keyword(Keyword::Uniform)?;
+        let mut uniforms = Vec::new();
+        loop {
+            let name = self.expect_identifier()?;
+            let eq_tok = self.next_token()?;
+            if eq_tok.kind != TokenKind::Equals {
+                return Err(ParseError::expected(&eq_tok, "'='"));
+            }
+            uniforms.push(Uniform {
+                name,
+                value: self.expect_number("UNIFORM value")?,
+                ty: UniformType::F32,
+                offset: 0,
+            });
+            match self.peek_token() {
+                Some(Ok(tok)) if matches!(tok.kind, TokenKind::Comma) => {
+                    self.next_token()?;
+                }
+                _ => break,
+            }
+        }
+        Ok(uniforms)
+    }
 
-    fn parse_action_section(&mut self) -> Result<ActionSection, ParseError> {
-        self.expect_keyword(Keyword::Pull)?;
-        let fields = self.parse_field_list()?;
+    /* ----------------------------- FRAMES ------------------------------- */
+
+    fn parse_frame_block(&mut self) -> Result<(String, Frame), ParseError> {
+        let frame_tok = self.expect_keyword(Keyword::Frame)?;
+        let frame_pos = (frame_tok.line, frame_tok.column);
+        let frame_name = self.expect_identifier()?;
         self.consume_newlines()?;
 
+        let mut provider: Option<String> = None;
+        let mut joins = Vec::new();
+        let mut adjust = Adjustments::default();
+        let mut fill = FillPolicy::default();
+        let mut fields: Option<Vec<String>> = None;
         let mut calcs = Vec::new();
-        while let Some(Ok(tok)) = self.peek_token() {
-            if let TokenKind::Keyword(Keyword::Calc) = tok.kind {
-                calcs.push(self.parse_calc()?);
-                self.consume_newlines()?;
-            } else {
+
+        loop {
+            let Some(kind) = self.peek_kind() else {
                 break;
+            };
+            let line = match kind {
+                TokenKind::Newline | TokenKind::Comment(_) => {
+                    let _ = self.next_token();
+                    continue;
+                }
+                TokenKind::Keyword(Keyword::Provider) if provider.is_none() => self
+                    .next_token()
+                    .and_then(|_| self.parse_field_list())
+                    .map(|mut p| {
+                        joins = p.split_off(1);
+                        provider = p.pop();
+                    }),
+                k if Self::is_section_start(&k) => break,
+                TokenKind::Keyword(Keyword::Pull) => self
+                    .next_token()
+                    .and_then(|_| self.parse_field_list())
+                    .map(|f| fields = Some(f)),
+                TokenKind::Keyword(Keyword::Calc) => self.parse_calc().map(|c| calcs.push(c)),
+                TokenKind::Keyword(Keyword::Adjust) => self.parse_adjust().map(|a| adjust = a),
+                TokenKind::Keyword(Keyword::Fill) => self.parse_fill().map(|f| fill = f),
+                _ => self.next_token().and_then(|tok| {
+                    Err(ParseError::expected(
+                        &tok,
+                        "PROVIDER, ADJUST, FILL, PULL or CALC",
+                    ))
+                }),
+            };
+            match line {
+                Ok(()) => self.consume_newlines()?,
+                Err(e) => self.recover(e),
             }
         }
 
+        let section = format!("FRAME \"{}\"", frame_name);
+        if provider.is_none() {
+            self.missing("PROVIDER", &section, frame_pos);
+        }
+        if fields.is_none() {
+            self.missing("PULL", &section, frame_pos);
+        }
+        let fields = fields.unwrap_or_default();
+
         // Dependency ordering with relaxed behavior:
         // - numeric literals count as available
         // - if stalled, push unknowns last (no error)
@@ -511,11 +1072,62 @@ impl<'a> Parser<'a> {
                 fields: fields.clone(),
                 calc: Some(calcs),
             };
-            Some(order_calcs_flat(&tmp)?)
+            match order_calcs_flat(&tmp) {
+                Ok(ordered) => Some(ordered),
+                Err(e) => {
+                    self.errors.push(e);
+                    tmp.calc
+                }
+            }
         };
 
-        self.consume_newlines()?;
-        Ok(ActionSection { fields, calc })
+        Ok((
+            frame_name,
+            Frame {
+                provider: provider.unwrap_or_default(),
+                joins,
+                adjust,
+                fill,
+                actions: ActionSection { fields, calc },
+                span: self.span_from(&frame_tok),
+            },
+        ))
+    }
+
+    /* ---------------------- Action-section parsing --------------------- */
+
+    fn parse_adjust(&mut self) -> Result<Adjustments, ParseError> {
+        self.expect_keyword(Keyword::Adjust)?;
+        let mut adjust = Adjustments::default();
+        loop {
+            let tok = self.next_token()?;
+            match &tok.kind {
+                TokenKind::Identifier(s) if s.eq_ignore_ascii_case("splits") => {
+                    adjust.splits = true
+                }
+                TokenKind::Identifier(s) if s.eq_ignore_ascii_case("dividends") => {
+                    adjust.dividends = true
+                }
+                _ => return Err(ParseError::expected(&tok, "splits or dividends")),
+            }
+            match self.peek_token() {
+                Some(Ok(tok)) if matches!(tok.kind, TokenKind::Comma) => {
+                    self.next_token()?;
+                }
+                _ => break,
+            }
+        }
+        Ok(adjust)
+    }
+
+    fn parse_fill(&mut self) -> Result<FillPolicy, ParseError> {
+        self.expect_keyword(Keyword::Fill)?;
+        let tok = self.next_token()?;
+        match &tok.kind {
+            TokenKind::Identifier(s) => FillPolicy::from_str(s),
+            _ => None,
+        }
+        .ok_or_else(|| ParseError::expected(&tok, "forward, backward, zero, drop or none"))
     }
 
     fn parse_field_list(&mut self) -> Result<Vec<String>, ParseError> {
@@ -534,7 +1146,7 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_calc(&mut self) -> Result<Calc, ParseError> {
-        self.expect_keyword(Keyword::Calc)?;
+        let calc_tok = self.expect_keyword(Keyword::Calc)?;
         let inputs = self.parse_field_list()?;
 
         let op_tok = self.next_token()?;
@@ -550,244 +1162,368 @@ impl<'a> Parser<'a> {
                     | Keyword::DoubleVolatility
                     | Keyword::Constant
                     | Keyword::LinearRegression
+                    | Keyword::Kernel
                 )
             ) => k,
             _ => {
                 return Err(ParseError::expected(
                     &op_tok,
-                    "CALC op (DIFFERENCE|SUM|MULTIPLY|DIVIDE|SMA|VOLATILITY|DOUBLE_VOLATILITY|CONSTANT|LINEAR_REGRESSION)",
+                    "CALC op (DIFFERENCE|SUM|MULTIPLY|DIVIDE|SMA|VOLATILITY|DOUBLE_VOLATILITY|CONSTANT|LINEAR_REGRESSION|KERNEL)",
                 ))
             }
         };
+        let kernel = match operation {
+            Keyword::Kernel => Some(self.parse_kernel_call(&inputs)?),
+            _ => None,
+        };
+        let centered = self.peek_kind() == Some(TokenKind::Keyword(Keyword::Centered));
+        if centered {
+            let tok = self.next_token()?;
+            if !is_windowed(&operation) {
+                return Err(ParseError::new(
+                    format!(
+                        "CENTERED only applies to SMA, VOLATILITY and DOUBLE_VOLATILITY, not {}",
+                        operation.as_str()
+                    ),
+                    tok.line,
+                    tok.column,
+                ));
+            }
+        }
 
         self.expect_keyword(Keyword::Called)?;
         let alias = self.expect_identifier()?;
         Ok(Calc {
             inputs,
             operation,
+            kernel,
+            centered,
             alias,
+            span: self.span_from(&calc_tok),
         })
     }
 
+    /// The KERNEL named after `CALC inputs KERNEL`: a declared one, taking the columns in
+    /// `inputs` and then numbers for its UNIFORMs.
+    fn parse_kernel_call(&mut self, inputs: &[String]) -> Result<Kernel, ParseError> {
+        let name_tok = self.next_token()?;
+        let TokenKind::Identifier(name) = &name_tok.kind else {
+            return Err(ParseError::expected(&name_tok, "KERNEL name"));
+        };
+        let err = |msg: String| ParseError::new(msg, name_tok.line, name_tok.column);
+        let kernel = self
+            .kernels
+            .get(name)
+            .ok_or_else(|| err(format!("unknown KERNEL \"{}\" (declare it above)", name)))?;
+        let columns = inputs.iter().filter(|i| !is_numeric_literal(i)).count();
+        let values = inputs.len() - columns;
+        if inputs[..columns].iter().any(|i| is_numeric_literal(i)) {
+            return Err(err(format!(
+                "KERNEL {} takes its columns first, then UNIFORM values",
+                name
+            )));
+        }
+        if columns != kernel.inputs.len() || values > kernel.uniforms.len() {
+            return Err(err(format!(
+                "KERNEL {} takes {} column(s) and up to {} UNIFORM value(s), got {} and {}",
+                name,
+                kernel.inputs.len(),
+                kernel.uniforms.len(),
+                columns,
+                values
+            )));
+        }
+        Ok(kernel.clone())
+    }
+
     /* ----------------------------- GRAPH -------------------------------- */
 
-    fn parse_graph_section(&mut self) -> Result<Option<GraphSection>, ParseError> {
+    fn parse_graph_section(&mut self) -> Option<GraphSection> {
+        let graph_tok = match self.expect_keyword(Keyword::Graph) {
+            Ok(tok) => tok,
+            Err(e) => {
+                self.recover(e);
+                return None;
+            }
+        };
+        let graph_pos = (graph_tok.line, graph_tok.column);
+
+        let mut xaxis: Option<String> = None;
         let mut commands = Vec::new();
-        self.consume_newlines()?;
 
-        let Some(Ok(tok)) = self.peek_token() else {
-            return Ok(None);
-        };
-        if tok.kind != TokenKind::Keyword(Keyword::Graph) {
-            return Ok(None);
+        loop {
+            let Some(kind) = self.peek_kind() else {
+                break;
+            };
+            let line = match kind {
+                TokenKind::Newline | TokenKind::Comment(_) => {
+                    let _ = self.next_token();
+                    continue;
+                }
+                k if Self::is_section_start(&k) => break,
+                TokenKind::Keyword(Keyword::Xaxis) => self
+                    .next_token()
+                    .and_then(|_| self.expect_identifier())
+                    .map(|x| xaxis = Some(x)),
+                TokenKind::Keyword(Keyword::Line) => {
+                    self.parse_line_command().map(|c| commands.extend(c))
+                }
+                TokenKind::Keyword(Keyword::Bar) => {
+                    self.parse_bar_command().map(|c| commands.push(c))
+                }
+                TokenKind::Keyword(Keyword::Candle) => {
+                    self.parse_candle_command().map(|c| commands.push(c))
+                }
+                _ => self
+                    .next_token()
+                    .and_then(|tok| Err(ParseError::expected(&tok, "XAXIS, LINE, BAR or CANDLE"))),
+            };
+            if let Err(e) = line {
+                self.recover(e);
+            }
         }
 
-        self.next_token()?; // GRAPH
-        self.consume_newlines()?;
+        if xaxis.is_none() {
+            self.missing("XAXIS", "GRAPH", graph_pos);
+        }
 
-        self.expect_keyword(Keyword::Xaxis)?;
-        let xaxis = self.expect_identifier()?;
-        self.consume_newlines()?;
+        Some(GraphSection {
+            xaxis: xaxis.unwrap_or_default(),
+            commands,
+            span: self.span_from(&graph_tok),
+        })
+    }
+
+    fn parse_line_command(&mut self) -> Result<Vec<DrawCommand>, ParseError> {
+        let line_tok = self.expect_keyword(Keyword::Line)?;
+        let fields = self.parse_field_list()?;
+        self.expect_keyword(Keyword::For)?;
+        let frame = self.expect_identifier()?;
+        let span = self.span_from(&line_tok);
+        Ok(fields
+            .iter()
+            .map(|field| DrawCommand::Line {
+                name: field.clone(),
+                series: fields.clone(),
+                frame: frame.clone(),
+                span,
+            })
+            .collect())
+    }
+
+    fn parse_bar_command(&mut self) -> Result<DrawCommand, ParseError> {
+        let bar_tok = self.expect_keyword(Keyword::Bar)?;
+        let y = self.expect_identifier()?;
+        self.expect_keyword(Keyword::For)?;
+        let frame = self.expect_identifier()?;
+        Ok(DrawCommand::Bar {
+            name: y.clone(),
+            y,
+            frame,
+            span: self.span_from(&bar_tok),
+        })
+    }
+
+    fn parse_candle_command(&mut self) -> Result<DrawCommand, ParseError> {
+        let candle_tok = self.expect_keyword(Keyword::Candle)?;
+        let open = self.expect_identifier()?;
+        self._expect_comma_or_newline()?;
+        let high = self.expect_identifier()?;
+        self._expect_comma_or_newline()?;
+        let low = self.expect_identifier()?;
+        self._expect_comma_or_newline()?;
+        let close = self.expect_identifier()?;
+        self.expect_keyword(Keyword::For)?;
+        let frame = self.expect_identifier()?;
+        Ok(DrawCommand::Candle {
+            name: "Candle".to_string(),
+            open,
+            high,
+            low,
+            close,
+            frame,
+            span: self.span_from(&candle_tok),
+        })
+    }
+
+    /* ------------------------------ TRADE ------------------------------- */
+
+    fn parse_trade_section(&mut self) -> Option<TradeSection> {
+        let trade_tok = match self.expect_keyword(Keyword::Trade) {
+            Ok(tok) => tok,
+            Err(e) => {
+                self.recover(e);
+                return None;
+            }
+        };
+        let trade_pos = (trade_tok.line, trade_tok.column);
+
+        let mut trade_type: Option<TradeType> = None;
+        let mut over_frame: Option<(String, Span)> = None;
+        let mut entry: Option<(Vec<String>, f64, Span)> = None;
+        let mut exit: Option<(Vec<String>, f64, Span)> = None;
+        let mut stop_loss: Option<f64> = None;
+        let mut hold: Option<Hold> = None;
 
         loop {
-            match self.peek_token() {
-                Some(Ok(tok)) => match &tok.kind {
-                    TokenKind::Keyword(Keyword::Line) => {
-                        self.next_token()?;
-                        let fields = self.parse_field_list()?;
-                        self.expect_keyword(Keyword::For)?;
-                        let frame = self.expect_identifier()?;
-                        for field in &fields {
-                            commands.push(DrawCommand::Line {
-                                name: field.clone(),
-                                series: fields.clone(),
-                                frame: frame.clone(),
-                            });
-                        }
-                    }
-                    TokenKind::Keyword(Keyword::Bar) => {
-                        self.next_token()?;
-                        let y = self.expect_identifier()?;
-                        self.expect_keyword(Keyword::For)?;
-                        let frame = self.expect_identifier()?;
-                        commands.push(DrawCommand::Bar {
-                            name: y.clone(),
-                            y,
-                            frame,
-                        });
-                    }
-                    TokenKind::Keyword(Keyword::Candle) => {
-                        self.next_token()?;
-                        let open = self.expect_identifier()?;
-                        self._expect_comma_or_newline()?;
-                        let high = self.expect_identifier()?;
-                        self._expect_comma_or_newline()?;
-                        let low = self.expect_identifier()?;
-                        self._expect_comma_or_newline()?;
-                        let close = self.expect_identifier()?;
-                        self.expect_keyword(Keyword::For)?;
-                        let frame = self.expect_identifier()?;
-                        commands.push(DrawCommand::Candle {
-                            name: "Candle".to_string(),
-                            open,
-                            high,
-                            low,
-                            close,
-                            frame,
-                        });
-                    }
-                    TokenKind::Newline => {
-                        self.next_token()?;
-                    }
-                    _ => break,
-                },
-                _ => break,
+            let Some(kind) = self.peek_kind() else {
+                break;
+            };
+            let line = match kind {
+                TokenKind::Newline | TokenKind::Comment(_) => {
+                    let _ = self.next_token();
+                    continue;
+                }
+                k if Self::is_section_start(&k) => break,
+                TokenKind::Keyword(Keyword::OptionCall) => {
+                    self.next_token().map(|_| trade_type = Some(TradeType::OptionCall))
+                }
+                TokenKind::Keyword(Keyword::OptionPut) => {
+                    self.next_token().map(|_| trade_type = Some(TradeType::OptionPut))
+                }
+                TokenKind::Keyword(Keyword::Stock) => {
+                    self.next_token().map(|_| trade_type = Some(TradeType::Stock))
+                }
+                TokenKind::Keyword(Keyword::OverFrame) => self.next_token().and_then(|tok| {
+                    let frame = self.expect_identifier()?;
+                    over_frame = Some((frame, self.span_from(&tok)));
+                    Ok(())
+                }),
+                TokenKind::Keyword(Keyword::Entry) => self
+                    .parse_trade_rule(Keyword::Entry, "within_entry")
+                    .map(|r| entry = Some(r)),
+                TokenKind::Keyword(Keyword::Exit) => self
+                    .parse_trade_rule(Keyword::Exit, "within_exit")
+                    .map(|r| exit = Some(r)),
+                TokenKind::Keyword(Keyword::Limit) => self
+                    .next_token()
+                    .and_then(|_| self.expect_number::<f64>("stop_loss"))
+                    .map(|v| stop_loss = Some(v)),
+                TokenKind::Keyword(Keyword::Hold) => self.parse_hold().map(|h| hold = Some(h)),
+                _ => self.next_token().and_then(|tok| {
+                    Err(ParseError::expected(
+                        &tok,
+                        "trade type (OPTION CALL|OPTION PUT|STOCK), OVERFRAME, ENTRY, EXIT, LIMIT or HOLD",
+                    ))
+                }),
+            };
+            if let Err(e) = line {
+                self.recover(e);
+            }
+        }
+
+        if trade_type.is_none() {
+            self.missing(
+                "trade type (OPTION CALL|OPTION PUT|STOCK)",
+                "TRADE",
+                trade_pos,
+            );
+        }
+        for (what, present) in [
+            ("OVERFRAME", over_frame.is_some()),
+            ("ENTRY", entry.is_some()),
+            ("EXIT", exit.is_some()),
+            ("LIMIT", stop_loss.is_some()),
+            ("HOLD", hold.is_some()),
+        ] {
+            if !present {
+                self.missing(what, "TRADE", trade_pos);
             }
         }
 
-        Ok(Some(GraphSection { xaxis, commands }))
+        let (entry, within_entry, entry_span) = entry.unwrap_or_default();
+        let (exit, within_exit, exit_span) = exit.unwrap_or_default();
+        let (over_frame, over_frame_span) = over_frame.unwrap_or_default();
+        Some(TradeSection {
+            trade_type: trade_type.unwrap_or(TradeType::Stock),
+            over_frame,
+            entry,
+            within_entry,
+            exit,
+            within_exit,
+            stop_loss: stop_loss.unwrap_or_default(),
+            hold: hold.unwrap_or_default(),
+            span: self.span_from(&trade_tok),
+            over_frame_span,
+            entry_span,
+            exit_span,
+        })
     }
 
-    /* ------------------------------ TRADE ------------------------------- */
+    /// `HOLD 14` bars, `HOLD 10d` trading days or `HOLD 2h` of trading time.
+    fn parse_hold(&mut self) -> Result<Hold, ParseError> {
+        self.expect_keyword(Keyword::Hold)?;
+        let Some(TokenKind::Duration(d)) = self.peek_kind() else {
+            return self.expect_number::<usize>("hold").map(Hold::Bars);
+        };
+        let tok = self.next_token()?;
+        let invalid = || ParseError::new("invalid hold", tok.line, tok.column);
+        match d.strip_suffix('d') {
+            Some(days) => days.parse().map(Hold::Sessions).map_err(|_| invalid()),
+            None => duration_to_seconds(&d)
+                .map(Hold::TradingTime)
+                .map_err(|_| invalid()),
+        }
+    }
 
-    fn parse_trade_section(&mut self) -> Result<Option<TradeSection>, ParseError> {
-        self.consume_newlines()?;
-        match self.peek_token() {
-            Some(Ok(tok)) if matches!(tok.kind, TokenKind::Keyword(Keyword::Trade)) => {
-                self.next_token()?; // TRADE
-                self.consume_newlines()?;
-
-                let trade_type = match self.next_token()? {
-                    Token {
-                        kind: TokenKind::Keyword(Keyword::OptionCall),
-                        ..
-                    } => TradeType::OptionCall,
-                    Token {
-                        kind: TokenKind::Keyword(Keyword::OptionPut),
-                        ..
-                    } => TradeType::OptionPut,
-                    Token {
-                        kind: TokenKind::Keyword(Keyword::Stock),
-                        ..
-                    } => TradeType::Stock,
-                    tok => {
-                        return Err(ParseError::expected(
-                            &tok,
-                            "trade type (OPTION CALL|OPTION PUT|STOCK)",
-                        ))
-                    }
-                };
-                self.consume_newlines()?;
-
-                self.expect_keyword(Keyword::OverFrame)?;
-                let over_frame = self.expect_identifier()?;
-                self.consume_newlines()?;
-
-                self.expect_keyword(Keyword::Entry)?;
-                let mut entry = Vec::new();
-                loop {
-                    match self.peek_token() {
-                        Some(Ok(tok)) if matches!(tok.kind, TokenKind::Identifier(_)) => {
-                            entry.push(self.expect_identifier()?)
-                        }
-                        Some(Ok(tok)) if matches!(tok.kind, TokenKind::Comma) => {
-                            self.next_token()?;
-                        }
-                        Some(Ok(tok)) if matches!(tok.kind, TokenKind::Literal(_)) => {
-                            entry.push(self.expect_literal()?)
-                        }
-                        _ => break,
-                    }
+    /// `ENTRY a.x, b.y, <threshold>` / `EXIT ...`: columns followed by a trailing threshold.
+    fn parse_trade_rule(
+        &mut self,
+        kw: Keyword,
+        what: &str,
+    ) -> Result<(Vec<String>, f64, Span), ParseError> {
+        let kw_tok = self.expect_keyword(kw)?;
+        let mut items = Vec::new();
+        let mut last_pos = (kw_tok.line, kw_tok.column);
+        loop {
+            match self.peek_token() {
+                Some(Ok(tok)) if matches!(tok.kind, TokenKind::Identifier(_)) => {
+                    last_pos = (tok.line, tok.column);
+                    items.push(self.expect_identifier()?)
                 }
-                let within_entry = entry
-                    .pop()
-                    .ok_or_else(|| ParseError::eof("missing within_entry"))?
-                    .parse::<f64>()
-                    .map_err(|_| ParseError::eof("invalid within_entry"))?;
-                self.consume_newlines()?;
-
-                self.expect_keyword(Keyword::Exit)?;
-                let mut exit = Vec::new();
-                loop {
-                    match self.peek_token() {
-                        Some(Ok(tok)) if matches!(tok.kind, TokenKind::Identifier(_)) => {
-                            exit.push(self.expect_identifier()?)
-                        }
-                        Some(Ok(tok)) if matches!(tok.kind, TokenKind::Comma) => {
-                            self.next_token()?;
-                        }
-                        Some(Ok(tok)) if matches!(tok.kind, TokenKind::Literal(_)) => {
-                            exit.push(self.expect_literal()?)
-                        }
-                        _ => break,
-                    }
+                Some(Ok(tok)) if matches!(tok.kind, TokenKind::Comma) => {
+                    self.next_token()?;
                 }
-                let within_exit = exit
-                    .pop()
-                    .ok_or_else(|| ParseError::eof("missing within_exit"))?
-                    .parse::<f64>()
-                    .map_err(|_| ParseError::eof("invalid within_exit"))?;
-                self.consume_newlines()?;
-
-                self.expect_keyword(Keyword::Limit)?;
-                let stop_loss = match self.next_token()? {
-                    Token {
-                        kind: TokenKind::Literal(lit),
-                        ..
-                    }
-                    | Token {
-                        kind: TokenKind::Identifier(lit),
-                        ..
-                    } => lit
-                        .parse::<f64>()
-                        .map_err(|_| ParseError::eof("invalid stop_loss"))?,
-                    tok => return Err(ParseError::expected(&tok, "stop_loss value")),
-                };
-                self.consume_newlines()?;
-
-                self.expect_keyword(Keyword::Hold)?;
-                let hold = match self.next_token()? {
-                    Token {
-                        kind: TokenKind::Literal(lit),
-                        ..
-                    }
-                    | Token {
-                        kind: TokenKind::Identifier(lit),
-                        ..
-                    } => lit
-                        .parse::<i32>()
-                        .map_err(|_| ParseError::eof("invalid hold"))?,
-                    tok => return Err(ParseError::expected(&tok, "hold value")),
-                };
-                self.consume_newlines()?;
-
-                Ok(Some(TradeSection {
-                    trade_type,
-                    over_frame,
-                    entry,
-                    within_entry,
-                    exit,
-                    within_exit,
-                    stop_loss,
-                    hold,
-                }))
+                _ => break,
             }
-            _ => Ok(None),
         }
+        let threshold = items
+            .pop()
+            .ok_or_else(|| ParseError::new(format!("missing {}", what), last_pos.0, last_pos.1))?
+            .parse::<f64>()
+            .map_err(|_| ParseError::new(format!("invalid {}", what), last_pos.0, last_pos.1))?;
+        Ok((items, threshold, self.span_from(&kw_tok)))
     }
 }
 
 /* ------------------------ TimeSpec helpers -------------------------- */
 
-fn yyyymmdd_to_iso8601_z(s: &str) -> Result<String, &'static str> {
-    if s.len() != 8 || !s.chars().all(|c| c.is_ascii_digit()) {
-        return Err("invalid date literal (expected YYYYMMDD)");
-    }
-    let (y, m, d) = (&s[0..4], &s[4..6], &s[6..8]);
-    if !(m >= "01" && m <= "12") || !(d >= "01" && d <= "31") {
-        return Err("invalid date components");
+fn date_to_iso8601_z(s: &str) -> Result<String, &'static str> {
+    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map(|d| d.format("%Y-%m-%dT00:00:00Z").to_string())
+        .map_err(|_| "invalid date (expected YYYY-MM-DD)")
+}
+
+/// `YYYY-MM-DD` -> epoch seconds at midnight UTC.
+pub fn date_to_epoch(s: &str) -> Result<i64, String> {
+    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp())
+        .map_err(|_| format!("invalid date '{}' (expected YYYY-MM-DD)", s))
+}
+
+/// A duration literal (`30s`, `5m`, `1h`, `2d`) in seconds.
+pub fn duration_to_seconds(s: &str) -> Result<i64, String> {
+    let unit = match s.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 3_600,
+        Some('d') => 86_400,
+        _ => return Err(format!("invalid duration '{}'", s)),
+    };
+    match s[..s.len() - 1].parse::<i64>() {
+        Ok(n) if n > 0 => Ok(n * unit),
+        _ => Err(format!("invalid duration '{}'", s)),
     }
-    Ok(format!("{y}-{m}-{d}T00:00:00Z"))
 }
 
 /// Parse a QQL source string and get the AST.
@@ -795,6 +1531,11 @@ pub fn parse(src: &str) -> Result<Query, ParseError> {
     Parser::new(src).parse()
 }
 
+/// Parse a QQL source string, collecting every error instead of stopping at the first.
+pub fn parse_recovering(src: &str) -> ParseOutcome {
+    Parser::new(src).parse_recovering()
+}
+
 /* ================= Dependency ordering (relaxed) ================= */
 
 fn is_numeric_literal(s: &str) -> bool {
@@ -810,13 +1551,17 @@ fn is_numeric_literal(s: &str) -> bool {
     s.parse::<f64>().is_ok()
 }
 
-fn calc_outputs(c: &Calc) -> Vec<String> {
+pub(crate) fn calc_outputs(c: &Calc) -> Vec<String> {
     match c.operation {
         Keyword::Volatility | Keyword::DoubleVolatility => vec![
             c.alias.clone(),
             format!("{}_pos", c.alias),
             format!("{}_neg", c.alias),
         ],
+        Keyword::Kernel => match &c.kernel {
+            Some(kernel) => kernel.output_columns(&c.alias),
+            None => vec![c.alias.clone()],
+        },
         _ => vec![c.alias.clone()],
     }
 }
@@ -833,8 +1578,8 @@ pub fn order_calcs_by_waves(action: &ActionSection) -> Result<Vec<Vec<Calc>>, Pa
             if !seen.insert(&c.alias) {
                 return Err(ParseError::new(
                     format!("duplicate CALC alias: '{}'", c.alias),
-                    0,
-                    0,
+                    c.span.line,
+                    c.span.column,
                 ));
             }
         }
@@ -875,6 +1620,101 @@ pub fn order_calcs_flat(action: &ActionSection) -> Result<Vec<Calc>, ParseError>
         .collect())
 }
 
+/* ========================== Look-ahead ========================== */
+
+/// CALC operations over a window of bars, which can be CENTERED.
+pub fn is_windowed(operation: &Keyword) -> bool {
+    matches!(
+        operation,
+        Keyword::Sma | Keyword::Volatility | Keyword::DoubleVolatility
+    )
+}
+
+/// A TRADE rule reading a column whose value at a bar depends on later bars, so a
+/// backtest over it trades on the future.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LookAhead {
+    /// `ENTRY` or `EXIT`
+    pub rule: &'static str,
+    /// the column as the rule names it, `frame.column`
+    pub column: String,
+    /// the CALC that looks ahead, and how
+    pub reason: String,
+    pub span: Span,
+}
+
+impl std::fmt::Display for LookAhead {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "TRADE {} reads {}, which sees later bars: {}",
+            self.rule, self.column, self.reason
+        )
+    }
+}
+
+/// The TRADE's ENTRY and EXIT columns that see bars after the one they're on: outputs of
+/// CENTERED windows and LINEAR_REGRESSION (one line fit over all the bars), and CALCs
+/// over those. KERNELs are taken at their word.
+pub fn look_ahead(query: &Query) -> Vec<LookAhead> {
+    let Some(trade) = &query.trade else {
+        return Vec::new();
+    };
+    let rules = [
+        ("ENTRY", &trade.entry, trade.entry_span),
+        ("EXIT", &trade.exit, trade.exit_span),
+    ];
+    let mut found = Vec::new();
+    for (rule, columns, span) in rules {
+        for column in columns.iter() {
+            let Some((frame, name)) = column.split_once('.') else {
+                continue;
+            };
+            let Some(frame) = query.frame.get(frame) else {
+                continue;
+            };
+            let calcs = frame.actions.calc.as_deref().unwrap_or_default();
+            if let Some(reason) = peeks(calcs, name, calcs.len()) {
+                found.push(LookAhead {
+                    rule,
+                    column: column.clone(),
+                    reason,
+                    span,
+                });
+            }
+        }
+    }
+    found
+}
+
+/// Why `column` looks ahead, following the CALCs it comes from at most `depth` deep.
+fn peeks(calcs: &[Calc], column: &str, depth: usize) -> Option<String> {
+    let calc = calcs
+        .iter()
+        .find(|c| calc_outputs(c).iter().any(|out| out == column))?;
+    if calc.centered {
+        return Some(format!(
+            "CALC {} is a CENTERED {}",
+            calc.alias,
+            calc.operation.as_str()
+        ));
+    }
+    if calc.operation == Keyword::LinearRegression {
+        return Some(format!(
+            "CALC {} fits one line over every bar of the frame",
+            calc.alias
+        ));
+    }
+    if depth == 0 {
+        return None;
+    }
+    calc.inputs
+        .iter()
+        .filter(|input| !is_numeric_literal(input))
+        .find_map(|input| peeks(calcs, input, depth - 1))
+        .map(|reason| format!("{} reads {}", calc.alias, reason))
+}
+
 /* ======================= UNIT TESTS (abridged) ======================= */
 
 #[cfg(test)]
@@ -909,4 +1749,202 @@ mod tests {
             "provider yahoo_finance search ticker=NVDA date=2025-09-05T00:00:00Z..2025-10-05T00:00:00Z"
         );
     }
+
+    #[test]
+    fn test_parse_recovering_collects_errors_and_keeps_partial_query() {
+        let src = indoc! {r#"
+            PROVIDER aapl_data
+                PROVIDER yahoo_finance
+                TICKER aapl
+                FROM 20200101 TO 20250901
+
+            FRAME aapl
+                PROVIDER aapl_data
+                PULL open, close, low, high
+                CALC open, close BOGUS CALLED oc_diff
+                CALC high SMA CALLED h_sma
+
+            GRAPH
+                XAXIS aapl
+                LINE FOR aapl
+                LINE h_sma FOR aapl
+
+            TRADE
+                STOCK
+                OVERFRAME aapl
+                ENTRY aapl.low, aapl.h_sma, 0.05
+                EXIT aapl.high, aapl.h_sma, 0.05
+                LIMIT 0.1
+        "#};
+
+        let out = parse_recovering(src);
+        assert_eq!(out.errors.len(), 3, "{:#?}", out.errors);
+        assert_eq!(out.errors[0].line, 9);
+        assert_eq!(out.errors[1].line, 14);
+        assert!(out.errors[2].message.contains("HOLD"));
+
+        let q = out.query;
+        assert!(q.providers.contains_key("aapl_data"));
+        let frame = q.frame.get("aapl").unwrap();
+        assert_eq!(frame.actions.calc.as_ref().unwrap().len(), 1);
+        assert_eq!(q.graph.unwrap().commands.len(), 1);
+        assert_eq!(q.trade.unwrap().over_frame, "aapl");
+
+        assert!(parse(src).is_err());
+    }
+
+    #[test]
+    fn test_parse_recovering_skips_unknown_top_level_lines() {
+        let src = indoc! {r#"
+            PROIVDER aapl_data
+            FRAME first
+                PROVIDER aapl_data
+                -- commented out line
+                PULL open, close
+            GRAPH
+                XAXIS first
+            --  LINE open FOR first
+                LINE close FOR first
+        "#};
+
+        let out = parse_recovering(src);
+        assert_eq!(out.errors.len(), 1, "{:#?}", out.errors);
+        assert_eq!(out.errors[0].line, 1);
+        assert!(out.query.frame.contains_key("first"));
+        assert_eq!(out.query.graph.unwrap().commands.len(), 1);
+    }
+
+    #[test]
+    fn test_file_params_go_first() {
+        let src = indoc! {r#"
+            -- settings of the file
+            PARAM calcs = LAZY
+            PARAM seed = 7
+            PROVIDER p USING synthetic
+                PARAM seed = 7
+            FRAME f
+                PROVIDER p
+                PULL close
+            PARAM calcs = gpu
+        "#};
+
+        let out = parse_recovering(src);
+        assert_eq!(out.query.calcs, Some(CalcBackend::Lazy));
+        let errors: Vec<(usize, &str)> = out
+            .errors
+            .iter()
+            .map(|e| (e.line, e.message.as_str()))
+            .collect();
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert_eq!(errors[0].0, 3);
+        assert!(errors[0].1.contains("unknown PARAM \"seed\""));
+        // not inside a section either
+        assert_eq!(errors[1].0, 9);
+        assert!(out.query.frame.contains_key("f"));
+    }
+
+    #[test]
+    fn test_look_ahead_in_trade_rules() {
+        let src = r#"
+PROVIDER p USING synthetic
+    FROM 2020-01-01 TO 2020-06-30
+
+FRAME f
+    PROVIDER p
+    PULL open, close
+    CALC close SMA CALLED trail
+    CALC close SMA CENTERED CALLED mid
+    CALC close, mid DIFFERENCE CALLED spread
+    CALC close LINEAR_REGRESSION CALLED fit
+
+TRADE
+    STOCK
+    OVERFRAME f
+    ENTRY f.close, f.spread, 0.05
+    EXIT f.close, f.trail, 0.05
+    LIMIT 0.1
+    HOLD 5
+"#;
+        let query = parse(src).unwrap();
+        assert!(query.frame["f"].actions.calc.as_ref().unwrap()[1].centered);
+        let found = look_ahead(&query);
+        assert_eq!(found.len(), 1, "{found:?}");
+        assert_eq!(
+            (found[0].rule, found[0].column.as_str()),
+            ("ENTRY", "f.spread")
+        );
+        assert_eq!(found[0].reason, "spread reads CALC mid is a CENTERED SMA");
+
+        // trailing windows are fine; a fit over the whole frame is not
+        let query = parse(&src.replace("f.spread", "f.trail")).unwrap();
+        assert!(look_ahead(&query).is_empty());
+        let found = look_ahead(&parse(&src.replace("f.trail", "f.fit")).unwrap());
+        assert_eq!((found.len(), found[1].rule), (2, "EXIT"));
+
+        // and CENTERED is only for windows
+        let err = parse(&src.replace("LINEAR_REGRESSION", "LINEAR_REGRESSION CENTERED"));
+        assert!(err.unwrap_err().message.contains("CENTERED only applies"));
+    }
+
+    #[test]
+    fn test_ast_nodes_carry_spans() {
+        let src = indoc! {r#"
+            PROVIDER aapl_data
+                PROVIDER yahoo_finance
+                TICKER aapl
+                FROM 20200101 TO 20250901
+            FRAME aapl
+                PROVIDER aapl_data
+                PULL open, close
+                CALC close SMA CALLED c_sma
+            GRAPH
+                XAXIS aapl
+                LINE close FOR aapl
+            TRADE
+                STOCK
+                OVERFRAME aapl
+                ENTRY aapl.open, aapl.close, 0.05
+                EXIT aapl.close, aapl.open, 0.05
+                LIMIT 0.1
+                HOLD 5
+        "#};
+
+        let q = parse(src).unwrap();
+        let provider = &q.providers["aapl_data"];
+        assert_eq!((provider.span.line, provider.span.column), (1, 1));
+        assert_eq!(provider.span.end_line, 4);
+
+        let frame = &q.frame["aapl"];
+        assert_eq!((frame.span.line, frame.span.end_line), (5, 8));
+
+        let calc = &frame.actions.calc.as_ref().unwrap()[0];
+        assert_eq!(calc.span, Span::new(8, 5, 8, 32));
+
+        let graph = q.graph.unwrap();
+        assert_eq!(graph.commands[0].span(), Span::new(11, 5, 11, 24));
+
+        let trade = q.trade.unwrap();
+        assert_eq!((trade.span.line, trade.span.end_line), (12, 18));
+        assert_eq!(trade.over_frame_span, Span::new(14, 5, 14, 19));
+        assert_eq!((trade.entry_span.line, trade.exit_span.line), (15, 16));
+
+        // errors about a name defined twice point at the second definition
+        let twice = format!(
+            "{src}PROVIDER aapl_data\n    TICKER msft\nFRAME aapl\n    PROVIDER aapl_data\n    PULL open\n    CALC open SMA CALLED o\n    CALC close SMA CALLED o\n"
+        );
+        let errors: Vec<(usize, usize)> = parse_recovering(&twice)
+            .errors
+            .iter()
+            .map(|e| (e.line, e.column))
+            .collect();
+        assert_eq!(errors, vec![(19, 1), (25, 5), (21, 1)]);
+        let query = parse(src).unwrap();
+        let mut provider = query.providers["aapl_data"].clone();
+        provider.time_spec = Some(TimeSpec::DateRange {
+            from: "2020-02-30".into(),
+            to: "2020-03-01".into(),
+        });
+        let err = provider.search_query().unwrap_err();
+        assert_eq!((err.line, err.column), (1, 1));
+    }
 }
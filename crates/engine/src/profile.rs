@@ -0,0 +1,226 @@
+// profile.rs
+// -----------------------------------------------------------------------------
+// Where an engine run spends its time: nested, named spans recorded on the
+// thread running it (provider fetches, JSON decoding, GPU upload and dispatch,
+// trades), plus each GPU step's own time from timestamp queries when the
+// device has them. Outside a recording, spans cost a thread-local lookup.
+// -----------------------------------------------------------------------------
+
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+/// One timed step of a run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Timing {
+    pub name: String,
+    /// how deep it nests, 0 for the steps of the run itself
+    pub depth: usize,
+    /// microseconds from the start of the run
+    pub start_us: u64,
+    pub duration_us: u64,
+    /// measured on the GPU by a timestamp query, not on the host's clock
+    pub gpu: bool,
+}
+
+/// The timings of a run, in the order they started.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub timings: Vec<Timing>,
+}
+
+impl Profile {
+    /// Wall time of the run: the end of its last step.
+    pub fn total_us(&self) -> u64 {
+        self.timings
+            .iter()
+            .map(|t| t.start_us + t.duration_us)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The timings as a table, one row per step, for the output and the UI.
+    pub fn table(&self) -> PolarsResult<DataFrame> {
+        let name: Vec<String> = self
+            .timings
+            .iter()
+            .map(|t| format!("{}{}", "  ".repeat(t.depth), t.name))
+            .collect();
+        df!(
+            "step" => name,
+            "depth" => self.timings.iter().map(|t| t.depth as u32).collect::<Vec<_>>(),
+            "start_ms" => self.timings.iter().map(|t| t.start_us as f64 / 1e3).collect::<Vec<_>>(),
+            "duration_ms" => self.timings.iter().map(|t| t.duration_us as f64 / 1e3).collect::<Vec<_>>(),
+            "gpu" => self.timings.iter().map(|t| t.gpu).collect::<Vec<_>>(),
+        )
+    }
+}
+
+struct Recorder {
+    origin: Instant,
+    timings: Vec<Timing>,
+    depth: usize,
+}
+
+thread_local! {
+    static RECORDER: RefCell<Option<Recorder>> = const { RefCell::new(None) };
+}
+
+/// Run `f` and record the spans it opens on this thread. A recording already going on
+/// here is set aside until `f` returns.
+pub fn record<T>(f: impl FnOnce() -> T) -> (T, Profile) {
+    let outer = RECORDER.with(|r| {
+        r.borrow_mut().replace(Recorder {
+            origin: Instant::now(),
+            timings: Vec::new(),
+            depth: 0,
+        })
+    });
+    let out = f();
+    let recorder = RECORDER.with(|r| std::mem::replace(&mut *r.borrow_mut(), outer));
+    let timings = recorder.map(|r| r.timings).unwrap_or_default();
+    (out, Profile { timings })
+}
+
+/// Whether spans opened on this thread are being recorded.
+pub fn is_recording() -> bool {
+    RECORDER.with(|r| r.borrow().is_some())
+}
+
+/// Time the scope holding the returned guard as a step named `name`, nested under the
+/// spans open around it.
+pub fn span(name: impl Into<String>) -> SpanGuard {
+    let index = RECORDER.with(|r| {
+        let mut r = r.borrow_mut();
+        let r = r.as_mut()?;
+        r.timings.push(Timing {
+            name: name.into(),
+            depth: r.depth,
+            start_us: r.origin.elapsed().as_micros() as u64,
+            duration_us: 0,
+            gpu: false,
+        });
+        r.depth += 1;
+        Some(r.timings.len() - 1)
+    });
+    SpanGuard { index }
+}
+
+/// Ends its span when dropped.
+pub struct SpanGuard {
+    index: Option<usize>,
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        let Some(index) = self.index else {
+            return;
+        };
+        RECORDER.with(|r| {
+            if let Some(r) = r.borrow_mut().as_mut() {
+                let end = r.origin.elapsed().as_micros() as u64;
+                if let Some(t) = r.timings.get_mut(index) {
+                    t.duration_us = end.saturating_sub(t.start_us);
+                }
+                r.depth = r.depth.saturating_sub(1);
+            }
+        });
+    }
+}
+
+/// Add a step measured on the GPU, `start` on the host clock, under the open spans.
+pub fn gpu(name: impl Into<String>, start: Instant, duration: Duration) {
+    RECORDER.with(|r| {
+        if let Some(r) = r.borrow_mut().as_mut() {
+            r.timings.push(Timing {
+                name: name.into(),
+                depth: r.depth,
+                start_us: start.saturating_duration_since(r.origin).as_micros() as u64,
+                duration_us: duration.as_micros() as u64,
+                gpu: true,
+            });
+        }
+    });
+}
+
+/// Add `profile`, recorded on another thread from `started`, under the open spans.
+pub fn merge(profile: Profile, started: Instant) {
+    RECORDER.with(|r| {
+        if let Some(r) = r.borrow_mut().as_mut() {
+            let offset = started.saturating_duration_since(r.origin).as_micros() as u64;
+            r.timings
+                .extend(profile.timings.into_iter().map(|t| Timing {
+                    depth: t.depth + r.depth,
+                    start_us: t.start_us + offset,
+                    ..t
+                }));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu_service::GpuStatus;
+    use crate::testing::{frame_src, SYNTHETIC};
+    use crate::Engine;
+
+    #[test]
+    fn test_profile_nests_spans() {
+        assert!(!is_recording());
+        let _ignored = span("not recording");
+        let (value, profile) = record(|| {
+            let _run = span("run");
+            {
+                let _fetch = span("fetch");
+                std::thread::sleep(Duration::from_millis(2));
+            }
+            let started = Instant::now();
+            let (_, worker) = std::thread::spawn(|| record(|| drop(span("on the GPU thread"))))
+                .join()
+                .unwrap();
+            merge(worker, started);
+            7
+        });
+        assert_eq!(value, 7);
+        assert!(!is_recording());
+        let steps: Vec<(&str, usize)> = profile
+            .timings
+            .iter()
+            .map(|t| (t.name.as_str(), t.depth))
+            .collect();
+        assert_eq!(steps, [("run", 0), ("fetch", 1), ("on the GPU thread", 1)]);
+        let (run, fetch) = (&profile.timings[0], &profile.timings[1]);
+        assert!(fetch.duration_us >= 2_000);
+        assert!(run.start_us <= fetch.start_us);
+        assert!(run.duration_us >= fetch.duration_us);
+        assert_eq!(profile.total_us(), run.start_us + run.duration_us);
+        assert_eq!(profile.table().unwrap().height(), 3);
+    }
+
+    #[test]
+    fn test_engine_run_is_profiled() {
+        let calcs = "    CALC close, open DIFFERENCE CALLED oc\n    CALC close LINEAR_REGRESSION CALLED fit\n";
+        let src = format!("{SYNTHETIC}\n{}", frame_src("open, close", calcs));
+        let mut engine = Engine::new(&src, "127.0.0.1:7000", Some(true)).unwrap();
+        engine.run().unwrap();
+
+        let profile = engine.profile();
+        let names: Vec<&str> = profile.timings.iter().map(|t| t.name.as_str()).collect();
+        // without a device the CALCs fall back to the CPU, and that is what gets timed
+        let on_device: &[&str] = match engine.gpu.status() {
+            GpuStatus::Ready(_) => &["GPU upload", "GPU download"],
+            _ => &["CPU CALCs"],
+        };
+        for step in ["fetch PROVIDER p", "CALCs FRAME f"]
+            .iter()
+            .chain(on_device)
+        {
+            assert!(names.contains(step), "no '{step}' in {names:?}");
+        }
+        let output = engine.get_output().unwrap();
+        assert_eq!(output.get_profile(), Some(profile));
+        assert!(profile.total_us() > 0);
+    }
+}
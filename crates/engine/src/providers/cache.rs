@@ -0,0 +1,408 @@
+use super::{append_bars, bars_between, fnv1a, param, DataProvider};
+use crate::parser::{date_to_epoch, duration_to_seconds, ProviderInstance, TimeSpec};
+use chrono::{DateTime, Utc};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+const DAY: i64 = 86_400;
+
+/// Cache lookups during the last engine run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural =
+            |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+        write!(
+            f,
+            "cache: {}, {}",
+            plural(self.hits, "hit", "hits"),
+            plural(self.misses, "miss", "misses")
+        )
+    }
+}
+
+/// Days of a series already on disk: `[start, end)` in epoch seconds, fetched at `fetched_at`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Covered {
+    start: i64,
+    end: i64,
+    fetched_at: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    key: String,
+    coverage: Vec<Covered>,
+}
+
+/// On-disk cache of provider frames, one Parquet file per series.
+///
+/// A series is (backend, ticker, params); its file name is a hash of those, and a JSON
+/// manifest next to it records which days it covers. A `FROM .. TO` request fetches
+/// only the days the manifest doesn't cover and merges them in. Today's bars may still
+/// change, so today is never marked as covered.
+///
+/// Per PROVIDER block:
+/// - `PARAM cache = false` skips the cache, `PARAM cache = refresh` refetches everything
+/// - `PARAM cache_ttl = 12h` refetches days fetched longer ago than that
+#[derive(Debug)]
+pub struct ProviderCache {
+    dir: PathBuf,
+    stats: CacheStats,
+}
+
+impl ProviderCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    /// Delete every cached series.
+    pub fn clear(&mut self) -> Result<(), String> {
+        match fs::remove_dir_all(&self.dir) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!(
+                "Failed to clear cache {}: {}",
+                self.dir.display(),
+                e
+            )),
+        }
+    }
+
+    /// `instance`'s bars, from disk where possible and from `provider` otherwise.
+    pub fn fetch(
+        &mut self,
+        instance: &ProviderInstance,
+        provider: &mut dyn DataProvider,
+    ) -> Result<DataFrame, String> {
+        let Some(TimeSpec::DateRange { from, to }) = &instance.time_spec else {
+            return provider.fetch(instance);
+        };
+        let mode = param(instance, "cache")
+            .unwrap_or("true")
+            .to_ascii_lowercase();
+        if mode == "false" {
+            return provider.fetch(instance);
+        }
+        let ttl = param(instance, "cache_ttl")
+            .map(duration_to_seconds)
+            .transpose()?;
+        let (start, end) = (date_to_epoch(from)?, date_to_epoch(to)? + DAY);
+        let now = Utc::now().timestamp();
+
+        let key = series_key(instance);
+        let name = format!("{:016x}", fnv1a(key.as_bytes()));
+        let (bars_path, manifest_path) = (
+            self.dir.join(format!("{}.parquet", name)),
+            self.dir.join(format!("{}.json", name)),
+        );
+
+        let (mut manifest, mut bars) = match mode.as_str() {
+            "refresh" => (Manifest::default(), None),
+            _ => load(&bars_path, &manifest_path, &key).unwrap_or_default(),
+        };
+        if let Some(ttl) = ttl {
+            manifest.coverage.retain(|s| s.fetched_at + ttl > now);
+        }
+
+        let missing = missing_ranges(start, end, &manifest.coverage);
+        if missing.is_empty() {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            let today = now - now.rem_euclid(DAY);
+            for (a, b) in missing {
+                let fetched = provider.fetch(&with_range(instance, a, b))?;
+                let fetched = bars_between(&fetched, a, b)?;
+                bars = Some(match bars {
+                    Some(old) => match merge(&old, &fetched, a, b) {
+                        Ok(merged) => merged,
+                        // the provider changed shape: start the series over
+                        Err(_) => {
+                            manifest.coverage.clear();
+                            fetched
+                        }
+                    },
+                    None => fetched,
+                });
+                if a < today {
+                    manifest.coverage.push(Covered {
+                        start: a,
+                        end: b.min(today),
+                        fetched_at: now,
+                    });
+                }
+            }
+            manifest.key = key;
+            manifest.coverage = merge_coverage(manifest.coverage);
+            if let Some(bars) = bars.as_mut() {
+                if let Err(e) = save(&self.dir, &bars_path, &manifest_path, bars, &manifest) {
+                    log::warn!("Failed to write provider cache: {}", e);
+                }
+            }
+        }
+
+        match bars {
+            Some(bars) => bars_between(&bars, start, end),
+            None => Ok(DataFrame::empty()),
+        }
+    }
+}
+
+/// Everything that decides what a provider returns, except the date range.
+fn series_key(instance: &ProviderInstance) -> String {
+    let mut params: Vec<String> = instance
+        .params
+        .iter()
+        .filter(|(k, _)| !k.eq_ignore_ascii_case("cache") && !k.eq_ignore_ascii_case("cache_ttl"))
+        .map(|(k, v)| format!("{}={}", k.to_ascii_lowercase(), v))
+        .collect();
+    params.sort();
+    format!(
+        "{}\n{}\n{}",
+        instance.backend.as_deref().unwrap_or_default(),
+        instance
+            .ticker
+            .as_deref()
+            .unwrap_or_default()
+            .to_ascii_uppercase(),
+        params.join("\n")
+    )
+}
+
+fn with_range(instance: &ProviderInstance, start: i64, end: i64) -> ProviderInstance {
+    let day = |t: i64| {
+        DateTime::from_timestamp(t, 0)
+            .unwrap_or_default()
+            .format("%Y-%m-%d")
+            .to_string()
+    };
+    ProviderInstance {
+        time_spec: Some(TimeSpec::DateRange {
+            from: day(start),
+            to: day(end - DAY),
+        }),
+        ..instance.clone()
+    }
+}
+
+/// Parts of `[start, end)` not covered by `coverage`.
+fn missing_ranges(start: i64, end: i64, coverage: &[Covered]) -> Vec<(i64, i64)> {
+    let mut covered: Vec<(i64, i64)> = coverage.iter().map(|s| (s.start, s.end)).collect();
+    covered.sort();
+    let mut missing = Vec::new();
+    let mut cursor = start;
+    for (a, b) in covered {
+        if b <= cursor || a >= end {
+            continue;
+        }
+        if a > cursor {
+            missing.push((cursor, a));
+        }
+        cursor = cursor.max(b);
+    }
+    if cursor < end {
+        missing.push((cursor, end));
+    }
+    missing
+}
+
+/// Join overlapping and touching spans, keeping the oldest fetch time so a TTL stays honest.
+fn merge_coverage(mut coverage: Vec<Covered>) -> Vec<Covered> {
+    coverage.sort_by_key(|s| s.start);
+    let mut merged: Vec<Covered> = Vec::new();
+    for span in coverage {
+        match merged.last_mut() {
+            Some(last) if span.start <= last.end => {
+                last.end = last.end.max(span.end);
+                last.fetched_at = last.fetched_at.min(span.fetched_at);
+            }
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+/// `old` with its bars in `[start, end)` replaced by `new`.
+fn merge(old: &DataFrame, new: &DataFrame, start: i64, end: i64) -> Result<DataFrame, String> {
+    if old.width() == 0 {
+        return Ok(new.clone());
+    }
+    let ts = old
+        .column("timestamp")
+        .and_then(|c| c.cast(&DataType::Int64))
+        .map_err(|e| e.to_string())?;
+    let ts = ts.i64().map_err(|e| e.to_string())?;
+    let keep = !(ts.gt_eq(start) & ts.lt(end));
+    let kept = old.filter(&keep).map_err(|e| e.to_string())?;
+    append_bars(&kept, new)
+}
+
+fn load(
+    bars_path: &Path,
+    manifest_path: &Path,
+    key: &str,
+) -> Option<(Manifest, Option<DataFrame>)> {
+    let manifest: Manifest = serde_json::from_str(&fs::read_to_string(manifest_path).ok()?).ok()?;
+    if manifest.key != key {
+        return None;
+    }
+    let bars = ParquetReader::new(File::open(bars_path).ok()?)
+        .finish()
+        .ok()?;
+    Some((manifest, Some(bars)))
+}
+
+fn save(
+    dir: &Path,
+    bars_path: &Path,
+    manifest_path: &Path,
+    bars: &mut DataFrame,
+    manifest: &Manifest,
+) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    // write then rename, so a crash never leaves half a file behind
+    let tmp = bars_path.with_extension("parquet.tmp");
+    let file = File::create(&tmp).map_err(|e| e.to_string())?;
+    ParquetWriter::new(file)
+        .finish(bars)
+        .map_err(|e| e.to_string())?;
+    fs::rename(&tmp, bars_path).map_err(|e| e.to_string())?;
+
+    let json = serde_json::to_string_pretty(manifest).map_err(|e| e.to_string())?;
+    fs::write(manifest_path, json).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheStats, ProviderCache};
+    use crate::parser::{date_to_epoch, parse, ProviderInstance, TimeSpec};
+    use crate::providers::DataProvider;
+    use polars::prelude::*;
+    use std::sync::{Arc, Mutex};
+
+    /// One bar per day, close = day number; logs the ranges it was asked for.
+    #[derive(Debug, Default)]
+    struct Daily {
+        calls: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    impl DataProvider for Daily {
+        fn fetch(&mut self, instance: &ProviderInstance) -> Result<DataFrame, String> {
+            let Some(TimeSpec::DateRange { from, to }) = &instance.time_spec else {
+                return Err("no range".into());
+            };
+            self.calls.lock().unwrap().push((from.clone(), to.clone()));
+            let days: Vec<i64> =
+                (date_to_epoch(from)? / 86_400..=date_to_epoch(to)? / 86_400).collect();
+            df!(
+                "timestamp" => days.iter().map(|d| d * 86_400).collect::<Vec<_>>(),
+                "close" => days.iter().map(|d| *d as f64).collect::<Vec<_>>(),
+            )
+            .map_err(|e| e.to_string())
+        }
+    }
+
+    fn instance(range: &str, extra: &str) -> ProviderInstance {
+        let src = format!(
+            "PROVIDER p USING remote\n    TICKER aapl\n    {}\n{}",
+            range, extra
+        );
+        parse(&src).unwrap().providers.remove("p").unwrap()
+    }
+
+    fn scratch_cache(name: &str) -> ProviderCache {
+        let dir = std::env::temp_dir().join(format!("qstudio_{}_{}", name, std::process::id()));
+        let mut cache = ProviderCache::new(dir);
+        cache.clear().unwrap();
+        cache
+    }
+
+    #[test]
+    fn test_cache_fetches_only_missing_days() {
+        let mut cache = scratch_cache("cache_incremental");
+        let mut provider = Daily::default();
+        let calls = provider.calls.clone();
+
+        let first = instance("FROM 2020-01-01 TO 2020-01-10", "");
+        assert_eq!(cache.fetch(&first, &mut provider).unwrap().height(), 10);
+        assert_eq!(cache.fetch(&first, &mut provider).unwrap().height(), 10);
+        assert_eq!(calls.lock().unwrap().len(), 1);
+
+        let wider = instance("FROM 2019-12-30 TO 2020-01-15", "");
+        let df = cache.fetch(&wider, &mut provider).unwrap();
+        assert_eq!(df.height(), 17);
+        assert!(df.equals(&Daily::default().fetch(&wider).unwrap()));
+        assert_eq!(
+            calls.lock().unwrap()[1..],
+            [
+                ("2019-12-30".into(), "2019-12-31".into()),
+                ("2020-01-11".into(), "2020-01-15".into())
+            ]
+        );
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 2 });
+        assert_eq!(cache.stats().to_string(), "cache: 1 hit, 2 misses");
+
+        // a new cache over the same directory reads what was written
+        let mut reopened = ProviderCache::new(cache.dir().to_path_buf());
+        let inner = instance("FROM 2020-01-02 TO 2020-01-03", "");
+        assert_eq!(reopened.fetch(&inner, &mut provider).unwrap().height(), 2);
+        assert_eq!(reopened.stats(), CacheStats { hits: 1, misses: 0 });
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn test_cache_controls() {
+        let mut cache = scratch_cache("cache_controls");
+        let mut provider = Daily::default();
+        let calls = provider.calls.clone();
+        let range = "FROM 2020-01-01 TO 2020-01-05";
+
+        cache.fetch(&instance(range, ""), &mut provider).unwrap();
+        cache
+            .fetch(&instance(range, "    PARAM cache = false\n"), &mut provider)
+            .unwrap();
+        cache
+            .fetch(
+                &instance(range, "    PARAM cache = refresh\n"),
+                &mut provider,
+            )
+            .unwrap();
+        // params other than the cache controls are part of the series
+        cache
+            .fetch(&instance(range, "    PARAM adjust = true\n"), &mut provider)
+            .unwrap();
+        cache
+            .fetch(
+                &instance(range, "    PARAM cache_ttl = 1h\n"),
+                &mut provider,
+            )
+            .unwrap();
+        assert_eq!(calls.lock().unwrap().len(), 4);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 3 });
+        cache.clear().unwrap();
+    }
+}
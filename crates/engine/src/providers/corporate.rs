@@ -0,0 +1,277 @@
+use super::file::{timestamps, TIMESTAMP_COLUMNS};
+use crate::parser::Adjustments;
+use polars::prelude::*;
+
+const SPLIT_COLUMNS: [&str; 3] = ["split", "split_ratio", "stock splits"];
+const DIVIDEND_COLUMNS: [&str; 3] = ["dividend", "dividends", "amount"];
+const PRICE_COLUMNS: [&str; 4] = ["open", "high", "low", "close"];
+
+/// Split and dividend events in one shape: `timestamp` (the ex-date, epoch seconds),
+/// `split` (new shares per old share, 4 for a 4:1 split, 1 for none) and `dividend` (cash
+/// per share as paid, 0 for none). Splits may be written `4`, `4:1` or `4/1`; a 0 split,
+/// as some vendors write rows without one, counts as none.
+pub fn corporate_actions(raw: &DataFrame, format: Option<&str>) -> Result<DataFrame, String> {
+    let find = |candidates: &[&str]| {
+        raw.get_columns()
+            .iter()
+            .find(|c| candidates.iter().any(|n| c.name().eq_ignore_ascii_case(n)))
+    };
+    let ts = find(&[&["ex_date"], &TIMESTAMP_COLUMNS[..]].concat())
+        .ok_or("no ex-date column in the split/dividend data")?;
+    let ts = timestamps(ts, format)?;
+    let rows = raw.height();
+
+    let split: Vec<f64> = match find(&SPLIT_COLUMNS) {
+        Some(c) if c.dtype() == &DataType::String => c
+            .str()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|v| v.map_or(Ok(1.0), parse_ratio))
+            .collect::<Result<_, _>>()?,
+        Some(c) => c
+            .cast(&DataType::Float64)
+            .map_err(|e| format!("split column: {}", e))?
+            .f64()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|v| v.filter(|r| *r > 0.0).unwrap_or(1.0))
+            .collect(),
+        None => vec![1.0; rows],
+    };
+    let dividend: Vec<f64> = match find(&DIVIDEND_COLUMNS) {
+        Some(c) => c
+            .cast(&DataType::Float64)
+            .map_err(|e| format!("dividend column: {}", e))?
+            .f64()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|v| v.unwrap_or(0.0))
+            .collect(),
+        None => vec![0.0; rows],
+    };
+
+    DataFrame::new(vec![
+        ts,
+        Column::new("split".into(), split),
+        Column::new("dividend".into(), dividend),
+    ])
+    .and_then(|df| df.drop_nulls(Some(&["timestamp".to_string()])))
+    .and_then(|df| df.sort(["timestamp"], SortMultipleOptions::default()))
+    .map_err(|e| e.to_string())
+}
+
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let ratio = match s.split_once([':', '/']) {
+        Some((new, old)) => match (new.trim().parse::<f64>(), old.trim().parse::<f64>()) {
+            (Ok(new), Ok(old)) if old > 0.0 => Some(new / old),
+            _ => None,
+        },
+        None => s.parse::<f64>().ok(),
+    };
+    match ratio {
+        Some(r) if r > 0.0 => Ok(r),
+        Some(_) => Ok(1.0),
+        None => Err(format!("can't read split ratio '{}'", s)),
+    }
+}
+
+/// `bars` with `open`, `high`, `low` and `close` adjusted backwards for the events in
+/// `actions`, so prices before an ex-date are comparable with those after it.
+///
+/// - splits: earlier prices divided by the ratio, earlier `volume` multiplied by it
+/// - dividends: earlier prices scaled by `1 - dividend / close` of the bar before the ex-date
+///
+/// Unless dividends go into the prices, a `dividend` column gives the cash paid per share
+/// on each ex-date bar (in the frame's split-adjusted units), for a backtest's P&L.
+pub fn adjust_bars(
+    bars: &DataFrame,
+    actions: &DataFrame,
+    adjust: Adjustments,
+) -> Result<DataFrame, String> {
+    let f64s = |df: &DataFrame, name: &str| -> Result<Vec<Option<f64>>, String> {
+        Ok(df
+            .column(name)
+            .and_then(|c| c.cast(&DataType::Float64))
+            .map_err(|e| e.to_string())?
+            .f64()
+            .map_err(|e| e.to_string())?
+            .to_vec())
+    };
+    let ts: Vec<i64> = bars
+        .column("timestamp")
+        .and_then(|c| c.cast(&DataType::Int64))
+        .map_err(|e| e.to_string())?
+        .i64()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|t| t.unwrap_or(i64::MIN))
+        .collect();
+    if !ts.is_sorted() {
+        return Err("bars must be sorted by timestamp to adjust them".to_string());
+    }
+    let close = f64s(bars, "close")?;
+    let events: Vec<(i64, f64, f64)> = {
+        let at = actions
+            .column("timestamp")
+            .and_then(|c| c.i64().cloned())
+            .map_err(|e| e.to_string())?;
+        let split = f64s(actions, "split")?;
+        let dividend = f64s(actions, "dividend")?;
+        (0..actions.height())
+            .filter_map(|i| {
+                Some((
+                    at.get(i)?,
+                    split[i].unwrap_or(1.0),
+                    dividend[i].unwrap_or(0.0),
+                ))
+            })
+            .collect()
+    };
+
+    let n = bars.height();
+    // bars before ex-date `t` are `..first_on_or_after(t)`
+    let first_on_or_after = |t: i64| ts.partition_point(|b| *b < t);
+
+    // split factors first: dividends are paid in the shares of their day
+    let mut split_factor = vec![1.0; n];
+    for &(t, ratio, _) in &events {
+        if ratio != 1.0 {
+            split_factor[..first_on_or_after(t)]
+                .iter_mut()
+                .for_each(|f| *f /= ratio);
+        }
+    }
+
+    let mut price_factor = if adjust.splits {
+        split_factor.clone()
+    } else {
+        vec![1.0; n]
+    };
+    let mut paid = vec![0.0; n];
+    for &(t, _, cash) in &events {
+        let k = first_on_or_after(t);
+        if cash <= 0.0 || k == 0 || k == n {
+            continue;
+        }
+        if adjust.dividends {
+            if let Some(prev) = close[k - 1].filter(|c| *c > cash) {
+                let f = 1.0 - cash / prev;
+                price_factor[..k].iter_mut().for_each(|p| *p *= f);
+            }
+        } else {
+            let units = if adjust.splits { split_factor[k] } else { 1.0 };
+            paid[k] += cash * units;
+        }
+    }
+
+    let mut out = bars.clone();
+    for name in PRICE_COLUMNS {
+        if out.column(name).is_err() {
+            continue;
+        }
+        let adjusted: Vec<Option<f64>> = f64s(bars, name)?
+            .iter()
+            .zip(&price_factor)
+            .map(|(p, f)| p.map(|p| p * f))
+            .collect();
+        out.replace(name, Series::new(name.into(), adjusted))
+            .map_err(|e| e.to_string())?;
+    }
+    if let Some(dtype) = out.column("volume").ok().map(|c| c.dtype().clone()) {
+        if adjust.splits {
+            // whole shares stay whole, in the provider's dtype
+            let whole = dtype.is_integer();
+            let volume: Vec<Option<f64>> = f64s(bars, "volume")?
+                .iter()
+                .zip(&split_factor)
+                .map(|(v, f)| v.map(|v| if whole { (v / f).round() } else { v / f }))
+                .collect();
+            let volume = Series::new("volume".into(), volume)
+                .cast(&dtype)
+                .map_err(|e| e.to_string())?;
+            out.replace("volume", volume).map_err(|e| e.to_string())?;
+        }
+    }
+    if !adjust.dividends && events.iter().any(|e| e.2 > 0.0) {
+        out.with_column(Column::new("dividend".into(), paid))
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adjust_bars, corporate_actions};
+    use crate::parser::Adjustments;
+    use polars::prelude::*;
+
+    fn column(df: &DataFrame, name: &str) -> Vec<f64> {
+        df.column(name)
+            .unwrap()
+            .cast(&DataType::Float64)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_adjust_for_split_and_dividend() {
+        // 4:1 split on day 2, $1 dividend on day 4 (after the split)
+        let bars = df!(
+            "timestamp" => [0i64, 86_400, 172_800, 259_200, 345_600],
+            "open" => [400.0, 404.0, 101.0, 100.0, 99.0],
+            "close" => [404.0, 400.0, 100.0, 100.0, 99.0],
+            "volume" => [10i64, 10, 40, 40, 40],
+        )
+        .unwrap();
+        let raw = df!(
+            "Date" => ["1970-01-05", "1970-01-03"],
+            "Dividends" => [1.0, 0.0],
+            "Stock Splits" => ["0", "4:1"],
+        )
+        .unwrap();
+        let actions = corporate_actions(&raw, None).unwrap();
+        assert_eq!(column(&actions, "split"), [4.0, 1.0]);
+
+        let splits = Adjustments {
+            splits: true,
+            dividends: false,
+        };
+        let adjusted = adjust_bars(&bars, &actions, splits).unwrap();
+        assert_eq!(
+            column(&adjusted, "close"),
+            [101.0, 100.0, 100.0, 100.0, 99.0]
+        );
+        assert_eq!(column(&adjusted, "volume"), [40.0, 40.0, 40.0, 40.0, 40.0]);
+        assert_eq!(adjusted.column("volume").unwrap().dtype(), &DataType::Int64);
+        let mut fractional = bars.clone();
+        fractional
+            .with_column(Column::new(
+                "volume".into(),
+                [10.125, 10.0, 40.0, 40.0, 40.0],
+            ))
+            .unwrap();
+        let fractional = adjust_bars(&fractional, &actions, splits).unwrap();
+        assert_eq!(column(&fractional, "volume")[0], 40.5);
+        assert_eq!(
+            fractional.column("volume").unwrap().dtype(),
+            &DataType::Float64
+        );
+        // the dividend is cash in the backtest, on its ex-date bar
+        assert_eq!(column(&adjusted, "dividend"), [0.0, 0.0, 0.0, 0.0, 1.0]);
+
+        let both = Adjustments {
+            splits: true,
+            dividends: true,
+        };
+        let adjusted = adjust_bars(&bars, &actions, both).unwrap();
+        assert!(adjusted.column("dividend").is_err());
+        let close = column(&adjusted, "close");
+        assert_eq!(close[4], 99.0);
+        assert!((close[3] - 99.0).abs() < 1e-9, "{close:?}");
+        assert!((close[0] - 101.0 * 0.99).abs() < 1e-9, "{close:?}");
+    }
+}
@@ -0,0 +1,330 @@
+use super::{bars_between, param, DataProvider};
+use crate::parser::{date_to_epoch, DataKind, ProviderInstance, TimeSpec};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use polars::prelude::*;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+const PRICE_COLUMNS: [&str; 5] = ["open", "high", "low", "close", "adjclose"];
+pub(super) const TIMESTAMP_COLUMNS: [&str; 4] = ["timestamp", "date", "datetime", "time"];
+
+/// Integer timestamps at or above this are taken as milliseconds (in seconds it
+/// would be past the year 5000).
+const MILLIS_THRESHOLD: i64 = 100_000_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Csv,
+    Parquet,
+}
+
+/// Bars from a local CSV or Parquet file: `PROVIDER prices USING csv`.
+///
+/// Params:
+/// - `path`: the file; relative paths are resolved against the provider's base directory
+/// - `timestamp`, `open`, `high`, `low`, `close`, `adjclose`, `volume`: the file's column
+///   for that bar column, when it isn't named the same (matched case-insensitively)
+/// - `timestamp_format`: chrono format for text timestamps, e.g. `"%m/%d/%Y"`
+///
+/// The frame has the remote provider's shape: `timestamp` in epoch seconds, `f64` prices,
+/// `i64` volume, then any other columns of the file as they are. `FROM .. TO` keeps
+/// the bars from the FROM day through the TO day. FUNDAMENTAL blocks read filings instead,
+/// see [`super::fundamental::point_in_time`].
+#[derive(Debug)]
+pub struct FileProvider {
+    format: FileFormat,
+    base_dir: PathBuf,
+}
+
+impl FileProvider {
+    pub fn new(format: FileFormat, base_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            format,
+            base_dir: base_dir.into(),
+        }
+    }
+}
+
+impl FileFormat {
+    /// The format a file's extension names.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "csv" => Some(FileFormat::Csv),
+            "parquet" => Some(FileFormat::Parquet),
+            _ => None,
+        }
+    }
+
+    pub(super) fn read(self, path: &Path) -> Result<DataFrame, String> {
+        match self {
+            FileFormat::Csv => CsvReadOptions::default()
+                .with_has_header(true)
+                .try_into_reader_with_file_path(Some(path.to_path_buf()))
+                .and_then(|reader| reader.finish())
+                .map_err(|e| e.to_string()),
+            FileFormat::Parquet => {
+                let file = File::open(path).map_err(|e| e.to_string())?;
+                ParquetReader::new(file).finish().map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+impl DataProvider for FileProvider {
+    fn fetch(&mut self, instance: &ProviderInstance) -> Result<DataFrame, String> {
+        let param = |key: &str| param(instance, key);
+
+        let path = self
+            .base_dir
+            .join(param("path").ok_or("missing PARAM path = <file>")?);
+        let raw = self
+            .format
+            .read(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if instance.kind == DataKind::Fundamental {
+            // filings are dated by the registry, see `fundamental::point_in_time`
+            return Ok(raw);
+        }
+        let bars = to_bars(&raw, &param)?;
+
+        let bars = match &instance.time_spec {
+            Some(TimeSpec::DateRange { from, to }) => {
+                bars_between(&bars, date_to_epoch(from)?, date_to_epoch(to)? + 86_400)?
+            }
+            Some(TimeSpec::LiveSpec { .. }) => {
+                return Err("files can't be streamed LIVE, use FROM .. TO".to_string())
+            }
+            None => bars,
+        };
+        bars.sort(["timestamp"], SortMultipleOptions::default())
+            .map_err(|e| format!("Failed to sort bars: {}", e))
+    }
+}
+
+/// Rename and cast the file's columns into the bar shape.
+fn to_bars<'a>(
+    raw: &DataFrame,
+    param: &impl Fn(&str) -> Option<&'a str>,
+) -> Result<DataFrame, String> {
+    let names: Vec<String> = raw
+        .get_column_names()
+        .iter()
+        .map(|n| n.to_string())
+        .collect();
+    let find = |wanted: &str| names.iter().find(|n| n.eq_ignore_ascii_case(wanted));
+    let source = |bar: &str, defaults: &[&str]| match param(bar) {
+        Some(column) => find(column)
+            .map(Some)
+            .ok_or_else(|| format!("PARAM {} = {}: no such column in file", bar, column)),
+        None => Ok(defaults.iter().find_map(|d| find(d))),
+    };
+
+    let mut used = Vec::new();
+    let mut columns = Vec::new();
+
+    let ts_name = source("timestamp", &TIMESTAMP_COLUMNS)?
+        .ok_or("no timestamp column in file, name it with PARAM timestamp = <column>")?;
+    columns.push(timestamps(
+        raw.column(ts_name).map_err(|e| e.to_string())?,
+        param("timestamp_format"),
+    )?);
+    used.push(ts_name);
+
+    for bar in PRICE_COLUMNS.iter().chain(&["volume"]) {
+        let Some(name) = source(bar, &[bar])? else {
+            continue;
+        };
+        let dtype = if *bar == "volume" {
+            DataType::Int64
+        } else {
+            DataType::Float64
+        };
+        let column = raw
+            .column(name)
+            .and_then(|c| c.cast(&dtype))
+            .map_err(|e| format!("column '{}' is not numeric: {}", name, e))?
+            .with_name((*bar).into());
+        columns.push(column);
+        used.push(name);
+    }
+
+    for column in raw.get_columns() {
+        let taken = columns.iter().any(|c| c.name() == column.name());
+        if !taken && !used.iter().any(|u| u.as_str() == column.name().as_str()) {
+            columns.push(column.clone());
+        }
+    }
+    DataFrame::new(columns).map_err(|e| e.to_string())
+}
+
+/// Any date, datetime, integer or text column -> `timestamp` in epoch seconds.
+pub(super) fn timestamps(column: &Column, format: Option<&str>) -> Result<Column, String> {
+    let as_i64 = |c: &Column| -> Result<Vec<Option<i64>>, String> {
+        let c = c.cast(&DataType::Int64).map_err(|e| e.to_string())?;
+        Ok(c.i64().map_err(|e| e.to_string())?.to_vec())
+    };
+
+    let seconds: Vec<Option<i64>> = match column.dtype() {
+        DataType::String => column
+            .str()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|v| v.map(|s| parse_timestamp(s.trim(), format)).transpose())
+            .collect::<Result<_, _>>()?,
+        DataType::Date => as_i64(&column.cast(&DataType::Int32).map_err(|e| e.to_string())?)?
+            .into_iter()
+            .map(|d| d.map(|days| days * 86_400))
+            .collect(),
+        DataType::Datetime(unit, _) => {
+            let per_second = match unit {
+                TimeUnit::Nanoseconds => 1_000_000_000,
+                TimeUnit::Microseconds => 1_000_000,
+                TimeUnit::Milliseconds => 1_000,
+            };
+            as_i64(column)?
+                .into_iter()
+                .map(|t| t.map(|t| t.div_euclid(per_second)))
+                .collect()
+        }
+        dtype if dtype.is_primitive_numeric() => as_i64(column)?
+            .into_iter()
+            .map(|t| {
+                t.map(|t| {
+                    if t.abs() >= MILLIS_THRESHOLD {
+                        t.div_euclid(1_000)
+                    } else {
+                        t
+                    }
+                })
+            })
+            .collect(),
+        other => return Err(format!("can't read timestamps from a {} column", other)),
+    };
+    Ok(Column::new("timestamp".into(), seconds))
+}
+
+fn parse_timestamp(s: &str, format: Option<&str>) -> Result<i64, String> {
+    let midnight = |d: NaiveDate| d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+    match format {
+        Some(f) => NaiveDateTime::parse_from_str(s, f)
+            .map(|dt| dt.and_utc().timestamp())
+            .or_else(|_| NaiveDate::parse_from_str(s, f).map(midnight))
+            .or_else(|_| DateTime::parse_from_str(s, f).map(|dt| dt.timestamp()))
+            .map_err(|_| format!("timestamp '{}' does not match format '{}'", s, f)),
+        None => DateTime::parse_from_rfc3339(s)
+            .map(|dt| dt.timestamp())
+            .ok()
+            .or_else(|| {
+                ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+                    .iter()
+                    .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+                    .map(|dt| dt.and_utc().timestamp())
+            })
+            .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().map(midnight))
+            .ok_or_else(|| {
+                format!(
+                    "can't read timestamp '{}', set PARAM timestamp_format = \"...\"",
+                    s
+                )
+            }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileFormat, FileProvider};
+    use crate::parser::parse;
+    use crate::providers::DataProvider;
+    use polars::prelude::*;
+    use std::fs;
+
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("qstudio_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_csv_vendor_columns() {
+        let dir = scratch_dir("csv_provider");
+        fs::write(
+            dir.join("aapl.csv"),
+            "Date,Open,High,Low,Close,Volume,Exchange\n\
+             01/03/2020,10.0,11.0,9.5,10.5,1200,NASDAQ\n\
+             01/02/2020,9.0,10.0,8.5,9.5,1000.0,NASDAQ\n\
+             12/31/2019,8.0,9.0,7.5,8.5,900,NASDAQ\n",
+        )
+        .unwrap();
+        let query = parse(
+            r#"
+            PROVIDER prices USING csv
+                FROM 2020-01-01 TO 2020-01-03
+                PARAM path = aapl.csv
+                PARAM timestamp_format = "%m/%d/%Y"
+            "#,
+        )
+        .unwrap();
+
+        let mut provider = FileProvider::new(FileFormat::Csv, &dir);
+        let df = provider.fetch(&query.providers["prices"]).unwrap();
+
+        let names: Vec<&str> = df.get_column_names().iter().map(|n| n.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "timestamp",
+                "open",
+                "high",
+                "low",
+                "close",
+                "volume",
+                "Exchange"
+            ]
+        );
+        let ts: Vec<_> = df.column("timestamp").unwrap().i64().unwrap().to_vec();
+        assert_eq!(ts, [Some(1_577_923_200), Some(1_578_009_600)]);
+        assert_eq!(
+            df.column("close").unwrap().f64().unwrap().get(1),
+            Some(10.5)
+        );
+        assert_eq!(
+            df.column("volume").unwrap().i64().unwrap().get(0),
+            Some(1000)
+        );
+    }
+
+    #[test]
+    fn test_parquet_column_mapping() {
+        let dir = scratch_dir("parquet_provider");
+        let mut raw = df!(
+            "ts_ms" => [1_577_923_200_000i64, 1_578_009_600_000],
+            "px_last" => [9.5, 10.5],
+        )
+        .unwrap();
+        let file = fs::File::create(dir.join("spy.parquet")).unwrap();
+        ParquetWriter::new(file).finish(&mut raw).unwrap();
+
+        let src = format!(
+            r#"
+            PROVIDER spy USING parquet
+                PARAM path = "{}"
+                PARAM timestamp = ts_ms
+                PARAM close = px_last
+            "#,
+            dir.join("spy.parquet").display()
+        );
+        let query = parse(&src).unwrap();
+
+        let mut provider = FileProvider::new(FileFormat::Parquet, ".");
+        let df = provider.fetch(&query.providers["spy"]).unwrap();
+        assert_eq!(df.get_column_names(), ["timestamp", "close"]);
+        assert_eq!(
+            df.column("timestamp").unwrap().i64().unwrap().get(0),
+            Some(1_577_923_200)
+        );
+
+        let missing = parse("PROVIDER spy USING parquet\n    PARAM path = nope.parquet\n").unwrap();
+        let err = provider.fetch(&missing.providers["spy"]).unwrap_err();
+        assert!(err.contains("nope.parquet"), "{err}");
+    }
+}
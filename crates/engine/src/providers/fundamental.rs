@@ -0,0 +1,219 @@
+use super::file::timestamps;
+use super::param;
+use crate::parser::{date_to_epoch, duration_to_seconds, ProviderInstance, TimeSpec};
+use polars::prelude::*;
+
+const REPORT_COLUMNS: [&str; 4] = ["report_date", "period_end", "fiscal_date", "date"];
+const FILING_COLUMNS: [&str; 4] = ["filing_date", "filed", "accepted_date", "acceptance_date"];
+/// Columns of a point-in-time frame that say when a row applies, not what it reports.
+const DATE_COLUMNS: [&str; 3] = ["timestamp", "report_date", "filing_date"];
+
+/// Filings of a FUNDAMENTAL block, one row each, as they were known at the time:
+/// `timestamp` (when the filing became public), `report_date` (the end of the period it
+/// reports on) and `filing_date` in epoch seconds, then the reported values, numbers as `f64`.
+/// Rows are in filing order (same-day filings in source order), so a restated period
+/// appears once per filing and the restatement wins from its filing on.
+///
+/// Params:
+/// - `report_date`, `filing_date`: the columns holding them, when named otherwise
+/// - `filing_lag`: for data without filing dates, a duration (e.g. `45d`) after the
+///   report date when values are taken as public; without either there is no telling
+///   when a value was known, and that's an error rather than a look-ahead
+/// - `timestamp_format`: chrono format for text dates
+///
+/// `FROM .. TO` keeps the filings public by the end of the TO day; earlier ones are kept
+/// so the first bars of a join have values.
+pub fn point_in_time(raw: &DataFrame, instance: &ProviderInstance) -> Result<DataFrame, String> {
+    let param = |key: &str| param(instance, key);
+    let names: Vec<String> = raw
+        .get_column_names()
+        .iter()
+        .map(|n| n.to_string())
+        .collect();
+    let find = |key: &str, defaults: &[&str]| match param(key) {
+        Some(column) => names
+            .iter()
+            .find(|n| n.eq_ignore_ascii_case(column))
+            .map(Some)
+            .ok_or_else(|| format!("PARAM {} = {}: no such column", key, column)),
+        None => Ok(defaults
+            .iter()
+            .find_map(|d| names.iter().find(|n| n.eq_ignore_ascii_case(d)))),
+    };
+    let dates = |name: &str, as_name: &str| -> Result<Column, String> {
+        let column = raw.column(name).map_err(|e| e.to_string())?;
+        Ok(timestamps(column, param("timestamp_format"))?.with_name(as_name.into()))
+    };
+
+    let report_name = find("report_date", &REPORT_COLUMNS)?
+        .ok_or("no report date column, name it with PARAM report_date = <column>")?;
+    let filing_name = find("filing_date", &FILING_COLUMNS)?;
+    let report = dates(report_name, "report_date")?;
+    let filing = match (filing_name, param("filing_lag")) {
+        (Some(name), _) => dates(name, "filing_date")?,
+        (None, Some(lag)) => {
+            let lag = duration_to_seconds(lag)?;
+            let filed: Vec<Option<i64>> = report
+                .i64()
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .map(|t| t.map(|t| t + lag))
+                .collect();
+            Column::new("filing_date".into(), filed)
+        }
+        (None, None) => {
+            return Err(
+                "no filing date column: name it with PARAM filing_date = <column>, or set \
+                 PARAM filing_lag = 45d (using report dates would look ahead)"
+                    .to_string(),
+            )
+        }
+    };
+
+    let mut columns = vec![filing.clone().with_name("timestamp".into()), report, filing];
+    for column in raw.get_columns() {
+        let name = column.name().as_str();
+        let used = DATE_COLUMNS.iter().any(|d| name.eq_ignore_ascii_case(d))
+            || name == report_name
+            || filing_name.is_some_and(|f| name == f);
+        if used {
+            continue;
+        }
+        let column = if column.dtype().is_primitive_numeric() {
+            column.cast(&DataType::Float64).map_err(|e| e.to_string())?
+        } else {
+            column.clone()
+        };
+        columns.push(column);
+    }
+
+    let filings = DataFrame::new(columns)
+        .and_then(|df| df.drop_nulls(Some(&["filing_date".to_string()])))
+        .and_then(|df| {
+            df.sort(
+                ["filing_date", "report_date"],
+                SortMultipleOptions::default().with_maintain_order(true),
+            )
+        })
+        .map_err(|e| e.to_string())?;
+    match &instance.time_spec {
+        Some(TimeSpec::DateRange { to, .. }) => {
+            super::bars_between(&filings, i64::MIN, date_to_epoch(to)? + 86_400)
+        }
+        Some(TimeSpec::LiveSpec { .. }) => {
+            Err("FUNDAMENTAL data can't be streamed LIVE, use FROM .. TO".to_string())
+        }
+        None => Ok(filings),
+    }
+}
+
+/// `bars` with the values of the latest filing public before each bar: a filing stamped
+/// at a bar's own timestamp only shows from the next bar, and bars before the first filing
+/// get nulls. `filings` is a [`point_in_time`] frame.
+pub fn join_asof(bars: &DataFrame, filings: &DataFrame) -> Result<DataFrame, String> {
+    let i64s = |df: &DataFrame, name: &str| -> Result<Vec<Option<i64>>, String> {
+        Ok(df
+            .column(name)
+            .and_then(|c| c.cast(&DataType::Int64))
+            .map_err(|e| e.to_string())?
+            .i64()
+            .map_err(|e| e.to_string())?
+            .to_vec())
+    };
+    let filed: Vec<i64> = i64s(filings, "filing_date")?
+        .into_iter()
+        .flatten()
+        .collect();
+    if filed.len() != filings.height() || !filed.is_sorted() {
+        return Err("filings must be sorted by filing_date, without gaps".to_string());
+    }
+
+    let rows: IdxCa = i64s(bars, "timestamp")?
+        .into_iter()
+        .map(|t| {
+            let known = filed.partition_point(|f| t.is_some_and(|t| *f < t));
+            known.checked_sub(1).map(|i| i as IdxSize)
+        })
+        .collect();
+
+    let values = filings
+        .drop_many(DATE_COLUMNS)
+        .take(&rows)
+        .map_err(|e| e.to_string())?;
+    if let Some(clash) = values
+        .get_column_names()
+        .into_iter()
+        .find(|name| bars.column(name.as_str()).is_ok())
+    {
+        return Err(format!(
+            "column '{}' is in both the bars and the filings",
+            clash
+        ));
+    }
+    bars.hstack(values.get_columns()).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{join_asof, point_in_time};
+    use crate::parser::{parse, DataKind, ProviderInstance};
+    use crate::providers::{DataProvider, RemoteProvider};
+    use polars::prelude::*;
+
+    fn instance(params: &str) -> ProviderInstance {
+        let src = format!(
+            "PROVIDER fund USING csv\n    FUNDAMENTAL\n    FROM 2020-01-01 TO 2020-03-31\n{}",
+            params
+        );
+        parse(&src).unwrap().providers.remove("fund").unwrap()
+    }
+
+    fn eps(df: &DataFrame) -> Vec<Option<f64>> {
+        df.column("eps").unwrap().f64().unwrap().to_vec()
+    }
+
+    #[test]
+    fn test_fundamentals_join_without_look_ahead() {
+        // Q4 is filed Jan 30 and restated Feb 14; Q1 is filed after TO
+        let raw = df!(
+            "period_end" => ["2019-12-31", "2020-03-31", "2019-12-31"],
+            "filed" => ["2020-01-30", "2020-04-29", "2020-02-14"],
+            "eps" => [1i64, 2, 3],
+        )
+        .unwrap();
+        let fund = instance("");
+        assert_eq!(fund.kind, DataKind::Fundamental);
+        let filings = point_in_time(&raw, &fund).unwrap();
+        assert_eq!(
+            filings.get_column_names(),
+            ["timestamp", "report_date", "filing_date", "eps"]
+        );
+        assert_eq!(eps(&filings), [Some(1.0), Some(3.0)]);
+
+        // daily bars from Jan 1: Jan 30 is bar 29, Feb 14 bar 44
+        let bars = df!(
+            "timestamp" => (0..50).map(|d| 1_577_836_800 + d * 86_400).collect::<Vec<i64>>(),
+        )
+        .unwrap();
+        let joined = eps(&join_asof(&bars, &filings).unwrap());
+        assert_eq!(joined[29], None, "a filing shows from the bar after it");
+        assert_eq!(
+            (joined[30], joined[44], joined[45]),
+            (Some(1.0), Some(1.0), Some(3.0))
+        );
+
+        // without filing dates: an explicit lag, or an error
+        let undated = raw.drop("filed").unwrap();
+        let err = point_in_time(&undated, &fund).unwrap_err();
+        assert!(err.contains("filing_lag"), "{err}");
+        let lagged = point_in_time(&undated, &instance("    PARAM filing_lag = 30d\n")).unwrap();
+        assert_eq!(eps(&lagged), [Some(1.0), Some(3.0)]);
+        let joined = eps(&join_asof(&bars, &lagged).unwrap());
+        assert_eq!((joined[29], joined[30]), (None, Some(3.0)));
+
+        // filings only come from local backends, without a word to the provider server
+        let mut remote = RemoteProvider::new("127.0.0.1:1");
+        let err = remote.fetch(&instance("")).unwrap_err();
+        assert!(err.contains("no FUNDAMENTAL data"), "{err}");
+    }
+}
@@ -0,0 +1,389 @@
+// providers/mod.rs
+// -----------------------------------------------------------------------------
+// Data providers: where PROVIDER blocks get their bars from
+// -----------------------------------------------------------------------------
+
+pub mod cache;
+pub mod corporate;
+pub mod file;
+pub mod fundamental;
+pub mod remote;
+pub mod replay;
+pub mod synthetic;
+
+use crate::calendar::Calendar;
+use crate::parser::{DataKind, ProviderInstance, TimeSpec};
+use chrono::{DateTime, Utc};
+use polars::prelude::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub use cache::{CacheStats, ProviderCache};
+pub use file::{FileFormat, FileProvider};
+pub use remote::RemoteProvider;
+pub use replay::ReplayProvider;
+pub use synthetic::SyntheticProvider;
+
+/// A source of data for PROVIDER blocks, selected by the block's backend name
+/// (`PROVIDER name USING <backend>`).
+///
+/// The returned frame has one row per bar and the same shape the remote provider
+/// produces (see [`crate::json_values_to_df`]), so FRAMEs work the same whatever the source.
+pub trait DataProvider: Send + std::fmt::Debug {
+    fn fetch(&mut self, instance: &ProviderInstance) -> Result<DataFrame, String>;
+
+    /// Bars for a LIVE block newer than `after` (epoch seconds), or the current
+    /// session's bars when `after` is `None`.
+    ///
+    /// By default this fetches from the day of `after` (or today) through today and
+    /// keeps the new bars; providers that can stream should do better.
+    fn poll(
+        &mut self,
+        instance: &ProviderInstance,
+        after: Option<i64>,
+    ) -> Result<DataFrame, String> {
+        let day = |t: i64| {
+            DateTime::from_timestamp(t, 0)
+                .unwrap_or_default()
+                .format("%Y-%m-%d")
+                .to_string()
+        };
+        let now = Utc::now().timestamp();
+        let window = ProviderInstance {
+            time_spec: Some(TimeSpec::DateRange {
+                from: day(after.unwrap_or(now)),
+                to: day(now),
+            }),
+            ..instance.clone()
+        };
+        let bars = self.fetch(&window)?;
+        match after {
+            Some(after) => bars_between(&bars, after + 1, i64::MAX),
+            None => Ok(bars),
+        }
+    }
+
+    /// Pause, resume or step the feed of a LIVE block. Returns whether the provider
+    /// paces its bars at all; most follow the market and ignore this.
+    fn control(&mut self, _instance: &ProviderInstance, _control: ReplayControl) -> bool {
+        false
+    }
+
+    /// Split and dividend events for the block's ticker, in any shape
+    /// [`corporate::corporate_actions`] reads.
+    fn actions(&mut self, _instance: &ProviderInstance) -> Result<DataFrame, String> {
+        Err("no split/dividend data from this provider, set PARAM actions = <file>".to_string())
+    }
+}
+
+/// Pace controls for a LIVE block whose provider plays bars at its own speed (a replay).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayControl {
+    Pause,
+    Resume,
+    /// Release the next bar, paused or not.
+    Step,
+}
+
+/// Backend name -> provider. Backends without a registered provider go to the fallback,
+/// normally the remote provider server, through the cache if there is one.
+#[derive(Debug, Default)]
+pub struct ProviderRegistry {
+    backends: HashMap<String, Box<dyn DataProvider>>,
+    fallback: Option<Box<dyn DataProvider>>,
+    cache: Option<ProviderCache>,
+    /// where relative `PARAM actions` files are
+    base_dir: PathBuf,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The engine's default set: `csv` and `parquet` files (relative paths resolved against
+    /// `base_dir`), `synthetic` bars, `replay` of either as a LIVE feed, every other backend served by the provider server at `addr`.
+    /// The remote backends are cached under `cache_dir`, if given.
+    pub fn standard(addr: &str, base_dir: &Path, cache_dir: Option<&Path>) -> Self {
+        let mut registry = Self {
+            base_dir: base_dir.to_path_buf(),
+            ..Self::new()
+        };
+        registry.register(
+            "csv",
+            Box::new(FileProvider::new(FileFormat::Csv, base_dir)),
+        );
+        registry.register(
+            "parquet",
+            Box::new(FileProvider::new(FileFormat::Parquet, base_dir)),
+        );
+        registry.register("synthetic", Box::new(SyntheticProvider::new()));
+        registry.register("replay", Box::new(ReplayProvider::new(base_dir)));
+        registry.set_fallback(Box::new(RemoteProvider::new(addr)));
+        if let Some(dir) = cache_dir {
+            registry.set_cache(ProviderCache::new(dir));
+        }
+        registry
+    }
+
+    pub fn register(&mut self, backend: &str, provider: Box<dyn DataProvider>) {
+        self.backends.insert(backend.to_string(), provider);
+    }
+
+    pub fn set_fallback(&mut self, provider: Box<dyn DataProvider>) {
+        self.fallback = Some(provider);
+    }
+
+    pub fn set_cache(&mut self, cache: ProviderCache) {
+        self.cache = Some(cache);
+    }
+
+    /// Where relative paths in the query are resolved.
+    pub fn base_dir(&self) -> &Path {
+        &self.base_dir
+    }
+
+    pub fn cache(&self) -> Option<&ProviderCache> {
+        self.cache.as_ref()
+    }
+
+    pub fn cache_mut(&mut self) -> Option<&mut ProviderCache> {
+        self.cache.as_mut()
+    }
+
+    /// Bars for the block, or for a FUNDAMENTAL block its filings as
+    /// [`fundamental::point_in_time`] frames them. Filings aren't cached.
+    /// Bars are cut to the calendar's sessions with `PARAM session = regular`.
+    pub fn fetch(&mut self, instance: &ProviderInstance) -> Result<DataFrame, String> {
+        let data = self.dispatch(instance, |provider, cache| match (cache, instance.kind) {
+            (Some(cache), DataKind::Bars) => cache.fetch(instance, provider),
+            (_, DataKind::Bars) => provider.fetch(instance),
+            (_, DataKind::Fundamental) => provider
+                .fetch(instance)
+                .and_then(|raw| fundamental::point_in_time(&raw, instance)),
+        })?;
+        match instance.kind {
+            DataKind::Bars => self.sessions(instance, data),
+            DataKind::Fundamental => Ok(data),
+        }
+    }
+
+    /// The block's exchange calendar: `PARAM calendar = nyse`, `24/7` or a definition
+    /// file relative to `base_dir`; NYSE when not set.
+    pub fn calendar(&self, instance: &ProviderInstance) -> Result<Calendar, String> {
+        match param(instance, "calendar") {
+            Some(name) => Calendar::named(name, &self.base_dir)
+                .map_err(|e| format!("PROVIDER {} ({}): {}", instance.name, instance.span, e)),
+            None => Ok(Calendar::nyse()),
+        }
+    }
+
+    /// `bars` inside the calendar's sessions for `PARAM session = regular`; `all`, the
+    /// default, keeps pre- and post-market bars.
+    fn sessions(&self, instance: &ProviderInstance, bars: DataFrame) -> Result<DataFrame, String> {
+        let context = |e: String| format!("PROVIDER {} ({}): {}", instance.name, instance.span, e);
+        match param(instance, "session")
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            None | Some("all") => Ok(bars),
+            Some("regular") => self
+                .calendar(instance)?
+                .session_bars(&bars)
+                .map_err(context),
+            Some(other) => Err(context(format!(
+                "PARAM session = {}: expected regular or all",
+                other
+            ))),
+        }
+    }
+
+    /// The block's splits and dividends as [`corporate::corporate_actions`] frames them:
+    /// from the file in `PARAM actions` if set, else from its provider.
+    pub fn actions(&mut self, instance: &ProviderInstance) -> Result<DataFrame, String> {
+        let param = |key: &str| param(instance, key);
+        let context = |e: String| format!("PROVIDER {} ({}): {}", instance.name, instance.span, e);
+        let raw = match param("actions") {
+            Some(file) => {
+                let path = self.base_dir.join(file);
+                let format = FileFormat::from_path(&path).ok_or_else(|| {
+                    context(format!(
+                        "PARAM actions = {}: expected a .csv or .parquet file",
+                        file
+                    ))
+                })?;
+                format
+                    .read(&path)
+                    .map_err(|e| context(format!("Failed to read {}: {}", path.display(), e)))?
+            }
+            None => self.dispatch(instance, |provider, _| provider.actions(instance))?,
+        };
+        corporate::corporate_actions(&raw, param("timestamp_format")).map_err(context)
+    }
+
+    /// [`DataProvider::poll`] on the block's provider. Live bars are never cached.
+    pub fn poll(
+        &mut self,
+        instance: &ProviderInstance,
+        after: Option<i64>,
+    ) -> Result<DataFrame, String> {
+        if instance.kind == DataKind::Fundamental {
+            return Err(format!(
+                "PROVIDER {}: FUNDAMENTAL data can't be streamed LIVE, use FROM .. TO",
+                instance.name
+            ));
+        }
+        let bars = self.dispatch(instance, |provider, _| provider.poll(instance, after))?;
+        self.sessions(instance, bars)
+    }
+
+    /// [`DataProvider::control`] on the block's provider.
+    pub fn control(
+        &mut self,
+        instance: &ProviderInstance,
+        control: ReplayControl,
+    ) -> Result<bool, String> {
+        self.dispatch(instance, |provider, _| {
+            Ok(provider.control(instance, control))
+        })
+    }
+
+    /// Run `f` on the provider for `instance`'s backend, with the cache for fallback backends.
+    fn dispatch<T>(
+        &mut self,
+        instance: &ProviderInstance,
+        f: impl FnOnce(&mut dyn DataProvider, Option<&mut ProviderCache>) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let backend = instance
+            .backend
+            .as_deref()
+            .filter(|b| !b.is_empty())
+            .ok_or_else(|| format!("PROVIDER {} has no backend", instance.name))?;
+
+        let result = match self.backends.get_mut(backend) {
+            Some(provider) => f(provider.as_mut(), None),
+            None => {
+                let provider = self
+                    .fallback
+                    .as_mut()
+                    .ok_or_else(|| format!("No data provider for backend '{}'", backend))?;
+                f(provider.as_mut(), self.cache.as_mut())
+            }
+        };
+        result.map_err(|e| format!("PROVIDER {} ({}): {}", instance.name, instance.span, e))
+    }
+}
+
+/// The value of the block's `PARAM key`, keys matched case-insensitively.
+pub(crate) fn param<'a>(instance: &'a ProviderInstance, key: &str) -> Option<&'a str> {
+    instance
+        .params
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Bars with `start <= timestamp < end` (epoch seconds).
+pub(crate) fn bars_between(bars: &DataFrame, start: i64, end: i64) -> Result<DataFrame, String> {
+    if bars.width() == 0 {
+        return Ok(bars.clone());
+    }
+    let ts = bars
+        .column("timestamp")
+        .and_then(|c| c.cast(&DataType::Int64))
+        .map_err(|e| format!("bars: {}", e))?;
+    let ts = ts.i64().map_err(|e| e.to_string())?;
+    let mask = ts.gt_eq(start) & ts.lt(end);
+    bars.filter(&mask).map_err(|e| e.to_string())
+}
+
+/// `new` bars appended to `old`, lined up with `old`'s columns and sorted by timestamp.
+pub(crate) fn append_bars(old: &DataFrame, new: &DataFrame) -> Result<DataFrame, String> {
+    if new.width() == 0 {
+        return Ok(old.clone());
+    }
+    if old.width() == 0 {
+        return Ok(new.clone());
+    }
+    if old.width() != new.width() {
+        return Err("new bars have different columns".to_string());
+    }
+    let aligned = old
+        .get_columns()
+        .iter()
+        .map(|c| {
+            new.column(c.name())
+                .and_then(|n| n.cast(c.dtype()))
+                .map_err(|e| e.to_string())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let aligned = DataFrame::new(aligned).map_err(|e| e.to_string())?;
+
+    let mut bars = old.clone();
+    bars.vstack_mut(&aligned).map_err(|e| e.to_string())?;
+    bars.sort(["timestamp"], SortMultipleOptions::default())
+        .map_err(|e| e.to_string())
+}
+
+/// FNV-1a: a hash that stays the same across builds, for seeds and cache file names.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DataProvider, ProviderRegistry};
+    use crate::parser::{parse, ProviderInstance};
+    use polars::prelude::*;
+
+    #[derive(Debug)]
+    struct Fixed(&'static str);
+
+    impl DataProvider for Fixed {
+        fn fetch(&mut self, instance: &ProviderInstance) -> Result<DataFrame, String> {
+            df!("source" => [self.0], "ticker" => [instance.ticker.clone().unwrap_or_default()])
+                .map_err(|e| e.to_string())
+        }
+    }
+
+    #[test]
+    fn test_registry_dispatches_by_backend() {
+        let src = r#"
+            PROVIDER a USING local
+                TICKER aapl
+                FROM 2020-01-01 TO 2021-01-01
+            PROVIDER b USING elsewhere
+                TICKER msft
+                FROM 2020-01-01 TO 2021-01-01
+        "#;
+        let query = parse(src).unwrap();
+
+        let mut registry = ProviderRegistry::new();
+        registry.register("local", Box::new(Fixed("local")));
+        let err = registry.fetch(&query.providers["b"]).unwrap_err();
+        assert!(
+            err.contains("No data provider for backend 'elsewhere'"),
+            "{err}"
+        );
+
+        registry.set_fallback(Box::new(Fixed("fallback")));
+        for (name, source, ticker) in [("a", "local", "aapl"), ("b", "fallback", "msft")] {
+            let df = registry.fetch(&query.providers[name]).unwrap();
+            assert_eq!(
+                df.column("source").unwrap().str().unwrap().get(0),
+                Some(source)
+            );
+            assert_eq!(
+                df.column("ticker").unwrap().str().unwrap().get(0),
+                Some(ticker)
+            );
+        }
+
+        // the provider server has no splits or dividends to ask for
+        registry.set_fallback(Box::new(super::RemoteProvider::new("127.0.0.1:1")));
+        let err = registry.actions(&query.providers["b"]).unwrap_err();
+        assert!(err.contains("PARAM actions"), "{err}");
+    }
+}
@@ -0,0 +1,79 @@
+use super::DataProvider;
+use crate::json_values_to_df;
+use crate::parser::{DataKind, ProviderInstance};
+use polars::frame::DataFrame;
+use provider::{
+    models::Entity,
+    tcp::client::client::{Client, ClientBuilder},
+};
+use serde_json::Value;
+
+/// Forwards `provider {backend} search ticker=.. date=..` queries to the provider server.
+/// Connects on first use, so engines without remote PROVIDERs never need the server.
+/// The server only has bars: FUNDAMENTAL blocks need a local backend, and ADJUST frames
+/// their splits and dividends from `PARAM actions`.
+#[derive(Debug)]
+pub struct RemoteProvider {
+    addr: String,
+    client: Option<Client>,
+}
+
+impl RemoteProvider {
+    pub fn new(addr: &str) -> Self {
+        Self {
+            addr: addr.to_string(),
+            client: None,
+        }
+    }
+
+    fn client(&mut self) -> Result<&mut Client, String> {
+        if self.client.is_none() {
+            let client = ClientBuilder::new(&self.addr)
+                .connect()
+                .map_err(|e| format!("Failed to connect to provider: {}", e))?;
+            self.client = Some(client);
+        }
+        Ok(self.client.as_mut().unwrap())
+    }
+
+    fn query(&self, instance: &ProviderInstance) -> Result<String, String> {
+        instance
+            .search_query()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "remote providers need a TICKER and a FROM .. TO .. range".to_string())
+    }
+
+    fn request(&mut self, instance: &ProviderInstance, query: String) -> Result<DataFrame, String> {
+        let queries = vec![(instance.name.clone(), query)];
+        let result = self
+            .client()?
+            .get_data::<Vec<Entity>>(queries.clone())
+            .map_err(|e| format!("Failed to get data: {} for {:#?}", e, queries))?;
+
+        let (_, entity) = result
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("No data returned for {:#?}", queries))?;
+        let entity = entity
+            .first()
+            .ok_or_else(|| format!("No data returned for {:#?}", queries))?;
+
+        let data: Vec<Value> = serde_json::from_str(&entity.data)
+            .map_err(|e| format!("Failed to deserialize data: {} for {:#?}", e, entity.data))?;
+        json_values_to_df(&data)
+            .map_err(|e| format!("Failed to deserialize data: {} for {:#?}", e, data))
+    }
+}
+
+impl DataProvider for RemoteProvider {
+    fn fetch(&mut self, instance: &ProviderInstance) -> Result<DataFrame, String> {
+        if instance.kind == DataKind::Fundamental {
+            return Err(format!(
+                "PROVIDER {}: the provider server has no FUNDAMENTAL data, use csv, parquet or synthetic",
+                instance.name
+            ));
+        }
+        let query = self.query(instance)?;
+        self.request(instance, query)
+    }
+}
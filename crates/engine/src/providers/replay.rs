@@ -0,0 +1,276 @@
+use super::{param, DataProvider, FileFormat, FileProvider, ReplayControl, SyntheticProvider};
+use crate::parser::{ProviderInstance, TimeSpec};
+use polars::prelude::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Params the replay reads itself; the rest go to the source.
+const REPLAY_PARAMS: [&str; 5] = ["source", "speed", "warmup", "from", "to"];
+
+/// Replays stored bars as a LIVE feed, to work on LIVE strategies without a market:
+/// `PROVIDER feed USING replay` with `LIVE TICK 1s FOR 1h`.
+///
+/// Params:
+/// - `source`: `csv`, `parquet` or `synthetic`; by default from the extension of `path`
+/// - `from`, `to`: the stored range to replay (required for `synthetic`)
+/// - `speed`: `1` plays the bars in real time, `60` sixty times faster, `step` only on
+///   [`ReplayControl::Step`]; default 1
+/// - `warmup`: bars the run starts with, default 1
+///
+/// Other params go to the source, e.g. `path` and the column mapping of a file.
+/// The TICK is how often the engine asks for bars, so it caps how smooth the replay is.
+#[derive(Debug)]
+pub struct ReplayProvider {
+    csv: FileProvider,
+    parquet: FileProvider,
+    synthetic: SyntheticProvider,
+    /// by PROVIDER block name
+    replays: HashMap<String, Replay>,
+}
+
+impl ReplayProvider {
+    pub fn new(base_dir: impl Into<PathBuf>) -> Self {
+        let base_dir = base_dir.into();
+        Self {
+            csv: FileProvider::new(FileFormat::Csv, &base_dir),
+            parquet: FileProvider::new(FileFormat::Parquet, &base_dir),
+            synthetic: SyntheticProvider::new(),
+            replays: HashMap::new(),
+        }
+    }
+
+    /// Load the stored bars for `instance` and start its replay over.
+    fn load(&mut self, instance: &ProviderInstance, now: Instant) -> Result<&mut Replay, String> {
+        let param = |key: &str| param(instance, key);
+
+        let path_source = param("path").and_then(|p| {
+            let ext = p.rsplit('.').next()?.to_ascii_lowercase();
+            ["csv", "parquet"].contains(&ext.as_str()).then_some(ext)
+        });
+        let source = match param("source").map(str::to_ascii_lowercase).or(path_source) {
+            Some(source) => source,
+            None if param("path").is_none() => "synthetic".to_string(),
+            None => return Err("can't tell the file type, set PARAM source = csv".to_string()),
+        };
+        let provider: &mut dyn DataProvider = match source.as_str() {
+            "csv" => &mut self.csv,
+            "parquet" => &mut self.parquet,
+            "synthetic" => &mut self.synthetic,
+            other => return Err(format!("PARAM source = {}: can't replay from it", other)),
+        };
+
+        let speed = match param("speed") {
+            None => Some(1.0),
+            Some(s) if s.eq_ignore_ascii_case("step") => None,
+            Some(s) => match s.trim_end_matches(['x', 'X']).parse::<f64>() {
+                Ok(speed) if speed > 0.0 => Some(speed),
+                _ => return Err(format!("PARAM speed = {}: expected a number or step", s)),
+            },
+        };
+        let warmup = match param("warmup") {
+            Some(w) => w
+                .parse()
+                .map_err(|_| format!("PARAM warmup = {}: expected a number of bars", w))?,
+            None => 1,
+        };
+
+        let stored = ProviderInstance {
+            backend: Some(source),
+            time_spec: match (param("from"), param("to")) {
+                (Some(from), Some(to)) => Some(TimeSpec::DateRange {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                }),
+                (None, None) => None,
+                _ => return Err("replay needs both PARAM from and PARAM to".to_string()),
+            },
+            params: instance
+                .params
+                .iter()
+                .filter(|(k, _)| !REPLAY_PARAMS.iter().any(|p| k.eq_ignore_ascii_case(p)))
+                .cloned()
+                .collect(),
+            ..instance.clone()
+        };
+        let bars = provider.fetch(&stored)?;
+
+        let replay = Replay::new(bars, speed, warmup, now)?;
+        self.replays.insert(instance.name.clone(), replay);
+        Ok(self.replays.get_mut(&instance.name).unwrap())
+    }
+}
+
+impl DataProvider for ReplayProvider {
+    fn fetch(&mut self, _instance: &ProviderInstance) -> Result<DataFrame, String> {
+        Err("replay is a LIVE feed, use LIVE TICK .. FOR ..".to_string())
+    }
+
+    fn poll(
+        &mut self,
+        instance: &ProviderInstance,
+        after: Option<i64>,
+    ) -> Result<DataFrame, String> {
+        let now = Instant::now();
+        let replay = match (after, self.replays.contains_key(&instance.name)) {
+            (Some(_), true) => self.replays.get_mut(&instance.name).unwrap(),
+            _ => self.load(instance, now)?,
+        };
+        Ok(replay.released(now, after))
+    }
+
+    fn control(&mut self, instance: &ProviderInstance, control: ReplayControl) -> bool {
+        match self.replays.get_mut(&instance.name) {
+            Some(replay) => {
+                replay.control(control, Instant::now());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// One block's replay: a clock over the stored bars' timestamps that runs `speed` times
+/// faster than the wall clock, and the bars it has passed so far.
+#[derive(Debug)]
+struct Replay {
+    bars: DataFrame,
+    timestamps: Vec<i64>,
+    released: usize,
+    /// `None` when stepped
+    speed: Option<f64>,
+    paused: bool,
+    /// replay time (a bar timestamp) at `since`
+    clock: f64,
+    since: Instant,
+}
+
+impl Replay {
+    fn new(
+        bars: DataFrame,
+        speed: Option<f64>,
+        warmup: usize,
+        now: Instant,
+    ) -> Result<Self, String> {
+        let bars = bars
+            .sort(["timestamp"], SortMultipleOptions::default())
+            .map_err(|e| format!("Failed to sort bars: {}", e))?;
+        let timestamps: Vec<i64> = bars
+            .column("timestamp")
+            .and_then(|c| c.cast(&DataType::Int64))
+            .map_err(|e| format!("bars: {}", e))?
+            .i64()
+            .map_err(|e| e.to_string())?
+            .into_no_null_iter()
+            .collect();
+        if timestamps.is_empty() {
+            return Err("nothing to replay, the stored range has no bars".to_string());
+        }
+
+        let released = warmup.clamp(1, timestamps.len());
+        Ok(Replay {
+            clock: timestamps[released - 1] as f64,
+            bars,
+            timestamps,
+            released,
+            speed,
+            paused: false,
+            since: now,
+        })
+    }
+
+    fn clock_at(&self, now: Instant) -> f64 {
+        match self.speed {
+            Some(speed) if !self.paused => {
+                self.clock + now.duration_since(self.since).as_secs_f64() * speed
+            }
+            _ => self.clock,
+        }
+    }
+
+    /// The bars the clock has passed by `now` that are newer than `after`.
+    fn released(&mut self, now: Instant, after: Option<i64>) -> DataFrame {
+        self.released = self.released.max(self.passed(now));
+
+        let first = match after {
+            Some(after) => self.timestamps[..self.released].partition_point(|t| *t <= after),
+            None => 0,
+        };
+        self.bars.slice(first as i64, self.released - first)
+    }
+
+    fn control(&mut self, control: ReplayControl, now: Instant) {
+        match control {
+            ReplayControl::Pause => {
+                self.clock = self.clock_at(now);
+                self.paused = true;
+            }
+            ReplayControl::Resume => self.paused = false,
+            ReplayControl::Step => {
+                self.released = self.released.max(self.passed(now));
+                self.released = (self.released + 1).min(self.timestamps.len());
+                self.clock = self.timestamps[self.released - 1] as f64;
+            }
+        }
+        self.since = now;
+    }
+
+    /// How many bars are at or before the clock.
+    fn passed(&self, now: Instant) -> usize {
+        let clock = self.clock_at(now);
+        self.timestamps.partition_point(|t| *t as f64 <= clock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Replay;
+    use crate::providers::ReplayControl;
+    use polars::prelude::*;
+    use std::time::{Duration, Instant};
+
+    fn bars(n: i64) -> DataFrame {
+        let ts: Vec<i64> = (0..n).map(|i| 1_600_000_000 + i * 60).collect();
+        let close: Vec<f64> = (0..n).map(|i| i as f64).collect();
+        df!("timestamp" => ts, "close" => close).unwrap()
+    }
+
+    fn closes(df: &DataFrame) -> Vec<f64> {
+        df.column("close")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_replay_speed_pause_and_step() {
+        let start = Instant::now();
+        let secs = |s: u64| start + Duration::from_secs(s);
+
+        // 10x: a one-minute bar every 6 seconds
+        let mut replay = Replay::new(bars(20), Some(10.0), 3, start).unwrap();
+        assert_eq!(closes(&replay.released(start, None)), [0.0, 1.0, 2.0]);
+        let last = 1_600_000_000 + 2 * 60;
+        assert!(replay.released(secs(5), Some(last)).is_empty());
+        assert_eq!(closes(&replay.released(secs(13), Some(last))), [3.0, 4.0]);
+
+        replay.control(ReplayControl::Pause, secs(13));
+        assert_eq!(replay.released(secs(60), None).height(), 5);
+        replay.control(ReplayControl::Step, secs(60));
+        replay.control(ReplayControl::Step, secs(61));
+        assert_eq!(replay.released(secs(62), None).height(), 7);
+        // the clock carries on from the stepped bar
+        replay.control(ReplayControl::Resume, secs(70));
+        assert_eq!(replay.released(secs(76), None).height(), 8);
+        assert_eq!(replay.released(secs(1_000), None).height(), 20);
+
+        let mut stepped = Replay::new(bars(3), None, 1, start).unwrap();
+        assert_eq!(stepped.released(secs(600), None).height(), 1);
+        for _ in 0..5 {
+            stepped.control(ReplayControl::Step, secs(600));
+        }
+        assert_eq!(closes(&stepped.released(secs(600), None)), [0.0, 1.0, 2.0]);
+    }
+}
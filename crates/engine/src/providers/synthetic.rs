@@ -0,0 +1,239 @@
+use super::{fnv1a, DataProvider};
+use crate::parser::{date_to_epoch, duration_to_seconds, DataKind, ProviderInstance, TimeSpec};
+use polars::prelude::*;
+
+const SECONDS_PER_YEAR: f64 = 365.25 * 86_400.0;
+/// Steps simulated inside each bar to get its high and low.
+const SUBSTEPS: usize = 8;
+
+/// Seeded geometric Brownian motion bars, for tests and demos without a provider server:
+/// `PROVIDER demo USING synthetic`.
+///
+/// Params (all optional):
+/// - `seed` (default 42); the TICKER is mixed in, so tickers sharing a seed still differ
+/// - `drift`, `volatility`: annualised, default 0.05 and 0.2
+/// - `start_price`: default 100
+/// - `interval`: bar spacing as a duration, default `1d`
+///
+/// Bars run from midnight of the FROM day to the end of the TO day, in the remote
+/// provider's shape (`timestamp` in epoch seconds, OHLC and `adjclose` as `f64`, `i64` volume).
+/// The same query always gets the same bars.
+#[derive(Debug, Default)]
+pub struct SyntheticProvider;
+
+impl SyntheticProvider {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct GbmParams {
+    seed: u64,
+    drift: f64,
+    volatility: f64,
+    start_price: f64,
+    interval: i64,
+}
+
+impl GbmParams {
+    fn from_instance(instance: &ProviderInstance) -> Result<Self, String> {
+        let mut params = GbmParams {
+            seed: 42,
+            drift: 0.05,
+            volatility: 0.2,
+            start_price: 100.0,
+            interval: 86_400,
+        };
+        for (key, value) in &instance.params {
+            let number = || {
+                value
+                    .parse::<f64>()
+                    .map_err(|_| format!("PARAM {} = {}: expected a number", key, value))
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "seed" => {
+                    params.seed = value
+                        .parse()
+                        .map_err(|_| format!("PARAM seed = {}: expected an integer", value))?
+                }
+                "drift" => params.drift = number()?,
+                "volatility" => params.volatility = number()?,
+                "start_price" => params.start_price = number()?,
+                "interval" => params.interval = duration_to_seconds(value)?,
+                // read by the registry, not the generator
+                "actions" | "calendar" | "session" => {}
+                _ => return Err(format!("unknown PARAM {} for synthetic data", key)),
+            }
+        }
+        if params.volatility < 0.0 || params.start_price <= 0.0 {
+            return Err("volatility must be >= 0 and start_price > 0".to_string());
+        }
+        // one stream per ticker
+        if let Some(ticker) = &instance.ticker {
+            params.seed ^= fnv1a(ticker.to_ascii_uppercase().as_bytes());
+        }
+        Ok(params)
+    }
+}
+
+impl DataProvider for SyntheticProvider {
+    fn fetch(&mut self, instance: &ProviderInstance) -> Result<DataFrame, String> {
+        if instance.kind == DataKind::Fundamental {
+            return Err("synthetic data has no fundamentals".to_string());
+        }
+        let params = GbmParams::from_instance(instance)?;
+        let (start, end) = match &instance.time_spec {
+            Some(TimeSpec::DateRange { from, to }) => {
+                (date_to_epoch(from)?, date_to_epoch(to)? + 86_400)
+            }
+            _ => return Err("synthetic data needs a FROM .. TO range".to_string()),
+        };
+        generate(&params, start, end)
+    }
+}
+
+/// Bars at `start, start + interval, ..` up to (not including) `end`.
+fn generate(params: &GbmParams, start: i64, end: i64) -> Result<DataFrame, String> {
+    let mut rng = SplitMix64::new(params.seed);
+    let dt = params.interval as f64 / SECONDS_PER_YEAR / SUBSTEPS as f64;
+    let step_drift = (params.drift - 0.5 * params.volatility * params.volatility) * dt;
+    let step_vol = params.volatility * dt.sqrt();
+
+    let bars = ((end - start).max(0) + params.interval - 1) / params.interval;
+    let mut timestamp = Vec::with_capacity(bars as usize);
+    let (mut open, mut high, mut low, mut close) = (vec![], vec![], vec![], vec![]);
+    let mut volume = Vec::with_capacity(bars as usize);
+
+    let mut price = params.start_price;
+    for i in 0..bars {
+        let bar_open = price;
+        let (mut bar_high, mut bar_low) = (price, price);
+        let mut moved = 0.0;
+        for _ in 0..SUBSTEPS {
+            let z = rng.normal();
+            moved += z.abs();
+            price *= (step_drift + step_vol * z).exp();
+            bar_high = bar_high.max(price);
+            bar_low = bar_low.min(price);
+        }
+        timestamp.push(start + i * params.interval);
+        open.push(bar_open);
+        high.push(bar_high);
+        low.push(bar_low);
+        close.push(price);
+        // busier bars trade more
+        volume.push((1_000_000.0 * (0.5 + moved / SUBSTEPS as f64)) as i64);
+    }
+
+    df!(
+        "timestamp" => timestamp,
+        "open" => open,
+        "high" => high,
+        "low" => low,
+        "close" => close.clone(),
+        "adjclose" => close,
+        "volume" => volume,
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Small self-contained PRNG, so a seed gives the same bars on every build.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in (0, 1].
+    fn uniform(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal (Box-Muller).
+    fn normal(&mut self) -> f64 {
+        let (u1, u2) = (self.uniform(), self.uniform());
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SyntheticProvider;
+    use crate::parser::parse;
+    use crate::providers::DataProvider;
+    use polars::prelude::*;
+
+    fn fetch(src: &str) -> Result<DataFrame, String> {
+        let query = parse(src).unwrap();
+        let instance = query.providers.values().next().unwrap();
+        SyntheticProvider::new().fetch(instance)
+    }
+
+    fn column(df: &DataFrame, name: &str) -> Vec<f64> {
+        df.column(name)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_synthetic_is_seeded() {
+        let src = |seed: u32, ticker: &str| {
+            format!(
+                "PROVIDER demo USING synthetic\n    TICKER {}\n    FROM 2020-01-01 TO 2020-12-31\n    PARAM seed = {}\n",
+                ticker, seed
+            )
+        };
+        let a = fetch(&src(7, "aapl")).unwrap();
+        assert_eq!(a.height(), 366);
+        assert!(a.equals(&fetch(&src(7, "aapl")).unwrap()));
+        assert!(!a.equals(&fetch(&src(8, "aapl")).unwrap()));
+        assert!(!a.equals(&fetch(&src(7, "msft")).unwrap()));
+        assert_eq!(column(&a, "open")[0], 100.0);
+
+        let (open, high, low, close) = (
+            column(&a, "open"),
+            column(&a, "high"),
+            column(&a, "low"),
+            column(&a, "close"),
+        );
+        for i in 0..a.height() {
+            assert!(low[i] > 0.0 && low[i] <= open[i].min(close[i]));
+            assert!(high[i] >= open[i].max(close[i]));
+            if i > 0 {
+                assert_eq!(open[i], close[i - 1]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_synthetic_params() {
+        let df = fetch(
+            "PROVIDER demo USING synthetic\n    FROM 2020-01-01 TO 2020-01-01\n    PARAM interval = 1h\n    PARAM start_price = 50\n    PARAM volatility = 0\n    PARAM drift = 0\n",
+        )
+        .unwrap();
+        assert_eq!(df.height(), 24);
+        let ts = df.column("timestamp").unwrap().i64().unwrap();
+        assert_eq!(ts.get(1).unwrap() - ts.get(0).unwrap(), 3_600);
+        assert!(column(&df, "close")
+            .iter()
+            .all(|c| (*c - 50.0).abs() < 1e-9));
+
+        let err = fetch("PROVIDER demo USING synthetic\n    FROM 2020-01-01 TO 2020-01-02\n    PARAM colour = red\n")
+            .unwrap_err();
+        assert!(err.contains("unknown PARAM colour"), "{err}");
+        assert!(fetch("PROVIDER demo USING synthetic\n    LIVE TICK 1m FOR 1d\n").is_err());
+    }
+}
@@ -1,7 +1,9 @@
+use crate::profile;
 use anyhow::{anyhow, Context, Result};
 use polars::prelude::*;
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use wgpu::{util::DeviceExt, *};
 
 /* ----------------------- Built-in WGSL and helpers -------------------- */
@@ -9,11 +11,23 @@ use wgpu::{util::DeviceExt, *};
 /// y_idx = i * y[i]
 pub const WGSL_MUL_INDEX: &'static str = include_str!("shaders/mul_index.wgsl");
 
-/// Simple parallel reduction into a single f32 (sum)
-/// This uses a single-pass atomic-add pattern for simplicity and portability.
-/// For large arrays, a two-pass tree reduction is faster; this is acceptable to start.
+/// Compensated sum of a column into a len-2 output, the sum and its rounding error:
+/// read it with [`GpuRuntime::download_compensated`]. Within `2u * sum|x|` of the exact
+/// sum of the f32 inputs (`u = 2^-24`), where plain f32 accumulation drifts by up to
+/// `(n - 1)u * sum|x|`. One workgroup, so it's deterministic but not fast on huge columns:
+/// [`GpuRuntime::column_stats`] spreads the same sum over many.
 pub const WGSL_REDUCE_SUM: &'static str = include_str!("shaders/reduce_sum.wgsl");
 
+/// Compensated `[n, sum_x, sum_y, sum_xx, sum_xy]` of `(i, y[i] - shift)` over the non-NaN
+/// `y`, as (sum, error) pairs, for a least squares line solved in f64. A two-pass reduction:
+/// run it with [`GpuRuntime::tree_reduce`].
+pub const WGSL_LINREG_REDUCE: &str = include_str!("shaders/linreg_reduce.wgsl");
+
+/// Count, compensated sum, mean, variance, min and max of the non-NaN values of a column,
+/// as 8 f32 per state. A two-pass reduction: run it with [`GpuRuntime::tree_reduce`], or
+/// [`GpuRuntime::column_stats`] to have the statistics.
+pub const WGSL_REDUCE_STATS: &str = include_str!("shaders/reduce_stats.wgsl");
+
 /// Elementwise: out[i] = a + b * float(i)
 pub const WGSL_AXPB_INDEX: &'static str = include_str!("shaders/axpb_index.wgsl");
 
@@ -37,6 +51,20 @@ impl GpuDType {
     }
 }
 
+/// Whole-column statistics over the non-missing (non-NaN) values, from
+/// [`GpuRuntime::column_stats`]. With no values, `sum` is 0 and the rest NaN.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColumnStats {
+    pub count: usize,
+    /// Compensated: within a few f32 ulps of the f64 sum of the values.
+    pub sum: f64,
+    pub mean: f64,
+    /// Sample variance, over `count - 1`.
+    pub variance: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
 pub struct GpuColumn {
     pub name: String,
     pub dtype: GpuDType,
@@ -45,9 +73,16 @@ pub struct GpuColumn {
     pub usage: BufferUsages,
 }
 
+/// Name of the u32 column of a table's segment offsets, `segments.len()` of them.
+pub const SEGMENTS: &str = "__segments";
+
 pub struct GpuTable {
     pub columns: HashMap<String, GpuColumn>,
     pub row_count: usize, // length of “column outputs”
+    /// Row offsets of the frames packed into the table: frame `k` is rows
+    /// `segments[k]..segments[k + 1]`. Windowed kernels read them from the [`SEGMENTS`]
+    /// column so no window reaches into the next frame.
+    pub segments: Vec<usize>,
 }
 impl GpuTable {
     pub fn get(&self, name: &str) -> Result<&GpuColumn> {
@@ -131,7 +166,8 @@ impl GpuRuntime {
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: Some("compute-device"),
-                required_features: wgpu::Features::empty(),
+                // for the profile's GPU step times, where the adapter has them
+                required_features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
                 required_limits: limits,
                 memory_hints: wgpu::MemoryHints::MemoryUsage,
                 trace: wgpu::Trace::Off,
@@ -156,6 +192,16 @@ impl GpuRuntime {
         })
     }
 
+    /// Name of the adapter the device runs on.
+    pub fn adapter_name(&self) -> String {
+        self.adapter.get_info().name
+    }
+
+    /// Shaders compiled so far.
+    pub fn cached_shaders(&self) -> usize {
+        self.shader_cache.len()
+    }
+
     /// If `columns` is None, upload all GPU-supported columns.
     pub fn upload_dataframe(&self, df: &DataFrame, columns: Option<&[&str]>) -> Result<GpuTable> {
         let names: Vec<&str> = match columns {
@@ -166,38 +212,63 @@ impl GpuRuntime {
                 .map(|s| s.as_str())
                 .collect(),
         };
-        let row_count = df.height();
-        let mut map = HashMap::new();
-
+        let mut table = GpuTable {
+            columns: HashMap::new(),
+            row_count: df.height(),
+            segments: Vec::new(),
+        };
         for name in names {
             let Ok(s) = df.column(name) else {
                 continue;
             };
-            let Some(gpu_dt) = GpuDType::from_polars(s.dtype()) else {
-                continue;
-            };
-            let (bytes, len) = series_as_bytes(s.as_series().unwrap(), gpu_dt)?;
-            let buffer = self.device.create_buffer_init(&util::BufferInitDescriptor {
-                label: Some(&format!("col:{name}")),
-                contents: &bytes,
-                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
-            });
-            map.insert(
-                name.to_string(),
-                GpuColumn {
-                    name: name.to_string(),
-                    dtype: gpu_dt,
-                    len,
-                    buffer,
-                    usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
-                },
-            );
+            if GpuDType::from_polars(s.dtype()).is_some() {
+                self.upload_column(&mut table, s.as_materialized_series())?;
+            }
         }
+        self.set_segments(&mut table, &[0, df.height()])?;
+        Ok(table)
+    }
 
-        Ok(GpuTable {
-            columns: map,
-            row_count,
-        })
+    /// Split `table` into frames at the row `offsets` (from 0 up to the row count), for
+    /// running one pipeline over many frames packed end to end.
+    pub fn set_segments(&self, table: &mut GpuTable, offsets: &[usize]) -> Result<()> {
+        let ordered = offsets.windows(2).all(|w| w[0] <= w[1]);
+        if offsets.first() != Some(&0) || offsets.last() != Some(&table.row_count) || !ordered {
+            return Err(anyhow!(
+                "segment offsets {offsets:?} don't split {} rows",
+                table.row_count
+            ));
+        }
+        let values: Vec<u32> = offsets.iter().map(|&o| o as u32).collect();
+        self.upload_column(table, &Series::new(SEGMENTS.into(), values))?;
+        table.segments = offsets.to_vec();
+        Ok(())
+    }
+
+    /// Upload `series` into `table` under its name, replacing any column there, for later
+    /// steps to read (e.g. the output of a CPU calc between GPU ones).
+    pub fn upload_column(&self, table: &mut GpuTable, series: &Series) -> Result<()> {
+        let name = series.name().as_str();
+        let gpu_dt = GpuDType::from_polars(series.dtype())
+            .ok_or_else(|| anyhow!("'{name}' has no GPU dtype: {:?}", series.dtype()))?;
+        let (bytes, len) = series_as_bytes(series, gpu_dt)?;
+        let usage = BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+        let buffer = self.device.create_buffer_init(&util::BufferInitDescriptor {
+            label: Some(&format!("col:{name}")),
+            contents: &bytes,
+            usage,
+        });
+        table.columns.insert(
+            name.to_string(),
+            GpuColumn {
+                name: name.to_string(),
+                dtype: gpu_dt,
+                len,
+                buffer,
+                usage,
+            },
+        );
+        Ok(())
     }
 
     pub fn ensure_shader<'a>(&mut self, key: &str, wgsl_src: Option<Cow<'a, str>>) -> Result<()> {
@@ -214,14 +285,40 @@ impl GpuRuntime {
     }
 
     /// Run kernels; **append all declared outputs** to the table (variable lengths allowed).
+    /// The steps go to the GPU in one submission, each seeing the outputs of those before it.
     pub fn run_pipeline(
         &mut self,
         table: &mut GpuTable,
         steps: &[KernelStep],
     ) -> Result<Vec<String>> {
+        let _dispatch = profile::span("GPU dispatch");
         let mut created = Vec::new();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("kernel-encoder"),
+            });
+        // each step's time on the GPU for the profile, when the device can tell
+        let timestamps = (profile::is_recording()
+            && !steps.is_empty()
+            && self.device.features().contains(Features::TIMESTAMP_QUERY))
+        .then(|| {
+            let count = 2 * steps.len() as u32;
+            let set = self.device.create_query_set(&QuerySetDescriptor {
+                label: Some("kernel-timestamps"),
+                ty: QueryType::Timestamp,
+                count,
+            });
+            let resolve = self.device.create_buffer(&BufferDescriptor {
+                label: Some("kernel-timestamps"),
+                size: count as u64 * 8,
+                usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            (set, resolve)
+        });
 
-        for step in steps {
+        for (i, step) in steps.iter().enumerate() {
             self.ensure_shader(&step.shader_key, step.wgsl_src.clone())?;
             let module = self
                 .shader_cache
@@ -381,15 +478,16 @@ impl GpuRuntime {
                 entries: &bg_entries,
             });
 
-            let mut encoder = self
-                .device
-                .create_command_encoder(&CommandEncoderDescriptor {
-                    label: Some("kernel-encoder"),
-                });
             {
                 let mut cpass = encoder.begin_compute_pass(&ComputePassDescriptor {
                     label: Some("kernel-pass"),
-                    timestamp_writes: None,
+                    timestamp_writes: timestamps.as_ref().map(|(set, _)| {
+                        ComputePassTimestampWrites {
+                            query_set: set,
+                            beginning_of_pass_write_index: Some(2 * i as u32),
+                            end_of_pass_write_index: Some(2 * i as u32 + 1),
+                        }
+                    }),
                 });
                 cpass.set_pipeline(&pipeline);
                 cpass.set_bind_group(0, &bind_group_io, &[]);
@@ -397,13 +495,16 @@ impl GpuRuntime {
                     cpass.set_bind_group(1, bg_uni, &[]);
                 }
 
-                // Dispatch shape: default to row_count-based; if only scalar outputs and no inputs,
-                // allow dispatching a single group safely.
+                // Dispatch shape: default to row_count-based; with no inputs, one thread per
+                // element of the longest output (1 for pure-scalar kernels)
                 let total = if !step.inputs.is_empty() {
                     table.row_count as u32
                 } else {
-                    // e.g., pure-scalar compute kernels can still run with 1 element
-                    1
+                    step.outputs
+                        .iter()
+                        .map(|out| out.len.unwrap_or(table.row_count))
+                        .max()
+                        .unwrap_or(1) as u32
                 };
 
                 let elems_per_inv = step.elems_per_invocation.max(1);
@@ -412,8 +513,29 @@ impl GpuRuntime {
                 let groups_x = (invocations + workgroup - 1) / workgroup;
                 cpass.dispatch_workgroups(groups_x.max(1), 1, 1);
             }
-            self.queue.submit(std::iter::once(encoder.finish()));
-            let _ = self.device.poll(wgpu::PollType::Wait);
+        }
+        if let Some((set, resolve)) = &timestamps {
+            encoder.resolve_query_set(set, 0..2 * steps.len() as u32, resolve, 0);
+        }
+        let submitted = Instant::now();
+        self.queue.submit(std::iter::once(encoder.finish()));
+        let _ = self.device.poll(wgpu::PollType::Wait);
+
+        if let Some((_, resolve)) = &timestamps {
+            let bytes = self.read_buffer_bytes(resolve, steps.len() * 16)?;
+            let ticks: Vec<u64> = bytes
+                .chunks_exact(8)
+                .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+                .collect();
+            let ns_per_tick = self.queue.get_timestamp_period() as f64;
+            let nanos = |ticks: u64| Duration::from_nanos((ticks as f64 * ns_per_tick) as u64);
+            for (step, pass) in steps.iter().zip(ticks.chunks_exact(2)) {
+                profile::gpu(
+                    step.shader_key.as_ref(),
+                    submitted + nanos(pass[0].saturating_sub(ticks[0])),
+                    nanos(pass[1].saturating_sub(pass[0])),
+                );
+            }
         }
         Ok(created)
     }
@@ -457,6 +579,92 @@ impl GpuRuntime {
         Ok(arr[0])
     }
 
+    /// Download the column-shaped outputs `names` from `table`, all in one copy.
+    pub fn download_columns(&self, table: &GpuTable, names: &[&str]) -> Result<Vec<Series>> {
+        let columns = names
+            .iter()
+            .map(|name| table.get(name))
+            .collect::<Result<Vec<_>>>()?;
+        let mut offsets = Vec::with_capacity(columns.len());
+        let mut total = 0u64;
+        for col in &columns {
+            offsets.push(total);
+            let size = (col.len * col.dtype.size()) as u64;
+            total += size.next_multiple_of(COPY_BUFFER_ALIGNMENT);
+        }
+        if total == 0 {
+            return columns
+                .iter()
+                .map(|col| series_from_bytes(&col.name, col.dtype, 0, &[]))
+                .collect();
+        }
+
+        let staging = self.device.create_buffer(&BufferDescriptor {
+            label: Some("staging"),
+            size: total,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("copy-encoder"),
+            });
+        for (col, offset) in columns.iter().zip(&offsets) {
+            let size = (col.len * col.dtype.size()) as u64;
+            encoder.copy_buffer_to_buffer(
+                &col.buffer,
+                0,
+                &staging,
+                *offset,
+                size.next_multiple_of(COPY_BUFFER_ALIGNMENT),
+            );
+        }
+        self.queue.submit(Some(encoder.finish()));
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        staging.slice(..).map_async(MapMode::Read, move |r| {
+            tx.send(r).ok();
+        });
+        let _ = self.device.poll(wgpu::PollType::Wait);
+        rx.recv()
+            .unwrap()
+            .map_err(|_| anyhow!("map_async failed"))?;
+
+        let view = staging.slice(..).get_mapped_range();
+        let series = columns
+            .iter()
+            .zip(&offsets)
+            .map(|(col, offset)| {
+                let start = *offset as usize;
+                let bytes = &view[start..start + col.len * col.dtype.size()];
+                series_from_bytes(&col.name, col.dtype, col.len, bytes)
+            })
+            .collect();
+        drop(view);
+        staging.unmap();
+        series
+    }
+
+    /// Download the (sum, rounding error) f32 pairs of a compensated reduction as f64 sums.
+    pub fn download_compensated(&self, table: &GpuTable, name: &str) -> Result<Vec<f64>> {
+        let col = table.get(name)?;
+        if col.dtype != GpuDType::F32 || col.len % 2 != 0 {
+            return Err(anyhow!(
+                "download_compensated expects f32 pairs; '{}' has dtype {:?} len {}",
+                name,
+                col.dtype,
+                col.len
+            ));
+        }
+        let bytes = self.read_buffer_bytes(&col.buffer, col.len * 4)?;
+        let arr: &[f32] = bytemuck::try_cast_slice(&bytes).unwrap();
+        Ok(arr
+            .chunks(2)
+            .map(|pair| pair[0] as f64 + pair[1] as f64)
+            .collect())
+    }
+
     /// Low-level buffer read helper
     fn read_buffer_bytes(&self, buf: &Buffer, byte_len: usize) -> Result<Vec<u8>> {
         let staging = self.device.create_buffer(&BufferDescriptor {
@@ -506,84 +714,149 @@ impl GpuRuntime {
         Ok(out)
     }
 
-    /// Compute global linear regression (x = 0..N-1) predictions into `alias`.
-    /// Returns (a, b) as (intercept, slope) too, if you need them.
+    /// Two-pass tree reduction of column `input` with a shader that has two entry points
+    /// over states of `width` f32: `partials`, each workgroup folding a grid stride of the
+    /// column into one state at `width * workgroup_id` of its output, then `combine`, one
+    /// workgroup folding those into the first state of its own. Both take `uniform` at
+    /// group(1). Returns the final state.
+    ///
+    /// At most 256 workgroups run the first pass, so the second is a single tree.
+    pub fn tree_reduce(
+        &mut self,
+        table: &mut GpuTable,
+        key: &str,
+        wgsl_src: &str,
+        input: &str,
+        width: usize,
+        uniform: Option<Vec<u8>>,
+    ) -> Result<Vec<f32>> {
+        const WORKGROUP: usize = 256;
+        let len = table.get(input)?.len;
+        let per_thread = len.div_ceil(WORKGROUP * WORKGROUP).max(1);
+        let groups = len.div_ceil(per_thread).div_ceil(WORKGROUP).max(1);
+
+        let partials = format!("{key}:{input}:partials");
+        let result = format!("{key}:{input}");
+        let step =
+            |entry: &'static str, input: &str, output: &str, len: usize, elems: usize| KernelStep {
+                shader_key: Cow::Owned(key.to_string()),
+                wgsl_src: Some(Cow::Owned(wgsl_src.to_string())),
+                entry_point: Cow::Borrowed(entry),
+                inputs: vec![Cow::Owned(input.to_string())],
+                outputs: vec![OutputSpec::with_len(output.to_string(), GpuDType::F32, len)],
+                push_constants: None,
+                workgroup_size_x: WORKGROUP as u32,
+                elems_per_invocation: elems as u32,
+                uniform_bytes: uniform.clone(),
+            };
+        let steps = [
+            step("partials", input, &partials, groups * width, per_thread),
+            // one workgroup, however long the frame
+            step("combine", &partials, &result, width, table.row_count.max(1)),
+        ];
+        let run = self.run_pipeline(table, &steps);
+        let state = run.and_then(|_| {
+            let col = table.get(&result)?;
+            let bytes = self.read_buffer_bytes(&col.buffer, width * 4)?;
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+                .collect())
+        });
+        table.columns.remove(&partials);
+        table.columns.remove(&result);
+        state
+    }
+
+    /// Count, sum, mean, variance, min and max of a column's non-NaN values, in one
+    /// two-pass reduction on the GPU.
+    pub fn column_stats(&mut self, table: &mut GpuTable, name: &str) -> Result<ColumnStats> {
+        let state = self.tree_reduce(table, "reduce_stats", WGSL_REDUCE_STATS, name, 8, None)?;
+        let pair = |k: usize| state[k] as f64 + state[k + 1] as f64;
+        let (count, sum) = (pair(0), pair(2));
+        let none = count == 0.0;
+        Ok(ColumnStats {
+            count: count as usize,
+            sum,
+            mean: if none { f64::NAN } else { sum / count },
+            variance: if count < 2.0 {
+                f64::NAN
+            } else {
+                state[5] as f64 / (count - 1.0)
+            },
+            min: if none { f64::NAN } else { state[6] as f64 },
+            max: if none { f64::NAN } else { state[7] as f64 },
+        })
+    }
+
+    /// Compute global linear regression (x = 0..N-1) predictions into `alias`, skipping
+    /// NaN `y`. Returns (a, b) as (intercept, slope) too, if you need them.
+    ///
+    /// The sums are compensated on the GPU and the line solved in f64, so the fit matches an
+    /// f64 fit of the same f32 values to a few f32 ulps.
     pub fn linear_regression_global(
         &mut self,
         df: &DataFrame,
         y_col: &str,
         out_alias: &str,
     ) -> Result<(DataFrame, f32, f32)> {
-        // Upload just y_col
-        let mut table = self.upload_dataframe(df, Some(&[y_col]))?;
+        // Upload just y_col, as f32 whatever its dtype
+        let y = df.column(y_col)?.cast(&DataType::Float32)?;
+        let mut table = self.upload_dataframe(&DataFrame::new(vec![y.clone()])?, None)?;
 
-        // (1) prod = i * y
-        let step_prod = KernelStep {
-            shader_key: Cow::Borrowed("mul_index"),
-            wgsl_src: Some(Cow::Borrowed(WGSL_MUL_INDEX)),
-            entry_point: Cow::Borrowed("main"),
-            inputs: vec![Cow::Borrowed(y_col)],
-            outputs: vec![OutputSpec::column("iy", GpuDType::F32)],
-            push_constants: None,
-            workgroup_size_x: 256,
-            elems_per_invocation: 1,
-            uniform_bytes: None,
-        };
-        self.run_pipeline(&mut table, &[step_prod])?;
-
-        // (2) reduce sum_y and sum_iy to scalars
-        // Initialize the scalar outputs with 0s by creating buffers len=1
-        let step_sum_y = KernelStep {
-            shader_key: Cow::Borrowed("reduce_sum"),
-            wgsl_src: Some(Cow::Borrowed(WGSL_REDUCE_SUM)),
-            entry_point: Cow::Borrowed("main"),
-            inputs: vec![Cow::Borrowed(y_col)],
-            outputs: vec![OutputSpec::scalar("sum_y", GpuDType::F32)],
-            push_constants: None,
-            workgroup_size_x: 256,
-            elems_per_invocation: 1,
-            uniform_bytes: None,
-        };
-        let step_sum_iy = KernelStep {
-            shader_key: Cow::Borrowed("reduce_sum"),
-            wgsl_src: Some(Cow::Borrowed(WGSL_REDUCE_SUM)),
-            entry_point: Cow::Borrowed("main"),
-            inputs: vec![Cow::Borrowed("iy")],
-            outputs: vec![OutputSpec::scalar("sum_iy", GpuDType::F32)],
-            push_constants: None,
-            workgroup_size_x: 256,
-            elems_per_invocation: 1,
-            uniform_bytes: None,
+        // (1) sums of (i, y - shift), shifted by a y value so sum_xy doesn't cancel
+        let shift = y
+            .f32()?
+            .into_iter()
+            .flatten()
+            .find(|y| y.is_finite())
+            .unwrap_or(0.0);
+        #[repr(C)]
+        #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+        struct Shift {
+            shift: f32,
+            _pad0: f32,
+            _pad1: f32,
+            _pad2: f32,
+        }
+        let shift_uniform = bytemuck::bytes_of(&Shift {
+            shift,
+            _pad0: 0.0,
+            _pad1: 0.0,
+            _pad2: 0.0,
+        })
+        .to_vec();
+        let sums = self.tree_reduce(
+            &mut table,
+            "linreg_reduce",
+            WGSL_LINREG_REDUCE,
+            y_col,
+            10,
+            Some(shift_uniform),
+        )?;
+        let sums: Vec<f64> = sums
+            .chunks(2)
+            .map(|pair| pair[0] as f64 + pair[1] as f64)
+            .collect();
+        let [n, sx, sy, sxx, sxy] = sums[..] else {
+            return Err(anyhow!("linreg_reduce gave {} sums", sums.len()));
         };
 
-        // NOTE: out1[0] is uninitialized; to make the CAS loop work from 0, we need it zeroed.
-        // The buffer we allocate is zero-initialized by default on most platforms, but not guaranteed.
-        // To be safe, run a tiny one-shot “zero scalar” kernel or just overwrite after creation.
-        // Here we dispatch reduce twice; first pass on length-0 would do nothing. For simplicity,
-        // we’ll just run as-is; if you see non-zero garbage, add a dedicated zero-scalar kernel.
-
-        self.run_pipeline(&mut table, &[step_sum_y])?;
-        self.run_pipeline(&mut table, &[step_sum_iy])?;
-
-        let sum_y = self.download_scalar_f32(&table, "sum_y")?;
-        let sum_iy = self.download_scalar_f32(&table, "sum_iy")?;
-
-        // (3) Compute slope/intercept on CPU using known sums of x and x^2
-        let n = df.height() as f32;
-        // sum_i = 0 + 1 + ... + (n-1) = n(n-1)/2
-        let sx = n * (n - 1.0) * 0.5;
-        // sum_i2 = (n-1)n(2n-1)/6
-        let sx2 = (n - 1.0) * n * (2.0 * n - 1.0) / 6.0;
-
-        let denom = n * sx2 - sx * sx;
-        let b = if denom.abs() > 1e-12 {
-            (n * sum_iy - sx * sum_y) / denom
+        // (2) solve in f64
+        let denom = n * sxx - sx * sx;
+        let b = if n >= 2.0 && denom.abs() > 1e-12 {
+            (n * sxy - sx * sy) / denom
         } else {
             0.0
         };
-        let a = (sum_y - b * sx) / n;
+        let a = if n > 0.0 {
+            (sy - b * sx) / n + shift as f64
+        } else {
+            f64::NAN
+        };
+        let (a, b) = (a as f32, b as f32);
 
-        // (4) Broadcast predictions: ŷ[i] = a + b*i
+        // (3) Broadcast predictions: ŷ[i] = a + b*i
         #[repr(C)]
         #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
         struct Params {
@@ -617,7 +890,7 @@ impl GpuRuntime {
         };
         self.run_pipeline(&mut table, &[step_pred])?;
 
-        // (5) Append prediction column to DataFrame
+        // (4) Append prediction column to DataFrame
         let mut out = df.clone();
         self.download_append(&mut out, &table, out_alias)?;
         Ok((out, a, b))
@@ -662,3 +935,197 @@ fn series_from_bytes(name: &str, dt: GpuDType, len: usize, bytes: &[u8]) -> Resu
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{GpuDType, GpuRuntime, KernelStep, OutputSpec, SEGMENTS, WGSL_REDUCE_SUM};
+    use polars::prelude::*;
+    use std::borrow::Cow;
+
+    fn step<'a>(
+        key: &'a str,
+        src: &'a str,
+        inputs: &[&'a str],
+        output: OutputSpec<'a>,
+        uniform: &[u32],
+    ) -> KernelStep<'a> {
+        KernelStep {
+            shader_key: Cow::Borrowed(key),
+            wgsl_src: Some(Cow::Borrowed(src)),
+            entry_point: Cow::Borrowed("main"),
+            inputs: inputs.iter().map(|&i| Cow::Borrowed(i)).collect(),
+            outputs: vec![output],
+            push_constants: None,
+            workgroup_size_x: 256,
+            elems_per_invocation: 1,
+            uniform_bytes: (!uniform.is_empty()).then(|| bytemuck::cast_slice(uniform).to_vec()),
+        }
+    }
+
+    #[test]
+    fn test_compensated_kernels_match_f64() {
+        let mut rt = pollster::block_on(GpuRuntime::new()).unwrap();
+        // prices near 4000 in cents, trending slowly: where f32 accumulators drift
+        let n = 20_000;
+        let y: Vec<f32> = (0..n)
+            .map(|i| (4000.0 + (i * 7919 % 1000) as f64 * 0.01 + i as f64 * 1e-4) as f32)
+            .collect();
+        // the reference: f64 arithmetic over the same f32 values
+        let x: Vec<f64> = y.iter().map(|v| *v as f64).collect();
+        let u = f32::EPSILON as f64 / 2.0;
+        let df = df!("y" => &y).unwrap();
+
+        let mut table = rt.upload_dataframe(&df, Some(&["y"])).unwrap();
+        let sum = step(
+            "reduce_sum",
+            WGSL_REDUCE_SUM,
+            &["y"],
+            OutputSpec::with_len("sum", GpuDType::F32, 2),
+            &[],
+        );
+        rt.run_pipeline(&mut table, &[sum]).unwrap();
+        let sum = rt.download_compensated(&table, "sum").unwrap()[0];
+        let exact: f64 = x.iter().sum();
+        assert!(
+            (sum - exact).abs() <= 2.0 * u * exact,
+            "sum {sum} vs {exact}"
+        );
+
+        let period = 500;
+        let sma = step(
+            "sma",
+            include_str!("shaders/sma.wgsl"),
+            &["y", SEGMENTS],
+            OutputSpec::column("sma", GpuDType::F32),
+            &[period as u32, 0, 0, 0],
+        );
+        let vol = step(
+            "volatility_vol_only",
+            include_str!("shaders/volatility_vol_only.wgsl"),
+            &["y", SEGMENTS],
+            OutputSpec::column("vol", GpuDType::F32),
+            &[period as u32, 1f32.to_bits(), 0, 0],
+        );
+        rt.run_pipeline(&mut table, &[sma, vol]).unwrap();
+        let mut out = df.clone();
+        rt.download_append(&mut out, &table, "sma").unwrap();
+        rt.download_append(&mut out, &table, "vol").unwrap();
+        let column = |name: &str| -> Vec<f32> {
+            out.column(name)
+                .unwrap()
+                .f32()
+                .unwrap()
+                .into_no_null_iter()
+                .collect()
+        };
+        let (sma, vol) = (column("sma"), column("vol"));
+        for i in period - 1..n {
+            let window = &x[i + 1 - period..=i];
+            let mean = window.iter().sum::<f64>() / period as f64;
+            // the window's sum rounded to f32 (u), then divided (u); a plain f32 loop is
+            // out by almost 5u here
+            let got = sma[i] as f64;
+            assert!(
+                (got - mean).abs() <= 2.0 * u * mean,
+                "sma[{i}] {got} vs {mean}"
+            );
+            if i >= period && i % 97 == 0 {
+                // log returns of prices this close are only as good as the f32 ratio of
+                // two of them (u over returns of about 1e-3); summing them adds little
+                let returns: Vec<f64> = (i + 1 - period..=i)
+                    .map(|k| (x[k] as f32 / x[k - 1] as f32).ln() as f64)
+                    .collect();
+                let mean = returns.iter().sum::<f64>() / period as f64;
+                let sd = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>();
+                let sd = (sd / (period - 1) as f64).sqrt();
+                let got = vol[i] as f64;
+                assert!((got - sd).abs() <= 1e-4 * sd, "vol[{i}] {got} vs {sd}");
+            }
+        }
+
+        let (_, a, b) = rt.linear_regression_global(&df, "y", "fit").unwrap();
+        let xm = (n - 1) as f64 / 2.0;
+        let ym = x.iter().sum::<f64>() / n as f64;
+        let sxy: f64 = x
+            .iter()
+            .enumerate()
+            .map(|(i, y)| (i as f64 - xm) * (y - ym))
+            .sum();
+        let sxx: f64 = (0..n).map(|i| (i as f64 - xm).powi(2)).sum();
+        let slope = sxy / sxx;
+        let intercept = ym - slope * xm;
+        assert!(
+            (b as f64 - slope).abs() <= 1e-4 * slope.abs(),
+            "slope {b} vs {slope}"
+        );
+        assert!(
+            (a as f64 - intercept).abs() <= 4.0 * u * intercept,
+            "intercept {a} vs {intercept}"
+        );
+    }
+
+    #[test]
+    fn test_tree_reduction_matches_f64() {
+        let mut rt = pollster::block_on(GpuRuntime::new()).unwrap();
+        // enough rows for every thread to fold several, with gaps
+        let n = 300_000i64;
+        let y: Vec<Option<f32>> = (0..n)
+            .map(|i| {
+                let v = 4000.0 + (i * 7919 % 1000) as f64 * 0.01 - i as f64 * 1e-3;
+                (i % 1000 != 7).then_some(v as f32)
+            })
+            .collect();
+        let x: Vec<f64> = y.iter().flatten().map(|v| *v as f64).collect();
+        let u = f32::EPSILON as f64 / 2.0;
+        let df = df!("y" => &y).unwrap();
+        let mut table = rt.upload_dataframe(&df, Some(&["y"])).unwrap();
+
+        let stats = rt.column_stats(&mut table, "y").unwrap();
+        let sum: f64 = x.iter().sum();
+        let mean = sum / x.len() as f64;
+        let variance = x.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (x.len() - 1) as f64;
+        assert_eq!(stats.count, x.len());
+        assert!((stats.sum - sum).abs() <= 2.0 * u * sum, "{stats:?}");
+        assert!((stats.mean - mean).abs() <= 2.0 * u * mean, "{stats:?}");
+        // a plain sum of squares would cancel to nothing against 4000^2
+        assert!(
+            (stats.variance - variance).abs() <= 1e-4 * variance,
+            "{stats:?} {variance}"
+        );
+        assert_eq!(stats.min, x.iter().cloned().fold(f64::INFINITY, f64::min));
+        assert_eq!(
+            stats.max,
+            x.iter().cloned().fold(f64::NEG_INFINITY, f64::max)
+        );
+        // the scratch buffers don't stay in the table
+        let mut names: Vec<&str> = table.columns.keys().map(|k| k.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec![SEGMENTS, "y"]);
+
+        let (_, _, b) = rt.linear_regression_global(&df, "y", "fit").unwrap();
+        let points: Vec<(f64, f64)> = y
+            .iter()
+            .enumerate()
+            .filter_map(|(i, v)| Some((i as f64, (*v)? as f64)))
+            .collect();
+        let (xm, ym) = (
+            points.iter().map(|p| p.0).sum::<f64>() / points.len() as f64,
+            mean,
+        );
+        let sxy: f64 = points.iter().map(|(i, y)| (i - xm) * (y - ym)).sum();
+        let sxx: f64 = points.iter().map(|(i, _)| (i - xm).powi(2)).sum();
+        let slope = sxy / sxx;
+        assert!(
+            (b as f64 - slope).abs() <= 1e-4 * slope.abs(),
+            "slope {b} vs {slope}"
+        );
+
+        // a Float64 column goes up as the same f32 values, and fits the same line
+        let y64: Vec<Option<f64>> = y.iter().map(|v| v.map(|v| v as f64)).collect();
+        let df64 = df!("y" => &y64).unwrap();
+        let (_, a, b) = rt.linear_regression_global(&df, "y", "fit").unwrap();
+        let (fit64, a64, b64) = rt.linear_regression_global(&df64, "y", "fit").unwrap();
+        assert_eq!((a64, b64), (a, b));
+        assert_eq!(fit64.height(), y.len());
+    }
+}
@@ -12,12 +12,9 @@ fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
   let len: u32 = arrayLength(&price);
   if (i >= len) { return; }
 
-  var p = price[i];
-  var v = vol[i];
-
-  // replace non-finite with 0
-  if (!(p == p)) { p = 0.0; } // NaN test
-  if (!(v == v)) { v = 0.0; }
+  // a missing price or volatility leaves the band missing (NaN)
+  let p = price[i];
+  let v = vol[i];
 
   out_band[i] = p * (1.0 + U.scale * v);
 }
@@ -1,46 +1,92 @@
 // linreg_reduce.wgsl
-struct PC { len: u32, _pad0: u32, _pad1: u32, _pad2: u32 };
-
-struct Reductions {
-  n: atomic<u32>;
-  sum_x: atomic<f32>;
-  sum_y: atomic<f32>;
-  sum_xx: atomic<f32>;
-  sum_xy: atomic<f32>;
-};
-
-@group(0) @binding(0) var<storage, read> Y: array<f32>;
-@group(0) @binding(1) var<storage, read_write> RED: Reductions;
-
-fn isnan_f(x: f32) -> bool { return x != x; }
-
-// Atomic add for f32 via bitcasts (WGSL doesn’t have atomic<f32> add; emulate with CAS)
-// This is a standard pattern; fine for modest contention.
-fn atomic_add_f32(dst: ptr<storage, atomic<u32>>, val: f32) {
-  loop {
-    let old_bits = atomicLoad(dst);
-    let old = bitcast<f32>(old_bits);
-    let new = old + val;
-    let new_bits = bitcast<u32>(new);
-    if (atomicCompareExchangeWeak(dst, old_bits, new_bits).exchanged) {
-      break;
+// Sums for a least squares line through (i, Y[i]) over the non-NaN Y, as compensated
+// (sum, error) pairs for the caller to add and solve in f64:
+// [n, sum_x, sum_y, sum_xx, sum_xy], two f32 each, with y = Y[i] - shift.
+// Taking a shift near the data (any Y value will do) keeps sum_xy from cancelling when the
+// slope is small against the price level. A two-pass tree reduction as in reduce_stats.wgsl:
+// `partials` writes 10 f32 per workgroup, `combine` folds them into the first 10. Neumaier
+// summation throughout: each sum is within 2u of its terms' absolute sum, u = 2^-24, plus
+// the rounding of the x*x and x*y products (u each).
+struct Params { shift: f32, _pad0: f32, _pad1: f32, _pad2: f32 };
+@group(1) @binding(0) var<uniform> P: Params;
+
+@group(0) @binding(0) var<storage, read> src: array<f32>;
+@group(0) @binding(1) var<storage, read_write> out: array<f32>; // 10 per workgroup
+
+// the five running sums, and their rounding errors
+struct Acc { s: array<f32, 5>, c: array<f32, 5> };
+
+var<workgroup> partial: array<Acc, 256>;
+
+fn add(acc: ptr<function, Acc>, k: u32, x: f32) {
+  let s = (*acc).s[k];
+  let t = s + x;
+  if (abs(s) >= abs(x)) { (*acc).c[k] += (s - t) + x; } else { (*acc).c[k] += (x - t) + s; }
+  (*acc).s[k] = t;
+}
+
+fn merge(acc: ptr<function, Acc>, b: Acc) {
+  for (var k = 0u; k < 5u; k++) {
+    add(acc, k, b.s[k]);
+    (*acc).c[k] += b.c[k];
+  }
+}
+
+// pairwise over the workgroup's 256 sums, into partial[0], then out at `at`
+fn tree(lid: u32, at: u32) {
+  for (var stride = 128u; stride > 0u; stride = stride / 2u) {
+    if (lid < stride) {
+      var a = partial[lid];
+      merge(&a, partial[lid + stride]);
+      partial[lid] = a;
     }
+    workgroupBarrier();
   }
+  if (lid == 0u) {
+    for (var k = 0u; k < 5u; k++) {
+      out[10u * at + 2u * k] = partial[0].s[k];
+      out[10u * at + 2u * k + 1u] = partial[0].c[k];
+    }
+  }
+}
+
+@compute @workgroup_size(256)
+fn partials(
+  @builtin(local_invocation_id) lid: vec3<u32>,
+  @builtin(workgroup_id) wid: vec3<u32>,
+  @builtin(num_workgroups) groups: vec3<u32>,
+) {
+  let len = arrayLength(&src);
+  var acc: Acc;
+  for (var i = wid.x * 256u + lid.x; i < len; i += groups.x * 256u) {
+    let v = src[i];
+    if (v != v) { continue; }
+    let x = f32(i);
+    let y = v - P.shift;
+    add(&acc, 0u, 1.0);
+    add(&acc, 1u, x);
+    add(&acc, 2u, y);
+    add(&acc, 3u, x * x);
+    add(&acc, 4u, x * y);
+  }
+  partial[lid.x] = acc;
+  workgroupBarrier();
+  tree(lid.x, wid.x);
 }
 
 @compute @workgroup_size(256)
-fn main(@builtin(global_invocation_id) gid: vec3<u32>, @push_constant pc: PC) {
-  let i = gid.x;
-  if (i >= pc.len) { return; }
-  let y = Y[i];
-  if (isnan_f(y)) { return; }
-  let xf = f32(i);
-
-  // n += 1
-  atomicAdd(&RED.n, 1u);
-  // sum_x += x; sum_y += y; sum_xx += x*x; sum_xy += x*y
-  atomic_add_f32(&RED.sum_x, xf);
-  atomic_add_f32(&RED.sum_y, y);
-  atomic_add_f32(&RED.sum_xx, xf * xf);
-  atomic_add_f32(&RED.sum_xy, xf * y);
+fn combine(@builtin(local_invocation_id) lid: vec3<u32>) {
+  var acc: Acc;
+  let count = arrayLength(&src) / 10u;
+  for (var k = lid.x; k < count; k += 256u) {
+    var b: Acc;
+    for (var j = 0u; j < 5u; j++) {
+      b.s[j] = src[10u * k + 2u * j];
+      b.c[j] = src[10u * k + 2u * j + 1u];
+    }
+    merge(&acc, b);
+  }
+  partial[lid.x] = acc;
+  workgroupBarrier();
+  tree(lid.x, 0u);
 }
@@ -0,0 +1,111 @@
+// reduce_stats.wgsl
+// Count, sum, mean, variance, min and max of the non-NaN `src`, as a two-pass tree
+// reduction: `partials` has each workgroup fold a grid stride of `src` into one state per
+// thread and combine the 256 in workgroup memory, pairwise; `combine` then does the same
+// for the per-workgroup states in one workgroup. A state is 8 f32:
+//   [n, n_err, sum, sum_err, mean, m2, min, max]
+// n and sum are compensated (Neumaier) pairs; mean and m2 (the sum of squared deviations
+// from the mean) merge with Chan's formula, so the variance doesn't cancel when the values
+// are large against their spread.
+@group(0) @binding(0) var<storage, read> src: array<f32>;
+@group(0) @binding(1) var<storage, read_write> out: array<f32>; // 8 per workgroup
+
+struct State { n: vec2<f32>, sum: vec2<f32>, mean: f32, m2: f32, lo: f32, hi: f32 };
+
+var<workgroup> partial: array<State, 256>;
+
+fn inf() -> f32 { return bitcast<f32>(0x7f800000u); }
+
+fn empty() -> State { return State(vec2<f32>(0.0), vec2<f32>(0.0), 0.0, 0.0, inf(), -inf()); }
+
+fn one(x: f32) -> State { return State(vec2<f32>(1.0, 0.0), vec2<f32>(x, 0.0), x, 0.0, x, x); }
+
+// compensated a + b: .x the sum, .y the rounding errors so far
+fn add(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+  let t = a.x + b.x;
+  var c = a.y + b.y;
+  if (abs(a.x) >= abs(b.x)) { c += (a.x - t) + b.x; } else { c += (b.x - t) + a.x; }
+  return vec2<f32>(t, c);
+}
+
+fn merge(a: State, b: State) -> State {
+  if (b.n.x == 0.0) { return a; }
+  if (a.n.x == 0.0) { return b; }
+  let na = a.n.x + a.n.y;
+  let nb = b.n.x + b.n.y;
+  let w = nb / (na + nb);
+  let delta = b.mean - a.mean;
+  return State(
+    add(a.n, b.n),
+    add(a.sum, b.sum),
+    a.mean + delta * w,
+    a.m2 + b.m2 + delta * delta * na * w,
+    min(a.lo, b.lo),
+    max(a.hi, b.hi),
+  );
+}
+
+fn load(k: u32) -> State {
+  let b = 8u * k;
+  return State(
+    vec2<f32>(src[b], src[b + 1u]),
+    vec2<f32>(src[b + 2u], src[b + 3u]),
+    src[b + 4u],
+    src[b + 5u],
+    src[b + 6u],
+    src[b + 7u],
+  );
+}
+
+fn store(k: u32, s: State) {
+  let b = 8u * k;
+  out[b] = s.n.x;
+  out[b + 1u] = s.n.y;
+  out[b + 2u] = s.sum.x;
+  out[b + 3u] = s.sum.y;
+  out[b + 4u] = s.mean;
+  out[b + 5u] = s.m2;
+  out[b + 6u] = s.lo;
+  out[b + 7u] = s.hi;
+}
+
+// pairwise over the workgroup's 256 states, into partial[0]
+fn tree(lid: u32) {
+  for (var stride = 128u; stride > 0u; stride = stride / 2u) {
+    if (lid < stride) {
+      partial[lid] = merge(partial[lid], partial[lid + stride]);
+    }
+    workgroupBarrier();
+  }
+}
+
+@compute @workgroup_size(256)
+fn partials(
+  @builtin(local_invocation_id) lid: vec3<u32>,
+  @builtin(workgroup_id) wid: vec3<u32>,
+  @builtin(num_workgroups) groups: vec3<u32>,
+) {
+  var acc = empty();
+  let len = arrayLength(&src);
+  for (var i = wid.x * 256u + lid.x; i < len; i += groups.x * 256u) {
+    let x = src[i];
+    if (x == x) { acc = merge(acc, one(x)); }
+  }
+  partial[lid.x] = acc;
+  workgroupBarrier();
+  tree(lid.x);
+  if (lid.x == 0u) { store(wid.x, partial[0]); }
+}
+
+@compute @workgroup_size(256)
+fn combine(@builtin(local_invocation_id) lid: vec3<u32>) {
+  var acc = empty();
+  let count = arrayLength(&src) / 8u;
+  for (var k = lid.x; k < count; k += 256u) {
+    acc = merge(acc, load(k));
+  }
+  partial[lid.x] = acc;
+  workgroupBarrier();
+  tree(lid.x);
+  if (lid.x == 0u) { store(0u, partial[0]); }
+}
@@ -1,24 +1,50 @@
-struct Sum { value: atomic<f32>; };
-@group(0) @binding(0) var<storage, read>  src: array<f32>;
-@group(0) @binding(1) var<storage, read_write> out1: array<f32>; // len=1; we just write out1[0]
+// reduce_sum.wgsl
+// Compensated sum of `src` into total[0] + total[1]: the sum and the rounding error left
+// in it, for the caller to add in f64. One workgroup does it all, so it's deterministic:
+// each thread sums a stride of `src` with Neumaier's variant of Kahan summation, then the
+// 256 partial sums are combined pairwise.
+//
+// Error against the exact sum of the f32 inputs: |e| <= 2u * sum|x| + O(n u^2 sum|x|),
+// u = 2^-24, against (n - 1)u * sum|x| for a plain loop. NaNs propagate.
+@group(0) @binding(0) var<storage, read> src: array<f32>;
+@group(0) @binding(1) var<storage, read_write> total: array<f32>; // len 2
+
+var<workgroup> partial: array<vec2<f32>, 256>;
+
+// acc.x the running sum, acc.y the rounding errors so far
+fn add(acc: vec2<f32>, x: f32) -> vec2<f32> {
+  let t = acc.x + x;
+  var c = acc.y;
+  if (abs(acc.x) >= abs(x)) { c += (acc.x - t) + x; } else { c += (x - t) + acc.x; }
+  return vec2<f32>(t, c);
+}
+
+fn combine(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+  let s = add(a, b.x);
+  return vec2<f32>(s.x, s.y + b.y);
+}
 
 @compute @workgroup_size(256)
-fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
-    let i = gid.x;
-    if (i >= arrayLength(&src)) { return; }
+fn main(@builtin(local_invocation_id) lid: vec3<u32>, @builtin(workgroup_id) wid: vec3<u32>) {
+  // the dispatch covers the rows; the first workgroup takes all of them
+  if (wid.x != 0u) { return; }
 
-    // Bit-cast to atomic<u32> to use atomics on fp via IEEE bits
-    // (WGSL doesn't support atomic<f32> directly)
-    // We'll do float atomic add via a CAS loop
-    var newVal = src[i];
+  let len = arrayLength(&src);
+  var acc = vec2<f32>(0.0, 0.0);
+  for (var i = lid.x; i < len; i += 256u) {
+    acc = add(acc, src[i]);
+  }
+  partial[lid.x] = acc;
+  workgroupBarrier();
 
-    // custom atomic add (float) at out1[0]
-    loop {
-        let oldBits = bitcast<u32>(out1[0]);
-        let oldVal  = bitcast<f32>(oldBits);
-        let sum     = oldVal + newVal;
-        if (atomicCompareExchangeWeak(&bitcast<atomic<u32>>(out1[0]), oldBits, bitcast<u32>(sum)).exchanged) {
-            break;
-        }
+  for (var stride = 128u; stride > 0u; stride = stride / 2u) {
+    if (lid.x < stride) {
+      partial[lid.x] = combine(partial[lid.x], partial[lid.x + stride]);
     }
+    workgroupBarrier();
+  }
+  if (lid.x == 0u) {
+    total[0] = partial[0].x;
+    total[1] = partial[0].y;
+  }
 }
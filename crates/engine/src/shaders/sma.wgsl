@@ -0,0 +1,62 @@
+// sma.wgsl
+struct Params {
+  period: u32,
+  // the window ends this many bars after the output's: 0 trails, period / 2 centers
+  ahead: u32,
+  _pad1: u32,
+  _pad2: u32,
+}
+@group(1) @binding(0) var<uniform> P: Params;
+
+@group(0) @binding(0) var<storage, read> X: array<f32>;
+@group(0) @binding(1) var<storage, read> SEG: array<u32>;
+@group(0) @binding(2) var<storage, read_write> OUT: array<f32>;
+
+fn isnan_f(x: f32) -> bool { return x != x; }
+fn f32_nan() -> f32 { return bitcast<f32>(0x7fc00000u); }
+
+// Neumaier summation, acc.x the running sum and acc.y its rounding errors: a window's sum
+// is within 2u * sum|x| of exact (u = 2^-24) however long the period, where a plain loop
+// drifts by up to (period - 1)u * sum|x|.
+fn add(acc: vec2<f32>, x: f32) -> vec2<f32> {
+  let t = acc.x + x;
+  var c = acc.y;
+  if (abs(acc.x) >= abs(x)) { c += (acc.x - t) + x; } else { c += (x - t) + acc.x; }
+  return vec2<f32>(t, c);
+}
+
+// The frame holding row i, as [start, end) rows: SEG holds the offsets of the frames packed
+// into the buffers, SEG[k] <= i < SEG[k + 1], found by bisection.
+fn segment(i: u32) -> vec2<u32> {
+  var lo = 0u;
+  var hi = arrayLength(&SEG) - 1u;
+  while (hi - lo > 1u) {
+    let mid = (lo + hi) / 2u;
+    if (SEG[mid] <= i) { lo = mid; } else { hi = mid; }
+  }
+  return vec2<u32>(SEG[lo], SEG[hi]);
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+  let i = gid.x;
+  if (i >= arrayLength(&OUT)) { return; }
+  OUT[i] = f32_nan();
+
+  // OUT[i] is the average of the window ending P.ahead bars after i, missing where that
+  // window isn't all inside i's frame; each thread writes only its own OUT, so none race
+  let seg = segment(i);
+  let end = i - seg.x + P.ahead;
+  if (end >= seg.y - seg.x || end + 1u < P.period) { return; }
+  let start = end + 1u - P.period;
+
+  var acc = vec2<f32>(0.0, 0.0);
+  var count: u32 = 0u;
+  for (var j = start; j <= end; j++) {
+    let v = X[seg.x + j];
+    if (v == v) { acc = add(acc, v); count += 1u; }
+  }
+  if (count < P.period) { return; }
+
+  OUT[i] = (acc.x + acc.y) / f32(P.period);
+}
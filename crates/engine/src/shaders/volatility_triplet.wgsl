@@ -8,13 +8,26 @@ struct Params {
 @group(1) @binding(0) var<uniform> P: Params;
 
 @group(0) @binding(0) var<storage, read> PRICE: array<f32>;
-@group(0) @binding(1) var<storage, read_write> VOL: array<f32>;
-@group(0) @binding(2) var<storage, read_write> POS: array<f32>;
-@group(0) @binding(3) var<storage, read_write> NEG: array<f32>;
+@group(0) @binding(1) var<storage, read> SEG: array<u32>;
+@group(0) @binding(2) var<storage, read_write> VOL: array<f32>;
+@group(0) @binding(3) var<storage, read_write> POS: array<f32>;
+@group(0) @binding(4) var<storage, read_write> NEG: array<f32>;
 
 fn isnan_f(x: f32) -> bool { return x != x; }
 fn f32_nan() -> f32 { return bitcast<f32>(0x7fc00000u); }
 
+// The frame holding row i, as [start, end) rows: SEG holds the offsets of the frames packed
+// into the buffers, SEG[k] <= i < SEG[k + 1], found by bisection.
+fn segment(i: u32) -> vec2<u32> {
+  var lo = 0u;
+  var hi = arrayLength(&SEG) - 1u;
+  while (hi - lo > 1u) {
+    let mid = (lo + hi) / 2u;
+    if (SEG[mid] <= i) { lo = mid; } else { hi = mid; }
+  }
+  return vec2<u32>(SEG[lo], SEG[hi]);
+}
+
 @compute @workgroup_size(256)
 fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
   let i = gid.x;
@@ -24,12 +37,14 @@ fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
   POS[i] = f32_nan();
   NEG[i] = f32_nan();
 
-  if (i + 1u < P.period) { return; }
+  // each return needs the price before it, inside i's frame
+  let first = segment(i).x;
+  if (i + 1u < first + P.period) { return; }
   let start = i + 1u - P.period;
 
   // validate
   for (var j = start; j <= i; j++) {
-    if (j == 0u) { return; }
+    if (j == first) { return; }
     let p0 = PRICE[j - 1u];
     let p1 = PRICE[j];
     if (isnan_f(p0) || isnan_f(p1) || p0 <= 0.0 || p1 <= 0.0) { return; }
@@ -51,14 +66,14 @@ fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
     let d = r - mean;
     var_acc += d * d;
   }
-  var std = sqrt(var_acc / (nf - 1.0));
-  if (P.annualize_flag != 0u) { std = std * sqrt(252.0); }
+  var sd = sqrt(var_acc / (nf - 1.0));
+  if (P.annualize_flag != 0u) { sd = sd * sqrt(252.0); }
 
-  VOL[i] = std;
+  VOL[i] = sd;
 
   let price = PRICE[i];
   if (!isnan_f(price)) {
-    POS[i] = price * (1.0 + std * P.scale);
-    NEG[i] = price * (1.0 - std * P.scale);
+    POS[i] = price * (1.0 + sd * P.scale);
+    NEG[i] = price * (1.0 - sd * P.scale);
   }
 }
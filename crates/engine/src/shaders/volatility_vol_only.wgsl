@@ -1,13 +1,42 @@
 // volatility_vol_only.wgsl
-struct Uniforms { period: u32, annualize: u32, _p0: u32, _p1: u32 };
+// Sample standard deviation of the log returns over a window of `period` bars, times
+// `annualize` (the square root of the bars in a year), as on the CPU.
+// `ahead`: the window ends this many bars after the output's, 0 trails, period / 2 centers
+struct Uniforms { period: u32, annualize: f32, ahead: u32, _p1: u32 };
 @group(1) @binding(0) var<uniform> U: Uniforms;
 
 @group(0) @binding(0) var<storage, read> price: array<f32>;
-@group(0) @binding(1) var<storage, read_write> out_vol: array<f32>;
+@group(0) @binding(1) var<storage, read> SEG: array<u32>;
+@group(0) @binding(2) var<storage, read_write> out_vol: array<f32>;
 
-fn safe_sqrt(x: f32) -> f32 {
-  if (x < 0.0) { return 0.0; }
-  return sqrt(x);
+fn f32_nan() -> f32 { return bitcast<f32>(0x7fc00000u); }
+
+// Neumaier summation, acc.x the running sum and acc.y its rounding errors
+fn add(acc: vec2<f32>, x: f32) -> vec2<f32> {
+  let t = acc.x + x;
+  var c = acc.y;
+  if (abs(acc.x) >= abs(x)) { c += (acc.x - t) + x; } else { c += (x - t) + acc.x; }
+  return vec2<f32>(t, c);
+}
+
+// The frame holding row i, as [start, end) rows: SEG holds the offsets of the frames packed
+// into the buffers, SEG[k] <= i < SEG[k + 1], found by bisection.
+fn segment(i: u32) -> vec2<u32> {
+  var lo = 0u;
+  var hi = arrayLength(&SEG) - 1u;
+  while (hi - lo > 1u) {
+    let mid = (lo + hi) / 2u;
+    if (SEG[mid] <= i) { lo = mid; } else { hi = mid; }
+  }
+  return vec2<u32>(SEG[lo], SEG[hi]);
+}
+
+// Log return into bar k, NaN when either price is missing or not positive.
+fn log_return(k: u32) -> f32 {
+  let p0 = price[k - 1u];
+  let p1 = price[k];
+  if (!(p0 > 0.0) || !(p1 > 0.0)) { return f32_nan(); }
+  return log(p1 / p0);
 }
 
 @compute @workgroup_size(256)
@@ -16,36 +45,30 @@ fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
   let n: u32 = U.period;
   let len: u32 = arrayLength(&price);
   if (i >= len) { return; }
+  out_vol[i] = f32_nan();
 
-  // not enough samples → 0.0 (or write NaN if you prefer and post-fill)
-  if (n <= 1u || i + 1u < n) {
-    out_vol[i] = 0.0;
-    return;
-  }
+  // missing unless the window's returns, and the bar before its first, are in i's frame
+  let seg = segment(i);
+  let end: u32 = i + U.ahead;
+  if (n <= 1u || end < seg.x + n || end >= seg.y) { return; }
 
-  var sum: f32 = 0.0;
-  var sumsq: f32 = 0.0;
-  let start: u32 = i + 1u - n;
+  // two passes, compensated: sum of squares about the mean rather than
+  // sumsq - sum^2 / n, which cancels catastrophically for returns this small
+  let start: u32 = end + 1u - n;
+  var sum = vec2<f32>(0.0, 0.0);
   for (var k: u32 = 0u; k < n; k = k + 1u) {
-    let x = price[start + k];
-    sum += x;
-    sumsq += x * x;
+    let r = log_return(start + k);
+    if (r != r) { return; }
+    sum = add(sum, r);
   }
-
   let nf: f32 = f32(n);
-  let mean = sum / nf;
-  let numer = sumsq - sum * sum / nf;
-  // unbiased var, but guard denominator
-  let denom = max(nf - 1.0, 1.0);
-  var varv = numer / denom;
-
-  // clamp tiny negatives to 0
-  if (varv < 0.0) { varv = 0.0; }
-
-  var vol = safe_sqrt(varv);
-  if (U.annualize == 1u) {
-    // scale to daily*sqrt(252) or whatever you need; here keep as raw
-    // vol = vol * 15.874507; // e.g. sqrt(252) if your window is daily std of returns
+  let mean = (sum.x + sum.y) / nf;
+
+  var sq = vec2<f32>(0.0, 0.0);
+  for (var k: u32 = 0u; k < n; k = k + 1u) {
+    let d = log_return(start + k) - mean;
+    sq = add(sq, d * d);
   }
-  out_vol[i] = vol;
+  let varv = max((sq.x + sq.y) / (nf - 1.0), 0.0);
+  out_vol[i] = sqrt(varv) * U.annualize;
 }
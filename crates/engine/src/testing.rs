@@ -0,0 +1,39 @@
+// testing.rs
+// -----------------------------------------------------------------------------
+// Queries and bars the tests share: a synthetic PROVIDER to run against, a FRAME
+// over it with the CALCs under test, and daily bars for tests that bring their own
+// -----------------------------------------------------------------------------
+
+use crate::parser::{parse, ActionSection};
+use polars::prelude::*;
+
+/// PROVIDER `p`: synthetic bars over the first half of 2020.
+pub(crate) const SYNTHETIC: &str =
+    "PROVIDER p USING synthetic\n    FROM 2020-01-01 TO 2020-06-30\n";
+
+/// FRAME `f` over PROVIDER `p`, pulling `pull`, with the CALC lines in `calcs`.
+pub(crate) fn frame_src(pull: &str, calcs: &str) -> String {
+    format!("FRAME f\n    PROVIDER p\n    PULL {pull}\n{calcs}")
+}
+
+/// The actions of [`frame_src`], for running over bars of the test's own.
+pub(crate) fn frame_actions(pull: &str, calcs: &str) -> ActionSection {
+    parse(&frame_src(pull, calcs)).unwrap().frame["f"]
+        .actions
+        .clone()
+}
+
+/// `n` timestamps a day apart, from the epoch.
+pub(crate) fn daily_timestamps(n: usize) -> Vec<i64> {
+    (0..n as i64).map(|i| i * 86_400).collect()
+}
+
+/// Daily bars closing at `close`, each opening `gap` below its close.
+pub(crate) fn daily_bars(close: Vec<f64>, gap: f64) -> DataFrame {
+    df!(
+        "timestamp" => daily_timestamps(close.len()),
+        "open" => close.iter().map(|c| c - gap).collect::<Vec<_>>(),
+        "close" => close,
+    )
+    .unwrap()
+}
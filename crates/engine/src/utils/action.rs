@@ -1,477 +1,700 @@
+use crate::gpu_service::GpuService;
 use crate::lexer::Keyword;
+use crate::profile;
 use crate::runtime::GpuDType;
 use crate::runtime::GpuRuntime;
+use crate::runtime::GpuTable;
 use crate::runtime::KernelStep;
 use crate::runtime::OutputSpec;
-use polars::prelude::FillNullStrategy;
+use crate::runtime::SEGMENTS;
 use polars::prelude::*;
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::Instant;
 
 use polars::frame::DataFrame;
 use polars::series::IsSorted;
 
-use crate::calculation::Calculation;
-use crate::parser::{ActionSection, Calc};
+use crate::calculation::{periods_per_year, Calculation};
+use crate::calendar::Calendar;
+use crate::parser::{order_calcs_by_waves, order_calcs_flat, ActionSection, Calc};
+use crate::utils::incremental::CalcOutputs;
 
+/// `action`'s CALCs over `df` on the GPU, with VOLATILITY annualized on the default
+/// calendar.
 pub fn action_over_data_gpu(
     action: &ActionSection,
     df: DataFrame,
     rt: &mut GpuRuntime,
 ) -> Result<DataFrame, String> {
-    pollster::block_on(async move {
-        // Base: timestamp + selected fields
-        let mut base = Vec::new();
-        let ts = df
-            .column("timestamp")
-            .map_err(|e| format!("Failed to get timestamp column: {e}"))?
-            .clone();
-        base.push(ts);
-
-        let selected = df
-            .select(action.fields.iter().map(|s| s.as_str()).collect::<Vec<_>>())
-            .map_err(|e| format!("Failed to select fields: {e}"))?;
-        base.extend_from_slice(selected.get_columns());
-
-        let Some(calcs) = &action.calc else {
-            return DataFrame::new(base).map_err(|e| format!("Failed to create DataFrame: {e}"));
-        };
-
-        // let mut rt = GpuRuntime::new().await.map_err(|e| e.to_string())?;
-        let mut working_df = df.clone();
-
-        // sanitize core prices once
-        let core: Vec<&str> = ["open", "high", "low", "close"]
-            .into_iter()
-            .filter(|c| has_col(&working_df, c))
-            .collect();
-        sanitize_for_gpu(&mut working_df, &core).map_err(|e| format!("sanitize: {e}"))?;
-
-        let mut out_df =
-            DataFrame::new(base).map_err(|e| format!("Failed to create DataFrame: {e}"))?;
-
-        // process sorted calcs
-        for calc in calcs {
-            // upload all current columns except timestamp
-            let mut fields = working_df.clone();
-            // up front
-            let core: Vec<&str> = ["open", "high", "low", "close"]
-                .into_iter()
-                .filter(|c| df.column(c).is_ok())
-                .collect();
-            sanitize_for_gpu(&mut fields, &core).map_err(|e| format!("sanitize: {e}"))?;
-
-            let fields: Vec<&str> = fields
-                .get_column_names()
-                .into_iter()
-                .filter(|n| !n.as_str().eq_ignore_ascii_case("timestamp"))
-                .map(|s| s.as_str())
-                .collect();
-
-            // sanitize only this calc's inputs (coerce, fill, sort)
-            let mut needed: Vec<&str> = calc.inputs.iter().map(|s| s.as_str()).collect();
-            needed.retain(|c| *c != "timestamp");
-
-            sanitize_for_gpu(&mut working_df, &needed).map_err(|e| format!("sanitize: {e}"))?;
-            let mut table = rt
-                .upload_dataframe(&working_df, Some(&fields.clone()))
-                .map_err(|e| format!("GPU upload failed: {e}"))?;
-
-            match calc.operation {
-                Keyword::Constant => {
-                    #[repr(C)]
-                    #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
-                    struct Params {
-                        value: f32,
-                        _p0: f32,
-                        _p1: f32,
-                        _p2: f32,
-                    }
-                    let val = calc
-                        .inputs
-                        .get(0)
-                        .and_then(|s| s.parse::<f32>().ok())
-                        .unwrap_or(0.0);
-                    let uniform = bytemuck::bytes_of(&Params {
-                        value: val,
-                        _p0: 0.0,
-                        _p1: 0.0,
-                        _p2: 0.0,
-                    })
-                    .to_vec();
-
-                    let step = KernelStep {
-                        shader_key: Cow::Borrowed("constant_fill"),
-                        wgsl_src: Some(Cow::Borrowed(include_str!(
-                            "../shaders/constant_fill.wgsl"
-                        ))),
-                        entry_point: Cow::Borrowed("main"),
-                        inputs: vec![],
-                        outputs: vec![OutputSpec::column(calc.alias.clone(), GpuDType::F32)],
-                        push_constants: None,
-                        workgroup_size_x: 256,
-                        elems_per_invocation: 1,
-                        uniform_bytes: Some(uniform),
-                    };
-                    rt.run_pipeline(&mut table, &[step])
-                        .map_err(|e| e.to_string())?;
-
-                    for df_mut in [&mut out_df, &mut working_df] {
-                        rt.download_append(df_mut, &table, &calc.alias)
-                            .map_err(|e| e.to_string())?;
-                        cast_col(df_mut, &calc.alias, DataType::Float64)?;
-                    }
-                }
+    action_over_data_gpu_reusing(
+        action,
+        df,
+        &Calendar::default(),
+        rt,
+        &CalcOutputs::new(),
+        &mut CalcOutputs::new(),
+    )
+}
 
-                Keyword::Difference => {
-                    for (idx, w) in calc.inputs.windows(2).enumerate() {
-                        let a = &w[0];
-                        let b = &w[1];
-                        let out_name = if calc.inputs.len() == 2 {
-                            calc.alias.clone()
-                        } else {
-                            format!("{}_{}", calc.alias, idx)
-                        };
-                        let step = KernelStep {
-                            shader_key: Cow::Borrowed("difference_pair"),
-                            wgsl_src: Some(Cow::Borrowed(include_str!(
-                                "../shaders/difference_pair.wgsl"
-                            ))),
-                            entry_point: Cow::Borrowed("main"),
-                            inputs: vec![Cow::Owned(a.clone()), Cow::Owned(b.clone())],
-                            outputs: vec![OutputSpec::column(out_name.clone(), GpuDType::F32)],
-                            push_constants: None,
-                            workgroup_size_x: 256,
-                            elems_per_invocation: 1,
-                            uniform_bytes: None,
-                        };
-                        rt.run_pipeline(&mut table, &[step])
-                            .map_err(|e| e.to_string())?;
-                        for df_mut in [&mut out_df, &mut working_df] {
-                            rt.download_append(df_mut, &table, &out_name)
-                                .map_err(|e| e.to_string())?;
-                            cast_col(df_mut, &out_name, DataType::Float64)?;
-                        }
-                    }
-                }
+/// [`action_over_data_gpu`] on bars of `calendar`, taking the outputs of CALCs found in
+/// `reuse` instead of recomputing them. Every CALC's output columns end up in `outputs`.
+///
+/// The frame goes up to the GPU once. Each wave of CALCs from [`order_calcs_by_waves`]
+/// runs as one batch over the resident columns, so later waves read earlier outputs in
+/// place, and everything comes back in one download at the end.
+pub fn action_over_data_gpu_reusing(
+    action: &ActionSection,
+    mut df: DataFrame,
+    calendar: &Calendar,
+    rt: &mut GpuRuntime,
+    reuse: &CalcOutputs,
+    outputs: &mut CalcOutputs,
+) -> Result<DataFrame, String> {
+    sort_by_timestamp(&mut df)?;
+    let rows = df.height();
+    run_calcs(action, df, &[0, rows], calendar, rt, reuse, outputs)
+}
 
-                Keyword::Sma => {
-                    let src = calc
-                        .inputs
-                        .get(0)
-                        .cloned()
-                        .ok_or_else(|| "SMA requires one input column".to_string())?;
-                    let period = calc
-                        .inputs
-                        .get(1)
-                        .and_then(|s| s.parse::<u32>().ok())
-                        .unwrap_or(14);
-                    #[repr(C)]
-                    #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
-                    struct Params {
-                        period: u32,
-                        _p0: u32,
-                        _p1: u32,
-                        _p2: u32,
-                    }
-                    let uniform = bytemuck::bytes_of(&Params {
-                        period,
-                        _p0: 0,
-                        _p1: 0,
-                        _p2: 0,
-                    })
-                    .to_vec();
-
-                    let step = KernelStep {
-                        shader_key: Cow::Borrowed("sma_centered"),
-                        wgsl_src: Some(Cow::Borrowed(include_str!("../shaders/sma_centered.wgsl"))),
-                        entry_point: Cow::Borrowed("main"),
-                        inputs: vec![Cow::Owned(src.clone())],
-                        outputs: vec![OutputSpec::column(calc.alias.clone(), GpuDType::F32)],
-                        push_constants: None,
-                        workgroup_size_x: 256,
-                        elems_per_invocation: 1,
-                        uniform_bytes: Some(uniform),
-                    };
-                    rt.run_pipeline(&mut table, &[step])
-                        .map_err(|e| e.to_string())?;
-                    for df_mut in [&mut out_df, &mut working_df] {
-                        rt.download_append(df_mut, &table, &calc.alias)
-                            .map_err(|e| e.to_string())?;
-                        cast_col(df_mut, &calc.alias, DataType::Float64)?;
-                    }
-                }
+/// [`action_over_data_gpu`] over many frames with the same PULL and CALCs, one per
+/// ticker say: they go up packed end to end in one table, and each wave of CALCs runs
+/// once for all of them. Windowed CALCs (SMA, VOLATILITY) and LINEAR_REGRESSION see
+/// only their own frame's rows; KERNELs see the packed columns. Frame `k`'s CALC
+/// outputs end up in `outputs[k]`.
+pub fn action_over_frames_gpu(
+    action: &ActionSection,
+    dfs: Vec<DataFrame>,
+    calendar: &Calendar,
+    rt: &mut GpuRuntime,
+    outputs: &mut [CalcOutputs],
+) -> Result<Vec<DataFrame>, String> {
+    if dfs.len() != outputs.len() {
+        return Err(format!(
+            "{} frames but {} CALC outputs",
+            dfs.len(),
+            outputs.len()
+        ));
+    }
+    let Some(first) = dfs.first() else {
+        return Ok(Vec::new());
+    };
+    // the first frame's columns and types, for every frame
+    let schema: Vec<(PlSmallStr, DataType)> = first
+        .get_columns()
+        .iter()
+        .map(|c| (c.name().clone(), c.dtype().clone()))
+        .collect();
+    let mut segments = vec![0];
+    let mut packed: Option<DataFrame> = None;
+    for (k, mut df) in dfs.into_iter().enumerate() {
+        sort_by_timestamp(&mut df)?;
+        let columns = schema
+            .iter()
+            .map(|(name, dtype)| df.column(name)?.cast(dtype))
+            .collect::<PolarsResult<Vec<_>>>()
+            .and_then(DataFrame::new)
+            .map_err(|e| format!("frame {k}: {e}"))?;
+        segments.push(segments[k] + columns.height());
+        match &mut packed {
+            Some(packed) => {
+                packed
+                    .vstack_mut_owned(columns)
+                    .map_err(|e| format!("frame {k}: {e}"))?;
+            }
+            None => packed = Some(columns),
+        }
+    }
+    let packed = packed.unwrap_or_default();
+
+    let mut all = CalcOutputs::new();
+    let out = run_calcs(
+        action,
+        packed,
+        &segments,
+        calendar,
+        rt,
+        &CalcOutputs::new(),
+        &mut all,
+    )?;
+    let mut frames = Vec::with_capacity(outputs.len());
+    for (w, outputs) in segments.windows(2).zip(outputs.iter_mut()) {
+        let (start, len) = (w[0] as i64, w[1] - w[0]);
+        for (alias, columns) in &all {
+            let columns = columns.iter().map(|c| c.slice(start, len)).collect();
+            outputs.insert(alias.clone(), columns);
+        }
+        frames.push(out.slice(start, len));
+    }
+    Ok(frames)
+}
 
-                Keyword::Volatility | Keyword::DoubleVolatility => {
-                    let price_col = calc
-                        .inputs
-                        .get(0)
-                        .cloned()
-                        .ok_or_else(|| "Volatility requires a price column".to_string())?;
-                    let period = calc
-                        .inputs
-                        .get(1)
-                        .and_then(|s| s.parse::<u32>().ok())
-                        .unwrap_or(14);
-                    let scale: f32 = if calc.operation == Keyword::Volatility {
-                        0.5
-                    } else {
-                        1.0
-                    };
-
-                    #[repr(C)]
-                    #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
-                    struct VolParams {
-                        period: u32,
-                        annualize: u32,
-                        _p0: u32,
-                        _p1: u32,
-                    }
-                    let vol_uniform = bytemuck::bytes_of(&VolParams {
-                        period,
-                        annualize: 1,
-                        _p0: 0,
-                        _p1: 0,
-                    })
-                    .to_vec();
-
-                    let vol_name = calc.alias.clone();
-                    let step_vol = KernelStep {
-                        shader_key: Cow::Borrowed("volatility_vol_only"),
-                        wgsl_src: Some(Cow::Borrowed(include_str!(
-                            "../shaders/volatility_vol_only.wgsl"
-                        ))),
-                        entry_point: Cow::Borrowed("main"),
-                        inputs: vec![Cow::Owned(price_col.clone())],
-                        outputs: vec![OutputSpec::column(vol_name.clone(), GpuDType::F32)],
-                        push_constants: None,
-                        workgroup_size_x: 256,
-                        elems_per_invocation: 1,
-                        uniform_bytes: Some(vol_uniform),
-                    };
-                    rt.run_pipeline(&mut table, &[step_vol])
-                        .map_err(|e| e.to_string())?;
-                    for df_mut in [&mut out_df, &mut working_df] {
-                        rt.download_append(df_mut, &table, &vol_name)
-                            .map_err(|e| e.to_string())?;
-                        cast_col(df_mut, &vol_name, DataType::Float64)?;
-                    }
-
-                    #[repr(C)]
-                    #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
-                    struct BandParams {
-                        scale: f32,
-                        _p0: f32,
-                        _p1: f32,
-                        _p2: f32,
-                    }
-
-                    let pos_name = format!("{}_pos", vol_name);
-                    let step_pos = KernelStep {
-                        shader_key: Cow::Borrowed("band_from_vol"),
-                        wgsl_src: Some(Cow::Borrowed(include_str!(
-                            "../shaders/band_from_vol.wgsl"
-                        ))),
-                        entry_point: Cow::Borrowed("main"),
-                        inputs: vec![Cow::Owned(price_col.clone()), Cow::Owned(vol_name.clone())],
-                        outputs: vec![OutputSpec::column(pos_name.clone(), GpuDType::F32)],
-                        push_constants: None,
-                        workgroup_size_x: 256,
-                        elems_per_invocation: 1,
-                        uniform_bytes: Some(
-                            bytemuck::bytes_of(&BandParams {
-                                scale,
-                                _p0: 0.0,
-                                _p1: 0.0,
-                                _p2: 0.0,
-                            })
-                            .to_vec(),
-                        ),
-                    };
-                    rt.run_pipeline(&mut table, &[step_pos])
-                        .map_err(|e| e.to_string())?;
-                    for df_mut in [&mut out_df, &mut working_df] {
-                        rt.download_append(df_mut, &table, &pos_name)
-                            .map_err(|e| e.to_string())?;
-
-                        // normalize_gpu_output(ut_df, &vol_name, DataType::Float64)?;
-                        cast_col(df_mut, &pos_name, DataType::Float64)?;
-                    }
-
-                    let neg_name = format!("{}_neg", vol_name);
-                    let step_neg = KernelStep {
-                        shader_key: Cow::Borrowed("band_from_vol"),
-                        wgsl_src: Some(Cow::Borrowed(include_str!(
-                            "../shaders/band_from_vol.wgsl"
-                        ))),
-                        entry_point: Cow::Borrowed("main"),
-                        inputs: vec![Cow::Owned(price_col.clone()), Cow::Owned(vol_name.clone())],
-                        outputs: vec![OutputSpec::column(neg_name.clone(), GpuDType::F32)],
-                        push_constants: None,
-                        workgroup_size_x: 256,
-                        elems_per_invocation: 1,
-                        uniform_bytes: Some(
-                            bytemuck::bytes_of(&BandParams {
-                                scale: -scale,
-                                _p0: 0.0,
-                                _p1: 0.0,
-                                _p2: 0.0,
-                            })
-                            .to_vec(),
-                        ),
-                    };
-                    rt.run_pipeline(&mut table, &[step_neg])
-                        .map_err(|e| e.to_string())?;
-                    for df_mut in [&mut out_df, &mut working_df] {
-                        rt.download_append(df_mut, &table, &neg_name)
-                            .map_err(|e| e.to_string())?;
-                        cast_col(df_mut, &neg_name, DataType::Float64)?;
-                    }
-                }
+/// `action` over `frames` of bars on `calendar`, each with the CALC outputs it reuses,
+/// on `gpu`: several go up packed in one [`action_over_frames_gpu`] job, one alone. When
+/// the GPU can't take them (over its budget, no device, a job that fails or panics) they
+/// run one by one on the CPU.
+pub fn action_over_frames(
+    action: &ActionSection,
+    frames: Vec<(DataFrame, CalcOutputs)>,
+    calendar: &Calendar,
+    gpu: &GpuService,
+) -> Result<Vec<(DataFrame, CalcOutputs)>, String> {
+    let rows = frames.iter().map(|(df, _)| df.height()).sum();
+    let job = {
+        let action = action.clone();
+        let frames = frames.clone();
+        let calendar = calendar.clone();
+        let profiled = profile::is_recording();
+        move |rt: &mut GpuRuntime| {
+            // the GPU thread's steps go in the profile of the run waiting on them
+            let started = Instant::now();
+            if profiled {
+                let (done, profile) =
+                    profile::record(|| frames_on_gpu(&action, frames, &calendar, rt));
+                (done, Some((profile, started)))
+            } else {
+                (frames_on_gpu(&action, frames, &calendar, rt), None)
+            }
+        }
+    };
+    let queued = profile::span("GPU job");
+    let why = match gpu.run(gpu_bytes(action, rows), job) {
+        Ok((done, profile)) => {
+            if let Some((profile, started)) = profile {
+                profile::merge(profile, started);
+            }
+            match done {
+                Ok(done) => return Ok(done),
+                Err(e) => format!("the GPU job failed: {e}"),
+            }
+        }
+        Err(why) => why,
+    };
+    drop(queued);
+    log::warn!("CALCs run on the CPU: {}", why);
+    let _cpu = profile::span("CPU CALCs");
+    frames
+        .into_iter()
+        .map(|(df, reuse)| {
+            let mut outputs = CalcOutputs::new();
+            let df = action_over_data_cpu(action, df, calendar, &reuse, &mut outputs)
+                .map_err(|e| format!("{e} (GPU: {why})"))?;
+            Ok((df, outputs))
+        })
+        .collect()
+}
 
-                Keyword::LinearRegression => {
-                    // ensure LR inputs are f64
-                    coerce_inputs_to_f64(&mut working_df, &calc.inputs)
-                        .map_err(|e| format!("LinearRegression input coercion failed: {e}"))?;
-                    let calculation = Calculation::new(calc.clone());
-                    let cpu_df = calculation
-                        .calculate(&working_df)
-                        .map_err(|e| format!("LinearRegression CPU fallback failed: {e}"))?;
-                    for col in cpu_df.get_columns() {
-                        out_df
-                            .with_column(col.clone())
-                            .map_err(|e| format!("append col: {e}"))?;
-                        working_df
-                            .with_column(col.clone())
-                            .map_err(|e| format!("append working col: {e}"))?;
-                    }
-                }
+/// The GPU job of [`action_over_frames`].
+fn frames_on_gpu(
+    action: &ActionSection,
+    frames: Vec<(DataFrame, CalcOutputs)>,
+    calendar: &Calendar,
+    rt: &mut GpuRuntime,
+) -> Result<Vec<(DataFrame, CalcOutputs)>, String> {
+    if let [(df, reuse)] = &frames[..] {
+        let mut outputs = CalcOutputs::new();
+        let df =
+            action_over_data_gpu_reusing(action, df.clone(), calendar, rt, reuse, &mut outputs)?;
+        return Ok(vec![(df, outputs)]);
+    }
+    let mut outputs = vec![CalcOutputs::new(); frames.len()];
+    let dfs = frames.into_iter().map(|(df, _)| df).collect();
+    let dfs = action_over_frames_gpu(action, dfs, calendar, rt, &mut outputs)?;
+    Ok(dfs.into_iter().zip(outputs).collect())
+}
 
-                _ => {
-                    return Err(format!(
-                        "Unsupported operation in GPU path: {:?}",
-                        calc.operation
-                    ))
-                }
+/// Roughly what `action`'s CALCs put on the GPU over `rows` rows: a 4 byte value per
+/// row for each input column, output column and the segment offsets.
+pub fn gpu_bytes(action: &ActionSection, rows: usize) -> u64 {
+    let calcs = action.calc.iter().flatten();
+    let mut inputs: Vec<&str> = Vec::new();
+    let mut outputs = 0;
+    for calc in calcs {
+        for input in calc.inputs.iter().filter(|i| !is_numeric_literal(i)) {
+            if !inputs.contains(&input.as_str()) {
+                inputs.push(input);
             }
         }
-
-        Ok(out_df)
-    })
+        outputs += match calc_steps(calc, 1.0) {
+            Ok(Some(steps)) => steps.iter().map(|s| s.outputs.len()).sum(),
+            _ => 1,
+        };
+    }
+    (inputs.len() + outputs + 1) as u64 * rows as u64 * 4
 }
 
-// --- helpers ---
+/// [`action_over_data_gpu_reusing`] on the CPU, for frames the GPU can't take.
+/// KERNEL CALCs only run on the GPU, so they fail here unless reused.
+pub fn action_over_data_cpu(
+    action: &ActionSection,
+    mut df: DataFrame,
+    calendar: &Calendar,
+    reuse: &CalcOutputs,
+    outputs: &mut CalcOutputs,
+) -> Result<DataFrame, String> {
+    sort_by_timestamp(&mut df)?;
+    let ts = df
+        .column("timestamp")
+        .map_err(|e| format!("Failed to get timestamp column: {e}"))?
+        .clone();
+    let selected = df
+        .select(action.fields.iter().map(|s| s.as_str()).collect::<Vec<_>>())
+        .map_err(|e| format!("Failed to select fields: {e}"))?;
+    let mut base = vec![ts.clone()];
+    base.extend_from_slice(selected.get_columns());
+    let mut out_df =
+        DataFrame::new(base).map_err(|e| format!("Failed to create DataFrame: {e}"))?;
 
-fn is_numeric_literal(s: &str) -> bool {
-    let s = s.trim().trim_matches('"').trim_matches('\'');
-    if s.is_empty() {
-        return false;
+    let Some(calcs) = &action.calc else {
+        return Ok(out_df);
+    };
+    for calc in order_calcs_flat(action).map_err(|e| e.to_string())? {
+        let err = |e: String| format!("CALC {} ({}): {}", calc.alias, calc.span, e);
+        let columns = match reuse.get(&calc.alias) {
+            Some(columns) => columns.clone(),
+            None if calc.operation == Keyword::Kernel => {
+                return Err(err("KERNEL CALCs need a GPU".to_string()));
+            }
+            None => {
+                let mut inputs = vec![ts.clone()];
+                for name in calc.inputs.iter().filter(|i| has_col(&df, i)) {
+                    let column = df.column(name).map_err(|e| err(e.to_string()))?;
+                    inputs.push(
+                        column
+                            .cast(&DataType::Float64)
+                            .map_err(|e| err(e.to_string()))?,
+                    );
+                }
+                let inputs = DataFrame::new(inputs).map_err(|e| err(e.to_string()))?;
+                Calculation::new(calc.clone(), calendar)
+                    .calculate(&inputs)
+                    .map_err(err)?
+                    .get_columns()
+                    .to_vec()
+            }
+        };
+        for column in &columns {
+            df.with_column(column.clone())
+                .map_err(|e| err(e.to_string()))?;
+        }
+        outputs.insert(calc.alias.clone(), columns);
     }
-    if s.chars()
-        .any(|c| c.is_ascii_alphabetic() && c != 'e' && c != 'E')
-    {
-        return false;
+    for calc in calcs {
+        for column in &outputs[&calc.alias] {
+            out_df
+                .with_column(column.clone())
+                .map_err(|e| format!("CALC {} ({}): {}", calc.alias, calc.span, e))?;
+        }
     }
-    s.parse::<f64>().is_ok()
+    Ok(out_df)
 }
 
-/// Coerce only the inputs required for THIS calc, skipping literals.
-/// Returns Err only if a *non-literal* input column is truly missing or unsupported.
-fn coerce_calc_inputs_to_f32_nan(df: &mut DataFrame, calc: &Calc) -> Result<(), String> {
-    use polars::prelude::DataType;
+/// Run `action`'s CALCs over `df`, sorted by timestamp, with `segments` the row offsets of
+/// the frames packed into it.
+fn run_calcs(
+    action: &ActionSection,
+    df: DataFrame,
+    segments: &[usize],
+    calendar: &Calendar,
+    rt: &mut GpuRuntime,
+    reuse: &CalcOutputs,
+    outputs: &mut CalcOutputs,
+) -> Result<DataFrame, String> {
+    // Base: timestamp + selected fields
+    let mut base = Vec::new();
+    let ts = df
+        .column("timestamp")
+        .map_err(|e| format!("Failed to get timestamp column: {e}"))?
+        .clone();
+    base.push(ts);
 
-    for name in &calc.inputs {
-        if is_numeric_literal(name) {
-            continue; // don't coerce literals
-        }
+    let selected = df
+        .select(action.fields.iter().map(|s| s.as_str()).collect::<Vec<_>>())
+        .map_err(|e| format!("Failed to select fields: {e}"))?;
+    base.extend_from_slice(selected.get_columns());
+    let mut out_df =
+        DataFrame::new(base).map_err(|e| format!("Failed to create DataFrame: {e}"))?;
 
-        let s = df
-            .column(name)
-            .map_err(|_| format!("Required input column '{name}' not found"))?
-            .clone();
-
-        let s = match s.dtype() {
-            DataType::Float32 => s,
-            DataType::Float64
-            | DataType::Int64
-            | DataType::Int32
-            | DataType::UInt64
-            | DataType::UInt32 => s
-                .cast(&DataType::Float32)
-                .map_err(|e| format!("Failed to cast '{name}' to Float32: {e}"))?,
-            other => {
-                return Err(format!(
-                    "Column '{name}' has unsupported dtype for GPU: {other:?}"
-                ));
+    let Some(calcs) = &action.calc else {
+        return Ok(out_df);
+    };
+
+    // reused outputs go up with the frame, for the CALCs after them
+    let mut working_df = df;
+    for calc in calcs {
+        for column in reuse.get(&calc.alias).into_iter().flatten() {
+            working_df
+                .with_column(column.clone())
+                .map_err(|e| format!("CALC {} ({}): {}", calc.alias, calc.span, e))?;
+        }
+    }
+    let mut needed: Vec<&str> = Vec::new();
+    for input in calcs.iter().flat_map(|c| &c.inputs) {
+        if input != "timestamp" && has_col(&working_df, input) && !needed.contains(&input.as_str())
+        {
+            needed.push(input);
+        }
+    }
+    // sorted already: packed frames each are, and sorting the lot would mix them up
+    cast_for_gpu(&mut working_df, &needed).map_err(|e| format!("sanitize: {e}"))?;
+    let upload = profile::span("GPU upload");
+    let mut table = rt
+        .upload_dataframe(&working_df, Some(&needed))
+        .map_err(|e| format!("GPU upload failed: {e}"))?;
+    rt.set_segments(&mut table, segments)
+        .map_err(|e| format!("GPU upload failed: {e}"))?;
+    drop(upload);
+    // packed frames share their calendar and bar spacing, and so the annualizing factor
+    let annualize = periods_per_year(calendar, &working_df).sqrt() as f32;
+
+    let waves = order_calcs_by_waves(action).map_err(|e| e.to_string())?;
+    let mut gpu_outputs: HashMap<String, Vec<String>> = HashMap::new();
+    let mut cpu_outputs: HashMap<String, Vec<Column>> = HashMap::new();
+    for (k, wave) in waves.iter().enumerate() {
+        let _wave = profile::span(format!("GPU wave {}", k + 1));
+        let mut steps = Vec::new();
+        let mut on_cpu = Vec::new();
+        for calc in wave.iter().filter(|c| !reuse.contains_key(&c.alias)) {
+            match calc_steps(calc, annualize)
+                .map_err(|e| format!("CALC {} ({}): {}", calc.alias, calc.span, e))?
+            {
+                Some(calc_steps) => {
+                    let outputs = calc_steps.iter().flat_map(|s| &s.outputs);
+                    let names = outputs.map(|o| o.name.to_string());
+                    gpu_outputs.insert(calc.alias.clone(), names.collect());
+                    steps.extend(calc_steps);
+                }
+                None => on_cpu.push(calc),
             }
-        };
+        }
+        rt.run_pipeline(&mut table, &steps)
+            .map_err(|e| format!("GPU pipeline failed: {e}"))?;
+
+        // CPU CALCs read their inputs back and put their outputs up for the next waves
+        for calc in on_cpu {
+            let _cpu = profile::span(format!("CPU CALC {}", calc.alias));
+            let columns = cpu_calc(calc, &table, calendar, rt)
+                .map_err(|e| format!("CALC {} ({}): {}", calc.alias, calc.span, e))?;
+            for column in &columns {
+                let upload = column
+                    .cast(&DataType::Float32)
+                    .map_err(|e| format!("CALC {} ({}): {}", calc.alias, calc.span, e))?;
+                rt.upload_column(&mut table, upload.as_materialized_series())
+                    .map_err(|e| format!("CALC {} ({}): {}", calc.alias, calc.span, e))?;
+            }
+            cpu_outputs.insert(calc.alias.clone(), columns);
+        }
+    }
 
-        // Fill nulls with NaN to keep length identical
-        let ca = s.f32().unwrap();
-        let filled: Vec<f32> = (0..ca.len())
-            .map(|i| ca.get(i).unwrap_or(f32::NAN))
-            .collect();
-        let new_s = Series::new(name.as_str().into(), filled);
+    let names: Vec<&str> = calcs
+        .iter()
+        .flat_map(|c| gpu_outputs.get(&c.alias).into_iter().flatten())
+        .map(|n| n.as_str())
+        .collect();
+    let download = profile::span("GPU download");
+    let mut downloaded: HashMap<String, Column> = HashMap::new();
+    for series in rt
+        .download_columns(&table, &names)
+        .map_err(|e| format!("GPU download failed: {e}"))?
+    {
+        let column = f64_column(series.into_column())?;
+        downloaded.insert(column.name().to_string(), column);
+    }
+    drop(download);
 
-        df.replace(name, new_s)
-            .map_err(|e| format!("Failed replacing column '{name}': {e}"))?;
+    for calc in calcs {
+        let columns = match (reuse.get(&calc.alias), gpu_outputs.get(&calc.alias)) {
+            (Some(columns), _) => columns.clone(),
+            (None, Some(names)) => names.iter().filter_map(|n| downloaded.remove(n)).collect(),
+            (None, None) => cpu_outputs.remove(&calc.alias).unwrap_or_default(),
+        };
+        for column in &columns {
+            out_df
+                .with_column(column.clone())
+                .map_err(|e| format!("CALC {} ({}): {}", calc.alias, calc.span, e))?;
+        }
+        outputs.insert(calc.alias.clone(), columns);
     }
 
-    Ok(())
+    Ok(out_df)
 }
 
-fn coerce_needed_cols_to_f32_nan(df: &mut DataFrame, calcs: &[Calc]) -> Result<(), String> {
-    use std::collections::HashSet;
+/// The GPU steps of a CALC, or `None` for a CALC that runs on the CPU. VOLATILITY is
+/// scaled by `annualize`, the square root of the bars in a year.
+fn calc_steps(calc: &Calc, annualize: f32) -> Result<Option<Vec<KernelStep<'static>>>, String> {
+    let step = |key: &'static str,
+                src: &'static str,
+                inputs: Vec<String>,
+                output: &str,
+                uniform: Option<Vec<u8>>| KernelStep {
+        shader_key: Cow::Borrowed(key),
+        wgsl_src: Some(Cow::Borrowed(src)),
+        entry_point: Cow::Borrowed("main"),
+        inputs: inputs.into_iter().map(Cow::Owned).collect(),
+        outputs: vec![OutputSpec::column(output.to_string(), GpuDType::F32)],
+        push_constants: None,
+        workgroup_size_x: 256,
+        elems_per_invocation: 1,
+        uniform_bytes: uniform,
+    };
 
-    let mut needed: HashSet<String> = HashSet::new();
-    for c in calcs {
-        if !matches!(c.operation, Keyword::Constant) {
-            for s in &c.inputs {
-                needed.insert(s.clone());
+    let steps = match calc.operation {
+        Keyword::Constant => {
+            #[repr(C)]
+            #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+            struct Params {
+                value: f32,
+                _p0: f32,
+                _p1: f32,
+                _p2: f32,
             }
+            let val = calc
+                .inputs
+                .get(0)
+                .and_then(|s| s.parse::<f32>().ok())
+                .unwrap_or(0.0);
+            let uniform = bytemuck::bytes_of(&Params {
+                value: val,
+                _p0: 0.0,
+                _p1: 0.0,
+                _p2: 0.0,
+            })
+            .to_vec();
+
+            vec![step(
+                "constant_fill",
+                include_str!("../shaders/constant_fill.wgsl"),
+                vec![],
+                &calc.alias,
+                Some(uniform),
+            )]
         }
-    }
 
-    for name in needed {
-        let s = df
-            .column(&name)
-            .map_err(|_| format!("Required input column '{name}' not found"))?
-            .clone();
-
-        let s = match s.dtype() {
-            DataType::Float32 => s,
-            DataType::Float64
-            | DataType::Int64
-            | DataType::Int32
-            | DataType::UInt64
-            | DataType::UInt32 => s
-                .cast(&DataType::Float32)
-                .map_err(|e| format!("Failed to cast '{name}' to Float32: {e}"))?,
-            other => {
-                return Err(format!(
-                    "Column '{name}' has unsupported dtype for GPU: {other:?}"
-                ));
+        Keyword::Difference => calc
+            .inputs
+            .windows(2)
+            .enumerate()
+            .map(|(idx, w)| {
+                let out_name = if calc.inputs.len() == 2 {
+                    calc.alias.clone()
+                } else {
+                    format!("{}_{}", calc.alias, idx)
+                };
+                step(
+                    "difference_pair",
+                    include_str!("../shaders/difference_pair.wgsl"),
+                    w.to_vec(),
+                    &out_name,
+                    None,
+                )
+            })
+            .collect(),
+
+        Keyword::Sma => {
+            let src = calc
+                .inputs
+                .get(0)
+                .cloned()
+                .ok_or_else(|| "SMA requires one input column".to_string())?;
+            let period = calc
+                .inputs
+                .get(1)
+                .and_then(|s| s.parse::<u32>().ok())
+                .unwrap_or(14);
+            #[repr(C)]
+            #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+            struct Params {
+                period: u32,
+                ahead: u32,
+                _p1: u32,
+                _p2: u32,
             }
-        };
+            let uniform = bytemuck::bytes_of(&Params {
+                period,
+                ahead: calc.window_ahead(period as usize) as u32,
+                _p1: 0,
+                _p2: 0,
+            })
+            .to_vec();
+
+            vec![step(
+                "sma",
+                include_str!("../shaders/sma.wgsl"),
+                vec![src, SEGMENTS.to_string()],
+                &calc.alias,
+                Some(uniform),
+            )]
+        }
 
-        // Fill nulls with NaN to keep length identical
-        let ca = s.f32().unwrap();
-        let filled: Vec<f32> = (0..ca.len())
-            .map(|i| ca.get(i).unwrap_or(f32::NAN))
-            .collect();
-        let new_s = Series::new(name.as_str().into(), filled);
+        Keyword::Volatility | Keyword::DoubleVolatility => {
+            let price_col = calc
+                .inputs
+                .get(0)
+                .cloned()
+                .ok_or_else(|| "Volatility requires a price column".to_string())?;
+            let period = calc
+                .inputs
+                .get(1)
+                .and_then(|s| s.parse::<u32>().ok())
+                .unwrap_or(14);
+            let scale: f32 = if calc.operation == Keyword::Volatility {
+                0.5
+            } else {
+                1.0
+            };
+
+            #[repr(C)]
+            #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+            struct VolParams {
+                period: u32,
+                annualize: f32,
+                ahead: u32,
+                _p1: u32,
+            }
+            let vol_uniform = bytemuck::bytes_of(&VolParams {
+                period,
+                annualize,
+                ahead: calc.window_ahead(period as usize) as u32,
+                _p1: 0,
+            })
+            .to_vec();
+
+            #[repr(C)]
+            #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+            struct BandParams {
+                scale: f32,
+                _p0: f32,
+                _p1: f32,
+                _p2: f32,
+            }
+            let band = |scale: f32| {
+                bytemuck::bytes_of(&BandParams {
+                    scale,
+                    _p0: 0.0,
+                    _p1: 0.0,
+                    _p2: 0.0,
+                })
+                .to_vec()
+            };
+
+            // the bands read the volatility the first step leaves on the GPU
+            let vol_name = calc.alias.clone();
+            vec![
+                step(
+                    "volatility_vol_only",
+                    include_str!("../shaders/volatility_vol_only.wgsl"),
+                    vec![price_col.clone(), SEGMENTS.to_string()],
+                    &vol_name,
+                    Some(vol_uniform),
+                ),
+                step(
+                    "band_from_vol",
+                    include_str!("../shaders/band_from_vol.wgsl"),
+                    vec![price_col.clone(), vol_name.clone()],
+                    &format!("{}_pos", vol_name),
+                    Some(band(scale)),
+                ),
+                step(
+                    "band_from_vol",
+                    include_str!("../shaders/band_from_vol.wgsl"),
+                    vec![price_col, vol_name.clone()],
+                    &format!("{}_neg", vol_name),
+                    Some(band(-scale)),
+                ),
+            ]
+        }
 
-        df.replace(&name, new_s)
-            .map_err(|e| format!("Failed replacing column '{name}': {e}"))?;
-    }
+        Keyword::LinearRegression => return Ok(None),
+
+        Keyword::Kernel => {
+            let kernel = calc
+                .kernel
+                .as_ref()
+                .ok_or_else(|| "KERNEL CALC without its declaration".to_string())?;
+            let (values, columns): (Vec<&String>, Vec<&String>) =
+                calc.inputs.iter().partition(|i| is_numeric_literal(i));
+            let values: Vec<f64> = values.iter().filter_map(|v| v.parse().ok()).collect();
+            vec![KernelStep {
+                shader_key: Cow::Owned(kernel.shader_key()),
+                wgsl_src: Some(Cow::Owned(kernel.source.clone())),
+                entry_point: Cow::Borrowed("main"),
+                inputs: columns.into_iter().cloned().map(Cow::Owned).collect(),
+                outputs: kernel
+                    .output_columns(&calc.alias)
+                    .into_iter()
+                    .map(|name| OutputSpec::column(name, GpuDType::F32))
+                    .collect(),
+                push_constants: None,
+                workgroup_size_x: kernel.workgroup_size,
+                elems_per_invocation: 1,
+                uniform_bytes: kernel.uniform_bytes(&values),
+            }]
+        }
 
-    Ok(())
+        _ => {
+            return Err(format!(
+                "Unsupported operation in GPU path: {:?}",
+                calc.operation
+            ))
+        }
+    };
+    Ok(Some(steps))
 }
 
-#[inline]
-fn f32_to_bits(v: f32) -> u32 {
-    v.to_bits()
+/// Run a CPU CALC over its inputs, read back from the GPU.
+fn cpu_calc(
+    calc: &Calc,
+    table: &GpuTable,
+    calendar: &Calendar,
+    rt: &GpuRuntime,
+) -> Result<Vec<Column>, String> {
+    let mut inputs: Vec<&str> = Vec::new();
+    for name in calc.inputs.iter().filter(|s| s.parse::<f64>().is_err()) {
+        if table.get(name).is_err() {
+            return Err(format!("input column '{name}' not found"));
+        }
+        inputs.push(name);
+    }
+    let columns = rt
+        .download_columns(table, &inputs)
+        .map_err(|e| format!("GPU download failed: {e}"))?
+        .into_iter()
+        .map(|s| f64_column(s.into_column()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let df = DataFrame::new(columns).map_err(|e| e.to_string())?;
+    // frame by frame, when several are packed into the table
+    let mut out: Option<DataFrame> = None;
+    for w in table.segments.windows(2) {
+        let part = Calculation::new(calc.clone(), calendar)
+            .calculate(&df.slice(w[0] as i64, w[1] - w[0]))
+            .map_err(|e| format!("{:?} failed: {e}", calc.operation))?;
+        match &mut out {
+            Some(out) => {
+                out.vstack_mut_owned(part).map_err(|e| e.to_string())?;
+            }
+            None => out = Some(part),
+        }
+    }
+    Ok(out
+        .map(|out| out.get_columns().to_vec())
+        .unwrap_or_default())
 }
 
+// --- helpers ---
+
+fn is_numeric_literal(s: &str) -> bool {
+    let s = s.trim().trim_matches('"').trim_matches('\'');
+    if s.is_empty() {
+        return false;
+    }
+    if s.chars()
+        .any(|c| c.is_ascii_alphabetic() && c != 'e' && c != 'E')
+    {
+        return false;
+    }
+    s.parse::<f64>().is_ok()
+}
+
+/// `action` over `df` on the CPU, with VOLATILITY annualized on the default calendar.
 pub fn action_over_data(action: &ActionSection, df: DataFrame) -> Result<DataFrame, String> {
     let mut df = df.clone();
     let field = action.fields.clone();
@@ -496,13 +719,16 @@ pub fn action_over_data(action: &ActionSection, df: DataFrame) -> Result<DataFra
 
     // Otherwise, run each Calc and append the results
     for calc in calcs {
-        let calculation = Calculation::new(calc.clone());
+        let calculation = Calculation::new(calc.clone(), &Calendar::default());
 
         let calc_df = match calculation.calculate(&df) {
             Ok(result) => result,
             Err(e) => {
                 log::error!("Failed to calculate: {}", e);
-                return Err(format!("Failed to calculate: {}", e));
+                return Err(format!(
+                    "CALC {} ({}): Failed to calculate: {}",
+                    calc.alias, calc.span, e
+                ));
             }
         };
         // Append calc_df columns to the main DataFrame
@@ -518,8 +744,16 @@ pub fn action_over_data(action: &ActionSection, df: DataFrame) -> Result<DataFra
     Ok(result_df)
 }
 
+/// Sort `df` by timestamp and cast `cols` to f32 for upload. Missing values aren't
+/// filled here, the FRAME's FILL policy has already had them: they go up as NaN and
+/// come back missing in the CALCs over them.
 pub fn sanitize_for_gpu(df: &mut DataFrame, cols: &[&str]) -> Result<(), String> {
-    // 1) ensure sorted by timestamp (if present)
+    sort_by_timestamp(df)?;
+    cast_for_gpu(df, cols)
+}
+
+/// Sort `df` by timestamp, if it has one and isn't sorted already.
+pub(crate) fn sort_by_timestamp(df: &mut DataFrame) -> Result<(), String> {
     if let Ok(ts) = df.column("timestamp") {
         if matches!(ts.is_sorted_flag(), IsSorted::Not) {
             *df = df
@@ -530,176 +764,41 @@ pub fn sanitize_for_gpu(df: &mut DataFrame, cols: &[&str]) -> Result<(), String>
                 .map_err(|e| format!("sort by timestamp failed: {e}"))?;
         }
     }
+    Ok(())
+}
 
-    // 2) cast to f32, 3) fill nulls, 4) replace non-finite
-    // We do it column-by-column to avoid borrow issues.
+/// Cast `cols` to f32 for upload.
+fn cast_for_gpu(df: &mut DataFrame, cols: &[&str]) -> Result<(), String> {
     for &name in cols {
         if df.column(name).is_err() {
             continue;
         }
-
-        // cast → f32
         let f32col = df
             .column(name)
             .unwrap()
             .clone()
             .cast(&DataType::Float32)
             .map_err(|e| format!("cast '{name}' to f32 failed: {e}"))?;
-
-        // fill nulls (fwd then bwd)
-        let filled = f32col
-            .fill_null(FillNullStrategy::Forward(None))
-            .map_err(|e| format!("fill_null fwd '{name}' failed: {e}"))?
-            .fill_null(FillNullStrategy::Backward(None))
-            .map_err(|e| format!("fill_null bwd '{name}' failed: {e}"))?;
-
-        // replace non-finite (NaN/Inf) with neighbor values, then 0.0
-        // pass 1: forward
-        let cleaned_fwd = filled
-            .clone()
-            .f32()
-            .ok()
-            .map(|ca| {
-                let mut v = ca.to_vec();
-                for i in 0..v.len() {
-                    if let Some(x) = v[i] {
-                        if !x.is_finite() {
-                            // find previous finite
-                            let mut j = i.saturating_sub(1);
-                            while i > 0 {
-                                if let Some(px) = v[j] {
-                                    if px.is_finite() {
-                                        v[i] = Some(px);
-                                        break;
-                                    }
-                                }
-                                if j == 0 {
-                                    break;
-                                }
-                                j -= 1;
-                            }
-                        }
-                    }
-                }
-                Series::new(name.into(), v)
-            })
-            .unwrap_or(filled.as_series().unwrap().clone());
-
-        // pass 2: backward
-        let cleaned_bwd = cleaned_fwd
-            .clone()
-            .f32()
-            .ok()
-            .map(|ca| {
-                let mut v = ca.to_vec();
-                let n = v.len();
-                for idx in (0..n).rev() {
-                    if let Some(x) = v[idx] {
-                        if !x.is_finite() {
-                            // find next finite
-                            let mut j = idx + 1;
-                            while j < n {
-                                if let Some(nx) = v[j] {
-                                    if nx.is_finite() {
-                                        v[idx] = Some(nx);
-                                        break;
-                                    }
-                                }
-                                j += 1;
-                            }
-                        }
-                    }
-                }
-                // final pass: set any remaining None/non-finite to 0.0
-                for x in v.iter_mut() {
-                    if x.map(|y| !y.is_finite()).unwrap_or(true) {
-                        *x = Some(0.0);
-                    }
-                }
-                Series::new(name.into(), v)
-            })
-            .unwrap_or(cleaned_fwd);
-
-        df.replace(name, cleaned_bwd)
+        df.replace(name, f32col.take_materialized_series())
             .map_err(|e| format!("replace '{name}' failed: {e}"))?;
     }
 
     Ok(())
 }
 
-// after GPU download, upcast to f64 for downstream consumers
-fn cast_col(df: &mut DataFrame, name: &str, dtype: DataType) -> Result<(), String> {
-    if !has_col(df, name) {
-        return Ok(());
-    }
-    let casted = df
-        .column(name)
-        .map_err(|e| format!("get '{name}' failed: {e}"))?
-        .clone()
-        .cast(&dtype)
+/// A GPU output as f64 for downstream consumers; NaN (missing) becomes null.
+fn f64_column(column: Column) -> Result<Column, String> {
+    let name = column.name().clone();
+    let cast = column
+        .cast(&DataType::Float64)
         .map_err(|e| format!("cast '{name}' failed: {e}"))?;
-    df.with_column(casted)
-        .map_err(|e| format!("with_column '{name}' failed: {e}"))?;
-    Ok(())
-}
-
-fn coerce_inputs_to_f64_no_nan(df: &mut DataFrame, cols: &[String]) -> Result<(), String> {
-    for name in cols {
-        if df.column(name).is_err() {
-            continue;
-        }
-
-        let cast = df
-            .column(name)
-            .unwrap()
-            .clone()
-            .cast(&DataType::Float64)
-            .map_err(|e| format!("cast '{name}' to f64 failed: {e}"))?;
-
-        // forward/back fill nulls
-        let filled = cast
-            .fill_null(FillNullStrategy::Forward(None))
-            .map_err(|e| format!("fill fwd '{name}' failed: {e}"))?
-            .fill_null(FillNullStrategy::Backward(None))
-            .map_err(|e| format!("fill bwd '{name}' failed: {e}"))?;
-
-        // replace non-finite with 0
-        let v = filled
-            .f64()
-            .unwrap()
-            .into_iter()
-            .map(|o| {
-                let x = o.unwrap_or(0.0);
-                if x.is_finite() {
-                    Some(x)
-                } else {
-                    Some(0.0)
-                }
-            })
-            .collect::<Vec<_>>();
-
-        df.replace(name, Series::new(name.as_str().into(), v))
-            .map_err(|e| format!("replace '{name}' failed: {e}"))?;
-    }
-    Ok(())
-}
-
-// ensure LR inputs are Float64 (your Calculation expects f64)
-fn coerce_inputs_to_f64(df: &mut DataFrame, inputs: &[String]) -> Result<(), String> {
-    for name in inputs {
-        if !has_col(df, name) {
-            return Err(format!("LinearRegression input column '{name}' not found"));
-        }
-        let s64 = df
-            .column(name)
-            .map_err(|e| format!("get '{name}' failed: {e}"))?
-            .clone()
-            .cast(&DataType::Float64)
-            .map_err(|e| format!("cast '{name}' to f64 failed: {e}"))?;
-        df.with_column(s64)
-            .map_err(|e| format!("with_column '{name}' failed: {e}"))?;
-    }
-    Ok(())
+    let values: Float64Chunked = cast
+        .f64()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|v| v.filter(|v| v.is_finite()))
+        .collect();
+    Ok(values.with_name(name).into_column())
 }
 
 #[inline]
@@ -707,44 +806,187 @@ fn has_col(df: &DataFrame, name: &str) -> bool {
     df.get_column_names().iter().any(|n| n as &str == name)
 }
 
-fn normalize_gpu_output(df: &mut DataFrame, col: &str, want: DataType) -> Result<(), String> {
-    // cast to f64 for graph/LR
-    let s = df
-        .column(col)
-        .map_err(|_| format!("col '{col}' missing"))?
-        .clone()
-        .cast(&want)
-        .map_err(|e| format!("cast '{col}' to {want:?} failed: {e}"))?;
-
-    // replace NaN/Inf -> forward/back then zeros
-    let mut s = s.f64().map_err(|e| format!("{col} not f64: {e}"))?.clone();
-    // fill_null first (in case cast produced any)
-    let mut si = s
-        .clone()
-        .into_series()
-        .fill_null(FillNullStrategy::Forward(None))
-        .map_err(|e| format!("fill_null fwd '{col}' failed: {e}"))?
-        .fill_null(FillNullStrategy::Backward(None))
-        .map_err(|e| format!("fill_null bwd '{col}' failed: {e}"))?
-        .f64()
-        .unwrap()
-        .clone();
+#[cfg(test)]
+mod tests {
+    use super::{
+        action_over_data, action_over_data_cpu, action_over_data_gpu, action_over_data_gpu_reusing,
+        action_over_frames_gpu,
+    };
+    use crate::calendar::Calendar;
+    use crate::runtime::GpuRuntime;
+    use crate::testing::{daily_bars, frame_actions};
+    use crate::utils::incremental::CalcOutputs;
+    use polars::prelude::*;
+
+    #[test]
+    fn test_fused_waves_match_cpu() {
+        // wave 1: k, oc, fit (on the CPU); wave 2: over oc and fit; wave 3: over wave 2
+        let action = &frame_actions(
+            "open, close",
+            "
+    CALC 2.5 CONSTANT CALLED k
+    CALC close, open DIFFERENCE CALLED oc
+    CALC close LINEAR_REGRESSION CALLED fit
+    CALC close, fit DIFFERENCE CALLED resid
+    CALC oc SMA CALLED oc_sma
+    CALC resid, oc_sma DIFFERENCE CALLED spread
+",
+        );
+
+        let n = 1000;
+        let df = daily_bars(
+            (0..n).map(|i| 100.0 + (i % 17) as f64 * 0.5).collect(),
+            0.25,
+        );
+
+        let mut rt = pollster::block_on(GpuRuntime::new()).unwrap();
+        let gpu = action_over_data_gpu(action, df.clone(), &mut rt).unwrap();
+        let cpu = action_over_data(action, df).unwrap();
+        let values = |df: &DataFrame, name: &str| -> Vec<Option<f64>> {
+            df.column(name).unwrap().f64().unwrap().to_vec()
+        };
+        let fit = values(&cpu, "fit");
+        let (close, gpu_fit) = (values(&gpu, "close"), values(&gpu, "fit"));
+        let (k, oc) = (values(&gpu, "k"), values(&gpu, "oc"));
+        let (resid, oc_sma) = (values(&gpu, "resid"), values(&gpu, "oc_sma"));
+        let spread = values(&gpu, "spread");
+        assert!(oc_sma.iter().flatten().count() > n / 2);
+        let close_to = |a: Option<f64>, b: f64| a.is_some_and(|a| (a - b).abs() < 1e-3);
+        for i in 0..n {
+            // the constant fills every row, not just the first workgroup's
+            assert!(close_to(k[i], 2.5), "k[{i}]");
+            assert!(close_to(oc[i], 0.25), "oc[{i}]");
+            assert!(close_to(gpu_fit[i], fit[i].unwrap()), "fit[{i}]");
+            // the GPU wave after the CPU fit reads it in place
+            assert!(
+                close_to(resid[i], close[i].unwrap() - fit[i].unwrap()),
+                "resid[{i}]"
+            );
+            if let Some(avg) = oc_sma[i] {
+                assert!((avg - 0.25).abs() < 1e-3, "oc_sma[{i}]");
+                assert!(close_to(spread[i], resid[i].unwrap() - avg));
+            }
+        }
+    }
 
-    // turn NaN/Inf into 0.0
-    let v = si
-        .into_iter()
-        .map(|opt| {
-            let x = opt.unwrap_or(0.0);
-            if x.is_finite() {
-                Some(x)
-            } else {
-                Some(0.0)
+    #[test]
+    fn test_windows_trail_unless_centered() {
+        let action = &frame_actions(
+            "open, close",
+            "
+    CALC close SMA CALLED trail
+    CALC close SMA CENTERED CALLED mid
+    CALC close VOLATILITY CALLED vol
+    CALC close VOLATILITY CENTERED CALLED mid_vol
+",
+        );
+
+        let n = 200;
+        let close: Vec<f64> = (0..n).map(|i| 100.0 + (i * 7 % 11) as f64).collect();
+        let df = daily_bars(close.clone(), 0.5);
+
+        let mut rt = pollster::block_on(GpuRuntime::new()).unwrap();
+        let gpu = action_over_data_gpu(action, df.clone(), &mut rt).unwrap();
+        let cpu = action_over_data(action, df.clone()).unwrap();
+        let values = |df: &DataFrame, name: &str| -> Vec<Option<f64>> {
+            df.column(name).unwrap().f64().unwrap().to_vec()
+        };
+        let close_to = |a: Option<f64>, b: Option<f64>| match (a, b) {
+            (Some(a), Some(b)) => (a - b).abs() < 1e-3 * b.abs().max(1.0),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        let (period, ahead) = (14, 7);
+        for out in [&gpu, &cpu] {
+            let (trail, mid) = (values(out, "trail"), values(out, "mid"));
+            let (vol, mid_vol) = (values(out, "vol"), values(out, "mid_vol"));
+            for i in 0..n {
+                // a bar's trailing window ends at it, and never reads a later close
+                let mean = (i + 1 >= period)
+                    .then(|| close[i + 1 - period..=i].iter().sum::<f64>() / period as f64);
+                assert!(close_to(trail[i], mean), "trail[{i}]");
+                // the centered one is the trailing one, `ahead` bars later
+                let later = trail.get(i + ahead).copied().flatten();
+                assert!(close_to(mid[i], later), "mid[{i}]");
+                let later = vol.get(i + ahead).copied().flatten();
+                assert!(close_to(mid_vol[i], later), "mid_vol[{i}]");
             }
-        })
-        .collect::<Vec<_>>();
-    let out = Series::new(col.into(), v);
+        }
+        // annualized volatility of log returns, and its bands, on either
+        let outputs = ["vol", "vol_pos", "vol_neg", "mid_vol", "mid_vol_pos"];
+        for name in ["trail", "mid"].into_iter().chain(outputs) {
+            let (g, c) = (values(&gpu, name), values(&cpu, name));
+            assert!(
+                (0..n).all(|i| close_to(g[i], c[i])),
+                "{name}: GPU and CPU disagree"
+            );
+        }
 
-    df.replace(col, out)
-        .map_err(|e| format!("replace '{col}' failed: {e}"))?;
-    Ok(())
+        // the frame's calendar annualizes: one trading every day has more bars a year
+        let always = Calendar::always();
+        let ratio =
+            (always.periods_per_year(86_400) / Calendar::nyse().periods_per_year(86_400)).sqrt();
+        let mut none = CalcOutputs::new();
+        let on_gpu = action_over_data_gpu_reusing(
+            action,
+            df.clone(),
+            &always,
+            &mut rt,
+            &CalcOutputs::new(),
+            &mut none,
+        )
+        .unwrap();
+        let on_cpu = action_over_data_cpu(action, df, &always, &CalcOutputs::new(), &mut none);
+        for out in [on_gpu, on_cpu.unwrap()] {
+            let (vol, nyse) = (values(&out, "vol"), values(&gpu, "vol"));
+            for i in 0..n {
+                assert!(close_to(vol[i], nyse[i].map(|v| v * ratio)), "vol[{i}]");
+            }
+        }
+    }
+
+    #[test]
+    fn test_packed_frames_match_one_by_one() {
+        let action = &frame_actions(
+            "open, close",
+            "
+    CALC close, open DIFFERENCE CALLED oc
+    CALC close SMA CALLED c_sma
+    CALC close VOLATILITY CALLED vol
+    CALC close LINEAR_REGRESSION CALLED fit
+    CALC close, fit DIFFERENCE CALLED resid
+    CALC resid SMA CALLED resid_sma
+",
+        );
+
+        // tickers of different lengths and price levels, one shorter than the SMA
+        let frames: Vec<DataFrame> = [(300, 50.0), (9, 400.0), (1000, 4000.0)]
+            .iter()
+            .map(|&(n, level)| {
+                let close = (0..n).map(|i| level + (i * 7919 % 23) as f64 * 0.1 + i as f64 * 0.01);
+                daily_bars(close.collect(), 0.5)
+            })
+            .collect();
+
+        let mut rt = pollster::block_on(GpuRuntime::new()).unwrap();
+        let mut outputs = vec![CalcOutputs::new(); frames.len()];
+        let packed = action_over_frames_gpu(
+            action,
+            frames.clone(),
+            &Calendar::default(),
+            &mut rt,
+            &mut outputs,
+        )
+        .unwrap();
+        assert_eq!(packed.len(), frames.len());
+        for (k, df) in frames.into_iter().enumerate() {
+            let alone = action_over_data_gpu(action, df, &mut rt).unwrap();
+            // windows and the fit stay inside each frame: the same values as run alone
+            assert_eq!(packed[k], alone, "frame {k}");
+            assert_eq!(
+                outputs[k]["fit"][0],
+                *alone.column("fit").unwrap(),
+                "frame {k}"
+            );
+        }
+    }
 }
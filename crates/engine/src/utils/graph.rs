@@ -11,91 +11,96 @@ pub fn graph_over_data(
     let mut axis_labels: Vec<String> = vec![];
 
     for command in &graph_section.commands {
-        let df = frames
-            .get(&command.get_frame())
-            .ok_or_else(|| format!("Frame '{}' not found", command.get_frame()))?;
-
-        // Extract timestamps for x-axis; fallback to sequential indices if missing
-        let x_series = match extract_i64_column(df, "timestamp") {
-            Ok(series) => series,
-            Err(_) => (0..df.height()).map(|i| Some(i as i64)).collect(),
-        };
-
-        if axis_labels.is_empty() {
-            x_series.iter().for_each(|x| {
-                if let Some(value) = x {
-                    axis_labels.push(format!("{}", value));
-                } else {
-                    axis_labels.push("N/A".to_string());
-                }
-            });
-        }
+        draw_command(command, frames, &mut data, &mut axis_labels)
+            .map_err(|e| format!("GRAPH command ({}): {}", command.span(), e))?;
+    }
 
-        match command {
-            DrawCommand::Line { name, series, .. } => {
-                for field in series {
-                    let values = extract_f64_column(df, field)?
-                        .into_iter()
-                        .map(|v| v.unwrap_or(0.0))
-                        .collect();
-                    data.push(DrawType::Line(
-                        format!("{} - {}", command.get_frame(), name.clone()),
-                        values,
-                    ));
-                }
-            }
+    Ok(Graph {
+        data,
+        axis_labels,
+        title: "QQL Plot".into(),
+    })
+}
 
-            DrawCommand::Bar { name, .. } => {
-                let x: Vec<f64> = x_series.iter().map(|x| x.unwrap_or(0) as f64).collect();
-                data.push(DrawType::Bar(
-                    format!("{} - {}", command.get_frame(), name.clone()),
-                    x,
-                ));
+fn draw_command(
+    command: &DrawCommand,
+    frames: &HashMap<String, DataFrame>,
+    data: &mut Vec<DrawType>,
+    axis_labels: &mut Vec<String>,
+) -> Result<(), String> {
+    let df = frames
+        .get(&command.get_frame())
+        .ok_or_else(|| format!("Frame '{}' not found", command.get_frame()))?;
+
+    // Extract timestamps for x-axis; fallback to sequential indices if missing
+    let x_series = match extract_i64_column(df, "timestamp") {
+        Ok(series) => series,
+        Err(_) => (0..df.height()).map(|i| Some(i as i64)).collect(),
+    };
+
+    if axis_labels.is_empty() {
+        x_series.iter().for_each(|x| {
+            if let Some(value) = x {
+                axis_labels.push(format!("{}", value));
+            } else {
+                axis_labels.push("N/A".to_string());
             }
+        });
+    }
 
-            DrawCommand::Candle {
-                name,
-                open,
-                high,
-                low,
-                close,
-                ..
-            } => {
-                let open = extract_f64_column(df, open)?;
-                let high = extract_f64_column(df, high)?;
-                let low = extract_f64_column(df, low)?;
-                let close = extract_f64_column(df, close)?;
-
-                let candles = open
+    match command {
+        DrawCommand::Line { name, series, .. } => {
+            for field in series {
+                let values = extract_f64_column(df, field)?
                     .into_iter()
-                    .zip(high)
-                    .zip(low)
-                    .zip(close)
-                    .map(|(((o, h), l), c)| {
-                        (
-                            o.unwrap_or(0.0),
-                            h.unwrap_or(0.0),
-                            l.unwrap_or(0.0),
-                            c.unwrap_or(0.0),
-                        )
-                    })
+                    .map(|v| v.filter(|v| v.is_finite()))
                     .collect();
-
-                data.push(DrawType::Candlestick(
+                data.push(DrawType::Line(
                     format!("{} - {}", command.get_frame(), name.clone()),
-                    candles,
+                    values,
                 ));
             }
         }
 
-        add_trade_rects(df, &x_series, &mut data)?;
+        DrawCommand::Bar { name, .. } => {
+            let x: Vec<f64> = x_series.iter().map(|x| x.unwrap_or(0) as f64).collect();
+            data.push(DrawType::Bar(
+                format!("{} - {}", command.get_frame(), name.clone()),
+                x,
+            ));
+        }
+
+        DrawCommand::Candle {
+            name,
+            open,
+            high,
+            low,
+            close,
+            ..
+        } => {
+            let open = extract_f64_column(df, open)?;
+            let high = extract_f64_column(df, high)?;
+            let low = extract_f64_column(df, low)?;
+            let close = extract_f64_column(df, close)?;
+
+            let candles = open
+                .into_iter()
+                .zip(high)
+                .zip(low)
+                .zip(close)
+                .map(|(((o, h), l), c)| Some((o?, h?, l?, c?)))
+                .collect();
+
+            data.push(DrawType::Candlestick(
+                format!("{} - {}", command.get_frame(), name.clone()),
+                candles,
+            ));
+        }
     }
 
-    Ok(Graph {
-        data,
-        axis_labels,
-        title: "QQL Plot".into(),
-    })
+    add_trade_rects(df, &x_series, data)?;
+
+    Ok(())
 }
 
 fn extract_f64_column(df: &DataFrame, name: &str) -> Result<Vec<Option<f64>>, String> {
@@ -0,0 +1,359 @@
+use crate::lexer::Span;
+use crate::parser::{
+    calc_outputs, order_calcs_by_waves, ActionSection, Calc, DrawCommand, Frame, GraphSection,
+    ProviderInstance, Query, TradeSection,
+};
+use polars::prelude::Column;
+use std::collections::{HashMap, HashSet};
+
+/// A frame's CALC outputs from the last run: alias -> the columns it added.
+pub type CalcOutputs = HashMap<String, Vec<Column>>;
+
+/// What a run has to redo, compared with the last successful run.
+#[derive(Debug, Default, PartialEq)]
+pub struct Plan {
+    /// PROVIDER blocks to fetch again.
+    pub fetch: HashSet<String>,
+    /// FRAMEs to rebuild, each with the CALCs whose last outputs are still good.
+    pub frames: HashMap<String, HashSet<String>>,
+    pub graph: bool,
+    pub trade: bool,
+}
+
+impl Plan {
+    /// Everything, as on a first run.
+    pub fn full(query: &Query) -> Self {
+        Plan {
+            fetch: query.providers.keys().cloned().collect(),
+            frames: query
+                .frame
+                .keys()
+                .map(|name| (name.clone(), HashSet::new()))
+                .collect(),
+            graph: query.graph.is_some(),
+            trade: query.trade.is_some(),
+        }
+    }
+
+    /// Diff `next` against `prev`, the query of the last run, whose CALC outputs are
+    /// in `outputs` (by frame). Moving lines around or editing comments changes nothing.
+    pub fn diff(prev: &Query, next: &Query, outputs: &HashMap<String, CalcOutputs>) -> Self {
+        let fetch: HashSet<String> = next
+            .providers
+            .iter()
+            .filter(|(name, p)| {
+                prev.providers
+                    .get(*name)
+                    .is_none_or(|old| !same_provider(old, p))
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let mut frames = HashMap::new();
+        for (name, frame) in &next.frame {
+            match (prev.frame.get(name), outputs.get(name)) {
+                (Some(old), Some(outputs))
+                    if old.providers().eq(frame.providers())
+                        && old.adjust == frame.adjust
+                        && old.fill == frame.fill
+                        && old.actions.fields == frame.actions.fields
+                        && !frame.providers().any(|p| fetch.contains(p)) =>
+                {
+                    if !same_calcs(old, frame) {
+                        frames.insert(name.clone(), reusable_calcs(old, frame, outputs));
+                    }
+                }
+                _ => {
+                    frames.insert(name.clone(), HashSet::new());
+                }
+            }
+        }
+
+        let changed = |frame: &str| frames.contains_key(frame) || !prev.frame.contains_key(frame);
+        let graph = match (&prev.graph, &next.graph) {
+            (Some(old), Some(new)) => !same_graph(old, new) || graph_frames(new).any(&changed),
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+        let trade = match (&prev.trade, &next.trade) {
+            (Some(old), Some(new)) => !same_trade(old, new) || trade_frames(new).any(&changed),
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+
+        Plan {
+            fetch,
+            frames,
+            graph,
+            trade,
+        }
+    }
+
+    /// After LIVE `providers` got new bars: their frames, and the outputs drawing on them.
+    pub fn new_bars(query: &Query, providers: &HashSet<String>) -> Self {
+        let frames: HashMap<String, HashSet<String>> = query
+            .frame
+            .iter()
+            .filter(|(_, frame)| frame.providers().any(|p| providers.contains(p)))
+            .map(|(name, _)| (name.clone(), HashSet::new()))
+            .collect();
+        let changed = |frame: &str| frames.contains_key(frame);
+        Plan {
+            fetch: HashSet::new(),
+            graph: query
+                .graph
+                .as_ref()
+                .is_some_and(|g| graph_frames(g).any(changed)),
+            trade: query
+                .trade
+                .as_ref()
+                .is_some_and(|t| trade_frames(t).any(changed)),
+            frames,
+        }
+    }
+}
+
+/// CALCs of `new` that are unchanged from `old` and read nothing that changed,
+/// walking the dependency waves so changes flow downstream.
+fn reusable_calcs(old: &Frame, new: &Frame, outputs: &CalcOutputs) -> HashSet<String> {
+    let Ok(waves) = order_calcs_by_waves(&new.actions) else {
+        return HashSet::new();
+    };
+    let old_calcs: HashMap<&str, &Calc> = old
+        .actions
+        .calc
+        .iter()
+        .flatten()
+        .map(|c| (c.alias.as_str(), c))
+        .collect();
+
+    // columns whose values are new or gone: outputs of removed and edited CALCs
+    let mut dirty: HashSet<String> = HashSet::new();
+    for calc in old_calcs.values() {
+        let kept = new
+            .actions
+            .calc
+            .iter()
+            .flatten()
+            .any(|c| same_calc(c, calc));
+        if !kept {
+            dirty.extend(column_names(calc, outputs));
+        }
+    }
+
+    let mut reusable = HashSet::new();
+    for calc in waves.iter().flatten() {
+        let clean = old_calcs
+            .get(calc.alias.as_str())
+            .is_some_and(|old| same_calc(old, calc))
+            && outputs.contains_key(&calc.alias)
+            && !calc.inputs.iter().any(|input| dirty.contains(input));
+        if clean {
+            reusable.insert(calc.alias.clone());
+        } else {
+            dirty.extend(column_names(calc, outputs));
+        }
+    }
+    reusable
+}
+
+fn column_names(calc: &Calc, outputs: &CalcOutputs) -> Vec<String> {
+    let mut names = calc_outputs(calc);
+    if let Some(columns) = outputs.get(&calc.alias) {
+        names.extend(columns.iter().map(|c| c.name().to_string()));
+    }
+    names
+}
+
+fn same_provider(a: &ProviderInstance, b: &ProviderInstance) -> bool {
+    let strip = |p: &ProviderInstance| ProviderInstance {
+        span: Span::default(),
+        ..p.clone()
+    };
+    strip(a) == strip(b)
+}
+
+fn same_calc(a: &Calc, b: &Calc) -> bool {
+    // a KERNEL's WGSL or signature may change while the CALC line doesn't
+    let same_kernel = match (&a.kernel, &b.kernel) {
+        (Some(x), Some(y)) => {
+            x.source == y.source
+                && x.inputs == y.inputs
+                && x.outputs == y.outputs
+                && x.uniforms == y.uniforms
+        }
+        (x, y) => x.is_none() && y.is_none(),
+    };
+    a.inputs == b.inputs
+        && a.operation == b.operation
+        && a.centered == b.centered
+        && a.alias == b.alias
+        && same_kernel
+}
+
+/// Whether two frames' PULL and CALCs are the same, wherever they are in the query.
+pub fn same_actions(a: &ActionSection, b: &ActionSection) -> bool {
+    let (calcs_a, calcs_b) = (a.calc.as_deref(), b.calc.as_deref());
+    let (calcs_a, calcs_b) = (calcs_a.unwrap_or_default(), calcs_b.unwrap_or_default());
+    a.fields == b.fields
+        && calcs_a.len() == calcs_b.len()
+        && calcs_a.iter().zip(calcs_b).all(|(a, b)| same_calc(a, b))
+}
+
+fn same_calcs(a: &Frame, b: &Frame) -> bool {
+    let (a, b) = (a.actions.calc.as_deref(), b.actions.calc.as_deref());
+    let (a, b) = (a.unwrap_or_default(), b.unwrap_or_default());
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_calc(a, b))
+}
+
+fn same_graph(a: &GraphSection, b: &GraphSection) -> bool {
+    let strip = |c: &DrawCommand| {
+        let mut c = c.clone();
+        match &mut c {
+            DrawCommand::Line { span, .. }
+            | DrawCommand::Bar { span, .. }
+            | DrawCommand::Candle { span, .. } => *span = Span::default(),
+        }
+        c
+    };
+    a.xaxis == b.xaxis
+        && a.commands.len() == b.commands.len()
+        && a.commands
+            .iter()
+            .zip(&b.commands)
+            .all(|(a, b)| strip(a) == strip(b))
+}
+
+fn same_trade(a: &TradeSection, b: &TradeSection) -> bool {
+    let strip = |t: &TradeSection| TradeSection {
+        span: Span::default(),
+        over_frame_span: Span::default(),
+        entry_span: Span::default(),
+        exit_span: Span::default(),
+        ..t.clone()
+    };
+    strip(a) == strip(b)
+}
+
+fn graph_frames(graph: &GraphSection) -> impl Iterator<Item = &str> {
+    std::iter::once(graph.xaxis.as_str()).chain(graph.commands.iter().map(|c| match c {
+        DrawCommand::Line { frame, .. }
+        | DrawCommand::Bar { frame, .. }
+        | DrawCommand::Candle { frame, .. } => frame.as_str(),
+    }))
+}
+
+/// OVERFRAME plus the `frame.column` operands of ENTRY and EXIT.
+fn trade_frames(trade: &TradeSection) -> impl Iterator<Item = &str> {
+    std::iter::once(trade.over_frame.as_str()).chain(
+        trade
+            .entry
+            .iter()
+            .chain(&trade.exit)
+            .filter_map(|operand| operand.split_once('.').map(|(frame, _)| frame)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CalcOutputs, Plan};
+    use crate::parser::{parse, Query};
+    use polars::prelude::Column;
+    use std::collections::{HashMap, HashSet};
+
+    const SRC: &str = r#"
+PROVIDER a_data USING synthetic
+    FROM 2020-01-01 TO 2020-06-30
+
+PROVIDER b_data USING synthetic
+    FROM 2020-01-01 TO 2020-06-30
+
+FRAME a
+    PROVIDER a_data
+    PULL open, high, low, close
+    CALC open, close DIFFERENCE CALLED oc
+    CALC low SMA CALLED l_sma
+    CALC l_sma, oc DIFFERENCE CALLED spread
+
+FRAME b
+    PROVIDER b_data
+    PULL open, close
+    CALC close SMA CALLED c_sma
+
+GRAPH
+    XAXIS a
+    LINE l_sma FOR a
+
+TRADE
+    STOCK
+    OVERFRAME b
+    ENTRY b.close, b.c_sma, 0.05
+    EXIT b.close, b.c_sma, 0.05
+    LIMIT 0.1
+    HOLD 5
+"#;
+
+    /// Outputs as if `query` had just run.
+    fn outputs(query: &Query) -> HashMap<String, CalcOutputs> {
+        query
+            .frame
+            .iter()
+            .map(|(name, frame)| {
+                let calcs = frame.actions.calc.iter().flatten();
+                let outputs = calcs
+                    .map(|c| {
+                        (
+                            c.alias.clone(),
+                            vec![Column::new(c.alias.as_str().into(), [0.0])],
+                        )
+                    })
+                    .collect();
+                (name.clone(), outputs)
+            })
+            .collect()
+    }
+
+    fn plan(edit: impl Fn(&str) -> String) -> Plan {
+        let prev = parse(SRC).unwrap();
+        let next = parse(&edit(SRC)).unwrap();
+        Plan::diff(&prev, &next, &outputs(&prev))
+    }
+
+    fn set(items: &[&str]) -> HashSet<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_layout_changes_are_free() {
+        let p = plan(|s| format!("-- notes\n\n{}", s.replace("    PULL", "\tPULL")));
+        assert_eq!(p, Plan::default());
+    }
+
+    #[test]
+    fn test_calc_edit_recomputes_downstream_only() {
+        let p = plan(|s| s.replace("CALC low SMA", "CALC high SMA"));
+        assert!(p.fetch.is_empty());
+        assert_eq!(p.frames, HashMap::from([("a".to_string(), set(&["oc"]))]));
+        assert!(p.graph, "graph draws frame a");
+        assert!(!p.trade, "trade only reads frame b");
+    }
+
+    #[test]
+    fn test_section_edits() {
+        let p = plan(|s| s.replace("LINE l_sma FOR a", "LINE oc FOR a"));
+        assert_eq!((p.frames.len(), p.graph, p.trade), (0, true, false));
+
+        let p = plan(|s| s.replace("HOLD 5", "HOLD 10"));
+        assert_eq!((p.frames.len(), p.graph, p.trade), (0, false, true));
+
+        let p = plan(|s| {
+            s.replace(
+                "b_data USING synthetic\n    FROM 2020-01-01",
+                "b_data USING synthetic\n    FROM 2020-02-01",
+            )
+        });
+        assert_eq!(p.fetch, set(&["b_data"]));
+        assert_eq!(p.frames, HashMap::from([("b".to_string(), set(&[]))]));
+        assert_eq!((p.graph, p.trade), (false, true));
+    }
+}
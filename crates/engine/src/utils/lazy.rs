@@ -0,0 +1,312 @@
+// lazy.rs
+// -----------------------------------------------------------------------------
+// CALCs as one Polars lazy plan, for engines on CalcBackend::Lazy: each wave of
+// CALCs becomes a `with_columns` of expressions over the frame (arithmetic,
+// rolling windows, shifts, when/then), and Polars optimizes the whole plan and
+// runs it on its thread pool when collected. Inputs are cast to f64 up front,
+// so integer columns work as well as float ones.
+// -----------------------------------------------------------------------------
+
+use crate::calculation::periods_per_year;
+use crate::calendar::Calendar;
+use crate::lexer::Keyword;
+use crate::parser::{calc_outputs, order_calcs_by_waves, ActionSection, Calc};
+use crate::profile;
+use crate::utils::action::sort_by_timestamp;
+use crate::utils::incremental::CalcOutputs;
+use polars::prelude::*;
+
+/// Row numbers of the frame, the x of LINEAR_REGRESSION; not in the output.
+const ROW: &str = "__row";
+
+/// [`action_over_data_lazy`] over each of `frames` of bars on `calendar`, with the CALC
+/// outputs it reuses.
+pub fn action_over_frames_lazy(
+    action: &ActionSection,
+    frames: Vec<(DataFrame, CalcOutputs)>,
+    calendar: &Calendar,
+) -> Result<Vec<(DataFrame, CalcOutputs)>, String> {
+    let _lazy = profile::span("Polars CALCs");
+    frames
+        .into_iter()
+        .map(|(df, reuse)| {
+            let mut outputs = CalcOutputs::new();
+            let df = action_over_data_lazy(action, df, calendar, &reuse, &mut outputs)?;
+            Ok((df, outputs))
+        })
+        .collect()
+}
+
+/// [`action_over_data_cpu`](crate::utils::action::action_over_data_cpu) as one Polars
+/// lazy plan, over bars on `calendar`: the outputs of CALCs found in `reuse` are taken as they are, the rest
+/// computed, and every CALC's output columns end up in `outputs`. KERNEL CALCs only run
+/// on the GPU, so they fail here unless reused.
+pub fn action_over_data_lazy(
+    action: &ActionSection,
+    mut df: DataFrame,
+    calendar: &Calendar,
+    reuse: &CalcOutputs,
+    outputs: &mut CalcOutputs,
+) -> Result<DataFrame, String> {
+    sort_by_timestamp(&mut df)?;
+    let mut columns = vec![df
+        .column("timestamp")
+        .map_err(|e| format!("Failed to get timestamp column: {e}"))?
+        .clone()];
+    for field in &action.fields {
+        let column = df
+            .column(field)
+            .map_err(|e| format!("Failed to select fields: {e}"))?;
+        columns.push(column.clone());
+    }
+    let Some(calcs) = &action.calc else {
+        return DataFrame::new(columns).map_err(|e| format!("Failed to create DataFrame: {e}"));
+    };
+
+    for calc in calcs {
+        for column in reuse.get(&calc.alias).into_iter().flatten() {
+            df.with_column(column.clone())
+                .map_err(|e| format!("CALC {} ({}): {}", calc.alias, calc.span, e))?;
+        }
+    }
+    let annualize = periods_per_year(calendar, &df).sqrt();
+    let mut plan = df.lazy().with_row_index(ROW, None);
+    for wave in order_calcs_by_waves(action).map_err(|e| e.to_string())? {
+        let mut exprs = Vec::new();
+        for calc in wave.iter().filter(|c| !reuse.contains_key(&c.alias)) {
+            let calc_exprs = calc_exprs(calc, annualize)
+                .map_err(|e| format!("CALC {} ({}): {}", calc.alias, calc.span, e))?;
+            exprs.extend(calc_exprs);
+        }
+        if !exprs.is_empty() {
+            plan = plan.with_columns(exprs);
+        }
+    }
+    let out = plan.collect().map_err(|e| format!("CALCs failed: {e}"))?;
+
+    for calc in calcs {
+        let calc_columns = calc_outputs(calc)
+            .iter()
+            .map(|name| out.column(name).cloned())
+            .collect::<PolarsResult<Vec<_>>>()
+            .map_err(|e| format!("CALC {} ({}): {}", calc.alias, calc.span, e))?;
+        columns.extend(calc_columns.iter().cloned());
+        outputs.insert(calc.alias.clone(), calc_columns);
+    }
+    DataFrame::new(columns).map_err(|e| format!("Failed to create DataFrame: {e}"))
+}
+
+/// The expressions computing `calc`'s output columns, named as they come out.
+fn calc_exprs(calc: &Calc, annualize: f64) -> Result<Vec<Expr>, String> {
+    let input = |k: usize| -> Result<Expr, String> {
+        calc.inputs
+            .get(k)
+            .map(|name| col(name.as_str()).cast(DataType::Float64))
+            .ok_or_else(|| {
+                format!(
+                    "{} needs {} input column(s)",
+                    calc.operation.as_str(),
+                    k + 1
+                )
+            })
+    };
+    let period = calc
+        .inputs
+        .get(1)
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(14);
+    // a window missing any of its bars is missing, and deviations are over n - 1
+    let window = RollingOptionsFixedWindow {
+        window_size: period,
+        min_periods: period,
+        fn_params: Some(RollingFnParams::Var(RollingVarParams { ddof: 1 })),
+        ..Default::default()
+    };
+    // the window ending `ahead` bars later, for CENTERED
+    let ahead = calc.window_ahead(period) as i64;
+    let align = |e: Expr| if ahead == 0 { e } else { e.shift(lit(-ahead)) };
+    let alias = calc.alias.as_str();
+
+    let exprs = match calc.operation {
+        Keyword::Constant => {
+            let constant = calc
+                .inputs
+                .first()
+                .and_then(|s| s.parse::<f64>().ok())
+                .unwrap_or(0.0);
+            vec![lit(constant).alias(alias)]
+        }
+        // the GPU and CPU write one column per pair of inputs; this plan doesn't
+        Keyword::Difference if calc.inputs.len() > 2 => {
+            return Err(format!(
+                "DIFFERENCE of {} input columns isn't supported here, only of 2",
+                calc.inputs.len()
+            ))
+        }
+        Keyword::Difference => vec![(input(1)? - input(0)?).alias(alias)],
+        Keyword::Sma => vec![align(input(0)?.rolling_mean(window)).alias(alias)],
+        Keyword::Volatility | Keyword::DoubleVolatility => {
+            let scale = if calc.operation == Keyword::Volatility {
+                0.5
+            } else {
+                1.0
+            };
+            let price = input(0)?;
+            let prev = price.clone().shift(lit(1));
+            let log_return = when(price.clone().gt(lit(0.0)).and(prev.clone().gt(lit(0.0))))
+                .then((price.clone() / prev).log(std::f64::consts::E))
+                .otherwise(lit(NULL));
+            let vol = align(log_return.rolling_std(window) * lit(annualize));
+            vec![
+                vol.clone().alias(alias),
+                (price.clone() * (lit(1.0) + vol.clone() * lit(scale)))
+                    .alias(format!("{alias}_pos")),
+                (price * (lit(1.0) - vol * lit(scale))).alias(format!("{alias}_neg")),
+            ]
+        }
+        Keyword::LinearRegression => {
+            // least squares over the bars with a value, a flat line at the first one
+            // when there aren't two to fit
+            let y = input(0)?;
+            let row = col(ROW).cast(DataType::Float64);
+            let x = when(y.clone().is_not_null())
+                .then(row.clone())
+                .otherwise(lit(NULL));
+            let (mean_x, mean_y) = (x.clone().mean(), y.clone().mean());
+            let (dx, dy) = (x - mean_x.clone(), y.clone() - mean_y.clone());
+            let sxx = (dx.clone() * dx.clone()).sum();
+            let fits = sxx.clone().gt(lit(0.0));
+            let slope = when(fits.clone())
+                .then((dx * dy).sum() / sxx)
+                .otherwise(lit(0.0));
+            let intercept = when(fits)
+                .then(mean_y - slope.clone() * mean_x)
+                .otherwise(y.drop_nulls().first().fill_null(lit(0.0)));
+            vec![(slope * row + intercept).alias(alias)]
+        }
+        Keyword::Kernel => return Err("KERNEL CALCs need a GPU".to_string()),
+        _ => return Err(format!("Unsupported operation: {:?}", calc.operation)),
+    };
+    Ok(exprs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{daily_timestamps, frame_actions, frame_src, SYNTHETIC};
+    use crate::utils::action::action_over_data_cpu;
+    use crate::{CalcBackend, Engine};
+
+    #[test]
+    fn test_lazy_plan_matches_cpu() {
+        let action = &frame_actions(
+            "open, close, volume",
+            "
+    CALC 2.5 CONSTANT CALLED k
+    CALC close, open DIFFERENCE CALLED oc
+    CALC close SMA CALLED c_sma
+    CALC close SMA CENTERED CALLED mid
+    CALC close VOLATILITY CALLED vol
+    CALC close DOUBLE_VOLATILITY CENTERED CALLED wide
+    CALC close LINEAR_REGRESSION CALLED fit
+    CALC fit, close DIFFERENCE CALLED resid
+    CALC volume SMA CALLED v_sma
+",
+        );
+
+        let n = 300;
+        let close: Vec<Option<f64>> = (0..n)
+            .map(|i| (i != 40).then(|| 100.0 + (i * 7919 % 23) as f64 * 0.3 + i as f64 * 0.05))
+            .collect();
+        let df = df!(
+            "timestamp" => daily_timestamps(n),
+            "open" => close.iter().map(|c| c.map(|c| c - 0.5)).collect::<Vec<_>>(),
+            "close" => close,
+            // integer volume: the CPU casts it, the plan must too
+            "volume" => (0..n as i64).map(|i| 1_000 + i * 13 % 97).collect::<Vec<_>>(),
+        )
+        .unwrap();
+
+        let calendar = Calendar::default();
+        let mut lazy_outputs = CalcOutputs::new();
+        let lazy = action_over_data_lazy(
+            action,
+            df.clone(),
+            &calendar,
+            &CalcOutputs::new(),
+            &mut lazy_outputs,
+        )
+        .unwrap();
+        let cpu = action_over_data_cpu(
+            action,
+            df,
+            &calendar,
+            &CalcOutputs::new(),
+            &mut CalcOutputs::new(),
+        )
+        .unwrap();
+        assert_eq!(lazy.get_column_names(), cpu.get_column_names());
+        assert_eq!(lazy_outputs["wide"].len(), 3);
+        for column in cpu.get_columns().iter().skip(1) {
+            let name = column.name().as_str();
+            let expected = column.cast(&DataType::Float64).unwrap();
+            let got = lazy.column(name).unwrap().cast(&DataType::Float64).unwrap();
+            let (expected, got) = (expected.f64().unwrap(), got.f64().unwrap());
+            for (i, (e, g)) in expected.iter().zip(got.iter()).enumerate() {
+                let same = match (e, g) {
+                    (Some(e), Some(g)) => (e - g).abs() <= 1e-9 * e.abs().max(1.0),
+                    (e, g) => e.is_none() && g.is_none(),
+                };
+                assert!(same, "{name}[{i}]: CPU {e:?}, lazy {g:?}");
+            }
+        }
+
+        // reused outputs aren't recomputed, and KERNELs can't be
+        let mut reuse = lazy_outputs.clone();
+        reuse.remove("resid");
+        reuse.insert(
+            "fit".to_string(),
+            vec![Column::new("fit".into(), vec![0.0; n])],
+        );
+        let df = lazy
+            .select(["timestamp", "open", "close", "volume"])
+            .unwrap();
+        let again =
+            action_over_data_lazy(action, df, &calendar, &reuse, &mut CalcOutputs::new()).unwrap();
+        let resid = again.column("resid").unwrap().f64().unwrap();
+        assert_eq!(resid.get(0), Some(100.0));
+    }
+
+    #[test]
+    fn test_engine_runs_lazy() {
+        let calcs = "    CALC close, open DIFFERENCE CALLED oc\n    CALC close SMA CALLED c_sma\n";
+        let src = format!("{SYNTHETIC}\n{}", frame_src("open, close", calcs));
+        let mut engine = Engine::new(&src, "127.0.0.1:7000", Some(true)).unwrap();
+        engine.set_backend(CalcBackend::Lazy);
+        engine.run().unwrap();
+        assert_eq!(engine.backend(), CalcBackend::Lazy);
+        let names: Vec<&str> = engine
+            .profile()
+            .timings
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect();
+        assert!(names.contains(&"Polars CALCs"), "{names:?}");
+        assert!(!names.contains(&"GPU job"), "{names:?}");
+
+        // a file picks its own backend over the engine's, and the plan takes DIFFERENCEs
+        // of two columns only
+        let src =
+            format!("PARAM calcs = lazy\n{src}    CALC close, open, close DIFFERENCE CALLED d3\n");
+        let mut engine = Engine::new(&src, "127.0.0.1:7000", Some(true)).unwrap();
+        assert_eq!(engine.backend(), CalcBackend::Lazy);
+        let err = engine.run().unwrap_err();
+        assert!(err.contains("DIFFERENCE of 3 input columns"), "{err}");
+        let src = src.replace("calcs = lazy", "calcs = gpu");
+        let mut engine = Engine::new(&src, "127.0.0.1:7000", Some(true)).unwrap();
+        engine.set_backend(CalcBackend::Lazy);
+        assert_eq!(engine.backend(), CalcBackend::Gpu);
+        engine.run().unwrap();
+        assert_eq!(engine.calc_outputs["f"]["d3"].len(), 2);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::parser::duration_to_seconds;
+use polars::prelude::*;
+use std::time::{Duration, Instant};
+
+/// Polling schedule of a LIVE provider: `LIVE TICK <interval> FOR <duration>` polls
+/// every `interval` until `duration` after the run that started it.
+#[derive(Debug, Clone)]
+pub struct LiveFeed {
+    interval: Duration,
+    next_tick: Instant,
+    ends_at: Instant,
+    /// Timestamp of the newest bar so far.
+    pub last_bar: Option<i64>,
+}
+
+impl LiveFeed {
+    pub fn new(interval: &str, duration: &str, now: Instant) -> Result<Self, String> {
+        let interval = Duration::from_secs(duration_to_seconds(interval)? as u64);
+        let duration = Duration::from_secs(duration_to_seconds(duration)? as u64);
+        Ok(LiveFeed {
+            interval,
+            next_tick: now + interval,
+            ends_at: now + duration,
+            last_bar: None,
+        })
+    }
+
+    pub fn next_tick(&self) -> Option<Instant> {
+        (!self.is_over()).then_some(self.next_tick)
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        !self.is_over() && now >= self.next_tick
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.next_tick > self.ends_at
+    }
+
+    /// Make the feed due at `now`, ahead of its schedule.
+    pub fn poll_now(&mut self, now: Instant) {
+        self.next_tick = self.next_tick.min(now);
+    }
+
+    /// Schedule the next poll after `now`, skipping ticks that were missed.
+    pub fn advance(&mut self, now: Instant) {
+        while self.next_tick <= now {
+            self.next_tick += self.interval;
+        }
+    }
+}
+
+/// Timestamp of the newest bar in `bars`.
+pub fn last_bar(bars: &DataFrame) -> Option<i64> {
+    bars.column("timestamp")
+        .and_then(|c| c.cast(&DataType::Int64))
+        .ok()?
+        .i64()
+        .ok()?
+        .max()
+}
@@ -1,3 +1,7 @@
 pub mod action;
 pub mod graph;
+pub mod incremental;
+pub mod lazy;
+pub mod live;
+pub mod quality;
 pub mod trade;
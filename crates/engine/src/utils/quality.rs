@@ -0,0 +1,352 @@
+use crate::calendar::{self, Calendar, Gap, DAY};
+use crate::parser::FillPolicy;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const PRICE_COLUMNS: [&str; 4] = ["open", "high", "low", "close"];
+/// Bar-to-bar moves further than this many robust standard deviations from the median
+/// move are outliers.
+const OUTLIER_SCORE: f64 = 10.0;
+
+/// What a FRAME's data looked like before its FILL policy ran.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct DataQuality {
+    pub rows: usize,
+    pub issues: Vec<Issue>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Issue {
+    /// bars the calendar expected that aren't there
+    Gap(Gap),
+    /// bars sharing a timestamp with an earlier one
+    Duplicates { count: usize, first: i64 },
+    /// bars before the bar above them
+    OutOfOrder { count: usize, first: i64 },
+    /// nulls and NaNs
+    Missing { column: String, count: usize },
+    /// zero or negative prices
+    NonPositive {
+        column: String,
+        count: usize,
+        first: i64,
+    },
+    /// bar-to-bar moves far from the column's usual ones
+    Outliers {
+        column: String,
+        count: usize,
+        first: i64,
+    },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = |t: i64| calendar::format_time(t, t % DAY == 0);
+        let bars = |n: usize| format!("{} bar{}", n, if n == 1 { "" } else { "s" });
+        match self {
+            Issue::Gap(gap) => write!(f, "{}", gap),
+            Issue::Duplicates { count, first } => write!(
+                f,
+                "{} with a duplicate timestamp, first at {}",
+                bars(*count),
+                time(*first)
+            ),
+            Issue::OutOfOrder { count, first } => write!(
+                f,
+                "{} out of timestamp order, first at {}",
+                bars(*count),
+                time(*first)
+            ),
+            Issue::Missing { column, count } => {
+                write!(f, "{} missing {}", bars(*count), column)
+            }
+            Issue::NonPositive {
+                column,
+                count,
+                first,
+            } => write!(
+                f,
+                "{} with a zero or negative {}, first at {}",
+                bars(*count),
+                column,
+                time(*first)
+            ),
+            Issue::Outliers {
+                column,
+                count,
+                first,
+            } => write!(
+                f,
+                "{} with an outlying {} move, first at {}",
+                bars(*count),
+                column,
+                time(*first)
+            ),
+        }
+    }
+}
+
+impl DataQuality {
+    /// Check `bars` for gaps (when there's a `calendar` to check them against), duplicate
+    /// and out of order timestamps, missing values, zero or negative prices and outliers.
+    pub fn check(bars: &DataFrame, calendar: Option<&Calendar>) -> Result<Self, String> {
+        let ts = timestamps(bars)?;
+        let mut issues = Vec::new();
+
+        let out_of_order: Vec<i64> = (1..ts.len())
+            .filter(|&i| ts[i] < ts[i - 1])
+            .map(|i| ts[i])
+            .collect();
+        if let Some(&first) = out_of_order.first() {
+            issues.push(Issue::OutOfOrder {
+                count: out_of_order.len(),
+                first,
+            });
+        }
+
+        // everything else in time order
+        let mut order: Vec<usize> = (0..ts.len()).collect();
+        order.sort_by_key(|&i| ts[i]);
+        let sorted: Vec<i64> = order.iter().map(|&i| ts[i]).collect();
+
+        let duplicates: Vec<i64> = sorted
+            .windows(2)
+            .filter(|w| w[0] == w[1])
+            .map(|w| w[1])
+            .collect();
+        if let Some(&first) = duplicates.first() {
+            issues.push(Issue::Duplicates {
+                count: duplicates.len(),
+                first,
+            });
+        }
+        if let Some(calendar) = calendar {
+            let mut distinct = sorted.clone();
+            distinct.dedup();
+            issues.extend(calendar.gaps(&distinct).into_iter().map(Issue::Gap));
+        }
+
+        for column in bars.get_columns() {
+            let name = column.name().as_str();
+            if name == "timestamp" || !column.dtype().is_primitive_numeric() {
+                continue;
+            }
+            let values = f64s(column)?;
+            let count = values.iter().filter(|v| v.is_none()).count();
+            if count > 0 {
+                issues.push(Issue::Missing {
+                    column: name.to_string(),
+                    count,
+                });
+            }
+        }
+
+        for name in PRICE_COLUMNS {
+            let Ok(column) = bars.column(name) else {
+                continue;
+            };
+            let values = f64s(column)?;
+            let prices: Vec<(i64, f64)> = order
+                .iter()
+                .filter_map(|&i| Some((ts[i], values[i]?)))
+                .collect();
+
+            let non_positive: Vec<i64> = prices
+                .iter()
+                .filter(|(_, p)| *p <= 0.0)
+                .map(|(t, _)| *t)
+                .collect();
+            if let Some(&first) = non_positive.first() {
+                issues.push(Issue::NonPositive {
+                    column: name.to_string(),
+                    count: non_positive.len(),
+                    first,
+                });
+            }
+
+            let outliers = outliers(&prices);
+            if let Some(&first) = outliers.first() {
+                issues.push(Issue::Outliers {
+                    column: name.to_string(),
+                    count: outliers.len(),
+                    first,
+                });
+            }
+        }
+
+        Ok(DataQuality {
+            rows: bars.height(),
+            issues,
+        })
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Timestamps of the bars whose log return is an outlier: more than [`OUTLIER_SCORE`]
+/// median absolute deviations (scaled to a standard deviation) from the median return.
+fn outliers(prices: &[(i64, f64)]) -> Vec<i64> {
+    let returns: Vec<(i64, f64)> = prices
+        .windows(2)
+        .filter(|w| w[0].1 > 0.0 && w[1].1 > 0.0)
+        .map(|w| (w[1].0, (w[1].1 / w[0].1).ln()))
+        .collect();
+    let median = |mut v: Vec<f64>| -> Option<f64> {
+        if v.is_empty() {
+            return None;
+        }
+        let mid = v.len() / 2;
+        Some(*v.select_nth_unstable_by(mid, f64::total_cmp).1)
+    };
+    let Some(centre) = median(returns.iter().map(|r| r.1).collect()) else {
+        return Vec::new();
+    };
+    let Some(mad) = median(returns.iter().map(|r| (r.1 - centre).abs()).collect()) else {
+        return Vec::new();
+    };
+    if mad == 0.0 {
+        return Vec::new();
+    }
+    let sigma = 1.4826 * mad;
+    returns
+        .iter()
+        .filter(|(_, r)| (r - centre).abs() / sigma > OUTLIER_SCORE)
+        .map(|(t, _)| *t)
+        .collect()
+}
+
+/// `bars` in timestamp order with the missing values (nulls and NaNs) of `columns`
+/// handled by `policy`. Missing values left behind are nulls.
+pub fn apply_fill(
+    bars: &DataFrame,
+    columns: &[&str],
+    policy: FillPolicy,
+) -> Result<DataFrame, String> {
+    let mut out = bars
+        .sort(
+            ["timestamp"],
+            SortMultipleOptions::default().with_maintain_order(true),
+        )
+        .map_err(|e| format!("sort by timestamp failed: {}", e))?;
+    let strategy = match policy {
+        FillPolicy::Forward => Some(FillNullStrategy::Forward(None)),
+        FillPolicy::Backward => Some(FillNullStrategy::Backward(None)),
+        FillPolicy::Zero => Some(FillNullStrategy::Zero),
+        FillPolicy::Drop | FillPolicy::None => None,
+    };
+
+    let mut present = Vec::new();
+    for &name in columns {
+        let Ok(column) = out.column(name) else {
+            continue;
+        };
+        if name == "timestamp" || !column.dtype().is_primitive_numeric() {
+            continue;
+        }
+        let mut column = column.clone();
+        if column.dtype().is_float() {
+            let values = f64s(&column)?;
+            column = Column::new(name.into(), values)
+                .cast(column.dtype())
+                .map_err(|e| format!("'{}': {}", name, e))?;
+        }
+        if let Some(strategy) = strategy {
+            column = column
+                .fill_null(strategy)
+                .map_err(|e| format!("FILL {} '{}': {}", policy.as_str(), name, e))?;
+        }
+        out.replace(name, column.take_materialized_series())
+            .map_err(|e| format!("'{}': {}", name, e))?;
+        present.push(name.to_string());
+    }
+
+    if policy == FillPolicy::Drop {
+        out = out
+            .drop_nulls(Some(&present))
+            .map_err(|e| format!("FILL drop: {}", e))?;
+    }
+    Ok(out)
+}
+
+/// Timestamps in frame order, nulls as `i64::MIN` so rows still line up.
+fn timestamps(bars: &DataFrame) -> Result<Vec<i64>, String> {
+    Ok(bars
+        .column("timestamp")
+        .and_then(|c| c.cast(&DataType::Int64))
+        .map_err(|e| e.to_string())?
+        .i64()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|t| t.unwrap_or(i64::MIN))
+        .collect())
+}
+
+/// The values of `column`, with NaN and infinities missing as well as nulls.
+fn f64s(column: &Column) -> Result<Vec<Option<f64>>, String> {
+    Ok(column
+        .cast(&DataType::Float64)
+        .map_err(|e| format!("'{}': {}", column.name(), e))?
+        .f64()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|v| v.filter(|v| v.is_finite()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_fill, DataQuality, Issue};
+    use crate::calendar::Calendar;
+    use crate::parser::FillPolicy;
+    use polars::prelude::*;
+
+    const DAY: i64 = 86_400;
+
+    #[test]
+    fn test_quality_report_and_fill() {
+        // Mon 2024-03-04 .. with Wednesday missing, Friday twice and Thursday last
+        let mon = 1_709_510_400;
+        let bars = df!(
+            "timestamp" => [mon, mon + DAY, mon + 4 * DAY, mon + 4 * DAY, mon + 3 * DAY, mon + 7 * DAY, mon + 8 * DAY],
+            "close" => [Some(100.0), Some(101.0), None, Some(102.0), Some(f64::NAN), Some(400.0), Some(-1.0)],
+        )
+        .unwrap();
+
+        let report = DataQuality::check(&bars, Some(&Calendar::nyse())).unwrap();
+        let kinds: Vec<String> = report.issues.iter().map(|i| i.to_string()).collect();
+        assert_eq!(report.rows, 7);
+        assert!(report.issues.contains(&Issue::OutOfOrder {
+            count: 1,
+            first: mon + 3 * DAY
+        }));
+        assert!(report.issues.contains(&Issue::Duplicates {
+            count: 1,
+            first: mon + 4 * DAY
+        }));
+        assert!(kinds.contains(&"1 bar missing between 2024-03-05 and 2024-03-07".into()));
+        assert!(kinds.contains(&"2 bars missing close".into()), "{kinds:?}");
+        assert!(report.issues.contains(&Issue::NonPositive {
+            column: "close".into(),
+            count: 1,
+            first: mon + 8 * DAY
+        }));
+        assert!(matches!(report.issues.last(), Some(Issue::Outliers { .. })));
+
+        let close = |df: DataFrame| -> Vec<Option<f64>> {
+            df.column("close").unwrap().f64().unwrap().to_vec()
+        };
+        let forward = apply_fill(&bars, &["close"], FillPolicy::Forward).unwrap();
+        // sorted: Thursday's NaN comes before Friday's null and takes Tuesday's close
+        assert_eq!(
+            close(forward),
+            [100.0, 101.0, 101.0, 101.0, 102.0, 400.0, -1.0].map(Some)
+        );
+        let none = apply_fill(&bars, &["close"], FillPolicy::None).unwrap();
+        assert_eq!(close(none).iter().filter(|v| v.is_none()).count(), 2);
+        let dropped = apply_fill(&bars, &["close"], FillPolicy::Drop).unwrap();
+        assert_eq!(dropped.height(), 5);
+    }
+}
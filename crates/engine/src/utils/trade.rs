@@ -1,4 +1,5 @@
-use crate::parser::TradeSection;
+use crate::calendar::{bar_interval, Calendar};
+use crate::parser::{Hold, Span, TradeSection};
 use polars::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -90,7 +91,7 @@ impl Trade {
         entry_thr: f64,
         exit_thr: f64,
         stop_loss: f64,
-        hold: usize,
+        hold_until: &[usize],
     ) -> Result<(), String> {
         let n = self.height();
         if n == 0 || self.width_entry() < 2 || self.width_exit() < 1 {
@@ -110,8 +111,8 @@ impl Trade {
             let limit_val = entry_val * (1.0 - stop_loss);
 
             let mut closed = false;
-            for la in 1..=hold {
-                let idx = row + la;
+            let until = hold_until[row];
+            for idx in row + 1..=until {
                 if idx >= n {
                     break;
                 }
@@ -134,12 +135,12 @@ impl Trade {
             }
 
             if !closed {
-                let close_idx = (row + hold).min(n - 1);
+                let close_idx = until.min(n - 1);
                 self.exit_out[close_idx] = Some(entry_uid.clone());
                 self.reset_uid();
             }
 
-            row += hold.max(1);
+            row = until.max(row + 1);
         }
 
         Ok(())
@@ -228,6 +229,39 @@ fn build_open_lookup(frame: &DataFrame) -> Result<HashMap<i64, f64>, String> {
     Ok(map)
 }
 
+/// Ex-date timestamps, and the running total of dividends paid through each.
+type DividendTotals = (Vec<i64>, Vec<f64>);
+
+/// Cash dividends of an ADJUST frame. `None` when the frame has no `dividend` column.
+fn dividend_totals(frame: &DataFrame) -> Result<Option<DividendTotals>, String> {
+    let Ok(cash) = frame.column("dividend") else {
+        return Ok(None);
+    };
+    let cash = cash
+        .cast(&DataType::Float64)
+        .map_err(|e| format!("'dividend' not numeric: {e}"))?;
+    let ts = frame
+        .column("timestamp")
+        .map_err(|e| format!("frame missing 'timestamp': {e}"))?
+        .i64()
+        .map_err(|e| format!("'timestamp' not i64: {e}"))?;
+    let mut paid: Vec<(i64, f64)> = ts
+        .into_iter()
+        .zip(cash.f64().map_err(|e| e.to_string())?)
+        .filter_map(|(t, d)| Some((t?, d.unwrap_or(0.0))))
+        .collect();
+    paid.sort_by_key(|(t, _)| *t);
+    let mut total = 0.0;
+    Ok(Some(
+        paid.into_iter()
+            .map(|(t, d)| {
+                total += d;
+                (t, total)
+            })
+            .unzip(),
+    ))
+}
+
 pub fn trade_graphing_util(
     context: TradeSection,
     trades: &DataFrame,
@@ -271,23 +305,37 @@ pub fn trade_graphing_util(
     rects
 }
 
+/// P&L of `trades` over the over frame `frame`, dividends paid while open included.
 pub fn trade_summary_util(
-    _context: TradeSection,
+    context: TradeSection,
     trades: &DataFrame,
     frame: &DataFrame,
-) -> TradeSummary {
+) -> Result<TradeSummary, String> {
     let mut tsum = TradeSummary::default();
+    // a position held over an ex-date is paid that dividend
+    let dividends = dividend_totals(frame)
+        .map_err(|e| format!("OVERFRAME ({}): dividends: {}", context.over_frame_span, e))?;
     let lookup = match build_open_lookup(frame) {
         Ok(m) => m,
-        Err(_) => return tsum,
+        Err(_) => return Ok(tsum),
     };
 
     let entry_ca = match trades.column("Entry").and_then(|s| s.i64()) {
         Ok(ca) => ca,
-        Err(_) => return tsum,
+        Err(_) => return Ok(tsum),
     };
     let exit_ca = trades.column("Exit").ok().and_then(|s| s.i64().ok());
     let limit_ca = trades.column("Limit").ok().and_then(|s| s.i64().ok());
+    let paid = |from: i64, to: i64| match &dividends {
+        Some((ts, total)) => {
+            let through = |t: i64| match ts.partition_point(|x| *x <= t) {
+                0 => 0.0,
+                i => total[i - 1],
+            };
+            through(to) - through(from)
+        }
+        None => 0.0,
+    };
 
     for idx in 0..trades.height() {
         let entry_ts = entry_ca.get(idx).unwrap_or(0);
@@ -295,14 +343,16 @@ pub fn trade_summary_util(
 
         if let Some(limit_ts) = limit_ca.and_then(|c| c.get(idx)) {
             if let Some(limit) = lookup.get(&limit_ts) {
-                tsum.bar_chart_data.push(limit - entry);
-                tsum.avg_loss_per_1000 += entry / 1000.0 * (limit - entry);
+                let pnl = limit - entry + paid(entry_ts, limit_ts);
+                tsum.bar_chart_data.push(pnl);
+                tsum.avg_loss_per_1000 += entry / 1000.0 * pnl;
             }
         }
         if let Some(exit_ts) = exit_ca.and_then(|c| c.get(idx)) {
             if let Some(profit) = lookup.get(&exit_ts) {
-                tsum.bar_chart_data.push(profit - entry);
-                tsum.avg_win_per_1000 += entry / 1000.0 * (profit - entry);
+                let pnl = profit - entry + paid(entry_ts, exit_ts);
+                tsum.bar_chart_data.push(pnl);
+                tsum.avg_win_per_1000 += entry / 1000.0 * pnl;
             }
         }
     }
@@ -320,7 +370,7 @@ pub fn trade_summary_util(
             0.0
         };
     }
-    tsum
+    Ok(tsum)
 }
 
 //* -------- parse "frame.col" -------- */
@@ -416,31 +466,74 @@ fn to_matrix_opt_f64(df: &DataFrame, cols: &[String]) -> Result<Vec<Vec<Option<f
     Ok(m)
 }
 
+/// The last row a position opened at each row may stay open to: `HOLD` bars on, or
+/// the first bar at or past the `HOLD` duration on `calendar`. Past the last row when
+/// the data ends first.
+fn hold_until(hold: Hold, timestamps: &[Option<i64>], calendar: &Calendar) -> Vec<usize> {
+    let n = timestamps.len();
+    let ts: Vec<i64> = timestamps.iter().map(|t| t.unwrap_or(i64::MIN)).collect();
+    match hold {
+        Hold::Bars(bars) => (0..n).map(|row| row + bars).collect(),
+        Hold::Sessions(days) => {
+            let daily = bar_interval(&ts).is_some_and(|i| i >= 86_400);
+            let dates: Vec<_> = ts.iter().map(|t| calendar.bar_date(*t, daily)).collect();
+            dates
+                .iter()
+                .map(|date| {
+                    let until = calendar.add_trading_days(*date, days);
+                    dates.partition_point(|d| *d < until)
+                })
+                .collect()
+        }
+        Hold::TradingTime(secs) => ts
+            .iter()
+            .map(|t| {
+                let until = calendar.add_trading_time(*t, secs);
+                ts.partition_point(|x| *x < until)
+            })
+            .collect(),
+    }
+}
+
+/// The trades `trade_section`'s rules make over `frames`. Errors start with the rule that
+/// failed and where it is, `ENTRY (line 3, column 5): ...`, or the TRADE section's.
 pub fn trades_over_data(
     trade_section: &TradeSection,
     frames: &HashMap<String, DataFrame>,
+    calendar: &Calendar,
 ) -> Result<DataFrame, String> {
+    let at = |rule: &'static str, span: Span| move |e: String| format!("{rule} ({span}): {e}");
+    let on_entry = at("ENTRY", trade_section.entry_span);
+    let on_exit = at("EXIT", trade_section.exit_span);
+    let on_over = at("OVERFRAME", trade_section.over_frame_span);
+    let on_trade = at("TRADE", trade_section.span);
+
     let entry_keys = trade_section
         .entry
         .iter()
         .map(split_key_col)
-        .collect::<Result<Vec<_>, _>>()?;
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(on_entry)?;
     let exit_keys = trade_section
         .exit
         .iter()
         .map(split_key_col)
-        .collect::<Result<Vec<_>, _>>()?;
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(on_exit)?;
 
-    let entry_cols = populate(entry_keys, frames)?;
-    let exit_cols = populate(exit_keys, frames)?;
+    let entry_cols = populate(entry_keys, frames).map_err(on_entry)?;
+    let exit_cols = populate(exit_keys, frames).map_err(on_exit)?;
 
-    // Build timestamps from the first available frame (kept for the intermediate filter step)
-    let timestamps: Vec<Option<i64>> = match frames.values().next() {
+    // Build timestamps from the over frame, or the first available one (kept for the intermediate filter step)
+    let over = frames
+        .get(&trade_section.over_frame)
+        .or_else(|| frames.values().next());
+    let timestamps: Vec<Option<i64>> = match over {
         Some(df0) => df0
             .column("timestamp")
-            .map_err(|e| format!("Missing timestamp: {e}"))?
+            .map_err(|e| on_over(format!("Missing timestamp: {e}")))?
             .i64()
-            .map_err(|e| format!("Timestamp not i64: {e}"))?
+            .map_err(|e| on_over(format!("Timestamp not i64: {e}")))?
             .to_vec(),
         None => return Ok(empty_trades_output()), // no frames at all
     };
@@ -453,7 +546,11 @@ pub fn trades_over_data(
             if e.contains("entry/exit heights differ") || e.contains("no overlapping timestamps") {
                 return Ok(empty_trades_output());
             }
-            return Err(e);
+            return Err(if e.starts_with("exit") {
+                on_exit(e)
+            } else {
+                on_entry(e)
+            });
         }
     };
 
@@ -462,12 +559,22 @@ pub fn trades_over_data(
         return Ok(empty_trades_output());
     }
 
-    trade.calculate(
-        trade_section.within_entry,
-        trade_section.within_exit,
-        trade_section.stop_loss,
-        trade_section.hold as usize,
-    )?;
+    if timestamps.len() != trade.height() {
+        return Err(on_over(format!(
+            "{} has {} bars, the ENTRY and EXIT columns {}",
+            trade_section.over_frame,
+            timestamps.len(),
+            trade.height()
+        )));
+    }
+    trade
+        .calculate(
+            trade_section.within_entry,
+            trade_section.within_exit,
+            trade_section.stop_loss,
+            &hold_until(trade_section.hold, &timestamps, calendar),
+        )
+        .map_err(on_trade)?;
 
     // Build the intermediate (timestamp, entry/exit/limit flags) df
     let df = df![
@@ -476,7 +583,7 @@ pub fn trades_over_data(
         "exit"  => trade.exit_out,
         "limit" => trade.limit_out,
     ]
-    .map_err(|e| format!("Failed to create DataFrame: {e}"))?;
+    .map_err(|e| on_trade(format!("Failed to create DataFrame: {e}")))?;
 
     // Keep only rows that have any marker
     let filtered = df
@@ -517,7 +624,7 @@ pub fn trades_over_data(
         .left_join(limits, col("id"), col("id"))
         .filter(col("Exit").is_not_null().or(col("Limit").is_not_null()))
         .collect()
-        .map_err(|e| format!("Failed to build trade summary: {e}"))?;
+        .map_err(|e| on_trade(format!("Failed to build trade summary: {e}")))?;
 
     // If the joins/filtering still produce nothing, return the empty schema.
     if out.height() == 0 {
@@ -816,3 +923,40 @@ fn empty_trades_output() -> DataFrame {
     DataFrame::new(vec![id.into(), entry.into(), exit.into(), limit.into()])
         .expect("empty trades schema")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_summary_needs_i64_timestamps_for_dividends() {
+        let src = "FRAME f\n    PROVIDER p\n    PULL close\nTRADE\n    STOCK\n    OVERFRAME f\n    ENTRY f.close, f.close, 0\n    EXIT f.close, f.close, 0\n    LIMIT 0.1\n    HOLD 2\n";
+        let trade = parse(src).unwrap().trade.unwrap();
+        let frame = df!(
+            "timestamp" => [0i64, 86_400, 172_800],
+            "open" => [10.0, 11.0, 12.0],
+            "dividend" => [0.0, 0.5, 0.0],
+        )
+        .unwrap();
+        let trades =
+            df!("Entry" => [0i64], "Exit" => [Some(172_800i64)], "Limit" => [None::<i64>]).unwrap();
+        let summary = trade_summary_util(trade.clone(), &trades, &frame).unwrap();
+        assert_eq!(summary.bar_chart_data, vec![2.5]);
+
+        // dates instead of epoch seconds: an error, not a summary without the dividend
+        let mut dated = frame.clone();
+        dated
+            .with_column(
+                frame
+                    .column("timestamp")
+                    .unwrap()
+                    .cast(&DataType::Date)
+                    .unwrap(),
+            )
+            .unwrap();
+        let err = trade_summary_util(trade, &trades, &dated).unwrap_err();
+        assert!(err.contains("OVERFRAME (line 6, column 5)"), "{err}");
+        assert!(err.contains("not i64"), "{err}");
+    }
+}
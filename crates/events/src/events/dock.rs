@@ -10,6 +10,7 @@ pub enum DockEvent {
     UpdateOutput { name: String, content: Output },
     ShowTrades { name: String },
     ShowTables { name: String },
+    ShowProfile { name: String },
 }
 
 impl DockEvent {
@@ -47,6 +48,10 @@ impl DockEvent {
                 // Implement tables showing logic here
                 self.clone()
             }
+            DockEvent::ShowProfile { name } => {
+                log::info!("Showing run profile for: {}", name);
+                self.clone()
+            }
 
             DockEvent::ShowFile { buffer, .. } => {
                 log::info!("Showing file: {}", buffer);
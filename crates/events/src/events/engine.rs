@@ -15,6 +15,9 @@ pub enum EngineEvent {
     Output { name: String, data: Output },
     SaveFile { filename: String, content: String },
     UpdateCode { filename: String },
+    PauseReplay { filename: String },
+    ResumeReplay { filename: String },
+    StepReplay { filename: String },
 }
 
 impl EngineEvent {
@@ -84,6 +87,12 @@ impl EngineEvent {
                 ));
                 EventResponse::Info(format!("Requested code update for file: {}", filename))
             }
+            EngineEvent::PauseReplay { filename }
+            | EngineEvent::ResumeReplay { filename }
+            | EngineEvent::StepReplay { filename } => {
+                let _ = engine_tx.send((client, Event::EngineEvent(self.clone())));
+                EventResponse::Info(format!("Requested replay control for file: {}", filename))
+            }
         }
     }
 }
@@ -45,6 +45,7 @@ pub enum UiEvent {
     ShowGraph { name: String },
     ShowTrades { name: String },
     ShowTables { name: String },
+    ShowProfile { name: String },
 }
 
 impl std::fmt::Debug for UiEvent {
@@ -87,6 +88,7 @@ impl std::fmt::Debug for UiEvent {
             UiEvent::ShowGraph { name } => write!(f, "UiEvent::ShowGraph {{ name: {} }}", name),
             UiEvent::ShowTrades { name } => write!(f, "UiEvent::ShowTrades {{ name: {} }}", name),
             UiEvent::ShowTables { name } => write!(f, "UiEvent::ShowTables {{ name: {} }}", name),
+            UiEvent::ShowProfile { name } => write!(f, "UiEvent::ShowProfile {{ name: {} }}", name),
         }
     }
 }
@@ -0,0 +1,922 @@
+// analysis.rs
+// -----------------------------------------------------------------------------
+// Editor queries over a single QQL document (diagnostics, completion, hover,
+// go-to-definition, symbols). Independent of the LSP transport.
+// -----------------------------------------------------------------------------
+
+use engine::lexer::{Keyword, Lexer, Token, TokenKind};
+use engine::parser::{
+    is_windowed, look_ahead, DataKind, DrawCommand, ParseOutcome, Parser, Span, TimeSpec,
+};
+use std::path::Path;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, DocumentSymbol,
+    Documentation, Hover, HoverContents, MarkupContent, MarkupKind, Position, Range, SymbolKind,
+};
+
+use crate::docs::{is_operation, keyword_doc};
+
+/// Section the parser would be in at the start of a line.
+#[derive(Debug, Clone, PartialEq)]
+enum Section {
+    TopLevel,
+    Provider {
+        has_backend: bool,
+    },
+    Frame {
+        name: Option<String>,
+        has_provider: bool,
+    },
+    Kernel,
+    Graph,
+    Trade,
+}
+
+const SECTION_KEYWORDS: [Keyword; 5] = [
+    Keyword::Provider,
+    Keyword::Kernel,
+    Keyword::Frame,
+    Keyword::Graph,
+    Keyword::Trade,
+];
+
+/// A parsed QQL document: the recovering parse plus its tokens for position lookups.
+pub struct Document {
+    outcome: ParseOutcome,
+    tokens: Vec<Token>,
+}
+
+impl Document {
+    pub fn new(src: &str) -> Self {
+        Self::in_dir(src, Path::new("."))
+    }
+
+    /// A document whose relative KERNEL files are in `dir`, the one the file is in.
+    pub fn in_dir(src: &str, dir: &Path) -> Self {
+        let tokens = Lexer::new(src)
+            .take_while(|t| {
+                !matches!(
+                    t,
+                    Ok(Token {
+                        kind: TokenKind::EOF,
+                        ..
+                    })
+                )
+            })
+            .filter_map(Result::ok)
+            .filter(|t| !matches!(t.kind, TokenKind::Newline | TokenKind::Comment(_)))
+            .collect();
+        Self {
+            outcome: Parser::new(src).with_base_dir(dir).parse_recovering(),
+            tokens,
+        }
+    }
+
+    /* ------------------------------ diagnostics ----------------------------- */
+
+    /// Parse errors plus references to providers/frames that are never declared, and
+    /// warnings on TRADE rules that look ahead.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut out: Vec<Diagnostic> = self
+            .outcome
+            .errors
+            .iter()
+            .map(|e| diagnostic(self.error_range(e.line, e.column), e.message.clone()))
+            .collect();
+
+        let query = &self.outcome.query;
+        for (name, frame) in &query.frame {
+            for provider in frame.providers() {
+                if !query.providers.contains_key(provider) {
+                    out.push(diagnostic(
+                        self.find_ident(frame.span, provider),
+                        format!("FRAME {} uses unknown PROVIDER {}", name, provider),
+                    ));
+                }
+            }
+        }
+        if let Some(graph) = &query.graph {
+            if !graph.xaxis.is_empty() && !query.frame.contains_key(&graph.xaxis) {
+                out.push(diagnostic(
+                    self.find_ident(graph.span, &graph.xaxis),
+                    format!("XAXIS references unknown FRAME {}", graph.xaxis),
+                ));
+            }
+            for command in &graph.commands {
+                let frame = command.get_frame();
+                if !query.frame.contains_key(&frame) {
+                    out.push(diagnostic(
+                        self.find_ident(command.span(), &frame),
+                        format!("unknown FRAME {}", frame),
+                    ));
+                }
+            }
+        }
+        if let Some(trade) = &query.trade {
+            if !trade.over_frame.is_empty() && !query.frame.contains_key(&trade.over_frame) {
+                out.push(diagnostic(
+                    self.find_ident(trade.span, &trade.over_frame),
+                    format!("OVERFRAME references unknown FRAME {}", trade.over_frame),
+                ));
+            }
+        }
+        for found in look_ahead(query) {
+            out.push(warning(
+                self.find_column(found.span, &found.column),
+                found.to_string(),
+            ));
+        }
+
+        out.sort_by_key(|d| (d.range.start.line, d.range.start.character));
+        out
+    }
+
+    /// Range of the token an error points at; errors without a position go at the end.
+    fn error_range(&self, line: usize, column: usize) -> Range {
+        if line == 0 {
+            let end = self
+                .tokens
+                .last()
+                .map(|t| to_position(t.line, t.end_column))
+                .unwrap_or_default();
+            return Range::new(end, end);
+        }
+        match self
+            .tokens
+            .iter()
+            .find(|t| t.line == line && t.column == column)
+        {
+            Some(tok) => token_range(tok),
+            None => Range::new(to_position(line, column), to_position(line, column + 1)),
+        }
+    }
+
+    /// First identifier inside `span` that is, or starts with, `name`.
+    fn find_ident(&self, span: Span, name: &str) -> Range {
+        self.tokens
+            .iter()
+            .filter(|t| span.contains(t.line, t.column))
+            .find_map(|t| match &t.kind {
+                TokenKind::Identifier(id) if id.split('.').next() == Some(name) => {
+                    Some(Range::new(
+                        to_position(t.line, t.column),
+                        to_position(t.line, t.column + name.chars().count()),
+                    ))
+                }
+                _ => None,
+            })
+            .unwrap_or_else(|| span_range(span))
+    }
+
+    /// The identifier inside `span` that is exactly `column`.
+    fn find_column(&self, span: Span, column: &str) -> Range {
+        self.tokens
+            .iter()
+            .filter(|t| span.contains(t.line, t.column))
+            .find(|t| identifier(t) == Some(column))
+            .map(token_range)
+            .unwrap_or_else(|| span_range(span))
+    }
+
+    /* ------------------------------- completion ----------------------------- */
+
+    pub fn completions(&self, pos: Position) -> Vec<CompletionItem> {
+        let (line, col) = from_position(pos);
+        let section = self.section_at(line);
+
+        // tokens fully left of the cursor, minus the word being typed
+        let current = self.token_at(line, col);
+        let before: Vec<&Token> = self
+            .tokens
+            .iter()
+            .filter(|t| t.line == line && t.end_column <= col)
+            .filter(|t| current.is_none_or(|c| !std::ptr::eq(*t, c)))
+            .collect();
+
+        let Some(first) = before.first() else {
+            return section_keywords(&section)
+                .into_iter()
+                .map(keyword_item)
+                .collect();
+        };
+        let last = before.last().unwrap();
+        let in_ref_section = matches!(section, Section::Graph | Section::Trade);
+
+        match (&first.kind, &last.kind) {
+            (_, TokenKind::Keyword(Keyword::For | Keyword::OverFrame | Keyword::Xaxis))
+                if in_ref_section =>
+            {
+                self.frame_items()
+            }
+            (TokenKind::Keyword(Keyword::Provider), _)
+                if before.len() == 1
+                    && matches!(
+                        section,
+                        Section::Frame {
+                            has_provider: false,
+                            ..
+                        }
+                    ) =>
+            {
+                self.provider_items()
+            }
+            (TokenKind::Keyword(Keyword::Calc), TokenKind::Keyword(Keyword::Called)) => vec![],
+            (TokenKind::Keyword(Keyword::Calc), TokenKind::Keyword(Keyword::Kernel)) => {
+                self.kernel_items()
+            }
+            (TokenKind::Keyword(Keyword::Calc), TokenKind::Keyword(kw)) if is_windowed(kw) => {
+                vec![
+                    keyword_item(Keyword::Centered),
+                    keyword_item(Keyword::Called),
+                ]
+            }
+            (TokenKind::Keyword(Keyword::Calc), _) => {
+                let mut items: Vec<CompletionItem> = Keyword::ALL
+                    .iter()
+                    .filter(|kw| is_operation(kw) || **kw == Keyword::Called)
+                    .cloned()
+                    .map(keyword_item)
+                    .collect();
+                if let Section::Frame {
+                    name: Some(frame), ..
+                } = &section
+                {
+                    items.extend(self.column_items(frame));
+                }
+                items
+            }
+            (TokenKind::Keyword(Keyword::Line | Keyword::Bar | Keyword::Candle), _) => {
+                let mut items = vec![keyword_item(Keyword::For)];
+                for frame in self.frame_names() {
+                    items.extend(self.column_items(&frame));
+                }
+                items
+            }
+            (TokenKind::Keyword(Keyword::Entry | Keyword::Exit), _) => {
+                match current
+                    .and_then(|t| identifier(t))
+                    .and_then(|id| id.split_once('.'))
+                {
+                    Some((frame, _)) => self.column_items(frame),
+                    None => self
+                        .frame_names()
+                        .into_iter()
+                        .flat_map(|frame| {
+                            self.frame_columns(&frame)
+                                .into_iter()
+                                .map(move |col| field_item(format!("{}.{}", frame, col), &frame))
+                        })
+                        .collect(),
+                }
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Replays the parser's section switching over every line before `line`.
+    fn section_at(&self, line: usize) -> Section {
+        let mut section = Section::TopLevel;
+        let mut i = 0;
+        while i < self.tokens.len() && self.tokens[i].line < line {
+            let line_no = self.tokens[i].line;
+            let start = i;
+            while i < self.tokens.len() && self.tokens[i].line == line_no {
+                i += 1;
+            }
+            let toks = &self.tokens[start..i];
+            let has_using = toks
+                .iter()
+                .any(|t| t.kind == TokenKind::Keyword(Keyword::Using));
+
+            section = match (&toks[0].kind, section) {
+                (TokenKind::Keyword(Keyword::Frame), _) => Section::Frame {
+                    name: toks.get(1).and_then(identifier).map(str::to_string),
+                    has_provider: false,
+                },
+                (TokenKind::Keyword(Keyword::Kernel), _) => Section::Kernel,
+                (TokenKind::Keyword(Keyword::Graph), _) => Section::Graph,
+                (TokenKind::Keyword(Keyword::Trade), _) => Section::Trade,
+                (
+                    TokenKind::Keyword(Keyword::Provider),
+                    Section::Provider { has_backend: false },
+                ) => Section::Provider { has_backend: true },
+                (
+                    TokenKind::Keyword(Keyword::Provider),
+                    Section::Frame {
+                        name,
+                        has_provider: false,
+                    },
+                ) => Section::Frame {
+                    name,
+                    has_provider: true,
+                },
+                (TokenKind::Keyword(Keyword::Provider), _) => Section::Provider {
+                    has_backend: has_using,
+                },
+                (TokenKind::Keyword(Keyword::Using), Section::Provider { .. }) => {
+                    Section::Provider { has_backend: true }
+                }
+                (_, section) => section,
+            };
+        }
+        section
+    }
+
+    fn frame_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.outcome.query.frame.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// PULL fields followed by CALC aliases.
+    fn frame_columns(&self, frame: &str) -> Vec<String> {
+        let Some(frame) = self.outcome.query.frame.get(frame) else {
+            return vec![];
+        };
+        let mut cols = frame.actions.fields.clone();
+        if let Some(calcs) = &frame.actions.calc {
+            cols.extend(calcs.iter().map(|c| c.alias.clone()));
+        }
+        cols
+    }
+
+    fn frame_items(&self) -> Vec<CompletionItem> {
+        self.frame_names()
+            .into_iter()
+            .map(|name| CompletionItem {
+                label: name,
+                kind: Some(CompletionItemKind::STRUCT),
+                detail: Some("FRAME".into()),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn provider_items(&self) -> Vec<CompletionItem> {
+        let mut names: Vec<&String> = self.outcome.query.providers.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| CompletionItem {
+                label: name.clone(),
+                kind: Some(CompletionItemKind::MODULE),
+                detail: Some("PROVIDER".into()),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn kernel_items(&self) -> Vec<CompletionItem> {
+        let mut names: Vec<&String> = self.outcome.query.kernels.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| CompletionItem {
+                label: name.clone(),
+                kind: Some(CompletionItemKind::FUNCTION),
+                detail: Some("KERNEL".into()),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn column_items(&self, frame: &str) -> Vec<CompletionItem> {
+        self.frame_columns(frame)
+            .into_iter()
+            .map(|col| field_item(col, frame))
+            .collect()
+    }
+
+    /* --------------------------------- hover -------------------------------- */
+
+    pub fn hover(&self, pos: Position) -> Option<Hover> {
+        let (line, col) = from_position(pos);
+        let tok = self.token_at(line, col)?;
+        let text = match &tok.kind {
+            TokenKind::Keyword(kw) => keyword_doc(kw)?.to_string(),
+            TokenKind::Identifier(_) => {
+                let (segment, frame) = segment_at(tok, col)?;
+                self.describe(segment, frame)?
+            }
+            _ => return None,
+        };
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: text,
+            }),
+            range: Some(token_range(tok)),
+        })
+    }
+
+    /// Markdown description of a provider, kernel, frame or CALC column name.
+    fn describe(&self, name: &str, frame: Option<&str>) -> Option<String> {
+        let query = &self.outcome.query;
+        if frame.is_none() {
+            if let Some(f) = query.frame.get(name) {
+                return Some(format!(
+                    "**FRAME** `{}`\n\nprovider `{}`\n\ncolumns: {}",
+                    name,
+                    f.providers().collect::<Vec<_>>().join("`, `"),
+                    self.frame_columns(name).join(", ")
+                ));
+            }
+            if let Some(p) = query.providers.get(name) {
+                let mut text = format!("**PROVIDER** `{}`", name);
+                if let Some(backend) = &p.backend {
+                    text.push_str(&format!("\n\nbackend `{}`", backend));
+                }
+                if let Some(ticker) = &p.ticker {
+                    text.push_str(&format!("\n\nticker `{}`", ticker));
+                }
+                match &p.time_spec {
+                    Some(TimeSpec::DateRange { from, to }) => {
+                        text.push_str(&format!("\n\n{} to {}", from, to))
+                    }
+                    Some(TimeSpec::LiveSpec { interval, duration }) => {
+                        text.push_str(&format!("\n\nlive, {} bars for {}", interval, duration))
+                    }
+                    None => {}
+                }
+                if p.kind == DataKind::Fundamental {
+                    text.push_str("\n\nfundamental filings");
+                }
+                return Some(text);
+            }
+            if let Some(k) = query.kernels.get(name) {
+                let mut text = format!("**KERNEL** `{}` from `{}`", name, k.path);
+                if !k.inputs.is_empty() {
+                    text.push_str(&format!("\n\ninputs: {}", k.inputs.join(", ")));
+                }
+                text.push_str(&format!("\n\noutputs: {}", k.outputs.join(", ")));
+                if !k.uniforms.is_empty() {
+                    let uniforms: Vec<String> = k
+                        .uniforms
+                        .iter()
+                        .map(|u| format!("{} = {}", u.name, u.value))
+                        .collect();
+                    text.push_str(&format!("\n\nuniforms: {}", uniforms.join(", ")));
+                }
+                return Some(text);
+            }
+        }
+        let (frame_name, calc) = self.find_calc(name, frame)?;
+        let operation = match &calc.kernel {
+            Some(kernel) => format!("KERNEL {}", kernel.name),
+            None => calc.operation.as_str().to_string(),
+        };
+        Some(format!(
+            "`{}.{}` = **{}** {}",
+            frame_name,
+            calc.alias,
+            operation,
+            calc.inputs.join(", ")
+        ))
+    }
+
+    fn find_calc(
+        &self,
+        alias: &str,
+        frame: Option<&str>,
+    ) -> Option<(&String, &engine::parser::Calc)> {
+        self.outcome
+            .query
+            .frame
+            .iter()
+            .filter(|(name, _)| frame.is_none_or(|f| f == name.as_str()))
+            .find_map(|(name, f)| {
+                f.actions
+                    .calc
+                    .as_ref()?
+                    .iter()
+                    .find(|c| c.alias == alias)
+                    .map(|c| (name, c))
+            })
+    }
+
+    /* ------------------------------ definition ------------------------------ */
+
+    /// Where the provider, kernel, frame or CALC column under the cursor is declared.
+    pub fn definition(&self, pos: Position) -> Option<Range> {
+        let (line, col) = from_position(pos);
+        let tok = self.token_at(line, col)?;
+        let (segment, frame) = segment_at(tok, col)?;
+        let query = &self.outcome.query;
+
+        if frame.is_none() {
+            if let Some(f) = query.frame.get(segment) {
+                return Some(self.name_range(f.span));
+            }
+            if let Some(p) = query.providers.get(segment) {
+                return Some(self.name_range(p.span));
+            }
+            if let Some(k) = query.kernels.get(segment) {
+                return Some(self.name_range(k.span));
+            }
+        }
+        self.find_calc(segment, frame)
+            .map(|(_, calc)| span_range(calc.span))
+    }
+
+    /// Range of the name following the keyword that opens `span`.
+    fn name_range(&self, span: Span) -> Range {
+        self.tokens
+            .iter()
+            .position(|t| t.line == span.line && t.column == span.column)
+            .and_then(|i| self.tokens.get(i + 1))
+            .filter(|t| t.line == span.line)
+            .map(token_range)
+            .unwrap_or_else(|| span_range(span))
+    }
+
+    /* -------------------------------- symbols ------------------------------- */
+
+    pub fn symbols(&self) -> Vec<DocumentSymbol> {
+        let query = &self.outcome.query;
+        let mut out = Vec::new();
+
+        for (name, p) in &query.providers {
+            out.push(symbol(
+                name.clone(),
+                p.backend.clone(),
+                SymbolKind::MODULE,
+                span_range(p.span),
+                self.name_range(p.span),
+                None,
+            ));
+        }
+        for (name, k) in &query.kernels {
+            out.push(symbol(
+                name.clone(),
+                Some(k.path.clone()),
+                SymbolKind::FUNCTION,
+                span_range(k.span),
+                self.name_range(k.span),
+                None,
+            ));
+        }
+        for (name, f) in &query.frame {
+            let children = f
+                .actions
+                .calc
+                .iter()
+                .flatten()
+                .map(|c| {
+                    let range = span_range(c.span);
+                    symbol(
+                        c.alias.clone(),
+                        Some(c.operation.as_str().to_string()),
+                        SymbolKind::FIELD,
+                        range,
+                        range,
+                        None,
+                    )
+                })
+                .collect();
+            out.push(symbol(
+                name.clone(),
+                Some(format!("PROVIDER {}", f.provider)),
+                SymbolKind::STRUCT,
+                span_range(f.span),
+                self.name_range(f.span),
+                Some(children),
+            ));
+        }
+        if let Some(graph) = &query.graph {
+            let children = graph
+                .commands
+                .iter()
+                .map(|c| {
+                    let (kind, name) = match c {
+                        DrawCommand::Line { name, .. } => ("LINE", name),
+                        DrawCommand::Bar { name, .. } => ("BAR", name),
+                        DrawCommand::Candle { name, .. } => ("CANDLE", name),
+                    };
+                    let range = span_range(c.span());
+                    symbol(
+                        format!("{} {}", kind, name),
+                        Some(c.get_frame()),
+                        SymbolKind::OBJECT,
+                        range,
+                        range,
+                        None,
+                    )
+                })
+                .collect();
+            let range = span_range(graph.span);
+            out.push(symbol(
+                "GRAPH".into(),
+                None,
+                SymbolKind::NAMESPACE,
+                range,
+                Range::new(range.start, range.start),
+                Some(children),
+            ));
+        }
+        if let Some(trade) = &query.trade {
+            let range = span_range(trade.span);
+            out.push(symbol(
+                "TRADE".into(),
+                Some(trade.over_frame.clone()),
+                SymbolKind::EVENT,
+                range,
+                Range::new(range.start, range.start),
+                None,
+            ));
+        }
+
+        out.sort_by_key(|s| (s.range.start.line, s.range.start.character));
+        out
+    }
+
+    /* -------------------------------- helpers ------------------------------- */
+
+    /// Token under (or immediately left of) the 1-based cursor column.
+    fn token_at(&self, line: usize, col: usize) -> Option<&Token> {
+        self.tokens
+            .iter()
+            .find(|t| t.line == line && t.column <= col && col <= t.end_column)
+    }
+}
+
+fn section_keywords(section: &Section) -> Vec<Keyword> {
+    use Keyword::*;
+    let mut kws = match section {
+        Section::TopLevel => vec![],
+        Section::Provider { has_backend } => {
+            let mut kws = vec![Using, Ticker, From, Live, Fundamental, Param];
+            if !has_backend {
+                kws.insert(0, Provider);
+            }
+            kws
+        }
+        Section::Frame { has_provider, .. } => {
+            let mut kws = vec![Pull, Calc, Adjust, Fill];
+            if !has_provider {
+                kws.insert(0, Provider);
+            }
+            kws
+        }
+        Section::Kernel => vec![Input, Output, Uniform],
+        Section::Graph => vec![Xaxis, Line, Bar, Candle],
+        Section::Trade => vec![
+            Stock, OptionCall, OptionPut, OverFrame, Entry, Exit, Limit, Hold,
+        ],
+    };
+    for kw in SECTION_KEYWORDS {
+        if !kws.contains(&kw) {
+            kws.push(kw);
+        }
+    }
+    kws
+}
+
+fn keyword_item(kw: Keyword) -> CompletionItem {
+    CompletionItem {
+        label: kw.as_str().to_string(),
+        kind: Some(CompletionItemKind::KEYWORD),
+        documentation: keyword_doc(&kw).map(|doc| {
+            Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: doc.to_string(),
+            })
+        }),
+        ..Default::default()
+    }
+}
+
+fn field_item(label: String, frame: &str) -> CompletionItem {
+    CompletionItem {
+        label,
+        kind: Some(CompletionItemKind::FIELD),
+        detail: Some(format!("column of {}", frame)),
+        ..Default::default()
+    }
+}
+
+fn identifier(tok: &Token) -> Option<&str> {
+    match &tok.kind {
+        TokenKind::Identifier(id) => Some(id),
+        _ => None,
+    }
+}
+
+/// For `frame.column` identifiers, the dotted part under the cursor and, when that is the
+/// column, the frame qualifying it.
+fn segment_at(tok: &Token, col: usize) -> Option<(&str, Option<&str>)> {
+    let id = identifier(tok)?;
+    let offset = col.saturating_sub(tok.column);
+    match id.split_once('.') {
+        Some((frame, column)) if offset > frame.chars().count() => Some((column, Some(frame))),
+        Some((frame, _)) => Some((frame, None)),
+        None => Some((id, None)),
+    }
+}
+
+fn diagnostic(range: Range, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("qql".into()),
+        message,
+        ..Default::default()
+    }
+}
+
+fn warning(range: Range, message: String) -> Diagnostic {
+    Diagnostic {
+        severity: Some(DiagnosticSeverity::WARNING),
+        ..diagnostic(range, message)
+    }
+}
+
+#[allow(deprecated)] // `DocumentSymbol::deprecated` has to be spelled out
+fn symbol(
+    name: String,
+    detail: Option<String>,
+    kind: SymbolKind,
+    range: Range,
+    selection_range: Range,
+    children: Option<Vec<DocumentSymbol>>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name,
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range,
+        children,
+    }
+}
+
+/* ------------------------- position conversions -------------------------- */
+// Lexer positions are 1-based characters, LSP positions are 0-based. QQL is ASCII,
+// so characters and UTF-16 code units line up.
+
+fn to_position(line: usize, column: usize) -> Position {
+    Position::new(
+        line.saturating_sub(1) as u32,
+        column.saturating_sub(1) as u32,
+    )
+}
+
+fn from_position(pos: Position) -> (usize, usize) {
+    (pos.line as usize + 1, pos.character as usize + 1)
+}
+
+fn token_range(tok: &Token) -> Range {
+    Range::new(
+        to_position(tok.line, tok.column),
+        to_position(tok.line, tok.end_column),
+    )
+}
+
+fn span_range(span: Span) -> Range {
+    Range::new(
+        to_position(span.line, span.column),
+        to_position(span.end_line, span.end_column),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const SRC: &str = indoc! {r#"
+        PROVIDER aapl_data
+            PROVIDER yahoo_finance
+            TICKER aapl
+            FROM 20200101 TO 20250901
+
+        FRAME aapl
+            PROVIDER aapl_data
+            PULL open, close
+            CALC close SMA CALLED c_sma
+
+        GRAPH
+            XAXIS aapl
+            LINE c_sma FOR aapl
+
+        TRADE
+            STOCK
+            OVERFRAME aapl
+            ENTRY aapl.close, aapl.c_sma, 0.05
+            EXIT aapl.close, aapl.open, 0.05
+            LIMIT 0.1
+            HOLD 5
+    "#};
+
+    fn labels(items: &[CompletionItem]) -> Vec<&str> {
+        items.iter().map(|i| i.label.as_str()).collect()
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        assert!(Document::new(SRC).diagnostics().is_empty());
+
+        let src = SRC.replace("LINE c_sma FOR aapl", "LINE c_sma FOR msft");
+        let diags = Document::new(&src).diagnostics();
+        assert_eq!(diags.len(), 1, "{:#?}", diags);
+        assert_eq!(
+            diags[0].range,
+            Range::new(Position::new(12, 19), Position::new(12, 23))
+        );
+
+        let src = SRC.replace("close SMA", "close BOGUS");
+        let diags = Document::new(&src).diagnostics();
+        assert_eq!(diags.len(), 1, "{:#?}", diags);
+        assert_eq!(
+            diags[0].range,
+            Range::new(Position::new(8, 15), Position::new(8, 20))
+        );
+
+        // a centered average lets ENTRY see later bars
+        let src = SRC.replace("close SMA", "close SMA CENTERED");
+        let diags = Document::new(&src).diagnostics();
+        assert_eq!(diags.len(), 1, "{:#?}", diags);
+        assert_eq!(diags[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diags[0].range,
+            Range::new(Position::new(17, 22), Position::new(17, 32))
+        );
+        assert!(diags[0].message.contains("CENTERED SMA"));
+
+        // a relative KERNEL is read from the file's directory
+        let dir = std::env::temp_dir().join(format!("qql_lsp_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("kernels")).unwrap();
+        std::fs::write(
+            dir.join("kernels").join("neg.wgsl"),
+            "@group(0) @binding(0) var<storage, read> a: array<f32>;\n\
+             @group(0) @binding(1) var<storage, read_write> out: array<f32>;\n\
+             @compute @workgroup_size(64)\n\
+             fn main(@builtin(global_invocation_id) id: vec3<u32>) { out[id.x] = -a[id.x]; }\n",
+        )
+        .unwrap();
+        let src = "KERNEL neg FROM \"kernels/neg.wgsl\"\n    INPUT a\n    OUTPUT out\n";
+        assert!(Document::in_dir(src, &dir).diagnostics().is_empty());
+        assert_eq!(Document::new(src).diagnostics().len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_completions() {
+        let doc = Document::new(SRC);
+
+        // after FOR: frame names
+        let items = doc.completions(Position::new(12, 19));
+        assert_eq!(labels(&items), vec!["aapl"]);
+
+        // CALC line: operations and the frame's columns
+        let items = doc.completions(Position::new(8, 15));
+        let items = labels(&items);
+        assert!(items.contains(&"SMA"));
+        assert!(items.contains(&"CALLED"));
+        assert!(items.contains(&"open"));
+
+        // after a windowed operation: CENTERED or CALLED
+        let items = doc.completions(Position::new(8, 19));
+        assert_eq!(labels(&items), vec!["CENTERED", "CALLED"]);
+
+        // ENTRY after `aapl.`: columns of aapl
+        let items = doc.completions(Position::new(17, 15));
+        assert_eq!(labels(&items), vec!["open", "close", "c_sma"]);
+
+        // start of a line in the FRAME: frame keywords first
+        let items = doc.completions(Position::new(8, 4));
+        assert_eq!(labels(&items)[..2], ["PULL", "CALC"]);
+    }
+
+    #[test]
+    fn test_hover_and_definition() {
+        let doc = Document::new(SRC);
+
+        let hover = doc.hover(Position::new(8, 16)).unwrap();
+        match hover.contents {
+            HoverContents::Markup(m) => assert!(m.value.contains("SMA")),
+            other => panic!("unexpected hover {:?}", other),
+        }
+
+        // `aapl` in `LINE c_sma FOR aapl` -> FRAME aapl
+        let def = doc.definition(Position::new(12, 20)).unwrap();
+        assert_eq!(def, Range::new(Position::new(5, 6), Position::new(5, 10)));
+
+        // `aapl_data` in the frame -> PROVIDER aapl_data
+        let def = doc.definition(Position::new(6, 14)).unwrap();
+        assert_eq!(def.start, Position::new(0, 9));
+
+        // `aapl.c_sma` -> the CALC defining c_sma
+        let def = doc.definition(Position::new(17, 28)).unwrap();
+        assert_eq!(def.start, Position::new(8, 4));
+    }
+
+    #[test]
+    fn test_symbols() {
+        let symbols = Document::new(SRC).symbols();
+        let names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["aapl_data", "aapl", "GRAPH", "TRADE"]);
+        assert_eq!(symbols[1].children.as_ref().unwrap()[0].name, "c_sma");
+    }
+}
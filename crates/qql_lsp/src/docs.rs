@@ -0,0 +1,93 @@
+// docs.rs
+// -----------------------------------------------------------------------------
+// Hover / completion documentation for QQL keywords
+// -----------------------------------------------------------------------------
+
+use engine::lexer::Keyword;
+
+/// Short markdown description of a keyword, shown on hover and in completion details.
+pub fn keyword_doc(kw: &Keyword) -> Option<&'static str> {
+    use Keyword::*;
+    let doc = match kw {
+        // sections
+        Provider => "`PROVIDER name` declares a data source; inside a FRAME, `PROVIDER name` selects which source the frame pulls from.",
+        Frame => "`FRAME name` defines a table built from a provider with `PULL` and `CALC` lines.",
+        Graph => "`GRAPH` plots frame columns. Needs an `XAXIS` and one or more `LINE`, `BAR` or `CANDLE` commands.",
+        Trade => "`TRADE` backtests entry/exit rules over a frame.",
+        Kernel => "`KERNEL name FROM \"file.wgsl\"` declares a WGSL compute kernel with `INPUT`, `OUTPUT` and `UNIFORM` lines; in a CALC, `KERNEL name` runs it on the GPU.",
+
+        // kernel signature
+        Input => "`INPUT col, ...` names the kernel's `read` storage bindings, @group(0) from binding 0, one column each.",
+        Output => "`OUTPUT name, ...` names the kernel's `read_write` storage bindings, after the INPUTs. With several, the CALC adds `alias_name` columns.",
+        Uniform => "`UNIFORM name = value, ...` default values of the members of the kernel's uniform struct at @group(1) @binding(0). A CALC can override them with numbers after its columns.",
+
+        // provider settings
+        Using => "`USING backend` picks the provider backend, e.g. `yahoo_finance`.",
+        Ticker => "`TICKER symbol` sets the instrument the provider fetches.",
+        From => "`FROM 2020-01-01 TO 2021-01-01` limits a provider to a historical date range.",
+        To => "End of a `FROM .. TO ..` date range.",
+        Live => "`LIVE TICK interval FOR duration` streams bars instead of a fixed date range.",
+        Tick => "Bar interval of a `LIVE` provider, e.g. `TICK 1m`.",
+        For => "In `LIVE`, how long to stream. In `GRAPH`, the frame a command plots.",
+        Param => "`PARAM key = value` passes a backend specific setting to the provider. Before the first section, `PARAM calcs = gpu` or `lazy` picks what runs the file's CALCs.",
+        Historical => "Historical market data source.",
+        Fundamental => "`FUNDAMENTAL` makes a provider serve filings (report date, filing date, values). Listed after a FRAME's provider, `PROVIDER prices, filings`, they join onto each bar as known at the time.",
+
+        // frame actions
+        Adjust => "`ADJUST splits, dividends` adjusts earlier prices for the provider's splits and/or dividends. Without `dividends`, cash dividends are added to the TRADE P&L instead.",
+        Fill => "`FILL forward|backward|zero|drop|none` says what the frame does with missing values before its CALCs: carry the last value forward (the default), take the next one, use 0, drop the bar, or leave them missing.",
+        Pull => "`PULL col, col, ...` selects provider columns into the frame.",
+        Calc => "`CALC inputs OPERATION CALLED alias` adds a computed column to the frame.",
+        Called => "Names the output column of a `CALC`.",
+        ShowTable => "Shows the frame as a table.",
+
+        // operations
+        Difference => "**DIFFERENCE** `a, b` — row-wise `b - a`.",
+        Sum => "**SUM** `a, b, ...` — row-wise sum of the inputs. Not yet supported by the engine.",
+        Multiply => "**MULTIPLY** `a, b, ...` — row-wise product of the inputs. Not yet supported by the engine.",
+        Divide => "**DIVIDE** `a, b` — row-wise `a / b`. Not yet supported by the engine.",
+        Sma => "**SMA** `col[, period]` — simple moving average of the last `period` bars (default 14), ending at each bar.",
+        Volatility => "**VOLATILITY** `col[, period]` — rolling standard deviation of log returns (default period 14).",
+        DoubleVolatility => "**DOUBLE_VOLATILITY** `col` — `VOLATILITY` scaled by two, for bands.",
+        LinearRegression => "**LINEAR_REGRESSION** `col` — least squares fitted line over the whole column.",
+        Constant => "**CONSTANT** `value` — a column filled with a single number.",
+        Centered => "`CENTERED` after SMA, VOLATILITY or DOUBLE_VOLATILITY centers each bar's window on it, so it reads `period / 2` later bars. For charts only: a TRADE reading it is warned of look-ahead.",
+
+        // graph
+        Xaxis => "`XAXIS frame` sets the frame whose timestamps form the x axis.",
+        Line => "`LINE col, ... FOR frame` draws one line per column.",
+        Bar => "`BAR col FOR frame` draws a bar series.",
+        Candle => "`CANDLE open, high, low, close FOR frame` draws candlesticks.",
+
+        // trade
+        Stock => "Trade the underlying stock.",
+        OptionCall => "Trade call options.",
+        OptionPut => "Trade put options.",
+        OverFrame => "`OVERFRAME frame` sets the frame the trade rules run over.",
+        Entry => "`ENTRY frame.a, frame.b, within` enters when `a` comes within `within` of `b`.",
+        Exit => "`EXIT frame.a, frame.b, within` exits when `a` comes within `within` of `b`.",
+        Limit => "`LIMIT pct` stop loss as a fraction of the entry price.",
+        Hold => "`HOLD bars` maximum number of bars to hold a position. `HOLD 10d` counts trading days and `HOLD 90m` trading time on the provider's calendar instead.",
+
+        Comma => return None,
+    };
+    Some(doc)
+}
+
+/// True for keywords that name a CALC operation.
+pub fn is_operation(kw: &Keyword) -> bool {
+    use Keyword::*;
+    matches!(
+        kw,
+        Difference
+            | Sum
+            | Multiply
+            | Divide
+            | Sma
+            | Volatility
+            | DoubleVolatility
+            | LinearRegression
+            | Constant
+            | Kernel
+    )
+}
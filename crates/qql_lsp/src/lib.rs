@@ -0,0 +1,27 @@
+//! Language server for QQL, speaking LSP over stdio.
+//!
+//! Built on `engine::parser`: diagnostics come from the recovering parser, everything
+//! else from the parsed query and its tokens.
+
+mod analysis;
+mod docs;
+mod server;
+
+pub use analysis::Document;
+pub use server::Backend;
+
+use tower_lsp::{LspService, Server};
+
+/// Serve LSP requests on stdin/stdout until the client disconnects.
+pub async fn serve_stdio() {
+    let (service, socket) = LspService::new(Backend::new);
+    Server::new(tokio::io::stdin(), tokio::io::stdout(), socket)
+        .serve(service)
+        .await;
+}
+
+/// Blocking wrapper around [`serve_stdio`] for callers without a tokio runtime.
+pub fn run_stdio() -> std::io::Result<()> {
+    tokio::runtime::Runtime::new()?.block_on(serve_stdio());
+    Ok(())
+}
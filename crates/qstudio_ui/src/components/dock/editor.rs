@@ -16,3 +16,12 @@ pub fn code_editor(ui: &mut egui::Ui, buffer: &mut String) {
         .with_numlines(true)
         .show(ui, buffer);
 }
+
+/// Replace `buffer` with its canonical QQL formatting. Sources that don't parse are left
+/// untouched.
+pub fn format_document(buffer: &mut String) {
+    match engine::format::format_source(buffer) {
+        Ok(formatted) => *buffer = formatted,
+        Err(e) => log::warn!("Not formatting document, it has errors: {}", e),
+    }
+}
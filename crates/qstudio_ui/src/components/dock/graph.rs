@@ -98,22 +98,34 @@ impl DrawGraph {
                     .follow_insertion_order(true),
             )
             .show(ui, |plot_ui| {
-                for draw_type in &self.graph.data {
+                for (i, draw_type) in self.graph.data.iter().enumerate() {
                     match draw_type {
                         DrawType::Line(name, values) => {
-                            let plot_points: PlotPoints = self
-                                .graph
-                                .axis_labels
-                                .iter()
-                                .zip(values)
-                                .map(|(label, value)| {
-                                    let x = label.parse::<f64>().unwrap_or(0.0);
-                                    let y = *value;
-                                    [x, y]
-                                })
-                                .collect();
-
-                            plot_ui.line(Line::new(name, plot_points));
+                            // one line per run of values, so missing ones leave a gap,
+                            // all one color, spread round the hue wheel like egui_plot does
+                            let color: Color32 = egui::ecolor::Hsva::new(
+                                (i as f32 * 0.618_034).fract(),
+                                0.85,
+                                0.5,
+                                1.0,
+                            )
+                            .into();
+                            let mut runs: Vec<Vec<[f64; 2]>> = vec![Vec::new()];
+                            for (label, value) in self.graph.axis_labels.iter().zip(values) {
+                                match value {
+                                    Some(y) => {
+                                        let x = label.parse::<f64>().unwrap_or(0.0);
+                                        runs.last_mut().unwrap().push([x, *y]);
+                                    }
+                                    None if runs.last().is_some_and(|r| !r.is_empty()) => {
+                                        runs.push(Vec::new())
+                                    }
+                                    None => {}
+                                }
+                            }
+                            for run in runs.into_iter().filter(|r| !r.is_empty()) {
+                                plot_ui.line(Line::new(name, PlotPoints::from(run)).color(color));
+                            }
                         }
 
                         DrawType::Bar(name, ys) => {
@@ -143,7 +155,8 @@ impl DrawGraph {
                             let elems: Vec<BoxElem> = candles
                                 .iter()
                                 .enumerate()
-                                .filter_map(|(i, &(open, high, low, close))| {
+                                .filter_map(|(i, candle)| {
+                                    let &(open, high, low, close) = candle.as_ref()?;
                                     let label = self.graph.axis_labels.get(i)?;
                                     let x = label.parse::<f64>().ok()?;
 
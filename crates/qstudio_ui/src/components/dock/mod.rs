@@ -25,6 +25,7 @@ use qstudio_tcp::Client;
 mod editor;
 mod graph;
 mod markdown;
+mod profile;
 mod table;
 mod trade;
 
@@ -61,6 +62,11 @@ pub enum PaneType {
         title: String,
         data: Receiver<Output>,
     },
+    ProfileView {
+        title: String,
+        data: Receiver<Output>,
+        profile_view: Option<profile::ProfileUi>,
+    },
 }
 
 impl PaneType {
@@ -73,6 +79,7 @@ impl PaneType {
             PaneType::TableView { name, .. } => format!("Table View - {}", name),
             PaneType::TradeView { title, .. } => format!("Trade View - {}", title),
             PaneType::FlowCharView { title, .. } => format!("Flow Chart View - {}", title),
+            PaneType::ProfileView { title, .. } => format!("Profile - {}", title),
         }
     }
 }
@@ -106,12 +113,17 @@ impl TabViewer for MyTabViewer {
     // ← Add your custom items here
     fn context_menu(
         &mut self,
-        _ui: &mut Ui,
-        _tab: &mut Self::Tab,
+        ui: &mut Ui,
+        tab: &mut Self::Tab,
         _surface: SurfaceIndex,
         _node: NodeIndex,
     ) {
-        // Import SearchMode if not already imported
+        if let PaneType::CodeEditor { buffer, .. } = tab {
+            if ui.button("Format document").clicked() {
+                editor::format_document(buffer);
+                ui.close();
+            }
+        }
     }
 
     fn ui(&mut self, ui: &mut Ui, tab: &mut Self::Tab) {
@@ -145,6 +157,11 @@ impl TabViewer for MyTabViewer {
                             log::error!("Failed to send SaveFile event: {}", e);
                         });
                 }
+                if ctx
+                    .input(|i| i.modifiers.shift && i.modifiers.alt && i.key_pressed(egui::Key::F))
+                {
+                    editor::format_document(buffer);
+                }
                 editor::code_editor(ui, buffer);
             }
             PaneType::GraphView {
@@ -165,6 +182,14 @@ impl TabViewer for MyTabViewer {
                 }
                 trade_summary.as_mut().unwrap().ui(ui);
             }
+            PaneType::ProfileView {
+                data, profile_view, ..
+            } => {
+                if profile_view.is_none() {
+                    *profile_view = Some(profile::ProfileUi::new(data.clone()));
+                }
+                profile_view.as_mut().unwrap().ui(ui);
+            }
             PaneType::TableView {
                 name, draw_table, ..
             } => {
@@ -289,6 +314,20 @@ impl PaneDock {
                         });
                         updated = true;
                     }
+                    events::events::dock::DockEvent::ShowProfile { name } => {
+                        log::info!("Showing run profile for: {}", name);
+                        let (tx, rx) = crossbeam_channel::unbounded::<Output>();
+                        self.panel_channels
+                            .entry(name.clone())
+                            .or_insert_with(Vec::new)
+                            .push((tx, rx.clone()));
+                        self.dock_state.push_to_focused_leaf(PaneType::ProfileView {
+                            title: name,
+                            data: rx,
+                            profile_view: None,
+                        });
+                        updated = true;
+                    }
                     events::events::dock::DockEvent::UpdateOutput { name, content } => {
                         // Implement output updating logic here
                         if let Some(channels) = self.panel_channels.get(&name) {
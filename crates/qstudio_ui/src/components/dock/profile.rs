@@ -0,0 +1,141 @@
+use crossbeam_channel::Receiver;
+use egui::{pos2, vec2, Align2, CornerRadius, FontId, Rect, RichText, Sense, Ui};
+use egui_extras::{Column, TableBuilder};
+use engine::output::Output;
+use engine::profile::Profile;
+
+const ROW_HEIGHT: f32 = 20.0;
+
+/// Where the last run spent its time: a timeline of its steps, nested ones under the
+/// steps they are part of, and the same steps as a table.
+#[derive(Debug, Clone)]
+pub struct ProfileUi {
+    pub listen: Receiver<Output>,
+    pub profile: Option<Profile>,
+}
+
+impl ProfileUi {
+    pub fn new(listen: Receiver<Output>) -> Self {
+        ProfileUi {
+            listen,
+            profile: None,
+        }
+    }
+
+    fn pump_snapshots(&mut self, ctx: &egui::Context) {
+        let mut update = false;
+        while let Ok(output) = self.listen.try_recv() {
+            if let Some(profile) = output.get_profile() {
+                self.profile = Some(profile.clone());
+                update = true;
+            }
+        }
+        if update {
+            ctx.request_repaint();
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui) {
+        self.pump_snapshots(ui.ctx());
+        match &self.profile {
+            Some(profile) if !profile.timings.is_empty() => {
+                ui.heading(format!(
+                    "Run took {:.1} ms",
+                    profile.total_us() as f64 / 1e3
+                ));
+                ui.separator();
+                timeline_ui(ui, profile);
+                ui.separator();
+                profile_table_ui(ui, profile);
+            }
+            _ => {
+                ui.label("No run profile available.");
+            }
+        }
+    }
+}
+
+/// One bar per step, across by time and down by nesting: host steps in blue, steps the
+/// GPU timed itself in green.
+fn timeline_ui(ui: &mut Ui, profile: &Profile) {
+    let theme = theme::get_mode_theme(ui.ctx());
+    let rows = profile
+        .timings
+        .iter()
+        .map(|t| t.depth + 1)
+        .max()
+        .unwrap_or(1);
+    let (rect, response) = ui.allocate_exact_size(
+        vec2(ui.available_width(), rows as f32 * ROW_HEIGHT),
+        Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    let scale = rect.width() / profile.total_us().max(1) as f32;
+
+    let mut hovered = None;
+    for timing in &profile.timings {
+        let left = rect.left() + timing.start_us as f32 * scale;
+        let top = rect.top() + timing.depth as f32 * ROW_HEIGHT;
+        let bar = Rect::from_min_size(
+            pos2(left, top),
+            vec2(
+                (timing.duration_us as f32 * scale).max(1.0),
+                ROW_HEIGHT - 2.0,
+            ),
+        );
+        let fill = if timing.gpu { theme.green } else { theme.blue };
+        painter.rect_filled(bar, CornerRadius::same(2), fill);
+        if bar.width() > 40.0 {
+            painter.with_clip_rect(bar.intersect(rect)).text(
+                bar.left_center() + vec2(4.0, 0.0),
+                Align2::LEFT_CENTER,
+                &timing.name,
+                FontId::proportional(12.0),
+                theme.crust,
+            );
+        }
+        if response.hover_pos().is_some_and(|pos| bar.contains(pos)) {
+            hovered = Some(timing);
+        }
+    }
+    if let Some(timing) = hovered {
+        response.on_hover_text_at_pointer(format!(
+            "{}\n{:.3} ms{}",
+            timing.name,
+            timing.duration_us as f64 / 1e3,
+            if timing.gpu { " on the GPU" } else { "" }
+        ));
+    }
+}
+
+fn profile_table_ui(ui: &mut Ui, profile: &Profile) {
+    let font = FontId::monospace(12.0);
+    TableBuilder::new(ui)
+        .striped(true)
+        .cell_layout(egui::Layout::left_to_right(egui::Align::LEFT))
+        .column(Column::initial(320.0).at_least(200.0).resizable(true))
+        .columns(Column::auto().at_least(90.0), 3)
+        .header(ROW_HEIGHT, |mut header| {
+            for title in ["Step", "Start (ms)", "Duration (ms)", "Timed by"] {
+                header.col(|ui| {
+                    ui.strong(title);
+                });
+            }
+        })
+        .body(|body| {
+            body.rows(ROW_HEIGHT, profile.timings.len(), |mut row| {
+                let timing = &profile.timings[row.index()];
+                let cells = [
+                    format!("{}{}", "  ".repeat(timing.depth), timing.name),
+                    format!("{:.3}", timing.start_us as f64 / 1e3),
+                    format!("{:.3}", timing.duration_us as f64 / 1e3),
+                    if timing.gpu { "GPU" } else { "host" }.to_string(),
+                ];
+                for cell in cells {
+                    row.col(|ui| {
+                        ui.label(RichText::new(cell).font(font.clone()));
+                    });
+                }
+            });
+        });
+}
@@ -32,6 +32,15 @@ impl EngineItem {
         }
     }
 
+    fn send_engine_event(&self, event: EngineEvent) {
+        self.ui_aluminum
+            .frontend_tx
+            .send((self.only_client.clone(), Event::EngineEvent(event)))
+            .unwrap_or_else(|e| {
+                log::error!("Failed to send engine event: {}", e);
+            });
+    }
+
     fn button_ui(&self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             match self.status.as_str() {
@@ -80,6 +89,52 @@ impl EngineItem {
                 }
             }
 
+            // replays of stored bars can be paused and stepped
+            if self.status.starts_with("Live") || self.status.starts_with("Replay") {
+                let (icon, hover, event) = if self.status == "Replay paused" {
+                    (
+                        egui_material_icons::icons::ICON_PLAY_ARROW,
+                        "Resume replay",
+                        EngineEvent::ResumeReplay {
+                            filename: self.name.clone(),
+                        },
+                    )
+                } else {
+                    (
+                        egui_material_icons::icons::ICON_PAUSE,
+                        "Pause replay",
+                        EngineEvent::PauseReplay {
+                            filename: self.name.clone(),
+                        },
+                    )
+                };
+                if ui
+                    .add(
+                        egui::Button::new(RichText::new(icon).size(16.0))
+                            .fill(egui::Color32::TRANSPARENT),
+                    )
+                    .on_hover_text(hover)
+                    .clicked()
+                {
+                    self.send_engine_event(event);
+                }
+
+                if ui
+                    .add(
+                        egui::Button::new(
+                            RichText::new(egui_material_icons::icons::ICON_SKIP_NEXT).size(16.0),
+                        )
+                        .fill(egui::Color32::TRANSPARENT),
+                    )
+                    .on_hover_text("Step to the next bar")
+                    .clicked()
+                {
+                    self.send_engine_event(EngineEvent::StepReplay {
+                        filename: self.name.clone(),
+                    });
+                }
+            }
+
             if ui
                 .add(
                     egui::Button::new(
@@ -193,6 +248,28 @@ impl EngineItem {
                         log::error!("Failed to send ShowTrades event: {}", e);
                     });
             }
+            if ui
+                .add(
+                    egui::Button::new(
+                        RichText::new(egui_material_icons::icons::ICON_TIMER).size(16.0),
+                    )
+                    .fill(egui::Color32::TRANSPARENT),
+                )
+                .on_hover_text("View run profile")
+                .clicked()
+            {
+                self.ui_aluminum
+                    .frontend_tx
+                    .send((
+                        self.only_client.clone(),
+                        Event::UiEvent(events::UiEvent::ShowProfile {
+                            name: self.name.clone(),
+                        }),
+                    ))
+                    .unwrap_or_else(|e| {
+                        log::error!("Failed to send ShowProfile event: {}", e);
+                    });
+            }
         });
     }
 
@@ -276,6 +276,25 @@ impl QStudioApp {
                                             );
                                         });
                                 }
+                                UiEvent::ShowProfile { name } => {
+                                    log::info!("Handling ShowProfile for: {}", name);
+                                    aluminum_clone
+                                        .dock_tx
+                                        .send((
+                                            client,
+                                            Event::DockEvent(
+                                                events::events::dock::DockEvent::ShowProfile {
+                                                    name,
+                                                },
+                                            ),
+                                        ))
+                                        .unwrap_or_else(|e| {
+                                            log::error!(
+                                                "Failed to forward ShowProfile event: {}",
+                                                e
+                                            );
+                                        });
+                                }
 
                                 UiEvent::NewOutputFromServer { filename, output } => {
                                     log::info!(
@@ -0,0 +1,39 @@
+use engine::format::format_source;
+use std::fs;
+
+/// `qstudio fmt`: format each file in place, or with `check` only report the ones that
+/// would change. Returns false if any file failed to parse or (with `check`) is unformatted.
+pub fn run(files: &[String], check: bool) -> bool {
+    let mut ok = true;
+    for file in files {
+        let src = match fs::read_to_string(file) {
+            Ok(src) => src,
+            Err(e) => {
+                log::error!("Failed to read {}: {}", file, e);
+                ok = false;
+                continue;
+            }
+        };
+        let formatted = match format_source(&src) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                log::error!("Failed to format {}: {}", file, e);
+                ok = false;
+                continue;
+            }
+        };
+        if formatted == src {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", file);
+            ok = false;
+        } else if let Err(e) = fs::write(file, formatted) {
+            log::error!("Failed to write {}: {}", file, e);
+            ok = false;
+        } else {
+            println!("formatted {}", file);
+        }
+    }
+    ok
+}
@@ -1,7 +1,8 @@
+pub mod fmt;
 pub mod server;
 pub mod utils;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 /// Simple program to greet a person
 #[derive(Parser, Debug, Clone)]
@@ -13,6 +14,16 @@ pub struct Args {
     /// server transmitting tcp stream address
     #[arg(long, default_value_t = String::from("127.0.0.1:7879"))]
     pub tx_address: String,
+    /// data provider server address, for PROVIDER backends without a local provider
+    #[arg(long, default_value_t = String::from("127.0.0.1:7000"))]
+    pub provider_address: String,
+    /// GPU memory, in MiB, a CALC job of the open files may use; bigger ones run on the CPU
+    #[arg(long, default_value_t = 1024)]
+    pub gpu_budget_mb: u64,
+    /// run CALCs as Polars lazy plans on the CPU instead of on the GPU, in files without
+    /// a `PARAM calcs` of their own
+    #[arg(long, default_value_t = false)]
+    pub lazy_calcs: bool,
 
     /// run client
     #[arg(short, long, default_value_t = false)]
@@ -21,7 +32,27 @@ pub struct Args {
     #[arg(short, long, default_value_t = false)]
     pub server: bool,
 
+    /// run the QQL language server on stdio (for VS Code, Neovim, ...)
+    #[arg(long, default_value_t = false)]
+    pub lsp: bool,
+
     /// Root directory for file system watcher
     #[arg(short, long, default_value_t = String::from("."))]
     pub root_dir: String,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Format .qql files in place
+    Fmt {
+        /// don't write; exit with an error if any file is not formatted
+        #[arg(long, default_value_t = false)]
+        check: bool,
+        /// files to format
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
 }
@@ -3,13 +3,27 @@ extern crate qstudio;
 use clap::Parser;
 
 use qstudio::server::QStudioServer;
-use qstudio::Args;
+use qstudio::{Args, Command};
 
 fn main() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
     let args = Args::parse();
 
+    if let Some(Command::Fmt { check, files }) = &args.command {
+        let ok = qstudio::fmt::run(files, *check);
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    if args.lsp {
+        // stdout belongs to the LSP client; logs still go to stderr
+        if let Err(e) = qql_lsp::run_stdio() {
+            log::error!("Language server failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     if !args.client && !args.server {
         log::error!("Please specify --client or --server to run the respective mode.");
         log::warn!("Also, you can run both with --client --server for a local only setup.");
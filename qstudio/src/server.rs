@@ -3,8 +3,9 @@ use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
 
 use busbar::{Copper, MakeT};
-use crossbeam_channel::{Receiver, Sender};
-use engine::Engine;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use engine::gpu_service::GpuService;
+use engine::{CalcBackend, Engine};
 use events::{Event, EventResponse, EventType, UiEvent};
 use qstudio_tcp::{Client, ClientList};
 
@@ -175,6 +176,14 @@ impl QStudioServer {
     pub fn spawn_engine_worker(&self) -> JoinHandle<()> {
         let rx = self.engine_rx.clone();
         let tx_address = self.args.tx_address.clone();
+        let provider_address = self.args.provider_address.clone();
+        // one GPU device and shader cache for every open file's engine
+        GpuService::init_shared(self.args.gpu_budget_mb << 20);
+        let backend = if self.args.lazy_calcs {
+            CalcBackend::Lazy
+        } else {
+            CalcBackend::Gpu
+        };
 
         thread::spawn(move || {
             log::info!("Starting Engine...");
@@ -182,8 +191,12 @@ impl QStudioServer {
 
             let event_closure = |event: Event, client: Client| match event {
                 Event::EngineEvent(engine_event) => {
-                    let notification =
-                        handle_engine_event(engine_event, &mut engines.lock().unwrap());
+                    let notification = handle_engine_event(
+                        engine_event,
+                        &mut engines.lock().unwrap(),
+                        &provider_address,
+                        backend,
+                    );
                     match client.send(Copper::ToServer {
                         client_id: "Test".into(),
                         callback_address: client.addr.clone(),
@@ -216,17 +229,55 @@ impl QStudioServer {
                             }
                         }
                     }
+                    for warning in engine.take_warnings() {
+                        let notification = EventResponse::Notification {
+                            parent_event_type: EventType::EngineEvent,
+                            kind: "Warning".into(),
+                            message: warning,
+                        };
+                        if let Err(e) = client.send(Copper::ToServer {
+                            client_id: "Test".into(),
+                            callback_address: client.addr.clone(),
+                            payload: notification.make_t(),
+                        }) {
+                            log::error!("Error sending data quality warning: {}", e);
+                        }
+                    }
+                }
+            };
+            // LIVE providers that are due get polled, new bars re-run their engine
+            let tick_closure = || {
+                let mut guard = engines.lock().unwrap();
+                for (filename, engine) in guard.iter_mut() {
+                    if let Err(e) = engine.tick() {
+                        log::error!("LIVE update failed for {}: {}", filename, e);
+                    }
                 }
             };
+
             let mut parent_client = Client::new(tx_address.clone());
             loop {
+                tick_closure();
                 new_output_closure(parent_client.clone());
-                match rx.recv() {
+
+                // wait for the next event, or only until the next LIVE tick
+                let next_tick = engines
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .filter_map(|engine| engine.next_tick())
+                    .min();
+                let received = match next_tick {
+                    Some(deadline) => rx.recv_deadline(deadline),
+                    None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match received {
                     Ok((client, event)) => {
                         log::info!("Engine received event: {}", event);
                         event_closure(event, client.clone());
                         parent_client = client; // keep client alive
                     }
+                    Err(RecvTimeoutError::Timeout) => continue,
                     Err(e) => {
                         log::error!("Engine error receiving event: {}", e);
                         break;
@@ -1,20 +1,27 @@
-use engine::Engine;
+use engine::providers::ReplayControl;
+use engine::{CalcBackend, Engine};
 use events::{events::engine::EngineEvent, EventResponse};
 use std::{collections::HashMap, fs};
 
 pub fn handle_engine_event(
     event: EngineEvent,
     engines: &mut HashMap<String, Engine>,
+    provider_addr: &str,
+    backend: CalcBackend,
 ) -> EventResponse {
     match event {
         EngineEvent::Start { filename } => {
+            let status;
             if let Some(engine) = engines.get_mut(&filename) {
                 log::warn!("Engine for file {} is already running.", filename);
                 let _ = engine.run();
+                status = started_status(engine);
             } else {
-                match Engine::new(&filename, "127.0.0.1:7000", None) {
+                match Engine::new(&filename, provider_addr, None) {
                     Ok(mut engine) => {
+                        engine.set_backend(backend);
                         let _ = engine.run();
+                        status = started_status(&engine);
                         engines.insert(filename.clone(), engine);
                         log::info!("Started engine for file: {}", filename);
                     }
@@ -30,7 +37,7 @@ pub fn handle_engine_event(
             }
             events::EventResponse::EngineEvent(EngineEvent::NewEngineMonitor {
                 name: filename,
-                status: "Started".into(),
+                status,
             })
         }
         EngineEvent::Stop { filename } => {
@@ -93,6 +100,8 @@ pub fn handle_engine_event(
                             filename: filename.clone(),
                         },
                         engines,
+                        provider_addr,
+                        backend,
                     )
                 }
                 Err(e) => {
@@ -114,9 +123,9 @@ pub fn handle_engine_event(
                 match engine.update_code() {
                     Ok(_) => {
                         log::info!("Code updated successfully for file: {}", filename);
-                        EventResponse::Info(format!(
-                            "Code updated successfully for file: {}",
-                            filename
+                        EventResponse::Info(with_cache_stats(
+                            format!("Code updated successfully for file: {}", filename),
+                            engine,
                         ))
                     }
                     Err(e) => {
@@ -133,9 +142,66 @@ pub fn handle_engine_event(
                 EventResponse::Info(format!("No running engine found for file: {}", filename))
             }
         }
+        EngineEvent::PauseReplay { filename } => {
+            control_replay(filename, ReplayControl::Pause, engines)
+        }
+        EngineEvent::ResumeReplay { filename } => {
+            control_replay(filename, ReplayControl::Resume, engines)
+        }
+        EngineEvent::StepReplay { filename } => {
+            control_replay(filename, ReplayControl::Step, engines)
+        }
         _ => {
             log::warn!("Received unsupported EngineEvent: {:?}", event);
             EventResponse::Info("Unsupported EngineEvent".into())
         }
     }
 }
+
+/// `message (cache: 2 hits, 1 miss)` when the engine caches provider data.
+fn with_cache_stats(message: String, engine: &Engine) -> String {
+    match engine.cache_stats() {
+        Some(stats) => format!("{} ({})", message, stats),
+        None => message,
+    }
+}
+
+/// `Started`, or `Live` while LIVE providers stream, with the cache stats.
+fn started_status(engine: &Engine) -> String {
+    let status = if engine.is_live() { "Live" } else { "Started" };
+    with_cache_stats(status.to_string(), engine)
+}
+
+/// Pause, resume or step the replay of `filename`; the engine monitor shows where it is.
+/// A step's new output goes out with the worker's other output updates.
+fn control_replay(
+    filename: String,
+    control: ReplayControl,
+    engines: &mut HashMap<String, Engine>,
+) -> EventResponse {
+    let Some(engine) = engines.get_mut(&filename) else {
+        log::warn!("No running engine found for file: {}", filename);
+        return EventResponse::Info(format!("No running engine found for file: {}", filename));
+    };
+    match engine.control_replay(control) {
+        Ok(_) => {
+            let status = match control {
+                ReplayControl::Pause => "Replay paused",
+                ReplayControl::Resume => "Replay running",
+                ReplayControl::Step => "Replay stepped",
+            };
+            EventResponse::EngineEvent(EngineEvent::NewEngineMonitor {
+                name: filename,
+                status: status.into(),
+            })
+        }
+        Err(e) => {
+            log::error!("Failed to control replay for file {}: {}", filename, e);
+            EventResponse::Notification {
+                parent_event_type: events::EventType::EngineEvent,
+                kind: "Error".into(),
+                message: format!("Failed to control replay for file {}: {}", filename, e),
+            }
+        }
+    }
+}